@@ -1,8 +1,10 @@
 import toml
 import sys
 import os
+import json
 import re
 import shutil
+import itertools
 
 import pandas as pd
 import numpy as np
@@ -11,6 +13,131 @@ from typing import List, Union
 
 SKIP_SGX = os.environ.get("EB_SKIP_SGX", False)
 
+# suffix of the experiment directories by pinning mode, as named by the benchmark
+# suffixes of the names of pinned experiments, for results of older versions (without them in the index)
+PINNING_SUFFIXES = {"none": "", "cores": "-pinned"}
+
+# deep-trace events of memory activity, in the order of the rows of the heatmap
+PAGE_EVENTS = ["mm-page-alloc", "mm-page-free", "kmalloc", "kfree", "sys-mmap", "sys-munmap", "sys-brk", "sys-brk-shrink"]
+HEATMAP_BUCKETS = 100
+
+# unit written by the collector -> (unit reported by the aggregator, multiplier); units not listed
+# here (counts, percentages, rates of events, watts, grams) are reported as they are written
+UNIT_CONVERSIONS = {
+    "ns": ("ms", 1e-6),
+    "msec": ("ms", 1.0),
+    "nJ": ("J", 1e-9),
+    "uJ": ("J", 1e-6),
+    "bytes": ("MiB", 1 / 2**20),
+    "kB": ("MiB", 1 / 2**10),
+    "bytes/s": ("MiB/s", 1 / 2**20),
+}
+
+def parse_size(value: str) -> int:
+    """
+    Parses a size with an optional K, M or G suffix (as accepted by Gramine) into bytes.
+    """
+    multipliers = {"K": 1 << 10, "M": 1 << 20, "G": 1 << 30}
+    value = value.strip()
+    if value and value[-1] in multipliers:
+        return int(value[:-1]) * multipliers[value[-1]]
+    return int(value)
+
+
+def format_size(size: int) -> str:
+    """
+    Formats a size in bytes using the largest K, M or G suffix that represents it exactly.
+    """
+    for suffix, multiplier in (("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)):
+        if size != 0 and size % multiplier == 0:
+            return f"{size // multiplier}{suffix}"
+    return str(size)
+
+
+def expand_enclave_size(value: Union[list, dict]) -> List[str]:
+    """
+    Expands an enclave_size range (e.g. { from = "64M", to = "4G", factor = 2 }) into a list of sizes.
+    Lists are returned unchanged. Mirrors the expansion performed when the configuration is loaded.
+    """
+    if isinstance(value, list):
+        return value
+    size, to, factor = parse_size(value["from"]), parse_size(value["to"]), value.get("factor", 2)
+    sizes = []
+    while size <= to:
+        sizes.append(format_size(size))
+        size *= factor
+    return sizes
+
+
+def expand_num_threads(value: Union[list, dict]) -> List[int]:
+    """
+    Expands a num_threads range (e.g. { from = 1, to = 32, step = "x2" }) into a list of thread counts.
+    Lists are returned unchanged. Mirrors the expansion performed when the configuration is loaded.
+    """
+    if isinstance(value, list):
+        return value
+    step = str(value.get("step", 1)).strip()
+    if step.startswith("x"):
+        next_value = lambda n: n * int(step[1:])
+    else:
+        next_value = lambda n: n + int(step.lstrip("+"))
+    threads, n = [], value["from"]
+    while n <= value["to"]:
+        threads.append(n)
+        n = next_value(n)
+    return threads
+
+
+def args_matrix_suffixes(matrix: dict) -> List[str]:
+    """
+    Returns the experiment directory suffixes for every combination of an args_matrix
+    (e.g. { "--batch-size" = ["32", "128"] } -> ["-batch-size=32", "-batch-size=128"]).
+    Mirrors the naming used by the profiler: arguments are sorted and leading dashes are removed.
+    """
+    keys = [k for k in sorted(matrix) if matrix[k]]
+    suffixes = []
+    for values in itertools.product(*[matrix[k] for k in keys]):
+        suffixes.append("".join(f"-{k.lstrip('-')}={v.replace('/', '_')}" for k, v in zip(keys, values)))
+    return suffixes
+
+
+def key_provisioning_modes(task: dict, storage: str) -> List[bool]:
+    """
+    Returns whether the SGX experiments of a task with a storage type have their key provisioned,
+    in the order they are run: with the embedded key first, unless key_provisioning sets
+    embedded_key = false. Only encrypted storage has a key to provision.
+    """
+    provisioning = task.get("key_provisioning")
+    if provisioning is None or storage != "encrypted":
+        return [False]
+    return [False, True] if provisioning.get("embedded_key", True) else [True]
+
+
+def normalize_units(df: pd.DataFrame, value_column: str, unit_column: str) -> pd.DataFrame:
+    """
+    Converts the values of a per-sample DataFrame to the units of UNIT_CONVERSIONS.
+
+    This is the only place where the aggregator changes the scale of a metric: every process_*
+    function that reports a unit column calls it on the raw rows, before computing statistics, so
+    means and standard deviations come out in the normalized unit. The one exception is
+    process_energy_samples: energy time series have no unit column and keep the microjoules and
+    nanoseconds of the collector, so that they can be overlaid on the other raw time series.
+
+    Parameters:
+    df (pd.DataFrame): Per-sample rows, with one value and one unit per row.
+    value_column (str): Name of the column holding the values.
+    unit_column (str): Name of the column holding the unit symbols written by the collector.
+
+    Returns:
+    pd.DataFrame: The DataFrame with converted values and renamed units.
+    """
+    df = df.copy()
+    for unit, (target, factor) in UNIT_CONVERSIONS.items():
+        rows = df[unit_column] == unit
+        df.loc[rows, value_column] = df.loc[rows, value_column].astype(float) * factor
+        df.loc[rows, unit_column] = target
+    return df
+
 def process_perf_samples(files: List[str]) -> pd.DataFrame:
     """
     Processes performance sample files to calculate the average and standard deviation of various metrics.
@@ -52,6 +179,7 @@ def process_perf_samples(files: List[str]) -> pd.DataFrame:
     df["unit_counter"] = df["unit_counter"].fillna("#")
     df["unit_metric"] = df["unit_metric"].fillna(0)
     df["metric"] = df["metric"].fillna(0)
+    df = normalize_units(df, "counter", "unit_counter")
 
     df_new = df.groupby("event").agg(
         counter_mean=("counter", "mean"),
@@ -68,6 +196,10 @@ def process_energy_samples(files: list, W: int = 10_000):  # W in microseconds (
     """
     Processes energy sample files to calculate the average energy consumption over a common time grid.
 
+    Unlike the other process_* functions, this one does not go through normalize_units: the
+    samples are a time series without a unit column, kept in the units of the collector
+    (microjoules on a nanosecond clock) like the other raw time series.
+
     Parameters:
     files (List[str]): List of file paths to the CSV files containing energy data.
     W (int): Coalescence window width in microseconds.
@@ -82,7 +214,9 @@ def process_energy_samples(files: list, W: int = 10_000):  # W in microseconds (
 
     for filename in files:
         df = pd.read_csv(filename)
-        df['relative_time'] = df['timestamp (ns)'] - df['timestamp (ns)'].iloc[0]
+        # the monotonic clock is not adjusted like the wall clock; older results only have the latter
+        clock = 'monotonic (ns)' if 'monotonic (ns)' in df.columns else 'timestamp (ns)'
+        df['relative_time'] = df[clock] - df[clock].iloc[0]
 
         if common_time_grid is None:
             max_time = df['relative_time'].max()
@@ -115,7 +249,7 @@ def process_io(files: List[str]) -> pd.DataFrame:
                   - 'value_mean': The mean of the 'value' for each dimension and description.
                   - 'value_unit': The unit of the value, taken from the first occurrence in each group.
     """
-    df = pd.concat([pd.read_csv(f) for f in files])
+    df = normalize_units(pd.concat([pd.read_csv(f) for f in files]), "value", "unit")
     df_new = df.groupby(["dimension", "description"]).agg(
         value_mean=("value", "mean"),
         value_unit=("unit", "first"),
@@ -123,6 +257,228 @@ def process_io(files: List[str]) -> pd.DataFrame:
 
     return df_new
 
+def process_syscalls(files: List[str]) -> pd.DataFrame:
+    """
+    Processes I/O sample files to calculate the average count and latency of each traced syscall.
+
+    Syscall rows (`sys_*`, and the `kprobe_*` rows of `extra_kprobes`) come in pairs in `io.csv`:
+    the number of calls (unit `#`) and the average latency (unit `ns`, reported in `ms` by
+    normalize_units). The latency percentiles of `sys_read` and `sys_write` (`sys_read_p99`,
+    `sys_write_max`, ...) only have the latency row, so their count is left empty.
+
+    Parameters:
+    files (List[str]): A list of file paths to the CSV files containing I/O data.
+
+    Returns:
+    pd.DataFrame: A DataFrame indexed by syscall with the columns 'count' and 'latency_ms',
+                  averaged across samples.
+    """
+    df = pd.concat([pd.read_csv(f) for f in files])
+    df = normalize_units(df[df["dimension"].str.match(r"(sys|kprobe)_")], "value", "unit")
+    df = df.groupby(["dimension", "unit"])["value"].mean().unstack("unit")
+    df = df.rename(columns={"#": "count", "ms": "latency_ms"})
+    df.index.name = "syscall"
+    return df.reindex(columns=["count", "latency_ms"])
+
+def syscall_overhead(native: pd.DataFrame, sgx: pd.DataFrame) -> pd.DataFrame:
+    """
+    Pairs the syscall statistics of an SGX experiment with those of its native baseline.
+
+    Parameters:
+    native (pd.DataFrame): The output of process_syscalls for the native experiment.
+    sgx (pd.DataFrame): The output of process_syscalls for the SGX experiment.
+
+    Returns:
+    pd.DataFrame: A DataFrame with one row per syscall traced in both experiments, with the columns of
+                  both inputs (suffixed with '_native' and '_sgx') and:
+                  - 'latency_overhead': SGX latency divided by native latency.
+                  - 'count_ratio': number of SGX calls divided by the number of native calls.
+                  Ratios with a zero denominator are left empty.
+    """
+    df = native.join(sgx, lsuffix="_native", rsuffix="_sgx", how="inner")
+    df["latency_overhead"] = df["latency_ms_sgx"] / df["latency_ms_native"]
+    df["count_ratio"] = df["count_sgx"] / df["count_native"]
+    return df.replace([np.inf, -np.inf], np.nan).reset_index()
+
+def pinning_comparison(unpinned: pd.DataFrame, pinned: pd.DataFrame) -> pd.DataFrame:
+    """
+    Compares the summary of an experiment run without CPU affinity with the same experiment pinned to cores.
+
+    Parameters:
+    unpinned (pd.DataFrame): The output of process_summary for the unpinned experiment.
+    pinned (pd.DataFrame): The output of process_summary for the pinned experiment.
+
+    Returns:
+    pd.DataFrame: A DataFrame with one row per metric of both summaries, with the columns of both inputs
+                  (suffixed with '_unpinned' and '_pinned') and:
+                  - 'delta_percent': change of the mean when pinned, in percent of the unpinned mean.
+                  - 'std_reduction_percent': decrease of the standard deviation when pinned, in percent of
+                    the unpinned one.
+                  Values with a zero denominator are left empty.
+    """
+    df = unpinned.set_index("metric").join(pinned.set_index("metric"), lsuffix="_unpinned", rsuffix="_pinned", how="inner")
+    df["delta_percent"] = (df["value_mean_pinned"] / df["value_mean_unpinned"] - 1) * 100
+    df["std_reduction_percent"] = (1 - df["value_std_pinned"] / df["value_std_unpinned"]) * 100
+    return df.replace([np.inf, -np.inf], np.nan).reset_index()
+
+def page_event_heatmap(trace_file: str, buckets: int = HEATMAP_BUCKETS) -> pd.DataFrame:
+    """
+    Buckets the memory events of a deep trace into a time x event type matrix.
+
+    The time axis spans the lifetime of the workload: from the `process-start` marker to the `process-exit`
+    marker, or the first and last event of the trace when a marker is missing.
+
+    Parameters:
+    trace_file (str): Path to the `trace.csv` file of a deep-trace run.
+    buckets (int): Number of time buckets.
+
+    Returns:
+    pd.DataFrame: A DataFrame with one row per time bucket and the columns:
+                  - 'time (s)': start of the bucket, in seconds from the start of the workload.
+                  - one column per event of PAGE_EVENTS with the number of such events in the bucket.
+    """
+    df = pd.read_csv(trace_file)
+    time = df["timestamp (ns)"]
+    start = df.loc[df["event"] == "process-start", "timestamp (ns)"]
+    stop = df.loc[df["event"] == "process-exit", "timestamp (ns)"]
+    origin = start.iloc[0] if not start.empty else time.min()
+    end = stop.iloc[0] if not stop.empty else time.max()
+    width = max(end - origin, 1) / buckets
+
+    df = df[df["event"].isin(PAGE_EVENTS) & (time >= origin) & (time <= end)]
+    bucket = ((df["timestamp (ns)"] - origin) // width).clip(upper=buckets - 1).astype(int)
+    counts = pd.crosstab(bucket, df["event"]).reindex(index=range(buckets), columns=PAGE_EVENTS, fill_value=0)
+    counts.insert(0, "time (s)", counts.index * width / 1e9)
+    return counts.reset_index(drop=True)
+
+def plot_heatmap(heatmap: pd.DataFrame, output_file: str) -> None:
+    """
+    Draws the output of page_event_heatmap as an image, one row per event type. Counts are on a logarithmic
+    color scale, as allocations usually outnumber the other events by orders of magnitude. Does nothing if
+    matplotlib is not installed.
+
+    Parameters:
+    heatmap (pd.DataFrame): The output of page_event_heatmap.
+    output_file (str): Path of the PNG file to write.
+    """
+    try:
+        import matplotlib
+        matplotlib.use("Agg")
+        import matplotlib.pyplot as plt
+    except ImportError:
+        return
+    times = heatmap["time (s)"]
+    end = times.iloc[-1] + (times.iloc[1] - times.iloc[0] if len(times) > 1 else 0)
+    fig, ax = plt.subplots(figsize=(12, 4))
+    image = ax.imshow(np.log1p(heatmap[PAGE_EVENTS].T.to_numpy()), aspect="auto", interpolation="nearest",
+                      extent=[0, end, len(PAGE_EVENTS), 0], cmap="viridis")
+    ax.set_yticks(np.arange(len(PAGE_EVENTS)) + 0.5, PAGE_EVENTS)
+    ax.set_xlabel("time (s)")
+    fig.colorbar(image, ax=ax, label="log(1 + events)")
+    fig.tight_layout()
+    fig.savefig(output_file)
+    plt.close(fig)
+
+def plot_trend(points: str, metric: str, output_file: str) -> None:
+    """
+    Plots the values of a metric across campaigns, one line per experiment and host.
+
+    Parameters:
+    points (str): JSON array of the points read from the trend database by `enclave-benchmark trends`.
+    metric (str): Name of the metric, used as the label of the y axis.
+    output_file (str): Path of the PNG file to write.
+    """
+    import matplotlib
+    matplotlib.use("Agg")
+    import matplotlib.pyplot as plt
+
+    df = pd.DataFrame(json.loads(points))
+    df["started"] = pd.to_datetime(df["started"])
+    series = ["host", "task", "mode", "num_threads", "enclave_size", "storage_type"]
+    fig, ax = plt.subplots(figsize=(12, 5))
+    for key, group in df.groupby(series, dropna=False):
+        label = " ".join(str(value) for value in key if pd.notna(value))
+        ax.plot(group["started"], group["value"], marker="o", label=label)
+    ax.set_xlabel("campaign")
+    ax.set_ylabel(metric)
+    ax.legend(fontsize="small")
+    fig.autofmt_xdate()
+    fig.tight_layout()
+    fig.savefig(output_file)
+    plt.close(fig)
+
+def process_summary(files: List[str]) -> pd.DataFrame:
+    """
+    Processes per-sample summary files to calculate the average and standard deviation of derived metrics.
+
+    Summary files contain metrics computed by the collector for each sample, such as energy-efficiency
+    figures (nJ per instruction, J per GB written). Samples where a metric could not be computed
+    simply miss the corresponding row.
+
+    Parameters:
+    files (List[str]): A list of file paths to the CSV files containing summary data. Each file is expected to
+                       have columns: "metric", "unit" and "value".
+
+    Returns:
+    pd.DataFrame: A DataFrame containing the aggregated summary data with the following columns:
+                  - 'metric': The metric name.
+                  - 'value_mean': The mean of the 'value' for each metric.
+                  - 'value_std': The standard deviation of the 'value' for each metric.
+                  - 'value_unit': The unit of the value, taken from the first occurrence in each group.
+    """
+    df = pd.concat([pd.read_csv(f) for f in files if os.path.isfile(f)])
+    df = normalize_units(df, "value", "unit")
+    df_new = df.groupby("metric").agg(
+        value_mean=("value", "mean"),
+        value_std=("value", "std"),
+        value_unit=("unit", "first"),
+    ).reset_index()
+
+    return df_new
+
+def process_phases(sample_directories: List[str], files: List[str]) -> dict:
+    """
+    Breaks the time series of the samples down by the phases announced by the workload.
+
+    Each row of a time series belongs to the phase of the last marker of `phases.csv` at or before
+    its timestamp; rows before the first marker are left out. The numeric columns (timestamps and
+    gap flags excluded) are averaged by phase, and by the text columns such as the CPU or the PSI
+    resource, within each sample, then across samples.
+
+    Parameters:
+    sample_directories (List[str]): The directories of the samples.
+    files (List[str]): The time series to break down (e.g. "cpu.csv", "package-0.csv").
+
+    Returns:
+    dict: A DataFrame by file name with the 'phase' and text columns and the '<column>_mean' and
+          '<column>_std' of every numeric column. Samples without phases are skipped; the result is empty if
+          no sample has any.
+    """
+    by_file = {}
+    for directory in sample_directories:
+        phases_file = os.path.join(directory, "phases.csv")
+        if not os.path.isfile(phases_file):
+            continue
+        phases = pd.read_csv(phases_file)[["timestamp (ns)", "phase"]].sort_values("timestamp (ns)")
+        for file in files:
+            path = os.path.join(directory, file)
+            if not os.path.isfile(path):
+                continue
+            df = pd.read_csv(path).sort_values("timestamp (ns)")
+            df = pd.merge_asof(df, phases, on="timestamp (ns)").dropna(subset=["phase"])
+            keys = ["phase"] + [c for c in df.select_dtypes(exclude="number").columns if c != "phase"]
+            columns = [c for c in df.select_dtypes("number").columns if "(ns)" not in c and c != "gap"]
+            by_file.setdefault(file, []).append((keys, df.groupby(keys)[columns].mean().reset_index()))
+
+    result = {}
+    for file, frames in by_file.items():
+        keys = frames[0][0]
+        df = pd.concat([frame for _, frame in frames])
+        result[file] = df.groupby(keys).agg(["mean", "std"])
+        result[file].columns = [f"{column}_{stat}" for column, stat in result[file].columns]
+        result[file] = result[file].reset_index()
+    return result
+
 def get_energy_files(samples_directory: str) -> List[str]:
     """
     Scans a directory for energy sample files and returns a list of matching filenames.
@@ -146,8 +502,57 @@ def get_energy_files(samples_directory: str) -> List[str]:
     return files
 
 
+def load_index(input_directory: str) -> dict:
+    """
+    Loads the lookup table of the experiments of a campaign, written by the benchmark in `index.json`.
+
+    Parameters:
+    input_directory (str): The output directory of the campaign.
+
+    Returns:
+    dict: The directory of every experiment, relative to the output directory, by (sgx, name) where
+          name describes the parameters of the experiment (e.g. `ls-1-64M-untrusted`). Empty for
+          results of older versions, without an index.
+    """
+    index_file = os.path.join(input_directory, "index.json")
+    if not os.path.isfile(index_file):
+        return {}
+    with open(index_file) as f:
+        return {(entry["sgx"], entry["name"]): entry["directory"] for entry in json.load(f)}
+
+def load_pinning_suffixes(input_directory: str) -> dict:
+    """
+    Loads the suffixes the pinning modes add to the names of the experiments, from `index.json`.
+
+    Parameters:
+    input_directory (str): The output directory of the campaign.
+
+    Returns:
+    dict: The suffix of every pinning mode (e.g. `-pinned` for `cores`); PINNING_SUFFIXES for
+          results of older versions, without the suffixes in the index.
+    """
+    suffixes = dict(PINNING_SUFFIXES)
+    index_file = os.path.join(input_directory, "index.json")
+    if os.path.isfile(index_file):
+        with open(index_file) as f:
+            suffixes.update({entry["pinning"]: entry["pinning_suffix"] for entry in json.load(f) if "pinning_suffix" in entry})
+    return suffixes
+
+def experiment_directory(config: dict, task: str, name: str, sgx: bool) -> str:
+    """
+    Returns the directory of an experiment, looked up by name in the index of the campaign.
+
+    Experiment directories are named after hashed ids; results of older versions, without an
+    index, are named after the parameters of the experiment.
+    """
+    input_directory = config["globals"]["output_directory"]
+    directory = config["globals"]["index"].get((sgx, name))
+    if directory is None:
+        directory = os.path.join(task, "gramine-sgx" if sgx else "no-gramine-sgx", name)
+    return os.path.join(input_directory, directory)
+
 # Function to process experiments
-def process_experiment(config: dict, task: str, thread: int, size: str = None, storage: Union[str, None] = None, sgx: bool = False)-> None:
+def process_experiment(config: dict, task: str, thread: int, size: str = None, storage: Union[str, None] = None, sgx: bool = False, matrix_suffix: str = "")-> None:
     """
     Processes experimental data for a given task and thread configuration, optionally considering storage type and SGX usage.
 
@@ -160,93 +565,254 @@ def process_experiment(config: dict, task: str, thread: int, size: str = None, s
     thread (int): The number of threads used in the experiment.
     storage (str, optional): The type of storage used in the experiment. Defaults to None, which implies "untrusted" storage.
     sgx (bool, optional): A flag indicating whether the experiment was run with SGX (Software Guard Extensions). Defaults to False.
+    matrix_suffix (str, optional): The suffix encoding the rpc_thread_num, log_level and key provisioning values and the args_matrix combination of the experiment. Defaults to "".
 
     Returns:
-    None: This function does not return a value. It writes the processed data to CSV files in the specified output directory.
+    Union[pd.DataFrame, None]: The syscall statistics of the experiment (see process_syscalls), or None if the
+                               experiment has no samples. The processed data is written to CSV files in the
+                               specified output directory.
     """
 
     n = config["globals"]["sample_size"]
-    input_directory = config["globals"]["output_directory"]
     deep_trace = config["globals"].get("deep_trace", False)
     output_directory = config["globals"]["aggregated_directory"]
     energy_files = config["globals"]["energy_files"]
 
     sgx_prefix = "sgx-" if sgx else ""
     storage_suffix = f"-{storage}" if storage and sgx else ""
-    experiment_type = "gramine-sgx" if sgx else "no-gramine-sgx"
     size_suffix = f"-{size}" if sgx and size is not None else ""
+
+    experiment_dir = experiment_directory(config, task, f"{task}-{thread}{size_suffix}{storage_suffix}{matrix_suffix}", sgx)
+
+    # with --shard an output directory holds only part of the plan; results of the other shards
+    # can be merged by copying their experiment directories next to these ones
+    if not os.path.isdir(experiment_dir):
+        print("Skipping", experiment_dir, "(not found)")
+        return None
     
-    experiment_dir = os.path.join(input_directory, 
-                                  task, 
-                                  experiment_type,
-                                  f"{task}-{thread}{size_suffix}{storage_suffix}")
-    
-    result_directory = os.path.join(output_directory, f"{sgx_prefix}{task}-{thread}{size_suffix}{storage_suffix}")
+    result_directory = os.path.join(output_directory, f"{sgx_prefix}{task}-{thread}{size_suffix}{storage_suffix}{matrix_suffix}")
     os.makedirs(result_directory, exist_ok=True)
 
-    perf_files = [os.path.join(experiment_dir, f"{i}/perf.csv") for i in range(1, n+1)]
+    # samples marked as invalid (failed hook with hooks_must_succeed, interrupted run) are skipped;
+    # samples are renamed into place only when complete, so a missing one was never finished
+    samples = [i for i in range(1, n+1)
+               if os.path.isdir(os.path.join(experiment_dir, str(i)))
+               and not os.path.isfile(os.path.join(experiment_dir, f"{i}/invalid"))]
+
+    # with a fault, faulted samples and the samples recovering from them are summarized apart
+    for marker in ["fault", "recovery"]:
+        marked = [i for i in samples if os.path.isfile(os.path.join(experiment_dir, f"{i}/{marker}"))]
+        marked_files = [os.path.join(experiment_dir, f"{i}/summary.csv") for i in marked]
+        if any(os.path.isfile(f) for f in marked_files):
+            df = process_summary(marked_files)
+            df.to_csv(os.path.join(result_directory, f"{marker}-summary.csv"), index=False)
+        samples = [i for i in samples if i not in marked]
+
+    perf_files = [os.path.join(experiment_dir, f"{i}/perf.csv") for i in samples]
     df = process_perf_samples(perf_files)
     df.to_csv(os.path.join(result_directory, "perf.csv"), index=False)
 
-    io_files = [os.path.join(experiment_dir, f"{i}/io.csv") for i in range(1, n+1)]
+    io_files = [os.path.join(experiment_dir, f"{i}/io.csv") for i in samples]
     df = process_io(io_files)
     df.to_csv(os.path.join(result_directory, "io.csv"), index=False)
 
+    summary_files = [os.path.join(experiment_dir, f"{i}/summary.csv") for i in samples]
+    if any(os.path.isfile(f) for f in summary_files):
+        df = process_summary(summary_files)
+        df.to_csv(os.path.join(result_directory, "summary.csv"), index=False)
+
     for file in energy_files:
-        files = [os.path.join(experiment_dir, f"{i}/{file}") for i in range(1, n+1)]
+        files = [os.path.join(experiment_dir, f"{i}/{file}") for i in samples]
         avg = process_energy_samples(files)
         avg.to_csv(os.path.join(result_directory, file))
 
+    # time series by phase of the workload, when it announced its phases
+    sample_directories = [os.path.join(experiment_dir, str(i)) for i in samples]
+    phases = process_phases(sample_directories, energy_files + ["cpu.csv", "memory.csv", "psi.csv"])
+    if phases:
+        os.makedirs(os.path.join(result_directory, "phases"), exist_ok=True)
+        for file, df in phases.items():
+            df.to_csv(os.path.join(result_directory, "phases", file), index=False)
+
     if deep_trace:
         deep_trace_directory = os.path.join(experiment_dir, "deep-trace")
         shutil.copytree(deep_trace_directory, os.path.join(result_directory, "deep-trace"))
+        trace_file = os.path.join(deep_trace_directory, "trace.csv")
+        if os.path.isfile(trace_file):
+            heatmap = page_event_heatmap(trace_file)
+            heatmap.to_csv(os.path.join(result_directory, "deep-trace", "heatmap.csv"), index=False)
+            plot_heatmap(heatmap, os.path.join(result_directory, "deep-trace", "heatmap.png"))
 
+    return process_syscalls(io_files) if io_files else None
 
-def aggregate(input_file: str, output_directory: str) -> None:
-    print("Reading from", input_file)
+
+def load_config(input_file: str, stack: Union[List[str], None] = None) -> dict:
+    """
+    Loads a configuration file, prepending the tasks of the files listed in its `include` array
+    (relative to the including file), as done by the benchmark.
+
+    Parameters:
+    input_file (str): The path to the TOML configuration file.
+    stack (List[str], optional): The files being included, used to detect cycles.
+
+    Returns:
+    dict: The configuration with all tasks.
+    """
+    stack = stack or []
+    path = os.path.realpath(input_file)
+    if path in stack:
+        raise ValueError(f"include cycle detected at {input_file}")
     config = toml.load(input_file)
+    tasks = []
+    for include in config.pop("include", []):
+        included = load_config(os.path.join(os.path.dirname(input_file), include), stack + [path])
+        tasks.extend(included.get("tasks", []))
+    config["tasks"] = tasks + config.get("tasks", [])
+    return config
+
+
+def aggregate(input_file: str, output_directory: str, globals_overrides: Union[dict, None] = None) -> None:
+    print("Reading from", input_file)
+    config = load_config(input_file)
+    # command line overrides (e.g. --output-dir, --sample-size, --deep-trace)
+    config["globals"].update(globals_overrides or {})
     config["globals"]["aggregated_directory"] = output_directory
 
     input_directory = config["globals"]["output_directory"]
+    config["globals"]["index"] = load_index(input_directory)
+    pinning_suffixes = load_pinning_suffixes(input_directory)
     os.makedirs(output_directory, exist_ok=True)
 
     print("Created output directory", output_directory)
 
+    # host description (kernel, CPU, vulnerability mitigations) needed to interpret the results
+    system_file = os.path.join(input_directory, "system.json")
+    if os.path.isfile(system_file):
+        shutil.copy(system_file, os.path.join(output_directory, "system.json"))
+
     tasks = config["tasks"]
 
     first_prog = os.path.basename(tasks[0]["executable"])
-    num_threads = tasks[0].get("num_threads", [1])
+    num_threads = expand_num_threads(tasks[0].get("num_threads", [1]))
 
-    first_exp = f"{first_prog}/no-gramine-sgx/{first_prog}-{num_threads[0]}/1"
-    energy_files = get_energy_files(os.path.join(input_directory, first_exp))
+    first_suffix = args_matrix_suffixes(tasks[0].get("args_matrix", {}))[0]
+    first_exp = experiment_directory(config, first_prog, f"{first_prog}-{num_threads[0]}{first_suffix}", False)
+    energy_files = get_energy_files(os.path.join(first_exp, "1"))
     config["globals"]["energy_files"] = energy_files
 
     print("Discovered following energy sample files", energy_files)
-    # Process non-gramine SGX tasks
+    # Process non-gramine SGX tasks; their syscall statistics are the baseline of the SGX ones
+    baselines = {}
+    # result directories of the experiments run both unpinned and pinned, as (unpinned, pinned)
+    pinned_pairs = []
     for task in tasks:
         prog = os.path.basename(task["executable"])
+        pinning = task.get("pinning", ["none"])
         print("Processing", task, end="... ")
-        for thread in task.get("num_threads", [1]):
-            process_experiment(config, prog, thread)
+        for thread in expand_num_threads(task.get("num_threads", [1])):
+            for pin in pinning:
+                pin_suffix = pinning_suffixes[pin]
+                for suffix in args_matrix_suffixes(task.get("args_matrix", {})):
+                    baselines[(prog, thread, pin_suffix, suffix)] = process_experiment(config, prog, thread, matrix_suffix=pin_suffix + suffix)
+            if "none" in pinning and "cores" in pinning:
+                pinned_pairs += [(f"{prog}-{thread}{suffix}", f"{prog}-{thread}{pinning_suffixes['cores']}{suffix}")
+                                 for suffix in args_matrix_suffixes(task.get("args_matrix", {}))]
         print("done")
 
     if SKIP_SGX:
         print("Skipped SGX parsing")
-        return
 
     # Process gramine SGX tasks
-    for task in tasks:
+    overheads = []
+    for task in [] if SKIP_SGX else tasks:
         prog = os.path.basename(task["executable"])
+        pinning = task.get("pinning", ["none"])
         print("Processing", task, end="... ")
-        for thread in task.get("num_threads", [1]):
+        for thread in expand_num_threads(task.get("num_threads", [1])):
             for storage in task.get("storage_type", ["untrusted"]):
-                for size in task["enclave_size"]:
-                    process_experiment(config, prog, thread, size, storage, sgx=True)
+                for size in expand_enclave_size(task["enclave_size"]):
+                    for rpc in task.get("rpc_thread_num", [0]):
+                        # swept log levels are marked in the experiment names
+                        for log in task.get("log_level") or [None]:
+                            for provisioned in key_provisioning_modes(task, storage):
+                                rpc_suffix = (f"-rpc{rpc}" if rpc else "") + (f"-log{log}" if log else "") + ("-provisioned" if provisioned else "")
+                                for pin in pinning:
+                                    pin_suffix = pinning_suffixes[pin]
+                                    for suffix in args_matrix_suffixes(task.get("args_matrix", {})):
+                                        syscalls = process_experiment(config, prog, thread, size, storage, sgx=True, matrix_suffix=rpc_suffix + pin_suffix + suffix)
+                                        baseline = baselines.get((prog, thread, pin_suffix, suffix))
+                                        if syscalls is None or baseline is None:
+                                            continue
+                                        df = syscall_overhead(baseline, syscalls)
+                                        df.insert(0, "experiment", f"sgx-{prog}-{thread}-{size}-{storage}{rpc_suffix}{pin_suffix}{suffix}")
+                                        df.insert(1, "baseline", f"{prog}-{thread}{pin_suffix}{suffix}")
+                                        overheads.append(df)
+                                if "none" in pinning and "cores" in pinning:
+                                    pinned_pairs += [(f"sgx-{prog}-{thread}-{size}-{storage}{rpc_suffix}{suffix}",
+                                                      f"sgx-{prog}-{thread}-{size}-{storage}{rpc_suffix}{pinning_suffixes['cores']}{suffix}")
+                                                     for suffix in args_matrix_suffixes(task.get("args_matrix", {}))]
         print("done")
 
+    # per-syscall cost of running in the enclave compared with the native baseline
+    if overheads:
+        pd.concat(overheads).to_csv(os.path.join(output_directory, "syscall_overhead.csv"), index=False)
+
+    # performance change and variance reduction of pinning the workload to cores
+    comparisons = []
+    for unpinned, pinned in pinned_pairs:
+        files = [os.path.join(output_directory, name, "summary.csv") for name in (unpinned, pinned)]
+        if not all(os.path.isfile(f) for f in files):
+            continue
+        df = pinning_comparison(pd.read_csv(files[0]), pd.read_csv(files[1]))
+        df.insert(0, "experiment", unpinned)
+        comparisons.append(df)
+    if comparisons:
+        pd.concat(comparisons).to_csv(os.path.join(output_directory, "pinning.csv"), index=False)
+
+
+def aggregate_imports(input_directory: str, output_directory: str) -> None:
+    """
+    Aggregates every run of a results tree created by `enclave-benchmark import`, each with the
+    configuration saved in its directory, and combines their summaries for cross-machine studies.
+
+    Parameters:
+    input_directory (str): The combined results directory, with a `sources.json` index.
+    output_directory (str): The directory where every run is aggregated in a `<host>/<run>`
+                            directory, next to a `summary.csv` of all the runs with `source` and
+                            `experiment` columns.
+    """
+    with open(os.path.join(input_directory, "sources.json")) as f:
+        sources = json.load(f)
+
+    summaries = []
+    for source in sources:
+        source_directory = os.path.join(input_directory, source["tag"])
+        config_file = os.path.join(source_directory, "config.toml")
+        if not os.path.isfile(config_file):
+            print("Skipping", source_directory, "(no config.toml)")
+            continue
+        # the configuration names the output directory of the host where the run took place
+        source_output = os.path.join(output_directory, source["tag"])
+        aggregate(config_file, source_output, {"output_directory": source_directory})
+        for experiment in sorted(os.listdir(source_output)):
+            summary = os.path.join(source_output, experiment, "summary.csv")
+            if os.path.isfile(summary):
+                df = pd.read_csv(summary)
+                df.insert(0, "source", source["tag"])
+                df.insert(1, "experiment", experiment)
+                summaries.append(df)
+
+    if summaries:
+        pd.concat(summaries).to_csv(os.path.join(output_directory, "summary.csv"), index=False)
+
+
 if __name__ == "__main__":
+    if len(sys.argv) == 4 and sys.argv[1] == "--imports":
+        aggregate_imports(sys.argv[2], sys.argv[3])
+        sys.exit(0)
     if len(sys.argv) != 3:
         print("Usage: python analysis/pre-process.py </path/to/toml> </path/to/output_directory>")
+        print("       python analysis/pre-process.py --imports </path/to/combined_directory> </path/to/output_directory>")
         sys.exit(1)
 
     aggregate(sys.argv[1], sys.argv[2])
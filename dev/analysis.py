@@ -58,7 +58,10 @@ def _(data, pd, plt):
         sgx_suffix = "sgx-" if enclave is not None else ""
         df = pd.read_csv(f"aggregated/{sgx_suffix}{app}-{threads}{size_suffix}{storage_suffix}/deep-trace/trace.csv")
 
-        df['relative_time'] = (df['timestamp (ns)'] - df['timestamp (ns)'].min()) /1e9
+        # the process-start marker (if present) is the origin of the time axis
+        start = df.loc[df['event'] == "process-start", 'timestamp (ns)']
+        origin = start.iloc[0] if not start.empty else df['timestamp (ns)'].min()
+        df['relative_time'] = (df['timestamp (ns)'] - origin) /1e9
 
         event_colors = {
             "sys-read":     {"color": "blue",   "alpha": 0.5},
@@ -69,10 +72,13 @@ def _(data, pd, plt):
             "kfree":        {"color": "orange", "alpha": 0.8},
             "disk-read":    {"color": "red",    "alpha": 0.5},
             "disk-write":   {"color": "red",    "alpha": 0.8},
+            "sys-mmap":     {"color": "purple", "alpha": 0.5},
+            "sys-munmap":   {"color": "purple", "alpha": 0.8},
+            "sys-brk":      {"color": "brown",  "alpha": 0.5},
         }
 
         system_events = ["sys-read", "sys-write", "disk-read", "disk-write"]
-        memory_events = ["mm-page-alloc", "mm-page-free", "kmalloc", "kfree"]
+        memory_events = ["mm-page-alloc", "mm-page-free", "kmalloc", "kfree", "sys-mmap", "sys-munmap", "sys-brk"]
 
         fig, (ax_sys, ax_mem) = plt.subplots(2, 1, sharex=True, figsize=(12, 10))
         title = f"{app}-{enclave} w/ {threads} threads {storage}" if sgx_suffix != "" else f"{app} {threads} threads w/out sgx"
@@ -0,0 +1,368 @@
+//! Memory bandwidth of the workload (see `BandwidthMonitor`): the MBM counters of Intel RDT, read
+//! through a monitoring group of resctrl holding the tasks of the workload, or the CAS counters of
+//! the integrated memory controllers, counted by socket with `perf stat`.
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs,
+  os::unix::process::CommandExt,
+  path::{Path, PathBuf},
+  process::{Child, Command, Stdio},
+  time::{Duration, SystemTime},
+};
+
+use tempfile::NamedTempFile;
+use tracing::warn;
+
+use crate::{common::MemoryBandwidthSource, constants::RESCTRL_DIRECTORY, stats::BandwidthSample};
+
+/// Files of the MBM counters in an L3 domain of a monitoring group, by counter name.
+const MBM_COUNTERS: [(&str, &str); 2] =
+  [("total", "mbm_total_bytes"), ("local", "mbm_local_bytes")];
+
+/// CAS events of the memory controllers, by counter name.
+const IMC_EVENTS: [(&str, &str); 2] = [
+  ("read", "uncore_imc/cas_count_read/"),
+  ("write", "uncore_imc/cas_count_write/"),
+];
+
+/// Bytes transferred by a CAS command: a cache line.
+const CAS_BYTES: f64 = 64.0;
+
+/// perf does not accept shorter intervals.
+const MIN_IMC_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A running measurement of the memory bandwidth.
+pub enum BandwidthMonitor {
+  /// A monitoring group of resctrl, removed when the monitor stops.
+  Resctrl {
+    group: PathBuf,
+    /// Tasks moved to the group so far.
+    tasks: BTreeSet<u32>,
+    /// Last reading of every counter, by domain and counter name.
+    last: BTreeMap<(String, &'static str), u64>,
+  },
+  /// `perf stat` counting the memory controllers of every socket each interval.
+  Imc {
+    perf: Child,
+    /// File perf writes the counts of every interval to, read once it stops; a pipe left unread
+    /// during the sample would fill up and block perf.
+    output: NamedTempFile,
+    /// Start of perf, in nanoseconds since the epoch; perf reports the time of every interval
+    /// from its start.
+    started: u128,
+  },
+}
+
+impl BandwidthMonitor {
+  /// Starts measuring the memory bandwidth from `source`; `name` identifies the monitoring group
+  /// of resctrl, created in `control_group` (the cache allocation group of the workload, if any)
+  /// since its tasks cannot leave it. Returns None (with a warning) if the host does not support
+  /// the source.
+  pub fn start(
+    source: MemoryBandwidthSource,
+    name: &str,
+    interval: Duration,
+    control_group: Option<&Path>,
+  ) -> Option<Self> {
+    let root = Path::new(RESCTRL_DIRECTORY);
+    let monitor = match source {
+      MemoryBandwidthSource::Resctrl => start_resctrl(root, control_group.unwrap_or(root), name),
+      MemoryBandwidthSource::Imc => start_imc(interval),
+    };
+    if let Err(e) = &monitor {
+      warn!(
+        "cannot measure the memory bandwidth with {}: {}; skipping",
+        source, e
+      );
+    }
+    monitor.ok()
+  }
+
+  /// Moves the `tasks` not in the group yet to it and reads the counters of resctrl. Samples of
+  /// the memory controllers are only known once perf stops.
+  pub fn sample(&mut self, tasks: &[u32]) -> Vec<BandwidthSample> {
+    match self {
+      Self::Resctrl {
+        group,
+        tasks: moved,
+        last,
+      } => {
+        for &task in tasks {
+          // a task that exited in the meantime cannot be moved
+          if !moved.contains(&task) && fs::write(group.join("tasks"), task.to_string()).is_ok() {
+            moved.insert(task);
+          }
+        }
+        read_mbm_counters(group, last)
+      }
+      Self::Imc { .. } => Vec::new(),
+    }
+  }
+
+  /// Stops the measurement, returning the samples since the last call of `sample`.
+  pub fn stop(self) -> Vec<BandwidthSample> {
+    match self {
+      Self::Resctrl {
+        group, mut last, ..
+      } => {
+        let samples = read_mbm_counters(&group, &mut last);
+        // the tasks left return to the default group
+        if let Err(e) = fs::remove_dir(&group) {
+          warn!("cannot remove the monitoring group {:?}: {}", group, e);
+        }
+        samples
+      }
+      Self::Imc {
+        perf,
+        output,
+        started,
+      } => {
+        // perf prints the counts of the last, partial interval on SIGINT
+        unsafe {
+          libc::kill(perf.id() as i32, libc::SIGINT);
+        }
+        // only errors are written to stderr
+        let Ok(errors) = perf.wait_with_output() else {
+          return Vec::new();
+        };
+        let counts = fs::read_to_string(output.path()).unwrap_or_default();
+        let samples = parse_imc_output(&counts, started);
+        if samples.is_empty() {
+          warn!(
+            "perf counted no memory controller traffic: {}",
+            String::from_utf8_lossy(&errors.stderr).trim()
+          );
+        }
+        samples
+      }
+    }
+  }
+}
+
+// the group starts from zero; counters of domains that cannot be read yet ("Unavailable") are
+// skipped until they can
+fn start_resctrl(
+  root: &Path,
+  control_group: &Path,
+  name: &str,
+) -> Result<BandwidthMonitor, std::io::Error> {
+  let features = fs::read_to_string(root.join("info/L3_MON/mon_features"))?;
+  if !features.lines().any(|feature| feature == "mbm_total_bytes") {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "no MBM counters in resctrl",
+    ));
+  }
+  let group = control_group.join("mon_groups").join(name);
+  fs::create_dir(&group)?;
+  let mut last = BTreeMap::new();
+  read_mbm_counters(&group, &mut last);
+  Ok(BandwidthMonitor::Resctrl {
+    group,
+    tasks: BTreeSet::new(),
+    last,
+  })
+}
+
+// domains are the mon_L3_<N> directories of mon_data
+fn read_mbm_counters(
+  group: &Path,
+  last: &mut BTreeMap<(String, &'static str), u64>,
+) -> Vec<BandwidthSample> {
+  let timestamp = SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .unwrap()
+    .as_nanos();
+  let mut samples = Vec::new();
+  let Ok(entries) = group.join("mon_data").read_dir() else {
+    return samples;
+  };
+  let mut entries: Vec<_> = entries.flatten().collect();
+  entries.sort_by_key(|entry| entry.file_name());
+  for entry in entries {
+    let Some(domain) = entry
+      .file_name()
+      .to_str()
+      .and_then(|name| name.strip_prefix("mon_L3_"))
+      .and_then(|id| id.parse::<u32>().ok())
+      .map(|id| format!("l3-{}", id))
+    else {
+      continue;
+    };
+    for (counter, file) in MBM_COUNTERS {
+      let Some(bytes) = fs::read_to_string(entry.path().join(file))
+        .ok()
+        .and_then(|content| content.trim().parse::<u64>().ok())
+      else {
+        continue;
+      };
+      if let Some(previous) = last.insert((domain.clone(), counter), bytes) {
+        samples.push(BandwidthSample {
+          timestamp,
+          domain: domain.clone(),
+          counter: counter.to_string(),
+          bytes: bytes.saturating_sub(previous),
+        });
+      }
+    }
+  }
+  samples
+}
+
+fn start_imc(interval: Duration) -> Result<BandwidthMonitor, std::io::Error> {
+  if !Path::new("/sys/bus/event_source/devices/uncore_imc_0").exists()
+    && !Path::new("/sys/bus/event_source/devices/uncore_imc").exists()
+  {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "no uncore_imc PMU",
+    ));
+  }
+  let events: Vec<&str> = IMC_EVENTS.iter().map(|(_, event)| *event).collect();
+  let started = SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .unwrap()
+    .as_nanos();
+  let output = NamedTempFile::new()?;
+  let perf = Command::new("perf")
+    .args([
+      "stat",
+      "--all-cpus",
+      "--per-socket",
+      "--field-separator",
+      ",",
+    ])
+    .arg("--interval-print")
+    .arg(interval.max(MIN_IMC_INTERVAL).as_millis().to_string())
+    .arg("--event")
+    .arg(events.join(","))
+    .arg("--output")
+    .arg(output.path())
+    .stdout(Stdio::null())
+    .stderr(Stdio::piped())
+    // a Ctrl-C on the terminal would stop the counters before the workload exits
+    .process_group(0)
+    .spawn()?;
+  Ok(BandwidthMonitor::Imc {
+    perf,
+    output,
+    started,
+  })
+}
+
+// lines look like "<time (s)>,S<socket>,<cpus>,<count>,<unit>,<event>,..."; perf scales the CAS
+// counts to MiB when the kernel exports the scale of the events, otherwise they are raw
+fn parse_imc_output(output: &str, started: u128) -> Vec<BandwidthSample> {
+  let mut samples = Vec::new();
+  for line in output.lines() {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [time, socket, _, count, unit, event, ..] = fields[..] else {
+      continue;
+    };
+    let Some((counter, _)) = IMC_EVENTS.iter().find(|(_, name)| *name == event) else {
+      continue;
+    };
+    let (Ok(time), Some(package), Ok(count)) = (
+      time.parse::<f64>(),
+      socket
+        .strip_prefix('S')
+        .and_then(|socket| socket.split('-').next())
+        .and_then(|socket| socket.parse::<u32>().ok()),
+      count.parse::<f64>(),
+    ) else {
+      continue;
+    };
+    let bytes = match unit {
+      "MiB" => count * 1024.0 * 1024.0,
+      "" => count * CAS_BYTES,
+      _ => continue,
+    };
+    samples.push(BandwidthSample {
+      timestamp: started + (time * 1e9).round() as u128,
+      domain: format!("package-{}", package),
+      counter: counter.to_string(),
+      bytes: bytes.round() as u64,
+    });
+  }
+  samples
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs;
+
+  use tempfile::TempDir;
+
+  use super::{parse_imc_output, start_resctrl, BandwidthMonitor};
+
+  #[test]
+  fn resctrl_group() {
+    let root = TempDir::new().unwrap();
+    let write = |path: &str, content: &str| {
+      let path = root.path().join(path);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, content).unwrap();
+    };
+    write("info/L3_MON/mon_features", "llc_occupancy\n");
+    fs::create_dir(root.path().join("mon_groups")).unwrap();
+    assert!(start_resctrl(root.path(), root.path(), "eb-42").is_err());
+
+    write(
+      "info/L3_MON/mon_features",
+      "llc_occupancy\nmbm_total_bytes\nmbm_local_bytes\n",
+    );
+    let mut monitor = start_resctrl(root.path(), root.path(), "eb-42").unwrap();
+    write("mon_groups/eb-42/mon_data/mon_L3_00/mbm_total_bytes", "0\n");
+    write(
+      "mon_groups/eb-42/mon_data/mon_L3_00/mbm_local_bytes",
+      "Unavailable\n",
+    );
+    assert!(monitor.sample(&[42, 43]).is_empty());
+    let BandwidthMonitor::Resctrl { tasks, .. } = &monitor else {
+      unreachable!();
+    };
+    assert_eq!(tasks.len(), 2);
+
+    write(
+      "mon_groups/eb-42/mon_data/mon_L3_00/mbm_total_bytes",
+      "4096\n",
+    );
+    write(
+      "mon_groups/eb-42/mon_data/mon_L3_00/mbm_local_bytes",
+      "1024\n",
+    );
+    let samples = monitor.sample(&[42]);
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].domain, "l3-0");
+    assert_eq!(samples[0].counter, "total");
+    assert_eq!(samples[0].bytes, 4096);
+
+    write(
+      "mon_groups/eb-42/mon_data/mon_L3_00/mbm_local_bytes",
+      "3072\n",
+    );
+    let samples = monitor.sample(&[]);
+    assert_eq!(samples[0].bytes, 0);
+    assert_eq!(samples[1].counter, "local");
+    assert_eq!(samples[1].bytes, 2048);
+  }
+
+  #[test]
+  fn imc_output() {
+    let output = "#           time socket cpus counts unit events
+     1.001004530,S0,8,512.00,MiB,uncore_imc/cas_count_read/,4004017720,100.00,,
+     1.001004530,S0,8,0.50,MiB,uncore_imc/cas_count_write/,4004017720,100.00,,
+     1.001004530,S1,8,<not counted>,MiB,uncore_imc/cas_count_read/,0,100.00,,
+     2.000000000,S1,8,1000,,uncore_imc/cas_count_write/,4004017720,100.00,,
+";
+    let samples = parse_imc_output(output, 1_000_000_000);
+    assert_eq!(samples.len(), 3);
+    assert_eq!(samples[0].timestamp, 2_001_004_530);
+    assert_eq!(samples[0].domain, "package-0");
+    assert_eq!(samples[0].counter, "read");
+    assert_eq!(samples[0].bytes, 512 * 1024 * 1024);
+    assert_eq!(samples[1].bytes, 512 * 1024);
+    assert_eq!(samples[2].domain, "package-1");
+    assert_eq!(samples[2].bytes, 64000);
+  }
+}
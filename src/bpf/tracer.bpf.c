@@ -6,11 +6,18 @@
 #include <bpf/bpf_tracing.h>
 #include <bpf/bpf_core_read.h>
 #include "core_fixes.bpf.h"
+#include <bpf/usdt.bpf.h>
 #include "maps.bpf.h"
+#include "bits.bpf.h"
 #include "tracer.h"
 
 const volatile pid_t targ_pid = 0;
 const volatile bool deep_trace = false;
+const volatile bool trace_children = false;
+const volatile bool filter_dev = false;
+const volatile u64 fault_after_syscalls = 0;
+const volatile int fault_signal = 0;
+const volatile u16 provision_port = 0;
 
 struct {
   __uint(type, BPF_MAP_TYPE_RINGBUF);
@@ -24,11 +31,20 @@ struct {
   __type(value, struct disk_counter);
 } counters SEC(".maps");
 
+// devices of `disk_devices`: when `filter_dev` is set, block requests to other
+// devices are ignored
 struct {
   __uint(type, BPF_MAP_TYPE_HASH);
-  __uint(max_entries, 2);
+  __uint(max_entries, 64);
   __type(key, u32);
-  __type(value, struct io_counter);
+  __type(value, u8);
+} disk_filter SEC(".maps");
+
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 4);
+  __type(key, u32);
+  __type(value, struct latency_hist);
 } agg_map SEC(".maps");
 
 struct {
@@ -43,6 +59,11 @@ struct sgx_counters {
   u64 encl_wb;
   u64 vma_access;
   u64 vma_fault;
+  u64 aesm_connections;
+  u64 aesm_wait_ns;
+  u64 eadd_pages;
+  u64 eadd_ns;
+  u64 einit_ns;
 };
 
 struct {
@@ -52,9 +73,137 @@ struct {
   __type(value, struct sgx_counters);
 } sgx_stats SEC(".maps");
 
-static __always_inline int snd_trace_event(__u32 evt) {
-  u32 pid = (u32)bpf_get_current_pid_tgid();
+struct aesm_connection {
+  s32 fd;
+  u64 start;
+};
+
+// open connections to aesmd by thread
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 64);
+  __type(key, u32);
+  __type(value, struct aesm_connection);
+} aesm_connections SEC(".maps");
+
+// start of the EADD or EINIT in progress by thread
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 64);
+  __type(key, u32);
+  __type(value, u64);
+} sgx_load_start SEC(".maps");
+
+struct extra_kprobe_call {
+  u64 ip;
+  u64 start;
+};
+
+// call in progress of a function with an extra kprobe by thread
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 1024);
+  __type(key, u32);
+  __type(value, struct extra_kprobe_call);
+} extra_kprobe_calls SEC(".maps");
+
+// calls of the functions with an extra kprobe by function address
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 64);
+  __type(key, u64);
+  __type(value, struct io_counter);
+} extra_kprobe_stats SEC(".maps");
+
+struct uprobe_call_key {
+  u32 tid;
+  u32 uprobe;
+};
+
+// calls in progress of the uprobes of a task
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 1024);
+  __type(key, struct uprobe_call_key);
+  __type(value, u64);
+} uprobe_calls SEC(".maps");
+
+// calls of the uprobes of a task (and of the OCALL handlers) by cookie
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 2 * MAX_UPROBES);
+  __type(key, u32);
+  __type(value, struct io_counter);
+} uprobe_stats SEC(".maps");
+
+// last program break of the target process
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 64);
+  __type(key, u32);
+  __type(value, u64);
+} brk_map SEC(".maps");
+
+// processes forked by the target process (and by their children), keyed by pid
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 4096);
+  __type(key, u32);
+  __type(value, u8);
+} target_tree SEC(".maps");
+
+struct vfs_counters {
+  u64 open;
+  u64 close;
+  u64 fsync;
+  u64 stat;
+};
+
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 1);
+  __type(key, u32);
+  __type(value, struct vfs_counters);
+} vfs_stats SEC(".maps");
+
+struct fault_state {
+  u64 syscalls;
+  u64 injected_ns;
+};
+
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 1);
+  __type(key, u32);
+  __type(value, struct fault_state);
+} fault_stats SEC(".maps");
+
+struct provisioning_session {
+  u64 sk;
+  u64 connected_ns;
+  u64 closed_ns;
+  u64 bytes_sent;
+  u64 bytes_received;
+};
 
+// the first connection of the target process tree to the secret provisioning
+// server
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 1);
+  __type(key, u32);
+  __type(value, struct provisioning_session);
+} provisioning SEC(".maps");
+
+// addresses of the secret provisioning server, IPv4 ones mapped into IPv6
+struct {
+  __uint(type, BPF_MAP_TYPE_HASH);
+  __uint(max_entries, 16);
+  __type(key, u8[16]);
+  __type(value, u8);
+} provision_addrs SEC(".maps");
+
+static __always_inline int snd_trace_event_bytes(__u32 evt, __u64 bytes) {
   u64 ts = bpf_ktime_get_ns();
   struct event *rb_event =
       bpf_ringbuf_reserve(&events, sizeof(struct event), 0);
@@ -66,16 +215,38 @@ static __always_inline int snd_trace_event(__u32 evt) {
 
   rb_event->ev_type = evt;
   rb_event->timestamp = ts;
+  rb_event->bytes = bytes;
 
   bpf_ringbuf_submit(rb_event, 0);
 
   return 0;
 }
 
+static __always_inline int snd_trace_event(__u32 evt) {
+  return snd_trace_event_bytes(evt, 0);
+}
+
+static __always_inline bool is_target_tree() {
+  u32 tgid = bpf_get_current_pid_tgid() >> 32;
+
+  return !targ_pid || targ_pid == tgid ||
+         bpf_map_lookup_elem(&target_tree, &tgid);
+}
+
+// with auxiliary executables, the processes started by the target process are
+// part of the workload
+static __always_inline bool is_target_process() {
+  if (trace_children) {
+    return is_target_tree();
+  }
+  return !targ_pid || targ_pid == (bpf_get_current_pid_tgid() >> 32);
+}
+
 static __always_inline int record_end_ts(int syscall) {
   u32 pid;
   u64 *start_ts;
-  u64 duration;
+  u64 duration, slot;
+  struct latency_hist *hist, zero = {};
 
   pid = (u32)bpf_get_current_pid_tgid();
 
@@ -90,13 +261,21 @@ static __always_inline int record_end_ts(int syscall) {
 
   bpf_map_delete_elem(&start_ts_map, &pid);
 
-  struct io_counter *value = bpf_map_lookup_elem(&agg_map, &syscall);
-  if (value) {
-    __sync_fetch_and_add(&value->count, 1);
-    __sync_fetch_and_add(&value->total_duration, duration);
-  } else {
-    struct io_counter init = {.count = 1, .total_duration = duration};
-    bpf_map_update_elem(&agg_map, &syscall, &init, BPF_ANY);
+  hist = bpf_map_lookup_or_try_init(&agg_map, &syscall, &zero);
+  if (!hist) {
+    return 0;
+  }
+
+  slot = log2l(duration);
+  if (slot >= LATENCY_SLOTS) {
+    slot = LATENCY_SLOTS - 1;
+  }
+  __sync_fetch_and_add(&hist->count, 1);
+  __sync_fetch_and_add(&hist->total_duration, duration);
+  __sync_fetch_and_add(&hist->slots[slot], 1);
+  // not atomic: concurrent exits may lose an update of the maximum
+  if (duration > hist->max) {
+    hist->max = duration;
   }
 
   return 0;
@@ -108,7 +287,7 @@ static __always_inline int record_start_ts() {
 
   pid = (u32)bpf_get_current_pid_tgid();
 
-  if (targ_pid && targ_pid != pid) {
+  if (trace_children ? !is_target_tree() : targ_pid && targ_pid != pid) {
     return 0;
   }
   ts = bpf_ktime_get_ns();
@@ -122,7 +301,8 @@ static __always_inline int record_start_ts() {
 SEC("tracepoint/syscalls/sys_enter_read")
 int trace_enter_read(struct trace_event_raw_sys_enter *ctx) {
   if (deep_trace) {
-    return record_start_ts() || snd_trace_event(EVENT_SYS_READ);
+    return record_start_ts() ||
+           snd_trace_event_bytes(EVENT_SYS_READ, ctx->args[2]);
   }
   return record_start_ts();
 }
@@ -131,7 +311,8 @@ int trace_enter_read(struct trace_event_raw_sys_enter *ctx) {
 SEC("tracepoint/syscalls/sys_enter_write")
 int trace_enter_write(struct trace_event_raw_sys_enter *ctx) {
   if (deep_trace) {
-    return record_start_ts() || snd_trace_event(EVENT_SYS_WRITE);
+    return record_start_ts() ||
+           snd_trace_event_bytes(EVENT_SYS_WRITE, ctx->args[2]);
   }
   return record_start_ts();
 }
@@ -148,25 +329,64 @@ int trace_exit_write(struct trace_event_raw_sys_enter *ctx) {
   return record_end_ts(SYSCALL_WRITE);
 }
 
+// fsync and fdatasync only have a latency histogram: database-like workloads
+// on encrypted mounts are bound by their tail latency
+SEC("tracepoint/syscalls/sys_enter_fsync")
+int trace_enter_fsync(struct trace_event_raw_sys_enter *ctx) {
+  return record_start_ts();
+}
+
+SEC("tracepoint/syscalls/sys_exit_fsync")
+int trace_exit_fsync(struct trace_event_raw_sys_exit *ctx) {
+  return record_end_ts(SYSCALL_FSYNC);
+}
+
+SEC("tracepoint/syscalls/sys_enter_fdatasync")
+int trace_enter_fdatasync(struct trace_event_raw_sys_enter *ctx) {
+  return record_start_ts();
+}
+
+SEC("tracepoint/syscalls/sys_exit_fdatasync")
+int trace_exit_fdatasync(struct trace_event_raw_sys_exit *ctx) {
+  return record_end_ts(SYSCALL_FDATASYNC);
+}
+
 SEC("tracepoint/block/block_rq_complete")
 int handle__block_rq_complete(struct trace_event_raw_block_rq_completion *ctx) {
   struct disk_counter *counterp, zero = {};
   sector_t sector;
   u32 nr_sector;
   u32 dev;
-  __u32 ev_type = (ctx->rwbs[0] == 'R') ? EVENT_READ_DISK : EVENT_WRITE_DISK;
-
-  if (deep_trace && snd_trace_event(ev_type)) {
-    return 1;
-  }
+  // rwbs starts with 'F' for flushes (alone or before a write), followed by the
+  // operation: 'R' read, 'W' write, 'D' discard, 'N' none
+  bool flush = ctx->rwbs[0] == 'F';
+  char op = flush ? ctx->rwbs[1] : ctx->rwbs[0];
+  __u32 ev_type = (op == 'R') ? EVENT_READ_DISK : EVENT_WRITE_DISK;
 
   sector = BPF_CORE_READ(ctx, sector);
   nr_sector = BPF_CORE_READ(ctx, nr_sector);
   dev = BPF_CORE_READ(ctx, dev);
 
+  if (filter_dev && !bpf_map_lookup_elem(&disk_filter, &dev)) {
+    return 0;
+  }
+
+  if (deep_trace && snd_trace_event_bytes(ev_type, (u64)nr_sector * 512)) {
+    return 1;
+  }
+
   counterp = bpf_map_lookup_or_try_init(&counters, &dev, &zero);
   if (!counterp)
     return 0;
+  if (flush)
+    __sync_fetch_and_add(&counterp->flushes, 1);
+  if (op == 'R') {
+    __sync_fetch_and_add(&counterp->read_requests, 1);
+    __sync_fetch_and_add(&counterp->read_bytes, (u64)nr_sector * 512);
+  } else if (op == 'W') {
+    __sync_fetch_and_add(&counterp->write_requests, 1);
+    __sync_fetch_and_add(&counterp->write_bytes, (u64)nr_sector * 512);
+  }
   if (counterp->last_sector) {
     if (counterp->last_sector == sector)
       __sync_fetch_and_add(&counterp->sequential, 1);
@@ -181,6 +401,50 @@ int handle__block_rq_complete(struct trace_event_raw_block_rq_completion *ctx) {
 // eBPF program attached to the block_rq_issue tracepoint
 
 // kernel memory
+// Memory mappings of the target process: Gramine maps the enclave and, with
+// EDMM, grows it through these calls instead of the page allocator.
+SEC("tracepoint/syscalls/sys_enter_mmap")
+int trace_enter_mmap(struct trace_event_raw_sys_enter *ctx) {
+  if (!deep_trace || !is_target_process()) {
+    return 0;
+  }
+  return snd_trace_event_bytes(EVENT_MMAP, ctx->args[1]);
+}
+
+SEC("tracepoint/syscalls/sys_enter_munmap")
+int trace_enter_munmap(struct trace_event_raw_sys_enter *ctx) {
+  if (!deep_trace || !is_target_process()) {
+    return 0;
+  }
+  return snd_trace_event_bytes(EVENT_MUNMAP, ctx->args[1]);
+}
+
+// brk returns the new program break: the size is the change from the previous
+// break of the process
+SEC("tracepoint/syscalls/sys_exit_brk")
+int trace_exit_brk(struct trace_event_raw_sys_exit *ctx) {
+  u32 tgid = bpf_get_current_pid_tgid() >> 32;
+  u64 brk = ctx->ret;
+  u64 *prev;
+  u64 last;
+
+  if (!deep_trace || !is_target_process()) {
+    return 0;
+  }
+  prev = bpf_map_lookup_elem(&brk_map, &tgid);
+  last = prev ? *prev : 0;
+  bpf_map_update_elem(&brk_map, &tgid, &brk, BPF_ANY);
+
+  // the first call only queries the initial break
+  if (!last || brk == last) {
+    return 0;
+  }
+  if (brk > last) {
+    return snd_trace_event_bytes(EVENT_BRK, brk - last);
+  }
+  return snd_trace_event_bytes(EVENT_BRK_SHRINK, last - brk);
+}
+
 SEC("tracepoint/kmem/mm_page_alloc")
 int trace_kmem_page_alloc(void *args) {
   if (deep_trace) {
@@ -212,11 +476,296 @@ int trace_kfree(void *args) {
   }
   return 0;
 }
+static __always_inline struct sgx_counters *lookup_sgx_counters() {
+  u32 key = 0;
+  struct sgx_counters zero = {};
+
+  return bpf_map_lookup_or_try_init(&sgx_stats, &key, &zero);
+}
+
+// Enclave launches talk to aesmd (launch tokens, attestation keys) over a unix
+// socket: the time between the connection and the close of the socket is
+// spent waiting on the service rather than running the enclave.
+static __always_inline bool is_aesm_socket(const char *path) {
+  // Gramine falls back to the abstract socket of older aesmd versions
+  const char named[] = "/var/run/aesmd/aesm.socket";
+  const char abstract[] = "\0sgx_aesm_socket_base";
+  bool is_named = true, is_abstract = true;
+
+#pragma unroll
+  for (int i = 0; i < sizeof(named); i++) {
+    if (path[i] != named[i]) {
+      is_named = false;
+      break;
+    }
+  }
+#pragma unroll
+  for (int i = 0; i < sizeof(abstract); i++) {
+    if (path[i] != abstract[i]) {
+      is_abstract = false;
+      break;
+    }
+  }
+  return is_named || is_abstract;
+}
+
+SEC("tracepoint/syscalls/sys_enter_connect")
+int trace_enter_connect(struct trace_event_raw_sys_enter *ctx) {
+  u32 tid = (u32)bpf_get_current_pid_tgid();
+  struct sockaddr_un addr = {};
+
+  if (!is_target_process()) {
+    return 0;
+  }
+  if (bpf_probe_read_user(&addr, sizeof(addr), (void *)ctx->args[1]) ||
+      addr.sun_family != AF_UNIX || !is_aesm_socket(addr.sun_path)) {
+    return 0;
+  }
+
+  // a retry on the same socket keeps the time of the first attempt
+  struct aesm_connection conn = {.fd = ctx->args[0],
+                                 .start = bpf_ktime_get_ns()};
+  bpf_map_update_elem(&aesm_connections, &tid, &conn, BPF_NOEXIST);
+  if (deep_trace) {
+    return snd_trace_event(EVENT_AESM_CONNECT);
+  }
+  return 0;
+}
+
+static __always_inline int increment_vfs_counter(u32 field_offset) {
+  u32 key = 0;
+  struct vfs_counters zero = {}, *stats;
+
+  if (!is_target_tree()) {
+    return 0;
+  }
+  stats = bpf_map_lookup_or_try_init(&vfs_stats, &key, &zero);
+  if (!stats) {
+    return 0;
+  }
+
+  u64 *counter = (u64 *)((void *)stats + field_offset);
+  __sync_fetch_and_add(counter, 1);
+  return 0;
+}
+
+SEC("tracepoint/syscalls/sys_enter_close")
+int trace_enter_close(struct trace_event_raw_sys_enter *ctx) {
+  u32 tid = (u32)bpf_get_current_pid_tgid();
+  struct aesm_connection *conn = bpf_map_lookup_elem(&aesm_connections, &tid);
+
+  // closes are counted here since the kernel functions releasing a file changed
+  // across versions
+  increment_vfs_counter(offsetof(struct vfs_counters, close));
+
+  if (!conn || conn->fd != (s32)ctx->args[0]) {
+    return 0;
+  }
+  u64 duration = bpf_ktime_get_ns() - conn->start;
+  bpf_map_delete_elem(&aesm_connections, &tid);
+
+  struct sgx_counters *stats = lookup_sgx_counters();
+  if (stats) {
+    __sync_fetch_and_add(&stats->aesm_connections, 1);
+    __sync_fetch_and_add(&stats->aesm_wait_ns, duration);
+  }
+  if (deep_trace) {
+    return snd_trace_event(EVENT_AESM_CLOSE);
+  }
+  return 0;
+}
+
+// Generic count and latency probes attached at runtime to the functions of
+// `extra_kprobes`. A call nested in another probed function replaces the outer
+// one, which is then not counted.
+SEC("kprobe")
+int extra_kprobe_enter(struct pt_regs *ctx) {
+  u32 tid = (u32)bpf_get_current_pid_tgid();
+
+  if (!is_target_process()) {
+    return 0;
+  }
+  struct extra_kprobe_call call = {.ip = bpf_get_func_ip(ctx),
+                                   .start = bpf_ktime_get_ns()};
+  bpf_map_update_elem(&extra_kprobe_calls, &tid, &call, BPF_ANY);
+  return 0;
+}
+
+SEC("kretprobe")
+int extra_kprobe_exit(struct pt_regs *ctx) {
+  u32 tid = (u32)bpf_get_current_pid_tgid();
+  struct extra_kprobe_call *call = bpf_map_lookup_elem(&extra_kprobe_calls, &tid);
+  struct io_counter zero = {};
+
+  if (!call) {
+    return 0;
+  }
+  u64 ip = call->ip;
+  u64 duration = bpf_ktime_get_ns() - call->start;
+  bpf_map_delete_elem(&extra_kprobe_calls, &tid);
+
+  struct io_counter *counter =
+      bpf_map_lookup_or_try_init(&extra_kprobe_stats, &ip, &zero);
+  if (counter) {
+    __sync_fetch_and_add(&counter->count, 1);
+    __sync_fetch_and_add(&counter->total_duration, duration);
+  }
+  return 0;
+}
+
+// Generic probes attached at runtime to the `uprobes` of a task and to the
+// OCALL handlers of Gramine. The attach cookie is the index of the uprobe in
+// the task, or MAX_UPROBES plus the index of the OCALL. Recursive calls of the
+// same function replace the outer call, which is then not counted.
+SEC("uprobe")
+int uprobe_enter(struct pt_regs *ctx) {
+  struct uprobe_call_key key = {.tid = (u32)bpf_get_current_pid_tgid(),
+                                .uprobe = bpf_get_attach_cookie(ctx)};
+  u64 ts = bpf_ktime_get_ns();
+
+  bpf_map_update_elem(&uprobe_calls, &key, &ts, BPF_ANY);
+  if (deep_trace && key.uprobe < MAX_UPROBES) {
+    return snd_trace_event(EVENT_UPROBE_ENTER | key.uprobe);
+  }
+  return 0;
+}
+
+SEC("uretprobe")
+int uprobe_exit(struct pt_regs *ctx) {
+  struct uprobe_call_key key = {.tid = (u32)bpf_get_current_pid_tgid(),
+                                .uprobe = bpf_get_attach_cookie(ctx)};
+  struct io_counter zero = {};
+  u64 *start = bpf_map_lookup_elem(&uprobe_calls, &key);
+
+  if (!start) {
+    return 0;
+  }
+  u64 duration = bpf_ktime_get_ns() - *start;
+  bpf_map_delete_elem(&uprobe_calls, &key);
+
+  struct io_counter *counter =
+      bpf_map_lookup_or_try_init(&uprobe_stats, &key.uprobe, &zero);
+  if (counter) {
+    __sync_fetch_and_add(&counter->count, 1);
+    __sync_fetch_and_add(&counter->total_duration, duration);
+  }
+  if (deep_trace && key.uprobe < MAX_UPROBES) {
+    return snd_trace_event(EVENT_UPROBE_EXIT | key.uprobe);
+  }
+  return 0;
+}
+
+// Generic program attached at runtime to the `usdt` probes of a task in deep
+// trace runs; the attach cookie is the index of the probe in the task.
+SEC("usdt")
+int usdt_event(struct pt_regs *ctx) {
+  return snd_trace_event(EVENT_USDT | (u32)bpf_usdt_cookie(ctx));
+}
+
+SEC("tracepoint/sched/sched_process_fork")
+int handle_process_fork(struct trace_event_raw_sched_process_fork *ctx) {
+  u32 child = ctx->child_pid;
+  u8 tracked = 1;
+
+  if (targ_pid && is_target_tree()) {
+    bpf_map_update_elem(&target_tree, &child, &tracked, BPF_ANY);
+  }
+  return 0;
+}
+
+SEC("tracepoint/sched/sched_process_exit")
+int handle_process_exit(void *ctx) {
+  u32 tid = (u32)bpf_get_current_pid_tgid();
+
+  bpf_map_delete_elem(&target_tree, &tid);
+  return 0;
+}
+
+// VFS operations of the target process tree: Gramine verifies trusted files
+// and splits protected files in chunks, which multiplies them without any
+// trace at the block layer
+SEC("kprobe/vfs_open")
+int count_vfs_open(struct pt_regs *ctx) {
+  return increment_vfs_counter(offsetof(struct vfs_counters, open));
+}
+
+SEC("kprobe/vfs_fsync_range")
+int count_vfs_fsync(struct pt_regs *ctx) {
+  return increment_vfs_counter(offsetof(struct vfs_counters, fsync));
+}
+
+SEC("kprobe/vfs_getattr")
+int count_vfs_stat(struct pt_regs *ctx) {
+  return increment_vfs_counter(offsetof(struct vfs_counters, stat));
+}
+
+// Injects the fault of a task once the target process tree has made
+// `fault_after_syscalls` system calls; user space completes it on seeing
+// `injected_ns`. Only loaded when the fault is triggered by system calls.
+SEC("tracepoint/raw_syscalls/sys_enter")
+int count_fault_syscalls(void *ctx) {
+  u32 key = 0;
+  struct fault_state zero = {}, *state;
+
+  if (!is_target_tree()) {
+    return 0;
+  }
+  state = bpf_map_lookup_or_try_init(&fault_stats, &key, &zero);
+  if (!state) {
+    return 0;
+  }
+  __sync_fetch_and_add(&state->syscalls, 1);
+  if (state->syscalls < fault_after_syscalls || state->injected_ns) {
+    return 0;
+  }
+  state->injected_ns = bpf_ktime_get_ns();
+  bpf_send_signal(fault_signal);
+  return 0;
+}
+
+// Times the first session of the target process tree with the secret
+// provisioning server of `provision_addrs` and `provision_port`, from its
+// connection to its close. The close may happen in softirq context, so the
+// socket is matched instead of the process. Only loaded with key provisioning.
+SEC("tracepoint/sock/inet_sock_set_state")
+int trace_provisioning(struct trace_event_raw_inet_sock_set_state *ctx) {
+  u32 key = 0;
+  u64 sk = (u64)ctx->skaddr;
+  struct provisioning_session zero = {}, *session;
+
+  if (ctx->protocol != IPPROTO_TCP) {
+    return 0;
+  }
+  if (ctx->newstate == TCP_SYN_SENT) {
+    // the tracepoint maps IPv4 addresses into IPv6 ones
+    if (ctx->dport != provision_port || !is_target_tree() ||
+        !bpf_map_lookup_elem(&provision_addrs, ctx->daddr_v6)) {
+      return 0;
+    }
+    session = bpf_map_lookup_or_try_init(&provisioning, &key, &zero);
+    if (session && __sync_val_compare_and_swap(&session->sk, 0, sk) == 0) {
+      session->connected_ns = bpf_ktime_get_ns();
+    }
+    return 0;
+  }
+  if (ctx->newstate != TCP_CLOSE) {
+    return 0;
+  }
+  session = bpf_map_lookup_elem(&provisioning, &key);
+  if (!session || session->sk != sk || session->closed_ns) {
+    return 0;
+  }
+  struct tcp_sock *tp = (struct tcp_sock *)ctx->skaddr;
+  session->bytes_sent = BPF_CORE_READ(tp, bytes_acked);
+  session->bytes_received = BPF_CORE_READ(tp, bytes_received);
+  session->closed_ns = bpf_ktime_get_ns();
+  return 0;
+}
+
 #ifndef EB_SKIP_SGX
 // Helper: Increment the counter for a given key.
 static __always_inline int increment_sgx_counter(u32 field_offset) {
-  u32 key = 0;
-  struct LowLevelSGX *stats = bpf_map_lookup_elem(&sgx_stats, &key);
+  struct sgx_counters *stats = lookup_sgx_counters();
 
   if (!stats)
     return 0;
@@ -246,6 +795,59 @@ int count_sgx_encl_ewb(struct pt_regs *ctx) {
   return increment_sgx_counter(offsetof(struct sgx_counters, encl_wb));
 }
 
+// Enclave build: every page is added (EADD and EEXTEND) by sgx_encl_add_page
+// and the enclave is initialized (EINIT) by sgx_encl_init. Not every kernel
+// exports them, so the programs are attached at runtime.
+static __always_inline int record_load_start() {
+  u32 tid = (u32)bpf_get_current_pid_tgid();
+  u64 ts;
+
+  if (!is_target_process()) {
+    return 0;
+  }
+  ts = bpf_ktime_get_ns();
+  bpf_map_update_elem(&sgx_load_start, &tid, &ts, BPF_ANY);
+  return 0;
+}
+
+static __always_inline int record_load_end(u32 field_offset, bool page) {
+  u32 tid = (u32)bpf_get_current_pid_tgid();
+  u64 *start = bpf_map_lookup_elem(&sgx_load_start, &tid);
+
+  if (!start) {
+    return 0;
+  }
+  u64 duration = bpf_ktime_get_ns() - *start;
+  bpf_map_delete_elem(&sgx_load_start, &tid);
+
+  struct sgx_counters *stats = lookup_sgx_counters();
+  if (!stats) {
+    return 0;
+  }
+  __sync_fetch_and_add((u64 *)((void *)stats + field_offset), duration);
+  if (page) {
+    __sync_fetch_and_add(&stats->eadd_pages, 1);
+  }
+  return 0;
+}
+
+SEC("kprobe")
+int sgx_eadd_enter(struct pt_regs *ctx) { return record_load_start(); }
+
+SEC("kretprobe")
+int sgx_eadd_exit(struct pt_regs *ctx) {
+  return record_load_end(offsetof(struct sgx_counters, eadd_ns),
+                         PT_REGS_RC(ctx) == 0);
+}
+
+SEC("kprobe")
+int sgx_einit_enter(struct pt_regs *ctx) { return record_load_start(); }
+
+SEC("kretprobe")
+int sgx_einit_exit(struct pt_regs *ctx) {
+  return record_load_end(offsetof(struct sgx_counters, einit_ns), false);
+}
+
 #endif
 
 char LICENSE[] SEC("license") = "GPL";
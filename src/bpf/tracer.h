@@ -3,6 +3,8 @@
 
 #define SYSCALL_WRITE 0
 #define SYSCALL_READ 1
+#define SYSCALL_FSYNC 2
+#define SYSCALL_FDATASYNC 3
 #define DISK_NAME_LEN 32
 
 // memory events
@@ -17,21 +19,72 @@
 #define EVENT_READ_DISK 6
 #define EVENT_WRITE_DISK 7
 
+// lifecycle markers, added to the event stream from user space
+#define EVENT_PROCESS_START 8
+#define EVENT_FIRST_OUTPUT 9
+#define EVENT_PROCESS_EXIT 10
+
+// connections to aesmd
+#define EVENT_AESM_CONNECT 11
+#define EVENT_AESM_CLOSE 12
+
+// memory mapping syscalls, with the size of the mapping (or of the change of
+// the program break)
+#define EVENT_MMAP 13
+#define EVENT_MUNMAP 14
+#define EVENT_BRK 15
+#define EVENT_BRK_SHRINK 16
+
+// the workload passed its ready check (lifecycle marker)
+#define EVENT_READY 17
+
+// uprobes of a task, told apart by their attach cookie; cookies from
+// MAX_UPROBES on time the OCALL handlers of Gramine and are not in the event
+// stream
+#define MAX_UPROBES 256
+
+// calls of the uprobes of a task, or-ed with the index of the uprobe
+#define EVENT_UPROBE_ENTER 0x100
+#define EVENT_UPROBE_EXIT 0x200
+// hits of the USDT probes of a task, or-ed with the index of the probe
+#define EVENT_USDT 0x300
+
+#ifndef AF_UNIX
+#define AF_UNIX 1
+#endif
+
 struct io_counter {
   __u64 count;
   __u64 total_duration;
 };
 
+// log2 buckets of the syscall latency histograms: bucket i counts the calls
+// that lasted [2^i, 2^(i+1)) ns, the last one everything above
+#define LATENCY_SLOTS 32
+
+struct latency_hist {
+  __u64 count;
+  __u64 total_duration;
+  __u64 max;
+  __u64 slots[LATENCY_SLOTS];
+};
+
 struct disk_counter {
   __u64 last_sector;
   __u64 bytes;
   __u32 sequential;
   __u32 random;
+  __u64 read_requests;
+  __u64 read_bytes;
+  __u64 write_requests;
+  __u64 write_bytes;
+  __u64 flushes;
 };
 
 struct event {
   __u32 ev_type;
   __u64 timestamp;
+  __u64 bytes;
 };
 
 #endif // __TRACER_H
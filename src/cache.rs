@@ -0,0 +1,243 @@
+//! Cache allocation of the workload (see `CacheGroup`): a control group of resctrl restricting the
+//! L3 ways its tasks can fill, with the cache allocation technology (CAT) of Intel RDT.
+use std::{
+  fs::{self, File, OpenOptions},
+  os::{fd::AsRawFd, unix::process::CommandExt},
+  path::{Path, PathBuf},
+  process::Command,
+  time::SystemTime,
+};
+
+use tracing::warn;
+
+use crate::{common::CacheAllocation, constants::RESCTRL_DIRECTORY, stats::CacheOccupancySample};
+
+/// Returns the capacity bitmask of `allocation` on the host: its `mask`, or its lowest `ways` of
+/// the L3 capacity bitmask of resctrl. Fails if resctrl does not support L3 allocation or the
+/// mask does not fit the cache.
+pub fn resolve_mask(allocation: &CacheAllocation) -> Result<u64, std::io::Error> {
+  resolve_mask_at(Path::new(RESCTRL_DIRECTORY), allocation)
+}
+
+fn resolve_mask_at(root: &Path, allocation: &CacheAllocation) -> Result<u64, std::io::Error> {
+  let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+  let read_info = |file: &str| -> Result<String, std::io::Error> {
+    fs::read_to_string(root.join("info/L3").join(file))
+      .map(|content| content.trim().to_string())
+      .map_err(|e| {
+        std::io::Error::new(
+          e.kind(),
+          format!("resctrl does not support L3 cache allocation: {}", e),
+        )
+      })
+  };
+  let full = u64::from_str_radix(&read_info("cbm_mask")?, 16)
+    .map_err(|e| invalid(format!("invalid L3 capacity bitmask: {}", e)))?;
+  let min_bits = read_info("min_cbm_bits")?.parse::<u32>().unwrap_or(1);
+  let mask = match allocation {
+    CacheAllocation {
+      ways: Some(ways), ..
+    } => {
+      if *ways == 0 || *ways > full.count_ones() {
+        return Err(invalid(format!(
+          "cannot allocate {} cache ways, the L3 cache has {}",
+          ways,
+          full.count_ones()
+        )));
+      }
+      // the ways of the full mask are contiguous from its lowest bit
+      (u64::MAX >> (64 - ways)) << full.trailing_zeros()
+    }
+    CacheAllocation {
+      mask: Some(mask), ..
+    } => allocation
+      .parsed_mask()
+      .filter(|m| m & !full == 0)
+      .ok_or_else(|| invalid(format!("cache mask {} does not fit {:x}", mask, full)))?,
+    _ => return Err(invalid(String::from("no cache ways or mask"))),
+  };
+  if mask.count_ones() < min_bits {
+    return Err(invalid(format!(
+      "cache mask {:#x} has fewer than the {} ways the cache allocates at least",
+      mask, min_bits
+    )));
+  }
+  Ok(mask)
+}
+
+/// A control group of resctrl whose tasks fill the L3 ways of a capacity bitmask in every cache
+/// domain. The group is removed (and its tasks return to the default group) on drop.
+#[derive(Debug)]
+pub struct CacheGroup {
+  path: PathBuf,
+  /// The `tasks` file of the group, written by workloads before they execute.
+  tasks: File,
+}
+
+impl CacheGroup {
+  pub fn create(name: &str, mask: u64) -> Result<Self, std::io::Error> {
+    Self::create_at(Path::new(RESCTRL_DIRECTORY), name, mask)
+  }
+
+  fn create_at(root: &Path, name: &str, mask: u64) -> Result<Self, std::io::Error> {
+    let schemata = l3_schemata(&fs::read_to_string(root.join("schemata"))?, mask);
+    let path = root.join(name);
+    fs::create_dir(&path)?;
+    let tasks = fs::write(path.join("schemata"), schemata)
+      .and_then(|_| OpenOptions::new().write(true).open(path.join("tasks")));
+    match tasks {
+      Ok(tasks) => Ok(Self { path, tasks }),
+      Err(e) => {
+        let _ = fs::remove_dir(&path);
+        Err(e)
+      }
+    }
+  }
+
+  /// Directory of the group, where monitoring groups of its tasks are created.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Moves `cmd` to the group before it executes; its threads and children inherit the group.
+  pub fn assign_on_spawn(&self, cmd: &mut Command) {
+    let fd = self.tasks.as_raw_fd();
+    // Safety: write is async-signal-safe and the file outlives the spawn. resctrl moves the
+    // calling task when it is given pid 0.
+    unsafe {
+      cmd.pre_exec(move || {
+        if libc::write(fd, b"0".as_ptr().cast(), 1) != 1 {
+          return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+  }
+}
+
+impl Drop for CacheGroup {
+  fn drop(&mut self) {
+    if let Err(e) = fs::remove_dir(&self.path) {
+      warn!(
+        "cannot remove the cache allocation group {:?}: {}",
+        self.path, e
+      );
+    }
+  }
+}
+
+// the default group lists every domain of the cache, e.g. "L3:0=7ff;1=7ff"
+fn l3_schemata(default_schemata: &str, mask: u64) -> String {
+  let domains: Vec<String> = default_schemata
+    .lines()
+    .filter_map(|line| line.trim().strip_prefix("L3:"))
+    .flat_map(|domains| domains.split(';'))
+    .filter_map(|domain| domain.split_once('='))
+    .map(|(id, _)| format!("{}={:x}", id.trim(), mask))
+    .collect();
+  format!("L3:{}\n", domains.join(";"))
+}
+
+/// Reads the L3 occupancy of the tasks of the resctrl group `group`, by cache domain. Empty if
+/// the host does not monitor cache occupancy.
+pub fn read_llc_occupancy(group: &Path) -> Vec<CacheOccupancySample> {
+  let timestamp = SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .unwrap()
+    .as_nanos();
+  let Ok(entries) = group.join("mon_data").read_dir() else {
+    return Vec::new();
+  };
+  let mut samples: Vec<CacheOccupancySample> = entries
+    .flatten()
+    .filter_map(|entry| {
+      let domain = entry
+        .file_name()
+        .to_str()?
+        .strip_prefix("mon_L3_")?
+        .parse::<u32>()
+        .ok()?;
+      let bytes = fs::read_to_string(entry.path().join("llc_occupancy"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+      Some(CacheOccupancySample {
+        timestamp,
+        domain: format!("l3-{}", domain),
+        bytes,
+      })
+    })
+    .collect();
+  samples.sort_by(|a, b| a.domain.cmp(&b.domain));
+  samples
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs;
+
+  use tempfile::TempDir;
+
+  use super::{l3_schemata, read_llc_occupancy, resolve_mask_at, CacheGroup};
+  use crate::common::CacheAllocation;
+
+  #[test]
+  fn cache_masks() {
+    let root = TempDir::new().unwrap();
+    let ways = |ways| CacheAllocation {
+      ways: Some(ways),
+      mask: None,
+    };
+    assert!(resolve_mask_at(root.path(), &ways(2)).is_err());
+
+    fs::create_dir_all(root.path().join("info/L3")).unwrap();
+    fs::write(root.path().join("info/L3/cbm_mask"), "7ff\n").unwrap();
+    fs::write(root.path().join("info/L3/min_cbm_bits"), "2\n").unwrap();
+    assert_eq!(resolve_mask_at(root.path(), &ways(4)).unwrap(), 0xf);
+    assert_eq!(resolve_mask_at(root.path(), &ways(11)).unwrap(), 0x7ff);
+    assert!(resolve_mask_at(root.path(), &ways(12)).is_err());
+    assert!(resolve_mask_at(root.path(), &ways(1)).is_err());
+
+    let mask = |mask: &str| CacheAllocation {
+      ways: None,
+      mask: Some(mask.to_string()),
+    };
+    assert_eq!(resolve_mask_at(root.path(), &mask("0x0f0")).unwrap(), 0xf0);
+    assert!(resolve_mask_at(root.path(), &mask("0xf00")).is_err());
+  }
+
+  #[test]
+  fn cache_group() {
+    let schemata = "    L3:0=7ff;1=7ff\n    MB:0=100;1=100\n";
+    assert_eq!(l3_schemata(schemata, 0xf0), "L3:0=f0;1=f0\n");
+
+    let root = TempDir::new().unwrap();
+    fs::write(root.path().join("schemata"), schemata).unwrap();
+    fs::create_dir(root.path().join("eb-42")).unwrap();
+    // resctrl does not create a group twice either
+    assert!(CacheGroup::create_at(root.path(), "eb-42", 0xf).is_err());
+  }
+
+  #[test]
+  fn llc_occupancy() {
+    let group = TempDir::new().unwrap();
+    for (domain, occupancy) in [("mon_L3_01", "2048"), ("mon_L3_00", "Unavailable")] {
+      fs::create_dir_all(group.path().join("mon_data").join(domain)).unwrap();
+      fs::write(
+        group
+          .path()
+          .join("mon_data")
+          .join(domain)
+          .join("llc_occupancy"),
+        occupancy,
+      )
+      .unwrap();
+    }
+    let samples = read_llc_occupancy(group.path());
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].domain, "l3-1");
+    assert_eq!(samples[0].bytes, 2048);
+    assert!(read_llc_occupancy(&group.path().join("missing")).is_empty());
+  }
+}
@@ -1,74 +1,510 @@
 use std::{
-  collections::{HashMap, HashSet},
+  collections::{BTreeMap, BTreeSet, HashMap, HashSet},
   env,
   fmt::Debug,
-  fs::{self, create_dir_all},
+  fs::{self, create_dir_all, File},
   mem::MaybeUninit,
+  os::unix::process::CommandExt,
   path::{Path, PathBuf},
-  process::{Child, Command, Stdio},
+  process::{Child, Command, ExitStatus, Stdio},
   sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc, Mutex,
+    atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering},
+    Arc, Mutex, OnceLock,
   },
   thread,
-  time::{Duration, SystemTime},
+  time::{Duration, Instant, SystemTime},
 };
 
 use duration_str::HumanFormat;
 use libbpf_rs::{
   skel::{OpenSkel, Skel, SkelBuilder},
-  MapCore, MapFlags, RingBufferBuilder,
+  MapCore, MapFlags, RingBufferBuilder, UprobeOpts, UsdtOpts,
 };
 use plain::Plain;
-use tracing::{error, trace, warn};
+use regex::Regex;
+use serde::Serialize;
+use tracing::{error, info, trace, warn};
 use utils::{
-  extract_rapl_path, get_map_result, get_sgx_stats, process_disk_stats, process_mem_stats,
-  run_command_with_args, save_deep_stats, save_energy_data, save_io_metrics, save_perf_output,
-  save_stdout_stderr,
+  append_csv, collect_sgx_profile_data, create_staging_directory, disable_aslr_on_spawn,
+  extract_perf_counter, extract_workload_metrics, get_efficiency_stats, get_map_result,
+  get_sgx_stats, gramine_loader, load_workload_metrics, mark_invalid, mark_recovery,
+  packages_energy_uj, peek_exited, phase_durations, pidfd_open, pin_on_spawn, probe_perf_event,
+  probe_tcp, process_disk_stats, process_mem_stats, process_tree, publish_sample, read_output,
+  resolve_kprobe_stats, run_hook, save_cache_occupancy_data, save_clock_reference, save_cpu_data,
+  save_deep_stats, save_energy_data, save_fault, save_io_metrics, save_memory_bandwidth_data,
+  save_memory_data, save_ocall_stats, save_perf_output, save_perf_scaling, save_phases,
+  save_pressure_data, save_sgx_stats_errors, save_stdout_stderr, save_summary, schedule_on_spawn,
+  wait_readable, watch_phases, HookOutcome,
 };
 
 use crate::{
-  common::ExperimentConfig,
-  constants::DEFAULT_PERF_EVENTS,
-  stats::{DeepTraceEvent, DiskStats, EnergySample, LowLevelSgxCounters, Partition, SGXStats},
+  bandwidth::BandwidthMonitor,
+  cache::{read_llc_occupancy, CacheGroup},
+  common::{
+    EnergySource, ExperimentConfig, Fault, FaultAction, FaultTrigger, MemoryBandwidthSource,
+    MetricPattern, PerfEvents, PerfProfile, ReadyCheck, ReadyProbe, RunHealth, SchedPolicy,
+    StorageType, SystemdScope, Uprobe, Usdt,
+  },
+  constants::{
+    CPU_CSV_HEADER, ENERGY_CSV_HEADER, GRAMINE_OCALLS, MEMORY_CSV_HEADER, METRICS_FILE_ENV,
+    PHASES_FILE, PHASES_FILE_ENV, PSI_CSV_HEADER, PSI_RESOURCES, TRACE_CSV_HEADER,
+    WORKLOAD_METRICS_DIRECTORY, WORKLOAD_METRICS_FILE,
+  },
+  energy::{self, EnergyCounter},
+  network::{NetworkNamespace, Shaping},
+  progress::Progress,
+  provisioning::{watch_first_access, ProvisioningServer, ProvisioningSession, TracedSession},
+  stats::{
+    monotonic_ns, BandwidthSample, CacheOccupancySample, CarbonEstimate, ClockReference, CpuSample,
+    CpuTimes, DeepTraceEvent, DiskStats, EfficiencyStats, EnergySample, HugePageStats,
+    LowLevelSgxCounters, MemorySample, OcallStats, Partition, PerfScaling, PhaseMarker,
+    PressureSample, ProbeStats, ProcIo, RateStats, SGXStats, SocketEnergy, SyscallStats,
+    VfsCounters, WorkloadMetric, EVENT_FIRST_OUTPUT, EVENT_PROCESS_EXIT, EVENT_PROCESS_START,
+    EVENT_READY, MAX_UPROBES,
+  },
+  system::{affinity, cpu_packages, parent_disk, TransparentHugePageMode},
   tracer::{
-    types::{disk_counter, io_counter},
+    types::{disk_counter, fault_state, io_counter, latency_hist},
     TracerSkelBuilder,
   },
 };
 unsafe impl Plain for io_counter {}
 unsafe impl Plain for disk_counter {}
+unsafe impl Plain for latency_hist {}
+
+/// How often the stop flag is checked while waiting for the target process. Exits are notified
+/// through a pidfd, so this does not bound the accuracy of the measured run time.
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a workload terminated at the end of its `sample_duration` may take to exit before it
+/// is killed.
+const TERMINATION_GRACE: Duration = Duration::from_secs(10);
+
+/// How long a connection attempt of a tcp ready check may take.
+const TCP_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How often the phases file of the workload is read: the resolution of the phase markers.
+const PHASE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Returns the events of `profile` and the `extra` ones, without duplicates.
+fn merge_perf_events(profile: PerfProfile, extra: &[String]) -> Vec<String> {
+  let perf_events: HashSet<String> = profile
+    .events()
+    .iter()
+    .map(|v| v.to_string())
+    .chain(extra.iter().cloned())
+    .collect();
+  Vec::from_iter(perf_events)
+}
+
+/// The settings of a collector which change the content of a sample directory (see
+/// `DefaultCollector::settings`).
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CollectorSettings<'a> {
+  pub sample_size: u32,
+  pub deep_trace: bool,
+  pub perf_profile: PerfProfile,
+  /// The events of the profile and the extra ones, sorted.
+  pub perf_events: Vec<&'a str>,
+  pub energy_source: EnergySource,
+  pub energy_sample_interval: Duration,
+  pub memory_bandwidth: Option<MemoryBandwidthSource>,
+  pub carbon_intensity_g_per_kwh: Option<f64>,
+  pub relative_timestamps: bool,
+  pub checkpoint_interval: Option<Duration>,
+  pub extra_kprobes: &'a [String],
+  pub disk_devices: &'a [u32],
+}
 
 pub struct DefaultCollector {
   sample_size: u32,
   deep_trace: bool,
   perf_events: Vec<String>,
-  rapl_paths: Vec<(String, PathBuf)>,
+  /// Profile of the default events, which are counted with the extra ones.
+  perf_profile: PerfProfile,
+  extra_perf_events: Vec<String>,
+  /// Default events dropped because the host cannot count them.
+  unsupported_perf_events: Vec<String>,
+  energy_source: EnergySource,
+  energy_counters: Vec<EnergyCounter>,
   energy_sample_interval: Duration,
+  /// Source of the memory bandwidth of the workload; not measured if None.
+  memory_bandwidth: Option<MemoryBandwidthSource>,
+  carbon_intensity_g_per_kwh: Option<f64>,
+  relative_timestamps: bool,
+  checkpoint_interval: Option<Duration>,
+  extra_kprobes: Vec<String>,
+  /// Devices the block tracer is restricted to; empty to account every device.
+  disk_devices: Vec<u32>,
   partitions: Vec<Partition>,
+  /// No further sample is started; the running one completes.
   stop: Arc<AtomicBool>,
+  /// The running workload is killed and its partial results are saved.
+  interrupt: Arc<AtomicBool>,
+  /// Process group of the running workload (0 if none), killed by `abort`.
+  workload: AtomicI32,
+  /// Problems of the samples run so far.
+  health: Mutex<RunHealth>,
+  progress: OnceLock<Progress>,
+}
+
+/// Probes on the code of the workload declared by a task.
+#[derive(Clone, Debug, Default)]
+struct WorkloadProbes {
+  uprobes: Vec<Uprobe>,
+  usdt: Vec<Usdt>,
+  /// The processes started by the workload are traced with it (see
+  /// `Task::auxiliary_executables`).
+  trace_children: bool,
+  /// The secret provisioning server whose session with the workload is timed.
+  provisioning: Option<ProvisioningServer>,
+  /// The untrusted loader of Gramine, whose OCALL handlers are timed (SGX workloads only).
+  ocall_loader: Option<PathBuf>,
+}
+
+/// Settings and resources shared by the samples of an experiment, set up by `attach`.
+struct ExperimentContext {
+  program: PathBuf,
+  args: Vec<String>,
+  env: Option<HashMap<String, String>>,
+  sample_interval: Duration,
+  probes: WorkloadProbes,
+  /// The namespace the workload runs in, deleted with the context.
+  network: Option<NetworkNamespace>,
+  sample_duration: Option<Duration>,
+  metric_patterns: Vec<MetricPattern>,
+  perf_events: Vec<String>,
+  perf_min_counted: Option<f64>,
+  systemd_scope: Option<SystemdScope>,
+  cpu_affinity: Vec<usize>,
+  nice: Option<i32>,
+  sched_policy: Option<SchedPolicy>,
+  disable_aslr: bool,
+  /// The control group of resctrl holding the workload, removed with the context.
+  cache_group: Option<CacheGroup>,
+  ready_check: Option<ReadyCheck>,
+}
+
+/// Injects the fault of a task into the process group of a running workload.
+struct FaultInjector {
+  fault: Fault,
+  pgid: i32,
+  spawn_ns: u64,
+  injected_ns: Option<u64>,
+  resumed_ns: Option<u64>,
+}
+
+/// A fault injected into a sample.
+#[derive(Debug, Clone, PartialEq)]
+struct FaultReport {
+  action: FaultAction,
+  /// Time from the spawn of the workload to the fault.
+  injected_after_ns: u64,
+  /// How long a stopped workload stayed stopped.
+  stopped_ns: Option<u64>,
 }
 
 struct TraceResult {
-  mem_counters: Vec<(u32, io_counter)>,
+  mem_counters: Vec<(u32, latency_hist)>,
   disk_counters: Vec<(u32, disk_counter)>,
   sgx_counters: Option<LowLevelSgxCounters>,
+  /// Whether the kprobes timing the enclave build were attached.
+  enclave_build_traced: bool,
+  key_provisioning: Option<ProvisioningSession>,
+  vfs_counters: VfsCounters,
+  probe_stats: Vec<ProbeStats>,
+  /// OCALLs timed on their handlers in the untrusted loader.
+  timed_ocalls: Vec<OcallStats>,
+  fault: Option<FaultReport>,
 
   deep_stats: Option<Vec<DeepTraceEvent>>,
 }
 
+#[derive(Default)]
 struct Metrics {
   energy_stats: HashMap<String, Vec<EnergySample>>,
+  pressure_stats: Vec<PressureSample>,
+  memory_stats: Vec<MemorySample>,
+  cpu_stats: Vec<CpuSample>,
+  memory_bandwidth: Vec<BandwidthSample>,
+  /// L3 occupancy of the cache allocation group of the workload.
+  cache_occupancy: Vec<CacheOccupancySample>,
   stdout: Vec<u8>,
   stderr: Vec<u8>,
   perf_output: Vec<u8>,
-  sys_write_count: u64,
-  sys_write_avg: u64,
-  sys_read_count: u64,
-  sys_read_avg: u64,
+  /// Share of the run time every perf event was counted.
+  perf_scaling: Vec<PerfScaling>,
+  syscall_stats: Vec<SyscallStats>,
+  vfs_counters: VfsCounters,
   disk_stats: Vec<DiskStats>,
   sgx_stats: Option<SGXStats>,
+  probe_stats: Vec<ProbeStats>,
+  /// OCALLs timed on their handlers in the untrusted loader, merged into those of the profile.
+  timed_ocalls: Vec<OcallStats>,
+  proc_io: Option<ProcIo>,
+  exit_status: Option<ExitStatus>,
+  /// Monotonic time of the first byte written on stdout.
+  first_output_ns: Option<u64>,
+  /// Monotonic time at which the exit of the target process was detected.
+  exit_ns: Option<u64>,
+  /// Time from the spawn (or, with a ready check, from the start of measurement) to the exit of
+  /// the target process.
+  runtime_ns: Option<u64>,
+  /// Time from the spawn of the target process to the start of measurement, once it passed its
+  /// ready check and the `pre_run` hook ran.
+  ready_after_ns: Option<u64>,
   deep_stats: Option<Vec<DeepTraceEvent>>,
+  peak_pss_kb: Option<u64>,
+  peak_uss_kb: Option<u64>,
+  /// Huge page activity of the host during the sample.
+  hugepages: Option<HugePageStats>,
+  efficiency: Option<EfficiencyStats>,
+  socket_energy: SocketEnergy,
+  carbon: Option<CarbonEstimate>,
+  fault: Option<FaultReport>,
+  /// The workload was terminated at the end of its `sample_duration`.
+  terminated: bool,
+  /// Rates of a fixed-duration sample, reported instead of its runtime.
+  rates: Option<RateStats>,
+  /// Metrics extracted from the output of the workload.
+  workload_metrics: Vec<WorkloadMetric>,
+  /// Phases announced by the workload, in order.
+  phases: Vec<PhaseMarker>,
+  /// Time spent in every phase.
+  phase_durations_ns: BTreeMap<String, u64>,
+  /// The first session of the enclave with the key provisioning server, if it got its key from
+  /// one.
+  key_provisioning: Option<ProvisioningSession>,
+  /// Monotonic time of the first file the enclave opened or created in its encrypted directory.
+  first_protected_access_ns: Option<u64>,
+  /// Monotonic time the target process was spawned at.
+  spawn_ns: u64,
+}
+
+/// Appends the time series collected so far to the files of the sample directory every
+/// `interval`, bounding the memory used by long samples and the data lost on a crash. Totals
+/// over the appended samples are kept for `summary.csv`.
+struct Checkpoint {
+  directory: PathBuf,
+  interval: Duration,
+  /// Subtracted from the timestamps when they are relative to the spawn of the target process.
+  reference: Option<ClockReference>,
+  /// Energy by package of the appended samples.
+  packages_energy_uj: Mutex<BTreeMap<u32, u64>>,
+  peak_pss_kb: AtomicU64,
+  peak_uss_kb: AtomicU64,
+}
+
+impl FaultInjector {
+  fn new(fault: Fault, pgid: i32, spawn_ns: u64) -> Self {
+    Self {
+      fault,
+      pgid,
+      spawn_ns,
+      injected_ns: None,
+      resumed_ns: None,
+    }
+  }
+
+  /// Returns the signal due at `now_ns`, if any. `traced_ns` is the time the tracer injected the
+  /// fault at, for faults triggered by system calls.
+  fn next_signal(&mut self, now_ns: u64, traced_ns: Option<u64>) -> Option<i32> {
+    match self.injected_ns {
+      None => {
+        let injected_ns = match self.fault.trigger {
+          FaultTrigger::After(after) => {
+            (now_ns >= self.spawn_ns + after.as_nanos() as u64).then_some(now_ns)
+          }
+          FaultTrigger::Syscalls(_) => traced_ns,
+        }?;
+        self.injected_ns = Some(injected_ns);
+        Some(self.fault.action.signal())
+      }
+      Some(injected_ns)
+        if self.fault.action == FaultAction::Stop
+          && self.resumed_ns.is_none()
+          && now_ns >= injected_ns + self.fault.stop_for.as_nanos() as u64 =>
+      {
+        self.resumed_ns = Some(now_ns);
+        Some(libc::SIGCONT)
+      }
+      _ => None,
+    }
+  }
+
+  /// Sends the signal due at `now_ns`, if any; the tracer already signalled the process which
+  /// made the last system call, the rest of the group is signalled here.
+  fn poll(&mut self, now_ns: u64, traced_ns: Option<u64>) {
+    if let Some(signal) = self.next_signal(now_ns, traced_ns) {
+      trace!("sending signal {} to process group {}", signal, self.pgid);
+      unsafe {
+        libc::kill(-self.pgid, signal);
+      }
+    }
+  }
+
+  fn report(&self) -> Option<FaultReport> {
+    let injected_ns = self.injected_ns?;
+    Some(FaultReport {
+      action: self.fault.action,
+      injected_after_ns: injected_ns.saturating_sub(self.spawn_ns),
+      stopped_ns: self
+        .resumed_ns
+        .map(|resumed_ns| resumed_ns.saturating_sub(injected_ns)),
+    })
+  }
+}
+
+/// Output of the target process returned by `wait_for_child`.
+struct ChildOutput {
+  stdout: Vec<u8>,
+  stderr: Vec<u8>,
+  proc_io: Option<ProcIo>,
+  exit_status: Option<ExitStatus>,
+  first_output_ns: Option<u64>,
+  exit_ns: Option<u64>,
+  /// The workload was terminated at the end of its `sample_duration`.
+  terminated: bool,
+}
+
+impl Metrics {
+  /// Adds `process-start`, `first-output`, `ready` and `process-exit` markers to the deep trace,
+  /// so that analysis can window on the lifetime of the workload.
+  fn add_lifecycle_markers(&mut self, start_ns: u64) {
+    let Some(deep_stats) = self.deep_stats.as_mut() else {
+      return;
+    };
+    let markers = [
+      (EVENT_PROCESS_START, Some(start_ns)),
+      (EVENT_FIRST_OUTPUT, self.first_output_ns),
+      (EVENT_READY, self.ready_after_ns.map(|ns| start_ns + ns)),
+      (EVENT_PROCESS_EXIT, self.exit_ns),
+    ];
+    for (ev_type, timestamp) in markers {
+      if let Some(timestamp) = timestamp {
+        deep_stats.push(DeepTraceEvent {
+          ev_type,
+          timestamp,
+          bytes: 0,
+        });
+      }
+    }
+    deep_stats.sort_by_key(|event| event.timestamp);
+  }
+
+  /// Makes the timestamps of all time series relative to the spawn of the target process.
+  fn normalize_timestamps(&mut self, reference: &ClockReference) {
+    for sample in self.energy_stats.values_mut().flatten() {
+      sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+    }
+    for sample in &mut self.pressure_stats {
+      sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+    }
+    for sample in &mut self.memory_stats {
+      sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+    }
+    for sample in &mut self.cpu_stats {
+      sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+    }
+    for sample in &mut self.memory_bandwidth {
+      sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+    }
+    for sample in &mut self.cache_occupancy {
+      sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+    }
+    for event in self.deep_stats.iter_mut().flatten() {
+      event.timestamp = event.timestamp.saturating_sub(reference.monotonic_ns);
+    }
+    for marker in &mut self.phases {
+      marker.timestamp = marker.timestamp.saturating_sub(reference.realtime_ns);
+      marker.monotonic_ns = marker.monotonic_ns.saturating_sub(reference.monotonic_ns);
+    }
+  }
+}
+
+impl Checkpoint {
+  /// Returns true (and restarts the interval) if a checkpoint is due.
+  fn due(&self, last: &mut Instant) -> bool {
+    if last.elapsed() < self.interval {
+      return false;
+    }
+    *last = Instant::now();
+    true
+  }
+
+  fn energy(&self, measures: &mut HashMap<String, Vec<EnergySample>>) -> std::io::Result<()> {
+    let energy_uj = packages_energy_uj(measures);
+    for (name, samples) in measures.iter_mut() {
+      // the last sample is kept to compute the power and the energy of the next interval
+      let Some(last) = samples.pop() else {
+        continue;
+      };
+      if let Some(reference) = &self.reference {
+        for sample in samples.iter_mut() {
+          sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+        }
+      }
+      let path = self.directory.join(format!("{}.csv", name));
+      append_csv(&path, ENERGY_CSV_HEADER, samples)?;
+      samples.clear();
+      samples.push(last);
+    }
+    let mut packages_energy_uj = self.packages_energy_uj.lock().unwrap();
+    for (package, energy_uj) in energy_uj {
+      *packages_energy_uj.entry(package).or_default() += energy_uj;
+    }
+    Ok(())
+  }
+
+  fn pressure(&self, measures: &mut Vec<PressureSample>) -> std::io::Result<()> {
+    if let Some(reference) = &self.reference {
+      for sample in measures.iter_mut() {
+        sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+      }
+    }
+    append_csv(&self.directory.join("psi.csv"), PSI_CSV_HEADER, measures)?;
+    measures.clear();
+    Ok(())
+  }
+
+  fn memory(&self, measures: &mut Vec<MemorySample>) -> std::io::Result<()> {
+    for sample in measures.iter_mut() {
+      self.peak_pss_kb.fetch_max(sample.pss_kb, Ordering::Relaxed);
+      self.peak_uss_kb.fetch_max(sample.uss_kb, Ordering::Relaxed);
+      if let Some(reference) = &self.reference {
+        sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+      }
+    }
+    append_csv(
+      &self.directory.join("memory.csv"),
+      MEMORY_CSV_HEADER,
+      measures,
+    )?;
+    measures.clear();
+    Ok(())
+  }
+
+  fn cpu(&self, measures: &mut Vec<CpuSample>) -> std::io::Result<()> {
+    if let Some(reference) = &self.reference {
+      for sample in measures.iter_mut() {
+        sample.timestamp = sample.timestamp.saturating_sub(reference.realtime_ns);
+      }
+    }
+    append_csv(&self.directory.join("cpu.csv"), CPU_CSV_HEADER, measures)?;
+    measures.clear();
+    Ok(())
+  }
+
+  fn deep_trace(&self, events: &mut Vec<DeepTraceEvent>) -> std::io::Result<()> {
+    if let Some(reference) = &self.reference {
+      for event in events.iter_mut() {
+        event.timestamp = event.timestamp.saturating_sub(reference.monotonic_ns);
+      }
+    }
+    append_csv(&self.directory.join("trace.csv"), TRACE_CSV_HEADER, events)?;
+    events.clear();
+    Ok(())
+  }
 }
 
 impl DefaultCollector {
@@ -77,87 +513,455 @@ impl DefaultCollector {
     deep_trace: bool,
     energy_sample_interval: Duration,
     extra_perf_events: Option<Vec<String>>,
+    carbon_intensity_g_per_kwh: Option<f64>,
+    relative_timestamps: bool,
   ) -> Self {
+    let extra_perf_events = extra_perf_events.unwrap_or_default();
     Self {
       sample_size,
       stop: Arc::new(AtomicBool::new(false)),
+      interrupt: Arc::new(AtomicBool::new(false)),
+      workload: AtomicI32::new(0),
+      health: Mutex::new(RunHealth::default()),
+      progress: OnceLock::new(),
       partitions: Partition::load(),
       deep_trace,
       energy_sample_interval,
-      perf_events: {
-        let mut perf_events: HashSet<String> =
-          HashSet::from_iter(DEFAULT_PERF_EVENTS.iter().map(|v| v.to_string()));
-        for extra_perf_event in extra_perf_events.unwrap_or_default() {
-          perf_events.insert(extra_perf_event);
+      carbon_intensity_g_per_kwh,
+      relative_timestamps,
+      checkpoint_interval: None,
+      extra_kprobes: Vec::new(),
+      disk_devices: Vec::new(),
+      unsupported_perf_events: Vec::new(),
+      perf_events: merge_perf_events(PerfProfile::default(), &extra_perf_events),
+      perf_profile: PerfProfile::default(),
+      extra_perf_events,
+      energy_source: EnergySource::default(),
+      energy_counters: energy::discover(&EnergySource::default()),
+      memory_bandwidth: None,
+    }
+  }
+
+  /// Reads the energy counters from `source` instead of the powercap interface.
+  pub fn with_energy_source(mut self, source: EnergySource) -> Self {
+    if source != self.energy_source {
+      self.energy_counters = energy::discover(&source);
+      self.energy_source = source;
+    }
+    self
+  }
+
+  /// Counts the events of `profile` instead of the default ones, with the extra events.
+  pub fn with_perf_profile(mut self, profile: PerfProfile) -> Self {
+    self.perf_events = merge_perf_events(profile, &self.extra_perf_events);
+    self.perf_profile = profile;
+    self
+  }
+
+  /// Measures the memory bandwidth of the workload from `source`.
+  pub fn with_memory_bandwidth(mut self, source: Option<MemoryBandwidthSource>) -> Self {
+    self.memory_bandwidth = source;
+    self
+  }
+
+  /// Appends the time series of running samples to their files every `interval` instead of
+  /// holding them in memory until the workload exits.
+  pub fn with_checkpoint_interval(mut self, interval: Option<Duration>) -> Self {
+    self.checkpoint_interval = interval;
+    self
+  }
+
+  /// Kernel functions whose calls by the target process are counted and timed.
+  pub fn with_extra_kprobes(mut self, symbols: Vec<String>) -> Self {
+    self.extra_kprobes = symbols;
+    self
+  }
+
+  /// Restricts the disk statistics to the traffic of the given devices, looked up by name in
+  /// `/proc/partitions`; a partition stands for its whole disk, which the block requests are
+  /// accounted to. Fails if a device does not exist.
+  pub fn with_disk_devices(mut self, names: &[String]) -> Result<Self, std::io::Error> {
+    let mut disk_devices: Vec<u32> = names
+      .iter()
+      .map(|name| {
+        let disk = parent_disk(Path::new("/sys/class/block"), name);
+        if &disk != name {
+          info!(
+            "disk device {} is a partition; the traffic of {} is accounted",
+            name, disk
+          );
         }
-        Vec::from_iter(perf_events.iter().map(String::from))
-      },
-      // discovery rapl paths: https://www.kernel.org/doc/html/next/power/powercap/powercap.html
-      rapl_paths: {
-        let base_path = Path::new("/sys/devices/virtual/powercap/intel-rapl");
-        let mut rapl_paths = Vec::new();
-
-        if base_path.is_dir() {
-          for entry in base_path.read_dir().unwrap().flatten() {
-            if let Some(s) = extract_rapl_path(&entry) {
-              let domain_name = s.0.clone();
-              rapl_paths.push(s);
-              for subentry in entry.path().read_dir().unwrap().flatten() {
-                if let Some(r) = extract_rapl_path(&subentry) {
-                  let name = format!("{}-{}", domain_name, r.0);
-                  rapl_paths.push((name, r.1));
-                }
-              }
-            }
-          }
-        } else {
-          warn!("system does not support RAPL interface; skipping");
+        self
+          .partitions
+          .iter()
+          .find(|p| p.name == disk)
+          .map(|p| p.dev)
+          .ok_or_else(|| {
+            std::io::Error::new(
+              std::io::ErrorKind::NotFound,
+              format!("disk device {} not found in /proc/partitions", name),
+            )
+          })
+      })
+      .collect::<Result<_, _>>()?;
+    disk_devices.sort_unstable();
+    disk_devices.dedup();
+    self.disk_devices = disk_devices;
+    Ok(self)
+  }
+
+  /// Probes the perf events of the collector and `task_events` (added by tasks) with a trial
+  /// `perf stat`, so that no experiment runs before an unsupported event is noticed. Events of the
+  /// perf profile the host cannot count are dropped with a warning; requested events must be
+  /// supported.
+  pub fn with_supported_perf_events(
+    mut self,
+    task_events: &[String],
+  ) -> Result<Self, std::io::Error> {
+    let mut events = self.perf_events.clone();
+    for event in task_events {
+      if !events.contains(event) {
+        events.push(event.clone());
+      }
+    }
+    let mut unsupported = Vec::new();
+    for event in events {
+      match probe_perf_event(&event) {
+        Ok(true) => {}
+        Ok(false) => unsupported.push(event),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+          warn!("perf not found; perf events are not validated");
+          return Ok(self);
         }
-        rapl_paths
-      },
+        Err(e) => return Err(e),
+      }
+    }
+
+    let (dropped, requested): (Vec<String>, Vec<String>) = unsupported
+      .into_iter()
+      .partition(|event| self.perf_profile.events().contains(&event.as_str()));
+    if !requested.is_empty() {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+          "perf events not supported by the host: {}",
+          requested.join(",")
+        ),
+      ));
     }
+    if !dropped.is_empty() {
+      warn!(
+        "perf events not supported by the host; skipping {}",
+        dropped.join(",")
+      );
+    }
+    self.perf_events.retain(|event| !dropped.contains(event));
+    self.unsupported_perf_events = dropped;
+    Ok(self)
   }
 
-  #[tracing::instrument(level = "trace", skip(self), err)]
+  /// Default perf events dropped by [`DefaultCollector::with_supported_perf_events`].
+  pub fn unsupported_perf_events(&self) -> &[String] {
+    &self.unsupported_perf_events
+  }
+
+  /// Returns the problems of the samples run so far: failed samples, timed out hooks and samples
+  /// with partial metrics.
+  pub fn health(&self) -> RunHealth {
+    *self.health.lock().unwrap()
+  }
+
+  /// Samples run for every experiment, including the deep-trace run.
+  pub fn samples_per_experiment(&self) -> usize {
+    self.sample_size as usize + usize::from(self.deep_trace)
+  }
+
+  /// Reports the progress of the samples on `progress`. Can be set only once.
+  pub fn set_progress(&self, progress: Progress) {
+    if self.progress.set(progress).is_err() {
+      warn!("progress is already reported");
+    }
+  }
+
+  pub fn progress(&self) -> Option<&Progress> {
+    self.progress.get()
+  }
+
+  // counts a published sample (`None` for the deep-trace run) in the health and the progress of
+  // the run
+  fn record_sample(&self, sample_path: &Path, sample: Option<u32>, succeeded: bool) {
+    let partial = sample_path.join("invalid").is_file();
+    {
+      let mut health = self.health.lock().unwrap();
+      if !succeeded {
+        health.failed_samples += 1;
+      }
+      if partial {
+        health.partial_samples += 1;
+      }
+    }
+    if let Some(progress) = self.progress() {
+      progress.sample_finished(sample, succeeded, partial);
+    }
+  }
+
+  #[tracing::instrument(level = "trace", skip(self, experiment, on_ready), err)]
   fn run_experiment(
     self: Arc<Self>,
-    program: &PathBuf,
-    args: &[String],
-    env: Option<HashMap<String, String>>,
+    experiment: &ExperimentContext,
     experiment_directory: &Path,
     deep_trace: bool,
-  ) -> Result<(), std::io::Error> {
+    fault: Option<&Fault>,
+    on_ready: &dyn Fn() -> Result<bool, std::io::Error>,
+  ) -> Result<bool, std::io::Error> {
+    let program = &experiment.program;
     let is_sgx = program.as_os_str() == "gramine-sgx";
 
     // skip sgx to speed development on non sgx machine
     if is_sgx && env::var_os("EB_SKIP_SGX").is_some_and(|v| v == "1") {
-      return Ok(());
+      return Ok(true);
     }
 
-    let mut cmd = Command::new(program);
-    let cmd = cmd.args(args).stdout(Stdio::piped()).stderr(Stdio::piped());
+    // own process group, so that a Ctrl-C on the terminal reaches only the benchmark, which
+    // decides whether the workload completes
+    let mut cmd = match &experiment.systemd_scope {
+      Some(scope) => {
+        let mut cmd = Command::new("systemd-run");
+        cmd.args(scope.systemd_run_args()).arg(program);
+        cmd
+      }
+      None => Command::new(program),
+    };
+    let cmd = cmd
+      .args(&experiment.args)
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .process_group(0);
 
-    if let Some(env) = env {
+    if let Some(env) = &experiment.env {
       cmd.envs(env);
     }
+    // Gramine writes its profile (sgx-perf*.data) to its working directory, so that of an enclave
+    // is its sample directory; the paths of the manifest and of its mounts are absolute
+    if is_sgx {
+      cmd.current_dir(experiment_directory);
+    }
+    if let Some(namespace) = &experiment.network {
+      namespace.enter_on_spawn(cmd);
+    }
+    if !experiment.cpu_affinity.is_empty() {
+      pin_on_spawn(cmd, &experiment.cpu_affinity);
+    }
+    if experiment.nice.is_some() || experiment.sched_policy.is_some() {
+      schedule_on_spawn(cmd, experiment.nice, experiment.sched_policy);
+    }
+    if experiment.disable_aslr {
+      disable_aslr_on_spawn(cmd);
+    }
+    if let Some(group) = &experiment.cache_group {
+      group.assign_on_spawn(cmd);
+    }
+    // the encrypted storage of an enclave is next to its sample directories
+    let encrypted_directory = experiment_directory
+      .parent()
+      .unwrap_or(experiment_directory)
+      .join(StorageType::Encrypted.to_string());
+    // an enclave writes its metrics to a directory of the experiment mounted by the manifest; they
+    // are moved to the sample directory afterwards
+    let metrics_file = experiment_directory.join(WORKLOAD_METRICS_FILE);
+    let enclave_metrics_file = if is_sgx {
+      let directory = experiment_directory
+        .parent()
+        .unwrap_or(experiment_directory)
+        .join(WORKLOAD_METRICS_DIRECTORY);
+      create_dir_all(&directory)?;
+      let file = directory.join(WORKLOAD_METRICS_FILE);
+      match fs::remove_file(&file) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+      }
+      Some(file)
+    } else {
+      cmd.env(METRICS_FILE_ENV, &metrics_file);
+      None
+    };
+    // the phases file of an enclave is next to its metrics file, in the mounted directory
+    let phases_file = match &enclave_metrics_file {
+      Some(file) => file.with_file_name(PHASES_FILE),
+      None => {
+        let file = experiment_directory.join(PHASES_FILE);
+        cmd.env(PHASES_FILE_ENV, &file);
+        file
+      }
+    };
+    File::create(&phases_file)?;
 
+    let hugepages = HugePageStats::load();
     let child = cmd.spawn();
 
     match child {
       Ok(child) => {
-        let metrics = self.collect_metrics(child, is_sgx, deep_trace);
+        let reference = ClockReference::now();
+        self.workload.store(child.id() as i32, Ordering::Relaxed);
+        let fault = fault.map(|fault| {
+          FaultInjector::new(fault.clone(), child.id() as i32, reference.monotonic_ns)
+        });
+        let checkpoint = self.checkpoint_interval.map(|interval| {
+          Arc::new(Checkpoint {
+            directory: experiment_directory.to_path_buf(),
+            interval,
+            reference: self.relative_timestamps.then_some(reference),
+            packages_energy_uj: Mutex::new(BTreeMap::new()),
+            peak_pss_kb: AtomicU64::new(0),
+            peak_uss_kb: AtomicU64::new(0),
+          })
+        });
+        // the affinity is set before the workload is executed (see `pin_on_spawn`)
+        let workload_cpus = affinity(child.id() as libc::pid_t).unwrap_or_default();
+        let phases_done = AtomicBool::new(false);
+        let (mut metrics, phases, first_protected_access_ns) = thread::scope(|s| {
+          let phases = s.spawn(|| watch_phases(&phases_file, &phases_done));
+          let first_access = s.spawn(|| {
+            is_sgx
+              .then(|| watch_first_access(&encrypted_directory, &phases_done))
+              .flatten()
+          });
+          let metrics = self.clone().collect_metrics(
+            child,
+            reference.monotonic_ns,
+            is_sgx,
+            deep_trace,
+            experiment.sample_interval,
+            checkpoint,
+            experiment.probes.clone(),
+            fault,
+            experiment.sample_duration,
+            experiment.perf_events.clone(),
+            experiment
+              .cache_group
+              .as_ref()
+              .map(|group| group.path().to_path_buf()),
+            experiment
+              .ready_check
+              .as_ref()
+              .map(|check| (check, experiment.network.as_ref(), on_ready)),
+          );
+          phases_done.store(true, Ordering::Relaxed);
+          (
+            metrics,
+            phases.join().unwrap(),
+            first_access.join().unwrap(),
+          )
+        });
+        metrics.first_protected_access_ns = first_protected_access_ns;
+        fs::remove_file(&phases_file)?;
+        self.workload.store(0, Ordering::Relaxed);
+        metrics.phase_durations_ns =
+          phase_durations(&phases, metrics.exit_ns.unwrap_or_else(monotonic_ns));
+        metrics.phases = phases;
+        metrics.socket_energy.workload_packages = cpu_packages(&workload_cpus);
+        metrics.hugepages = HugePageStats::load()
+          .zip(hugepages)
+          .map(|(after, before)| after.since(&before));
+        let start_ns = reference.monotonic_ns + metrics.ready_after_ns.unwrap_or_default();
+        metrics.runtime_ns = metrics
+          .exit_ns
+          .map(|exit_ns| exit_ns.saturating_sub(start_ns));
+        if let Some(rates) = metrics.rates.as_mut() {
+          rates.duration_ns = metrics.runtime_ns.unwrap_or_default();
+        }
+        metrics.workload_metrics = extract_workload_metrics(
+          &experiment.metric_patterns,
+          &metrics.stdout,
+          &metrics.stderr,
+        );
+        if let Some(file) = enclave_metrics_file.filter(|file| file.is_file()) {
+          fs::rename(file, &metrics_file)?;
+        }
+        // metrics written by the workload override the ones of the same name in its output
+        let written = load_workload_metrics(&metrics_file);
+        metrics
+          .workload_metrics
+          .retain(|metric| !written.iter().any(|w| w.name == metric.name));
+        metrics.workload_metrics.extend(written);
+        metrics.add_lifecycle_markers(reference.monotonic_ns);
+        if self.relative_timestamps {
+          metrics.normalize_timestamps(&reference);
+        }
+        let mut ocalls = Vec::new();
+        if is_sgx {
+          ocalls = OcallStats::with_times(
+            collect_sgx_profile_data(experiment_directory)?,
+            &metrics.timed_ocalls,
+          );
+          if let Some(sgx_stats) = metrics.sgx_stats.as_mut() {
+            sgx_stats.ocalls = ocalls.clone();
+          }
+        }
 
         save_perf_output(experiment_directory, &metrics.perf_output)?;
+        if !metrics.perf_scaling.is_empty() {
+          save_perf_scaling(experiment_directory, &metrics.perf_scaling)?;
+        }
+        if !metrics.phases.is_empty() {
+          save_phases(experiment_directory, &metrics.phases)?;
+        }
         save_stdout_stderr(experiment_directory, &metrics.stdout, &metrics.stderr)?;
         save_energy_data(experiment_directory, metrics.energy_stats.clone())?;
+        save_pressure_data(experiment_directory, &metrics.pressure_stats)?;
+        save_memory_data(experiment_directory, &metrics.memory_stats)?;
+        save_cpu_data(experiment_directory, &metrics.cpu_stats)?;
+        save_memory_bandwidth_data(experiment_directory, &metrics.memory_bandwidth)?;
+        save_cache_occupancy_data(experiment_directory, &metrics.cache_occupancy)?;
         save_io_metrics(experiment_directory, &metrics)?;
+        save_summary(experiment_directory, &metrics)?;
+        save_clock_reference(experiment_directory, &reference, self.relative_timestamps)?;
         if let Some(deep_stats) = metrics.deep_stats {
           save_deep_stats(experiment_directory, deep_stats)?;
         }
+        if !ocalls.is_empty() {
+          save_ocall_stats(experiment_directory, &ocalls)?;
+        }
+        if let Some(fault) = &metrics.fault {
+          save_fault(experiment_directory, fault)?;
+        }
+        if let Some(sgx_stats) = metrics
+          .sgx_stats
+          .as_ref()
+          .filter(|sgx_stats| !sgx_stats.parse_errors.is_empty())
+        {
+          save_sgx_stats_errors(experiment_directory, &sgx_stats.parse_errors)?;
+        }
+        if let Some((scaling, min_counted)) = experiment.perf_min_counted.and_then(|min_counted| {
+          metrics
+            .perf_scaling
+            .iter()
+            .find(|scaling| scaling.counted_percent < min_counted)
+            .map(|scaling| (scaling, min_counted))
+        }) {
+          mark_invalid(
+            experiment_directory,
+            &format!(
+              "perf event {} counted {:.2}% of the run time (min_counted {}%)",
+              scaling.event, scaling.counted_percent, min_counted
+            ),
+          )?;
+        }
+        // a sample interrupted by a stop request has no exit status and is not a failure, neither
+        // is a workload killed by its fault or terminated at the end of its duration
+        let killed = metrics
+          .fault
+          .as_ref()
+          .is_some_and(|fault| fault.action == FaultAction::Kill);
+        Ok(
+          killed || metrics.terminated || metrics.exit_status.is_none_or(|status| status.success()),
+        )
+      }
+      Err(e) => {
+        error!("cannot start child process {}", e);
+        Ok(false)
       }
-      Err(e) => error!("cannot start child process {}", e),
     }
-    Ok(())
   }
 
   #[tracing::instrument(level = "trace", skip(self), err)]
@@ -169,76 +973,381 @@ impl DefaultCollector {
       program,
       args,
       post_run,
+      setup,
+      teardown,
+      on_fail,
+      hooks_must_succeed,
+      hook_timeout,
       env,
+      energy_sample_interval,
+      uprobes,
+      usdt,
+      network,
+      netem,
+      fault,
+      sample_duration,
+      metrics,
+      perf_events,
+      systemd_scope,
+      cpu_affinity,
+      transparent_hugepages,
+      nice,
+      sched_policy,
+      ready_check,
+      disable_aslr,
+      auxiliary_executables,
+      cache_mask,
+      key_provisioning,
+      ..
     }: ExperimentConfig,
   ) -> Result<(), Box<dyn std::error::Error>> {
     let me = self.clone();
-    for n in 1..me.clone().sample_size + 1 {
-      if self.stop.clone().load(Ordering::Relaxed) {
-        break;
+    let sample_interval = energy_sample_interval.unwrap_or(self.energy_sample_interval);
+    // the eBPF program recognizes the connections of the enclaves to the key provisioning server
+    // by its addresses
+    let provisioning = key_provisioning
+      .map(|provisioning| {
+        ProvisioningServer::resolve(&provisioning.server).map_err(|e| {
+          format!(
+            "cannot resolve the key provisioning server {}: {}",
+            provisioning.server, e
+          )
+        })
+      })
+      .transpose()?;
+    // a stripped loader has no symbols for the OCALL handlers, whose time is then missing
+    let ocall_loader = (program.as_os_str() == "gramine-sgx")
+      .then(|| {
+        let loader = gramine_loader();
+        if loader.is_none() {
+          warn!("cannot find the loader of gramine-sgx; OCALLs are not timed");
+        }
+        loader
+      })
+      .flatten();
+    let probes = WorkloadProbes {
+      uprobes,
+      usdt,
+      trace_children: !auxiliary_executables.is_empty(),
+      provisioning,
+      ocall_loader,
+    };
+    let perf_min_counted = perf_events.min_counted;
+    let perf_events = self.perf_events_for(&perf_events);
+
+    // setup and teardown run once per experiment, pre_run and post_run around every sample
+    create_dir_all(&output_path)?;
+    let _transparent_hugepages = transparent_hugepages
+      .map(|mode| {
+        TransparentHugePageMode::set(mode)
+          .map_err(|e| format!("cannot set transparent huge pages to {}: {}", mode, e))
+      })
+      .transpose()?;
+    // the samples of the experiment share a control group of resctrl
+    let cache_group = cache_mask
+      .map(|mask| {
+        CacheGroup::create(&format!("eb-{}", std::process::id()), mask)
+          .map_err(|e| format!("cannot allocate the cache ways {:#x}: {}", mask, e))
+      })
+      .transpose()?;
+    // a priority the user is not allowed to set would fail every sample
+    if nice.is_some() || sched_policy.is_some() {
+      let mut cmd = Command::new("true");
+      schedule_on_spawn(&mut cmd, nice, sched_policy);
+      cmd.status().map_err(|e| {
+        format!(
+          "cannot run the workload with nice {:?} and sched_policy {}: {}",
+          nice,
+          sched_policy.map_or_else(|| String::from("-"), |policy| policy.to_string()),
+          e
+        )
+      })?;
+    }
+    let namespace = NetworkNamespace::create(&network)?;
+    // hooks reaching the workload on a veth pair need its address
+    let hook_env = namespace
+      .as_ref()
+      .map(NetworkNamespace::hook_env)
+      .unwrap_or_default();
+    let hook = |name: &str,
+                hook: &Option<(PathBuf, Vec<String>)>,
+                extra_args: &[String],
+                directory: &Path|
+     -> Result<bool, std::io::Error> {
+      let outcome = run_hook(name, hook, extra_args, &hook_env, directory, hook_timeout)?;
+      if outcome == HookOutcome::TimedOut {
+        self.health.lock().unwrap().timed_out_hooks += 1;
       }
-      let experiment_path = output_path.join(PathBuf::from(n.to_string()));
-      create_dir_all(&experiment_path)?;
+      Ok(outcome == HookOutcome::Succeeded)
+    };
+    let shaped = netem
+      .as_ref()
+      .map(|netem| Shaping::interfaces(netem, namespace.as_ref()))
+      .transpose()?;
+    let shape = || {
+      netem
+        .as_ref()
+        .zip(shaped.as_ref())
+        .map(|(netem, interfaces)| Shaping::apply(netem, interfaces))
+        .transpose()
+    };
+    let experiment = ExperimentContext {
+      program,
+      args,
+      env,
+      sample_interval,
+      probes,
+      network: namespace,
+      sample_duration,
+      metric_patterns: metrics,
+      perf_events,
+      perf_min_counted,
+      systemd_scope,
+      cpu_affinity,
+      nice,
+      sched_policy,
+      disable_aslr,
+      cache_group,
+      ready_check,
+    };
+    if !hook("setup", &setup, &[], &output_path)? && hooks_must_succeed {
+      return Err(format!("setup hook failed for {:?}", output_path).into());
+    }
 
-      let span = tracing::span!(tracing::Level::TRACE, "iteration", iteration = n);
-      let _enter = span.enter();
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+      // the sample after a faulted one measures the recovery of the workload
+      let mut faulted = None;
+      for n in 1..me.clone().sample_size + 1 {
+        if self.stop.clone().load(Ordering::Relaxed) {
+          break;
+        }
+        // samples are written to a staging directory renamed into place once complete, so that
+        // a crash never leaves a half-written sample behind
+        let sample_path = output_path.join(PathBuf::from(n.to_string()));
+        let experiment_path = create_staging_directory(&sample_path)?;
 
-      if let Some((cmd, args)) = &pre_run {
-        run_command_with_args(cmd, args)?;
-      }
+        let span = tracing::span!(tracing::Level::TRACE, "iteration", iteration = n);
+        let _enter = span.enter();
 
-      me.clone().run_experiment(
-        &program,
-        &args,
-        env.clone(),
-        experiment_path.as_path(),
-        false,
-      )?;
+        // shaping covers the hooks too, which often start the clients of the workload
+        let shaping = shape()?;
+        let pre_run_hook = || -> Result<bool, std::io::Error> {
+          if !hook("pre_run", &pre_run, &[], &experiment_path)? && hooks_must_succeed {
+            // the workload would run on a wrong state
+            mark_invalid(&experiment_path, "pre_run hook failed")?;
+            return Ok(false);
+          }
+          Ok(true)
+        };
+        // with a ready check, the clients are started once the workload is ready
+        if experiment.ready_check.is_none() && !pre_run_hook()? {
+          publish_sample(&experiment_path, &sample_path)?;
+          self.record_sample(&sample_path, Some(n), true);
+          continue;
+        }
+
+        let sample_fault = fault.as_ref().filter(|fault| n % fault.every == 0);
+        let succeeded = me.clone().run_experiment(
+          &experiment,
+          experiment_path.as_path(),
+          false,
+          sample_fault,
+          &pre_run_hook,
+        )?;
+        if self.interrupt.load(Ordering::Relaxed) {
+          mark_invalid(&experiment_path, "interrupted by the user")?;
+        }
+        if let Some(faulted) = faulted.take() {
+          mark_recovery(&experiment_path, faulted)?;
+        }
+        // the workload may exit before its fault is due
+        if sample_fault.is_some() && experiment_path.join("fault").is_file() {
+          faulted = Some(n);
+        }
+        if !succeeded {
+          let sample_directory = experiment_path.to_string_lossy().into_owned();
+          hook("on_fail", &on_fail, &[sample_directory], &experiment_path)?;
+        }
+
+        if !hook("post_run", &post_run, &[], &experiment_path)? && hooks_must_succeed {
+          mark_invalid(&experiment_path, "post_run hook failed")?;
+        }
+        drop(shaping);
+        publish_sample(&experiment_path, &sample_path)?;
+        self.record_sample(&sample_path, Some(n), succeeded);
+      }
 
-      if let Some((cmd, args)) = &post_run {
-        run_command_with_args(cmd, args)?;
+      if self.deep_trace && !self.stop.clone().load(Ordering::Relaxed) {
+        let span = tracing::span!(tracing::Level::TRACE, "deep_trace");
+        let _enter = span.enter();
+        let sample_path = output_path.join(PathBuf::from("deep-trace"));
+        let experiment_path = create_staging_directory(&sample_path)?;
+        let _shaping = shape()?;
+        let succeeded =
+          me.clone()
+            .run_experiment(&experiment, experiment_path.as_path(), true, None, &|| {
+              Ok(true)
+            })?;
+        if self.interrupt.load(Ordering::Relaxed) {
+          mark_invalid(&experiment_path, "interrupted by the user")?;
+        }
+        if !succeeded {
+          let sample_directory = experiment_path.to_string_lossy().into_owned();
+          hook("on_fail", &on_fail, &[sample_directory], &experiment_path)?;
+        }
+        publish_sample(&experiment_path, &sample_path)?;
+        self.record_sample(&sample_path, None, succeeded);
       }
-    }
+      Ok(())
+    })();
 
-    if self.deep_trace && !self.stop.clone().load(Ordering::Relaxed) {
-      let span = tracing::span!(tracing::Level::TRACE, "deep_trace");
-      let _enter = span.enter();
-      let experiment_path = output_path.join(PathBuf::from("deep-trace"));
-      create_dir_all(&experiment_path)?;
-      me.clone()
-        .run_experiment(&program, &args, env, experiment_path.as_path(), true)?;
+    if !hook("teardown", &teardown, &[], &output_path)? && hooks_must_succeed && result.is_ok() {
+      return Err(format!("teardown hook failed for {:?}", output_path).into());
     }
-    Ok(())
+    result
   }
 
-  fn collect_metrics(self: Arc<Self>, child: Child, is_sgx: bool, deep_trace: bool) -> Metrics {
+  #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+  fn collect_metrics(
+    self: Arc<Self>,
+    child: Child,
+    spawn_ns: u64,
+    is_sgx: bool,
+    deep_trace: bool,
+    sample_interval: Duration,
+    checkpoint: Option<Arc<Checkpoint>>,
+    probes: WorkloadProbes,
+    fault: Option<FaultInjector>,
+    sample_duration: Option<Duration>,
+    perf_events: Vec<String>,
+    cache_group: Option<PathBuf>,
+    ready: Option<(
+      &ReadyCheck,
+      Option<&NetworkNamespace>,
+      &dyn Fn() -> Result<bool, std::io::Error>,
+    )>,
+  ) -> Metrics {
     let pid = child.id();
     let stop = Arc::new(AtomicBool::new(false));
+    // with a ready check, measurement starts once the workload is ready
+    let start_ns = Arc::new(AtomicU64::new(if ready.is_some() { 0 } else { spawn_ns }));
+    let ready_ns = Arc::new(AtomicU64::new(0));
+
+    // the output is read from the spawn, for log ready checks
+    let wait_child_handle = {
+      let me = self.clone();
+      let stop = stop.clone();
+      let start_ns = start_ns.clone();
+      let log_ready = match ready {
+        Some((
+          ReadyCheck {
+            probe: ReadyProbe::Log(pattern),
+            ..
+          },
+          ..,
+        )) => Some((pattern.clone(), ready_ns.clone())),
+        _ => None,
+      };
+      thread::spawn(move || me.wait_for_child(child, &stop, sample_duration, &start_ns, log_ready))
+    };
+    let mut ready_after_ns = None;
+    if let Some((check, namespace, on_ready)) = ready {
+      let started = self.wait_until_ready(check, namespace, &ready_ns, || {
+        wait_child_handle.is_finished()
+      }) && on_ready().unwrap_or_else(|e| {
+        error!("cannot start the measurement: {}", e);
+        false
+      });
+      if started {
+        let now = monotonic_ns();
+        start_ns.store(now, Ordering::Relaxed);
+        ready_after_ns = Some(now.saturating_sub(spawn_ns));
+      } else if !wait_child_handle.is_finished() {
+        // the sample is not measured
+        unsafe {
+          libc::kill(-(pid as i32), libc::SIGKILL);
+        }
+      }
+    }
 
     let perf_handle = {
       let me = self.clone();
-      thread::spawn(move || me.run_perf(pid))
+      thread::spawn(move || me.run_perf(pid, &perf_events))
     };
 
     let energy_handle = {
       let me = self.clone();
       let stop = stop.clone();
-      thread::spawn(move || me.monitor_energy_consumption(&stop))
+      let checkpoint = checkpoint.clone();
+      thread::spawn(move || {
+        me.monitor_energy_consumption(&stop, sample_interval, checkpoint.as_deref())
+      })
     };
 
-    let tracing_handle = {
+    let pressure_handle = {
       let me = self.clone();
       let stop = stop.clone();
-      thread::spawn(move || me.trace_program(pid, &stop, is_sgx, deep_trace))
+      let checkpoint = checkpoint.clone();
+      thread::spawn(move || me.monitor_pressure(&stop, sample_interval, checkpoint.as_deref()))
     };
 
-    let wait_child_handle = {
+    let memory_handle = {
+      let me = self.clone();
+      let stop = stop.clone();
+      let checkpoint = checkpoint.clone();
+      thread::spawn(move || me.monitor_memory(pid, &stop, sample_interval, checkpoint.as_deref()))
+    };
+
+    let cpu_handle = {
+      let me = self.clone();
+      let stop = stop.clone();
+      let checkpoint = checkpoint.clone();
+      thread::spawn(move || me.monitor_cpu(&stop, sample_interval, checkpoint.as_deref()))
+    };
+
+    let bandwidth_handle = {
+      let me = self.clone();
+      let stop = stop.clone();
+      let cache_group = cache_group.clone();
+      thread::spawn(move || {
+        me.monitor_memory_bandwidth(pid, &stop, sample_interval, cache_group.as_deref())
+      })
+    };
+
+    let occupancy_handle = {
+      let me = self.clone();
+      let stop = stop.clone();
+      thread::spawn(move || {
+        me.monitor_cache_occupancy(cache_group.as_deref(), &stop, sample_interval)
+      })
+    };
+
+    let tracing_handle = {
       let me = self.clone();
       let stop = stop.clone();
-      thread::spawn(move || me.wait_for_child(child, &stop))
+      let checkpoint = checkpoint.clone();
+      thread::spawn(move || {
+        me.trace_program(
+          pid,
+          &stop,
+          is_sgx,
+          deep_trace,
+          &probes,
+          checkpoint.as_deref(),
+          fault,
+        )
+      })
     };
 
-    let (stdout, stderr) = wait_child_handle.join().unwrap();
+    let ChildOutput {
+      stdout,
+      stderr,
+      proc_io,
+      exit_status,
+      first_output_ns,
+      exit_ns,
+      terminated,
+    } = wait_child_handle.join().unwrap();
     trace!("target process joined");
 
     let trace_result = tracing_handle.join().unwrap();
@@ -247,33 +1356,174 @@ impl DefaultCollector {
     let energy_stats = energy_handle.join().unwrap();
     trace!("energy thread joined");
 
-    let perf_output = perf_handle.join().unwrap();
-    trace!("perf thread joined");
+    let pressure_stats = pressure_handle.join().unwrap();
+    trace!("pressure thread joined");
 
-    let disk_stats = process_disk_stats(&self.partitions, trace_result.disk_counters);
-    let (sys_write_count, sys_write_avg, sys_read_count, sys_read_avg) =
-      process_mem_stats(trace_result.mem_counters);
+    let memory_stats = memory_handle.join().unwrap();
+    trace!("memory thread joined");
 
-    let sgx_stats = trace_result
+    let cpu_stats = cpu_handle.join().unwrap();
+    trace!("cpu thread joined");
+
+    let memory_bandwidth = bandwidth_handle.join().unwrap();
+    trace!("memory bandwidth thread joined");
+
+    let cache_occupancy = occupancy_handle.join().unwrap();
+    trace!("cache occupancy thread joined");
+
+    let perf_output = perf_handle.join().unwrap();
+    trace!("perf thread joined");
+
+    let disk_stats = process_disk_stats(&self.partitions, trace_result.disk_counters);
+    let syscall_stats = process_mem_stats(trace_result.mem_counters);
+
+    let sgx_stats = trace_result
       .sgx_counters
-      .map(|sgx_counters| get_sgx_stats(&stderr, sgx_counters));
+      .map(|sgx_counters| get_sgx_stats(&stderr, sgx_counters, trace_result.enclave_build_traced));
+
+    // totals include the samples already written by checkpoints
+    let mut packages_uj = packages_energy_uj(&energy_stats);
+    if let Some(checkpoint) = &checkpoint {
+      for (package, energy_uj) in checkpoint.packages_energy_uj.lock().unwrap().iter() {
+        *packages_uj.entry(*package).or_default() += energy_uj;
+      }
+    }
+    let package_energy = (!packages_uj.is_empty()).then(|| packages_uj.values().sum());
+    let checkpointed_peak =
+      |peak: Option<&AtomicU64>| peak.map(|p| p.load(Ordering::Relaxed)).filter(|&p| p > 0);
+    let peak_pss_kb = memory_stats
+      .iter()
+      .map(|s| s.pss_kb)
+      .chain(checkpointed_peak(
+        checkpoint.as_ref().map(|c| &c.peak_pss_kb),
+      ))
+      .max();
+    let peak_uss_kb = memory_stats
+      .iter()
+      .map(|s| s.uss_kb)
+      .chain(checkpointed_peak(
+        checkpoint.as_ref().map(|c| &c.peak_uss_kb),
+      ))
+      .max();
+    let efficiency = get_efficiency_stats(package_energy, &perf_output, &disk_stats);
+    // the duration is known once the runtime is
+    let rates = sample_duration.map(|_| RateStats {
+      duration_ns: 0,
+      energy_uj: package_energy,
+      instructions: extract_perf_counter(&perf_output, "instructions"),
+      read_bytes: disk_stats.iter().map(|d| d.read_bytes).sum(),
+      write_bytes: disk_stats.iter().map(|d| d.write_bytes).sum(),
+      syscalls: syscall_stats.iter().map(|s| (s.name, s.count)).collect(),
+    });
+    let carbon = self.carbon_intensity_g_per_kwh.zip(package_energy).map(
+      |(carbon_intensity_g_per_kwh, energy_uj)| CarbonEstimate {
+        energy_uj,
+        carbon_intensity_g_per_kwh,
+      },
+    );
+
+    let perf_scaling = PerfScaling::from_perf_output(&perf_output);
+    let multiplexed: Vec<&str> = perf_scaling
+      .iter()
+      .filter(|scaling| scaling.multiplexed())
+      .map(|scaling| scaling.event.as_str())
+      .collect();
+    if !multiplexed.is_empty() {
+      warn!(
+        "perf events multiplexed, their counts are scaled: {}",
+        multiplexed.join(",")
+      );
+    }
 
     Metrics {
       stdout,
       stderr,
       perf_output,
+      perf_scaling,
       energy_stats,
+      pressure_stats,
+      memory_stats,
+      cpu_stats,
+      memory_bandwidth,
+      cache_occupancy,
+      peak_pss_kb,
+      peak_uss_kb,
+      hugepages: None,
       disk_stats,
       sgx_stats,
-      sys_read_avg,
-      sys_write_avg,
-      sys_read_count,
-      sys_write_count,
+      probe_stats: trace_result.probe_stats,
+      timed_ocalls: trace_result.timed_ocalls,
+      proc_io,
+      exit_status,
+      first_output_ns,
+      exit_ns,
+      runtime_ns: None,
+      ready_after_ns,
+      syscall_stats,
+      vfs_counters: trace_result.vfs_counters,
       deep_stats: trace_result.deep_stats,
+      efficiency,
+      socket_energy: SocketEnergy {
+        packages_uj,
+        workload_packages: BTreeSet::new(),
+      },
+      carbon,
+      fault: trace_result.fault,
+      terminated,
+      rates,
+      workload_metrics: Vec::new(),
+      phases: Vec::new(),
+      phase_durations_ns: BTreeMap::new(),
+      key_provisioning: trace_result.key_provisioning,
+      first_protected_access_ns: None,
+      spawn_ns,
+    }
+  }
+
+  /// Returns the perf events counted for a task: the events of the collector without the ones
+  /// removed by the task, the groups of the perf profile, the events the task adds and its groups
+  /// (`{a,b}`). An event of a group is only counted in it; the groups of the task take precedence
+  /// and those of the profile lose the events not counted.
+  fn perf_events_for(&self, changes: &PerfEvents) -> Vec<String> {
+    let grouped = |event: &String| changes.groups.iter().any(|group| group.contains(event));
+    let counted = |event: &String| {
+      self.perf_events.contains(event) && !changes.remove.contains(event) && !grouped(event)
+    };
+    let profile_groups: Vec<Vec<String>> = self
+      .perf_profile
+      .groups()
+      .iter()
+      .map(|group| {
+        group
+          .iter()
+          .map(|event| event.to_string())
+          .filter(counted)
+          .collect()
+      })
+      .filter(|group: &Vec<String>| group.len() > 1)
+      .collect();
+    let profile_grouped = |event: &String| profile_groups.iter().any(|g| g.contains(event));
+    let mut events: Vec<String> = self
+      .perf_events
+      .iter()
+      .filter(|event| counted(event) && !profile_grouped(event))
+      .cloned()
+      .collect();
+    for group in &profile_groups {
+      events.push(format!("{{{}}}", group.join(",")));
+    }
+    for event in &changes.add {
+      if !events.contains(event) && !grouped(event) && !profile_grouped(event) {
+        events.push(event.clone());
+      }
+    }
+    for group in &changes.groups {
+      events.push(format!("{{{}}}", group.join(",")));
     }
+    events
   }
 
-  fn run_perf(&self, pid: u32) -> Vec<u8> {
+  fn run_perf(&self, pid: u32, events: &[String]) -> Vec<u8> {
     let mut perf_output = Vec::new();
     let mut perf_cmd = Command::new("perf");
     perf_cmd
@@ -281,11 +1531,13 @@ impl DefaultCollector {
       .arg("--field-separator")
       .arg(",")
       .arg("--event")
-      .arg(self.perf_events.join(","))
+      .arg(events.join(","))
       .arg("--pid")
       .arg(pid.to_string())
       .stdout(Stdio::piped())
-      .stderr(Stdio::piped());
+      .stderr(Stdio::piped())
+      // a Ctrl-C on the terminal would stop the counters before the workload exits
+      .process_group(0);
 
     match perf_cmd.output() {
       Ok(output) => {
@@ -308,53 +1560,376 @@ impl DefaultCollector {
     perf_output
   }
 
-  fn monitor_energy_consumption(&self, stop: &AtomicBool) -> HashMap<String, Vec<EnergySample>> {
+  fn monitor_energy_consumption(
+    &self,
+    stop: &AtomicBool,
+    sample_interval: Duration,
+    checkpoint: Option<&Checkpoint>,
+  ) -> HashMap<String, Vec<EnergySample>> {
     let mut measures: HashMap<String, Vec<EnergySample>> = HashMap::new();
+    let mut last_checkpoint = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+      for counter in &self.energy_counters {
+        let name = &counter.name;
+        if let Some(energy_uj) = counter.read() {
+          // take the timestamps right after the read: sleep and file reads add jitter so the
+          // actual elapsed time (on the monotonic clock) is used to compute power instead of the
+          // sampling interval
+          let monotonic = monotonic_ns();
+          let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+          let samples = measures.entry(name.to_owned()).or_default();
+          let sample = EnergySample::after(
+            samples.last(),
+            timestamp,
+            monotonic,
+            energy_uj,
+            counter.range_uj,
+            sample_interval,
+          );
+          if sample.gap {
+            trace!(
+              "energy monitor stalled: {} read {:?} after the previous reading",
+              name,
+              Duration::from_nanos(monotonic - samples.last().unwrap().monotonic_ns)
+            );
+          }
+          samples.push(sample);
+        }
+      }
+      if let Some(checkpoint) = checkpoint.filter(|c| c.due(&mut last_checkpoint)) {
+        if let Err(e) = checkpoint.energy(&mut measures) {
+          error!("cannot checkpoint energy samples: {}", e);
+        }
+      }
+      thread::sleep(sample_interval);
+    }
+    measures
+  }
+
+  // samples Pressure Stall Information: https://docs.kernel.org/accounting/psi.html
+  fn monitor_pressure(
+    &self,
+    stop: &AtomicBool,
+    sample_interval: Duration,
+    checkpoint: Option<&Checkpoint>,
+  ) -> Vec<PressureSample> {
+    let mut measures = Vec::new();
+    if !Path::new("/proc/pressure").is_dir() {
+      warn!("system does not support PSI; skipping");
+      return measures;
+    }
+    let mut last_checkpoint = Instant::now();
     while !stop.load(Ordering::Relaxed) {
       let timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_nanos();
-      for (name, rapl_path) in &self.rapl_paths {
-        if let Ok(energy_str) = fs::read_to_string(rapl_path) {
-          // Parse the energy value as a number (assumes the file contains a numeric value)
-          if let Ok(energy_uj) = energy_str.trim().parse::<u64>() {
-            measures
-              .entry(name.to_owned())
-              .or_default()
-              .push(EnergySample {
-                timestamp,
-                energy_uj,
-              });
+      for resource in PSI_RESOURCES {
+        if let Ok(content) = fs::read_to_string(format!("/proc/pressure/{}", resource)) {
+          if let Some(sample) = PressureSample::from_str(timestamp, resource, &content) {
+            measures.push(sample);
           }
         }
       }
-      thread::sleep(self.energy_sample_interval);
+      if let Some(checkpoint) = checkpoint.filter(|c| c.due(&mut last_checkpoint)) {
+        if let Err(e) = checkpoint.pressure(&mut measures) {
+          error!("cannot checkpoint pressure samples: {}", e);
+        }
+      }
+      thread::sleep(sample_interval);
     }
     measures
   }
-  #[allow(clippy::type_complexity)]
+
+  // samples PSS and USS of the whole process tree so that shared libraries mapped by
+  // more processes are not counted multiple times
+  fn monitor_memory(
+    &self,
+    pid: u32,
+    stop: &AtomicBool,
+    sample_interval: Duration,
+    checkpoint: Option<&Checkpoint>,
+  ) -> Vec<MemorySample> {
+    let mut measures = Vec::new();
+    let mut last_checkpoint = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+      let mut sample = MemorySample {
+        timestamp: SystemTime::now()
+          .duration_since(SystemTime::UNIX_EPOCH)
+          .unwrap()
+          .as_nanos(),
+        ..Default::default()
+      };
+      for p in process_tree(pid) {
+        if let Ok(content) = fs::read_to_string(format!("/proc/{}/smaps_rollup", p)) {
+          sample.add_smaps_rollup(&content);
+        }
+      }
+      if sample.processes > 0 {
+        measures.push(sample);
+      }
+      if let Some(checkpoint) = checkpoint.filter(|c| c.due(&mut last_checkpoint)) {
+        if let Err(e) = checkpoint.memory(&mut measures) {
+          error!("cannot checkpoint memory samples: {}", e);
+        }
+      }
+      thread::sleep(sample_interval);
+    }
+    measures
+  }
+
+  // samples the utilization of every CPU of the host, so that energy readings can be related to
+  // the actual load and interference from other processes can be detected
+  fn monitor_cpu(
+    &self,
+    stop: &AtomicBool,
+    sample_interval: Duration,
+    checkpoint: Option<&Checkpoint>,
+  ) -> Vec<CpuSample> {
+    let mut measures = Vec::new();
+    let mut previous = Vec::new();
+    let mut last_checkpoint = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+      let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+      if let Ok(content) = fs::read_to_string("/proc/stat") {
+        let current = CpuTimes::from_proc_stat(&content);
+        for (previous, current) in previous.iter().zip(&current) {
+          measures.extend(CpuSample::between(timestamp, previous, current));
+        }
+        previous = current;
+      }
+      if let Some(checkpoint) = checkpoint.filter(|c| c.due(&mut last_checkpoint)) {
+        if let Err(e) = checkpoint.cpu(&mut measures) {
+          error!("cannot checkpoint cpu samples: {}", e);
+        }
+      }
+      thread::sleep(sample_interval);
+    }
+    measures
+  }
+
+  // samples the memory traffic of the workload; its threads are moved to the monitoring group of
+  // resctrl as they appear, while the memory controllers are counted for the whole host
+  fn monitor_memory_bandwidth(
+    &self,
+    pid: u32,
+    stop: &AtomicBool,
+    sample_interval: Duration,
+    cache_group: Option<&Path>,
+  ) -> Vec<BandwidthSample> {
+    let mut measures = Vec::new();
+    let Some(mut monitor) = self.memory_bandwidth.and_then(|source| {
+      BandwidthMonitor::start(source, &format!("eb-{}", pid), sample_interval, cache_group)
+    }) else {
+      return measures;
+    };
+    while !stop.load(Ordering::Relaxed) {
+      let tasks: Vec<u32> = process_tree(pid)
+        .into_iter()
+        .flat_map(|p| {
+          fs::read_dir(format!("/proc/{}/task", p))
+            .into_iter()
+            .flatten()
+        })
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+      measures.extend(monitor.sample(&tasks));
+      thread::sleep(sample_interval);
+    }
+    measures.extend(monitor.stop());
+    measures
+  }
+
+  // samples the L3 occupancy of the cache allocation group of the workload, where the host
+  // monitors it
+  fn monitor_cache_occupancy(
+    &self,
+    cache_group: Option<&Path>,
+    stop: &AtomicBool,
+    sample_interval: Duration,
+  ) -> Vec<CacheOccupancySample> {
+    let mut measures = Vec::new();
+    let Some(group) = cache_group else {
+      return measures;
+    };
+    while !stop.load(Ordering::Relaxed) {
+      measures.extend(read_llc_occupancy(group));
+      thread::sleep(sample_interval);
+    }
+    measures
+  }
+
+  #[allow(clippy::type_complexity, clippy::too_many_arguments)]
   fn trace_program(
     &self,
     pid: u32,
     stop: &AtomicBool,
     is_sgx: bool,
     deep_trace: bool,
+    probes: &WorkloadProbes,
+    checkpoint: Option<&Checkpoint>,
+    mut fault: Option<FaultInjector>,
   ) -> TraceResult {
     let skel_builder = TracerSkelBuilder::default();
     let mut open_object = MaybeUninit::uninit();
-    let open_skel = skel_builder
+    let mut open_skel = skel_builder
       .open(&mut open_object)
       .expect("cannot open ebpf program");
     open_skel.maps.rodata_data.targ_pid = pid as i32;
     open_skel.maps.rodata_data.deep_trace = deep_trace;
+    open_skel.maps.rodata_data.trace_children = probes.trace_children;
+    open_skel.maps.rodata_data.filter_dev = !self.disk_devices.is_empty();
+    // counting every system call is not free; the program is loaded only when it injects a fault
+    match fault.as_ref().map(|injector| &injector.fault) {
+      Some(Fault {
+        trigger: FaultTrigger::Syscalls(syscalls),
+        action,
+        ..
+      }) => {
+        open_skel.maps.rodata_data.fault_after_syscalls = *syscalls;
+        open_skel.maps.rodata_data.fault_signal = action.signal();
+      }
+      _ => open_skel.progs.count_fault_syscalls.set_autoload(false),
+    }
+    match &probes.provisioning {
+      Some(server) => open_skel.maps.rodata_data.provision_port = server.port,
+      None => open_skel.progs.trace_provisioning.set_autoload(false),
+    }
+    // the enclave build kprobes are attached below, only to SGX workloads
+    if !is_sgx {
+      open_skel.progs.sgx_eadd_enter.set_autoload(false);
+      open_skel.progs.sgx_eadd_exit.set_autoload(false);
+      open_skel.progs.sgx_einit_enter.set_autoload(false);
+      open_skel.progs.sgx_einit_exit.set_autoload(false);
+    }
     trace!(
       "attaching ebpf program on target process with pid {}",
       pid as i32
     );
     let mut prog = open_skel.load().expect("cannot load ebpf program");
+    for dev in &self.disk_devices {
+      prog
+        .maps
+        .disk_filter
+        .update(&dev.to_ne_bytes(), &[1], MapFlags::ANY)
+        .expect("cannot update disk filter");
+    }
+    for address in probes
+      .provisioning
+      .iter()
+      .flat_map(|server| &server.addresses)
+    {
+      prog
+        .maps
+        .provision_addrs
+        .update(address, &[1], MapFlags::ANY)
+        .expect("cannot update provisioning server addresses");
+    }
     prog.attach().expect("cannot attach program");
 
+    // the SGX driver of some kernels does not export the functions building an enclave; their
+    // counters are then missing instead of zero
+    let enclave_build_links = is_sgx
+      .then(|| {
+        let progs = &mut prog.progs;
+        [
+          progs
+            .sgx_eadd_enter
+            .attach_kprobe(false, "sgx_encl_add_page"),
+          progs.sgx_eadd_exit.attach_kprobe(true, "sgx_encl_add_page"),
+          progs.sgx_einit_enter.attach_kprobe(false, "sgx_encl_init"),
+          progs.sgx_einit_exit.attach_kprobe(true, "sgx_encl_init"),
+        ]
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| warn!("cannot attach the enclave build kprobes: {}", e))
+        .ok()
+      })
+      .flatten();
+
+    // the generic kprobe programs are attached to every function of `extra_kprobes`
+    let mut extra_links = Vec::new();
+    let mut probed = Vec::new();
+    for symbol in &self.extra_kprobes {
+      let links = prog
+        .progs
+        .extra_kprobe_enter
+        .attach_kprobe(false, symbol)
+        .and_then(|enter| {
+          let exit = prog.progs.extra_kprobe_exit.attach_kprobe(true, symbol)?;
+          Ok((enter, exit))
+        });
+      match links {
+        Ok(links) => {
+          extra_links.push(links);
+          probed.push(symbol.clone());
+        }
+        Err(e) => warn!("cannot attach kprobe to {}: {}", symbol, e),
+      }
+    }
+    // uprobes are told apart by their attach cookie, the index in the task; deep trace events
+    // have room for MAX_UPROBES of them
+    let mut attach_uprobe = |binary: &Path, symbol: &str, cookie: u32| {
+      let opts = |retprobe| UprobeOpts {
+        func_name: symbol.to_owned(),
+        retprobe,
+        cookie: cookie as u64,
+        ..Default::default()
+      };
+      prog
+        .progs
+        .uprobe_enter
+        .attach_uprobe_with_opts(pid as i32, binary, 0, opts(false))
+        .and_then(|enter| {
+          let exit =
+            prog
+              .progs
+              .uprobe_exit
+              .attach_uprobe_with_opts(pid as i32, binary, 0, opts(true))?;
+          Ok((enter, exit))
+        })
+    };
+    let mut traced = Vec::new();
+    for (index, uprobe) in probes.uprobes.iter().enumerate().take(MAX_UPROBES as usize) {
+      let links = attach_uprobe(&uprobe.binary, &uprobe.symbol, index as u32);
+      match links {
+        Ok(links) => {
+          extra_links.push(links);
+          traced.push((index as u32, uprobe.symbol.clone()));
+        }
+        Err(e) => warn!(
+          "cannot attach uprobe to {} in {:?}: {}",
+          uprobe.symbol, uprobe.binary, e
+        ),
+      }
+    }
+    // the OCALL handlers get the cookies from MAX_UPROBES on
+    let mut timed_handlers = Vec::new();
+    if let Some(loader) = &probes.ocall_loader {
+      for (index, ocall) in GRAMINE_OCALLS.iter().enumerate() {
+        let cookie = MAX_UPROBES + index as u32;
+        match attach_uprobe(loader, &format!("sgx_ocall_{}", ocall), cookie) {
+          Ok(links) => {
+            extra_links.push(links);
+            timed_handlers.push((cookie, *ocall));
+          }
+          Err(e) => trace!("cannot time the {} OCALL: {}", ocall, e),
+        }
+      }
+      if timed_handlers.is_empty() {
+        warn!(
+          "no OCALL handler found in {:?} (stripped?); OCALLs are not timed",
+          loader
+        );
+      }
+    }
+
     let mut maybe_ring_buffer = if deep_trace {
       let mut ring_buffer = RingBufferBuilder::new();
       let result = Arc::new(Mutex::new(Vec::new()));
@@ -375,22 +1950,49 @@ impl DefaultCollector {
     };
 
     // wait for target program to end
+    let mut last_checkpoint = Instant::now();
     while !stop.load(Ordering::Relaxed) {
-      if let Some((ref mut rb, _)) = maybe_ring_buffer {
-        rb.poll(Duration::from_millis(250))
+      if let Some((ref mut rb, ref events)) = maybe_ring_buffer {
+        rb.poll(CHILD_POLL_INTERVAL)
           .expect("cannot poll from ring buffer");
+        if let Some(checkpoint) = checkpoint.filter(|c| c.due(&mut last_checkpoint)) {
+          let mut events = std::mem::take(&mut *events.lock().unwrap());
+          if let Err(e) = checkpoint.deep_trace(&mut events) {
+            error!("cannot checkpoint deep trace events: {}", e);
+          }
+        }
       } else {
-        thread::sleep(Duration::from_secs(1));
+        thread::sleep(CHILD_POLL_INTERVAL);
+      }
+      if let Some(injector) = fault.as_mut() {
+        let traced_ns = prog
+          .maps
+          .fault_stats
+          .lookup(&0_i32.to_ne_bytes(), MapFlags::ANY)
+          .ok()
+          .flatten()
+          .map(|val_bytes| {
+            // Safety: fault_state is the value type of fault_stats.
+            unsafe { std::ptr::read_unaligned(val_bytes.as_ptr() as *const fault_state) }
+          })
+          .map(|state| state.injected_ns)
+          .filter(|&injected_ns| injected_ns > 0);
+        injector.poll(monotonic_ns(), traced_ns);
       }
     }
 
-    let mem_counters = get_map_result::<u32, io_counter>(
+    let mem_counters = get_map_result::<u32, latency_hist>(
       &prog.maps.agg_map,
       Some(&|key, value| {
         trace!(
           "got {} {} operations; average duration {}ns",
           value.count,
-          if *key == 0 { "write" } else { "read" },
+          match key {
+            0 => "write",
+            1 => "read",
+            2 => "fsync",
+            _ => "fdatasync",
+          },
           value.total_duration.checked_div(value.count).unwrap_or(0)
         );
       }),
@@ -417,6 +2019,74 @@ impl DefaultCollector {
       }),
     );
 
+    // USDT probes only mark events in the deep trace
+    let mut usdt_links = Vec::new();
+    if deep_trace {
+      for (index, usdt) in probes.usdt.iter().enumerate().take(256) {
+        let opts = UsdtOpts {
+          cookie: index as u64,
+          ..Default::default()
+        };
+        match prog.progs.usdt_event.attach_usdt_with_opts(
+          pid as i32,
+          &usdt.binary,
+          &usdt.provider,
+          &usdt.name,
+          opts,
+        ) {
+          Ok(link) => usdt_links.push(link),
+          Err(e) => warn!(
+            "cannot attach USDT probe {}:{} in {:?}: {}",
+            usdt.provider, usdt.name, usdt.binary, e
+          ),
+        }
+      }
+    }
+
+    let mut probe_stats = if probed.is_empty() {
+      Vec::new()
+    } else {
+      let counters = get_map_result::<u64, io_counter>(&prog.maps.extra_kprobe_stats, None);
+      let kallsyms = fs::read_to_string("/proc/kallsyms").unwrap_or_default();
+      resolve_kprobe_stats(&probed, &kallsyms, &counters)
+    };
+    let uprobe_counters: HashMap<u32, io_counter> =
+      if traced.is_empty() && timed_handlers.is_empty() {
+        HashMap::new()
+      } else {
+        get_map_result::<u32, io_counter>(&prog.maps.uprobe_stats, None)
+          .into_iter()
+          .collect()
+      };
+    let timed_ocalls = timed_handlers
+      .into_iter()
+      .map(|(cookie, ocall)| {
+        let counter = uprobe_counters.get(&cookie).copied().unwrap_or_default();
+        OcallStats {
+          name: format!("ocall_{}", ocall),
+          count: counter.count,
+          share: 0.0,
+          time_ns: Some(counter.total_duration),
+        }
+      })
+      .collect();
+    if !traced.is_empty() {
+      let counters = &uprobe_counters;
+      probe_stats.extend(traced.into_iter().map(|(index, symbol)| {
+        let counter = counters.get(&index).copied().unwrap_or_default();
+        ProbeStats {
+          kind: "uprobe",
+          symbol,
+          count: counter.count,
+          total_ns: counter.total_duration,
+        }
+      }));
+    }
+    drop(extra_links);
+    drop(usdt_links);
+    let enclave_build_traced = enclave_build_links.is_some();
+    drop(enclave_build_links);
+
     let key_bytes = 0_i32.to_ne_bytes();
 
     let sgx_counters = if is_sgx {
@@ -435,6 +2105,29 @@ impl DefaultCollector {
       None
     };
 
+    let vfs_counters = prog
+      .maps
+      .vfs_stats
+      .lookup(&key_bytes, MapFlags::ANY)
+      .ok()
+      .flatten()
+      .map(|val_bytes| {
+        // Safety: VfsCounters mirrors the layout of struct vfs_counters.
+        unsafe { std::ptr::read_unaligned(val_bytes.as_ptr() as *const VfsCounters) }
+      })
+      .unwrap_or_default();
+
+    let key_provisioning = prog
+      .maps
+      .provisioning
+      .lookup(&key_bytes, MapFlags::ANY)
+      .ok()
+      .flatten()
+      .and_then(|val_bytes| {
+        // Safety: TracedSession mirrors the layout of struct provisioning_session.
+        unsafe { std::ptr::read_unaligned(val_bytes.as_ptr() as *const TracedSession) }.session()
+      });
+
     // need to copy because there are problems when extracting from Arc<Mutex<T>>
     let deep_stats = match maybe_ring_buffer {
       Some((_, stats)) => {
@@ -447,19 +2140,147 @@ impl DefaultCollector {
     TraceResult {
       disk_counters,
       sgx_counters,
+      enclave_build_traced,
+      key_provisioning,
+      vfs_counters,
+      probe_stats,
+      timed_ocalls,
+      fault: fault.and_then(|injector| injector.report()),
       mem_counters,
       deep_stats,
     }
   }
 
-  fn wait_for_child(self: Arc<Self>, child: Child, finished: &AtomicBool) -> (Vec<u8>, Vec<u8>) {
-    let mut stdout = Vec::new();
-    let mut stderr = Vec::new();
+  /// Waits until the workload passes `check`, exits, is not ready within `check.timeout` or the
+  /// run is interrupted. Returns whether it is ready.
+  fn wait_until_ready(
+    &self,
+    check: &ReadyCheck,
+    namespace: Option<&NetworkNamespace>,
+    ready_ns: &AtomicU64,
+    exited: impl Fn() -> bool,
+  ) -> bool {
+    let deadline = Instant::now() + check.timeout;
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+      if let ReadyProbe::Tcp { host, port } = &check.probe {
+        scope.spawn(|| probe_tcp(host, *port, namespace, ready_ns, &done));
+      }
+      let ready = loop {
+        if ready_ns.load(Ordering::Relaxed) > 0 {
+          break true;
+        }
+        if exited() {
+          warn!("workload exited before passing its ready check");
+          break false;
+        }
+        if self.interrupt.load(Ordering::Relaxed) {
+          break false;
+        }
+        if Instant::now() >= deadline {
+          warn!(
+            "workload not ready after {}; killing it",
+            check.timeout.human_format()
+          );
+          break false;
+        }
+        thread::sleep(CHILD_POLL_INTERVAL);
+      };
+      done.store(true, Ordering::Relaxed);
+      ready
+    })
+  }
+
+  fn wait_for_child(
+    self: Arc<Self>,
+    mut child: Child,
+    finished: &AtomicBool,
+    sample_duration: Option<Duration>,
+    start_ns: &AtomicU64,
+    log_ready: Option<(Regex, Arc<AtomicU64>)>,
+  ) -> ChildOutput {
+    let mut proc_io = None;
+    let mut exit_status = None;
+    let mut exit_ns = None;
+    let pid = child.id();
+    // the duration starts with the measurement
+    let deadline_ns = |duration: Duration| {
+      let start_ns = start_ns.load(Ordering::Relaxed);
+      (start_ns > 0).then(|| start_ns + duration.as_nanos() as u64)
+    };
+    let mut terminated = None;
+
+    // the output is read while the program runs to timestamp its first output and to match the
+    // pattern of a log ready check
+    let first_output_ns = Arc::new(AtomicU64::new(0));
+    let stdout_reader = child.stdout.take().map(|pipe| {
+      let first_output_ns = first_output_ns.clone();
+      let log_ready = log_ready.clone();
+      thread::spawn(move || {
+        read_output(
+          pipe,
+          Some(&first_output_ns),
+          log_ready
+            .as_ref()
+            .map(|(pattern, ready_ns)| (pattern, ready_ns.as_ref())),
+        )
+      })
+    });
+    let stderr_reader = child.stderr.take().map(|pipe| {
+      thread::spawn(move || {
+        read_output(
+          pipe,
+          None,
+          log_ready
+            .as_ref()
+            .map(|(pattern, ready_ns)| (pattern, ready_ns.as_ref())),
+        )
+      })
+    });
     let child = Mutex::new(child);
 
-    let stop = self.stop.clone();
-    while !stop.load(Ordering::Relaxed) {
+    // a pidfd becomes readable as soon as the child exits, so short programs are not rounded up
+    // to the polling interval; kernels older than 5.3 fall back to polling
+    let pidfd = pidfd_open(pid);
+    let interrupt = self.interrupt.clone();
+    while !interrupt.load(Ordering::Relaxed) {
+      // an exited child stays a zombie until try_wait reaps it, so /proc/<pid>/io
+      // can still be read to get the final values
+      let exited = match &pidfd {
+        Some(pidfd) => wait_readable(pidfd, CHILD_POLL_INTERVAL),
+        None => peek_exited(pid).unwrap_or(true),
+      };
+      if let Some(io) = ProcIo::load(pid) {
+        proc_io = Some(io);
+      }
+      if !exited {
+        // fixed-duration samples end gracefully, unless the workload ignores the SIGTERM
+        let now = Instant::now();
+        match terminated {
+          None
+            if sample_duration
+              .and_then(deadline_ns)
+              .is_some_and(|deadline_ns| monotonic_ns() >= deadline_ns) =>
+          {
+            trace!("sample duration elapsed; terminating the target process");
+            unsafe {
+              libc::kill(-(pid as i32), libc::SIGTERM);
+            }
+            terminated = Some(now);
+          }
+          Some(at) if now.duration_since(at) >= TERMINATION_GRACE => {
+            warn!("target process still running after SIGTERM; killing it");
+            break;
+          }
+          _ => {}
+        }
+        if pidfd.is_none() {
+          thread::sleep(CHILD_POLL_INTERVAL);
+        }
+        continue;
+      }
       if let Ok(Some(status)) = child.lock().unwrap().try_wait() {
+        exit_ns = Some(monotonic_ns());
         if !status.success() {
           warn!(
             "child process exited with non-zero code {}",
@@ -468,40 +2289,95 @@ impl DefaultCollector {
               .map_or("unknown".to_string(), |c| c.to_string())
           );
         }
+        exit_status = Some(status);
         break;
       }
 
-      thread::sleep(Duration::from_secs(1));
+      thread::sleep(CHILD_POLL_INTERVAL);
     }
     let mut child = child.lock().unwrap();
-    if let Some(mut stdout_pipe) = child.stdout.take() {
-      let _ = std::io::copy(&mut stdout_pipe, &mut stdout);
-    }
-    if let Some(mut stderr_pipe) = child.stderr.take() {
-      let _ = std::io::copy(&mut stderr_pipe, &mut stderr);
+    if exit_status.is_none() {
+      // the workload may have spawned other processes
+      unsafe {
+        libc::kill(-(pid as i32), libc::SIGKILL);
+      }
     }
     if let Err(e) = child.kill() {
       error!("cannot kill child process with pid {}: {}", child.id(), e);
     }
+    let stdout = stdout_reader
+      .map(|reader| reader.join().unwrap_or_default())
+      .unwrap_or_default();
+    let stderr = stderr_reader
+      .map(|reader| reader.join().unwrap_or_default())
+      .unwrap_or_default();
 
     finished.store(true, Ordering::Relaxed);
-    (stdout, stderr)
+    let first_output_ns = first_output_ns.load(Ordering::Relaxed);
+    ChildOutput {
+      stdout,
+      stderr,
+      proc_io,
+      exit_status,
+      first_output_ns: (first_output_ns > 0).then_some(first_output_ns),
+      exit_ns,
+      terminated: terminated.is_some(),
+    }
+  }
+
+  /// Returns the collector settings which change the content of a sample directory.
+  pub fn settings(&self) -> CollectorSettings<'_> {
+    let mut perf_events: Vec<&str> = self.perf_events.iter().map(String::as_str).collect();
+    perf_events.sort_unstable();
+    CollectorSettings {
+      sample_size: self.sample_size,
+      deep_trace: self.deep_trace,
+      perf_profile: self.perf_profile,
+      perf_events,
+      energy_source: self.energy_source,
+      energy_sample_interval: self.energy_sample_interval,
+      memory_bandwidth: self.memory_bandwidth,
+      carbon_intensity_g_per_kwh: self.carbon_intensity_g_per_kwh,
+      relative_timestamps: self.relative_timestamps,
+      checkpoint_interval: self.checkpoint_interval,
+      extra_kprobes: &self.extra_kprobes,
+      disk_devices: &self.disk_devices,
+    }
   }
 
+  /// Completes the running sample and does not start any other.
   pub fn stop(self: Arc<Self>) {
     self.clone().stop.store(true, Ordering::Relaxed);
   }
+
+  /// Kills the running workload; the partial results of the sample are saved and the sample is
+  /// marked as invalid.
+  pub fn interrupt(self: Arc<Self>) {
+    self.stop.store(true, Ordering::Relaxed);
+    self.interrupt.store(true, Ordering::Relaxed);
+  }
+
+  /// Kills the process group of the running workload without waiting for anything; meant to be
+  /// called right before exiting the process.
+  pub fn abort(&self) {
+    let pgid = self.workload.load(Ordering::Relaxed);
+    if pgid > 0 {
+      unsafe {
+        libc::kill(-pgid, libc::SIGKILL);
+      }
+    }
+  }
 }
 
 impl Debug for DefaultCollector {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     write!(
             f,
-            "Default linux debug:\n perf_events={}\n rapl_paths={}\n sample_size={}\n energy_sample_interval={}",
+            "Default linux debug:\n perf_events={}\n energy_counters={}\n sample_size={}\n energy_sample_interval={}",
             self.perf_events.join(","),
-            self.rapl_paths
+            self.energy_counters
                 .iter()
-                .map(|(_, p)| p.to_str().unwrap().to_string())
+                .map(|counter| counter.path().to_string_lossy().into_owned())
                 .collect::<Vec<String>>()
                 .join(","),
             self.sample_size,
@@ -512,52 +2388,129 @@ impl Debug for DefaultCollector {
 
 mod utils {
   use std::{
-    collections::HashMap,
-    fs::{self, DirEntry, File},
-    io::{BufRead, Write},
+    collections::{BTreeMap, HashMap},
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufWriter, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::{
+      fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+      unix::process::CommandExt,
+    },
     path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime},
   };
 
+  use duration_str::HumanFormat;
   use libbpf_rs::{Map, MapCore, MapFlags};
   use plain::Plain;
+  use regex::Regex;
+  use serde::Serialize;
   use tracing::{trace, warn};
 
   use crate::{
     collector::{DiskStats, Partition, SGXStats},
-    constants::{ENERGY_CSV_HEADER, IO_CSV_HEADER, TRACE_CSV_HEADER},
-    stats::{EnergySample, ToCsv},
-    tracer::types::{disk_counter, io_counter},
+    common::{is_metric_name, is_metric_unit},
+    constants::{
+      CACHE_OCCUPANCY_CSV_HEADER, CPU_CSV_HEADER, ENERGY_CSV_HEADER, IO_CSV_HEADER,
+      MEMORY_BANDWIDTH_CSV_HEADER, MEMORY_CSV_HEADER, OCALL_CSV_HEADER, PERF_SCALING_CSV_HEADER,
+      PHASES_CSV_HEADER, PSI_CSV_HEADER, SUMMARY_CSV_HEADER, TRACE_CSV_HEADER,
+    },
+    network::NetworkNamespace,
+    stats::{
+      memory_traffic, monotonic_ns, peak_llc_occupancy, BandwidthSample, CacheOccupancySample,
+      ClockReference, CpuSample, EfficiencyStats, EnergySample, MemorySample, OcallStats,
+      PerfScaling, PhaseMarker, PressureSample, ToCsv, Unit,
+    },
+    system::cpu_set,
+    tracer::types::{disk_counter, io_counter, latency_hist},
   };
 
-  use super::{DeepTraceEvent, LowLevelSgxCounters};
+  use super::{
+    DeepTraceEvent, FaultAction, FaultReport, LowLevelSgxCounters, MetricPattern, ProbeStats,
+    SchedPolicy, SyscallStats, WorkloadMetric, CHILD_POLL_INTERVAL, PHASE_POLL_INTERVAL,
+    TCP_PROBE_TIMEOUT,
+  };
 
-  pub fn get_sgx_stats(stderr: &[u8], sgx_counters: LowLevelSgxCounters) -> SGXStats {
+  pub fn get_sgx_stats(
+    stderr: &[u8],
+    sgx_counters: LowLevelSgxCounters,
+    enclave_build_traced: bool,
+  ) -> SGXStats {
     let mut sgx_stats = extract_sgx_counters_from_stderr(stderr);
 
     sgx_stats.counters = sgx_counters;
+    sgx_stats.enclave_build_traced = enclave_build_traced;
 
     sgx_stats
   }
 
+  /// Parses the statistics printed by Gramine on exit with `sgx.enable_stats` (the totals of the
+  /// process come last, so the last value of a statistic is kept). Missing and malformed
+  /// statistics are recorded in `parse_errors` instead of failing the sample.
   fn extract_sgx_counters_from_stderr(stderr: &[u8]) -> SGXStats {
+    fn statistic(counters: &mut SGXStats, i: usize) -> &mut Option<u64> {
+      match i {
+        0 => &mut counters.eenter,
+        1 => &mut counters.eexit,
+        2 => &mut counters.aexit,
+        3 => &mut counters.sync_signals,
+        _ => &mut counters.async_signals,
+      }
+    }
+    const STATISTICS: [&str; 5] = [
+      "# of EENTERs:",
+      "# of EEXITs:",
+      "# of AEXs:",
+      "# of sync signals:",
+      "# of async signals:",
+    ];
     let mut counters = SGXStats::default();
-    for line in stderr.lines().map_while(Result::ok) {
-      if line.trim().starts_with("#") {
-        let parts = line.as_str().split_whitespace().collect::<Vec<&str>>();
-        match parts[2] {
-          "EENTERs:" => counters.eenter = parts[3].parse().unwrap(),
-          "EEXITs:" => counters.eexit = parts[3].parse().unwrap(),
-          "AEXs" => counters.aexit = parts[3].parse().unwrap(),
-          "sync" => counters.sync_signals = parts[4].parse().unwrap(),
-          "async" => counters.async_signals = parts[4].parse().unwrap(),
-          _ => {}
-        }
+    for (number, line) in String::from_utf8_lossy(stderr).lines().enumerate() {
+      let line = line.trim();
+      let Some(i) = STATISTICS.iter().position(|s| line.starts_with(s)) else {
+        continue;
+      };
+      let Ok(value) = line[STATISTICS[i].len()..].trim().parse::<u64>() else {
+        counters.parse_errors.push(format!(
+          "stderr line {}: malformed statistic {:?}",
+          number + 1,
+          line
+        ));
+        continue;
+      };
+      *statistic(&mut counters, i) = Some(value);
+    }
+    for (i, name) in STATISTICS.iter().enumerate() {
+      if statistic(&mut counters, i).is_none() {
+        counters
+          .parse_errors
+          .push(format!("stderr: no {:?} statistic", name));
       }
     }
     counters
   }
 
+  /// Flags a sample whose SGX statistics are incomplete (e.g. the enclave crashed) with an
+  /// `sgx-stats-errors` file listing the problems; its other metrics are kept.
+  pub fn save_sgx_stats_errors(
+    experiment_directory: &Path,
+    errors: &[String],
+  ) -> Result<(), std::io::Error> {
+    warn!(
+      "incomplete SGX statistics in {:?}: {}",
+      experiment_directory,
+      errors.join("; ")
+    );
+    fs::write(
+      experiment_directory.join("sgx-stats-errors"),
+      errors.join("\n") + "\n",
+    )
+  }
+
   pub fn process_disk_stats(
     partitions: &[Partition],
     disk_stats: Vec<(u32, disk_counter)>,
@@ -575,46 +2528,128 @@ mod utils {
           bytes: stats.bytes,
           perc_random: (stats.random * 100).checked_div(total).unwrap_or(0),
           perc_seq: (stats.sequential * 100).checked_div(total).unwrap_or(0),
+          read_requests: stats.read_requests,
+          read_bytes: stats.read_bytes,
+          write_requests: stats.write_requests,
+          write_bytes: stats.write_bytes,
+          flushes: stats.flushes,
         }
       })
       .collect::<Vec<DiskStats>>()
   }
 
-  pub fn process_mem_stats(mem_stats: Vec<(u32, io_counter)>) -> (u64, u64, u64, u64) {
-    let (mut sys_write_count, mut sys_write_avg) = (0, 0);
-    let (mut sys_read_count, mut sys_read_avg) = (0, 0);
-    for (op, stat) in mem_stats {
-      match op {
-        0 => {
-          sys_write_count = stat.count;
-          sys_write_avg = stat.total_duration.checked_div(stat.count).unwrap_or(0);
-        }
-        1 => {
-          sys_read_count = stat.count;
-          sys_read_avg = stat.total_duration.checked_div(stat.count).unwrap_or(0);
-        }
-        _ => panic!("unknown system call type expected 0 for READ and 1 for WRITE"),
-      }
+  /// Turns the latency histograms of the eBPF program into syscall statistics. Every traced
+  /// syscall is always reported, with zeros if the workload never called it.
+  pub fn process_mem_stats(mem_stats: Vec<(u32, latency_hist)>) -> Vec<SyscallStats> {
+    // keys are the SYSCALL_* ids of `src/bpf/tracer.h`
+    const SYSCALLS: [(u32, &str); 4] = [
+      (1, "sys_read"),
+      (0, "sys_write"),
+      (2, "sys_fsync"),
+      (3, "sys_fdatasync"),
+    ];
+    if let Some((op, _)) = mem_stats
+      .iter()
+      .find(|(op, _)| !SYSCALLS.iter().any(|(id, _)| id == op))
+    {
+      panic!("unknown system call type {}", op);
     }
-    (sys_write_count, sys_write_avg, sys_read_count, sys_read_avg)
+    SYSCALLS
+      .iter()
+      .map(|&(id, name)| {
+        let hist = mem_stats
+          .iter()
+          .find(|(op, _)| *op == id)
+          .map_or_else(latency_hist::default, |(_, hist)| *hist);
+        SyscallStats::from_histogram(name, hist.count, hist.total_duration, hist.max, &hist.slots)
+      })
+      .collect()
   }
 
-  pub fn extract_rapl_path(entry: &DirEntry) -> Option<(String, PathBuf)> {
-    if entry
-      .file_name()
-      .to_string_lossy()
-      .starts_with("intel-rapl:")
-      && entry.path().is_dir()
-    {
-      let component = fs::read_to_string(entry.path().join("name"))
-        .unwrap()
-        .trim()
-        .to_owned();
-      let energy_uj_path = entry.path().join("energy_uj");
-      Some((component, energy_uj_path))
-    } else {
-      None
+  /// Builds energy-efficiency metrics for a sample. Returns `None` if either the
+  /// package energy or the `instructions` perf counter is missing.
+  pub fn get_efficiency_stats(
+    package_energy_uj: Option<u64>,
+    perf_output: &[u8],
+    disk_stats: &[DiskStats],
+  ) -> Option<EfficiencyStats> {
+    let energy_uj = package_energy_uj?;
+    let instructions = extract_perf_counter(perf_output, "instructions")?;
+    Some(EfficiencyStats {
+      energy_uj,
+      instructions,
+      bytes_written: disk_stats.iter().map(|d| d.write_bytes).sum(),
+    })
+  }
+
+  // Returns the energy consumed by every top-level `package-N` RAPL domain, by package. Subdomains
+  // (core, uncore, dram) are already accounted in their package.
+  pub fn packages_energy_uj(
+    energy_stats: &HashMap<String, Vec<EnergySample>>,
+  ) -> BTreeMap<u32, u64> {
+    let mut packages = BTreeMap::new();
+    for (name, samples) in energy_stats {
+      let Some(package) = name
+        .strip_prefix("package-")
+        .and_then(|n| n.parse::<u32>().ok())
+      else {
+        continue;
+      };
+      // the energy counter wraps around at max_energy_range_uj: the interval where it happens is
+      // skipped if the range is unknown
+      let energy: u64 = samples
+        .windows(2)
+        .map(|w| EnergySample::energy_between(&w[0], w[1].energy_uj).unwrap_or_default())
+        .sum();
+      *packages.entry(package).or_default() += energy;
     }
+    packages
+  }
+
+  /// Counts `event` over `true` with `perf stat`. Fails with `NotFound` if perf is not installed.
+  pub fn probe_perf_event(event: &str) -> Result<bool, std::io::Error> {
+    let output = Command::new("perf")
+      .args([
+        "stat",
+        "--field-separator",
+        ",",
+        "--event",
+        event,
+        "--",
+        "true",
+      ])
+      .stdout(Stdio::null())
+      .output()?;
+    Ok(perf_event_supported(
+      output.status.success(),
+      &output.stderr,
+    ))
+  }
+
+  // an unknown event makes perf fail; a known event the PMU cannot count is reported as
+  // <not supported>
+  pub fn perf_event_supported(success: bool, perf_output: &[u8]) -> bool {
+    success
+      && !perf_output
+        .lines()
+        .map_while(Result::ok)
+        .any(|line| line.split(',').next() == Some("<not supported>"))
+  }
+
+  // perf stat with --field-separator produces lines like
+  // <counter>,<unit>,<event>,<runtime>,<perc_runtime>,<metric>,<unit_metric>
+  pub fn extract_perf_counter(perf_output: &[u8], event: &str) -> Option<u64> {
+    perf_output.lines().map_while(Result::ok).find_map(|line| {
+      let parts = line.split(',').collect::<Vec<&str>>();
+      if parts.len() < 3 {
+        return None;
+      }
+      let name = parts[2].split(':').next().unwrap_or_default();
+      if name != event {
+        return None;
+      }
+      parts[0].trim().parse::<u64>().ok()
+    })
   }
 
   #[allow(clippy::type_complexity)]
@@ -643,6 +2678,48 @@ mod utils {
     result
   }
 
+  /// Assigns the counters of the generic kprobe program, keyed by the address of the probed
+  /// function, to `symbols` using `/proc/kallsyms`. With IBT the reported address follows the
+  /// symbol by the size of the `endbr64` instruction.
+  pub fn resolve_kprobe_stats(
+    symbols: &[String],
+    kallsyms: &str,
+    counters: &[(u64, io_counter)],
+  ) -> Vec<ProbeStats> {
+    let mut addresses: HashMap<&str, Vec<u64>> = HashMap::new();
+    for line in kallsyms.lines() {
+      // "<address> <type> <name> [module]"
+      let mut fields = line.split_whitespace();
+      let (Some(address), Some(name)) = (fields.next(), fields.nth(1)) else {
+        continue;
+      };
+      if let (Some(symbol), Ok(address)) = (
+        symbols.iter().find(|s| *s == name),
+        u64::from_str_radix(address, 16),
+      ) {
+        addresses.entry(symbol).or_default().push(address);
+      }
+    }
+
+    symbols
+      .iter()
+      .map(|symbol| {
+        let starts = addresses
+          .get(symbol.as_str())
+          .map_or(&[][..], Vec::as_slice);
+        let calls = counters
+          .iter()
+          .filter(|(ip, _)| starts.iter().any(|&start| (start..start + 16).contains(ip)));
+        ProbeStats {
+          kind: "kprobe",
+          symbol: symbol.clone(),
+          count: calls.clone().map(|(_, c)| c.count).sum(),
+          total_ns: calls.map(|(_, c)| c.total_duration).sum(),
+        }
+      })
+      .collect()
+  }
+
   pub fn save_perf_output(
     experiment_directory: &Path,
     perf_output: &[u8],
@@ -650,6 +2727,98 @@ mod utils {
     std::fs::write(experiment_directory.join("perf.csv"), perf_output)
   }
 
+  /// Returns the SGX profiler output (`sgx-perf.data` or `sgx-perf-<pid>.data`) Gramine wrote to
+  /// the sample directory, its working directory.
+  pub fn sgx_profile_files(experiment_directory: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(experiment_directory)
+      .into_iter()
+      .flatten()
+      .flatten()
+      .map(|entry| entry.path())
+      .filter(|path| {
+        path.file_name().is_some_and(|name| {
+          let name = name.to_string_lossy();
+          name.starts_with("sgx-perf") && name.ends_with(".data")
+        })
+      })
+      .collect();
+    files.sort();
+    files
+  }
+
+  /// Writes a `perf report` of each SGX profiler output of the sample next to it and counts the
+  /// OCALLs by type in it. Returns no OCALL if profiling was disabled or `perf` is not available.
+  pub fn collect_sgx_profile_data(
+    experiment_directory: &Path,
+  ) -> Result<Vec<OcallStats>, std::io::Error> {
+    let mut ocalls = Vec::new();
+    for path in sgx_profile_files(experiment_directory) {
+      let name = path.file_name().unwrap_or_default().to_string_lossy();
+      match Command::new("perf")
+        .args([
+          "report",
+          "--stdio",
+          "--sort",
+          "dso,sym",
+          "-F",
+          "overhead,sample,dso,sym",
+          "-i",
+        ])
+        .arg(&path)
+        .stderr(Stdio::null())
+        .output()
+      {
+        Ok(output) if output.status.success() => {
+          let report = String::from_utf8_lossy(&output.stdout);
+          ocalls = OcallStats::merge(ocalls, OcallStats::from_perf_report(&report));
+          fs::write(path.with_extension("txt"), &output.stdout)?
+        }
+        Ok(output) => warn!(
+          "cannot summarize {}: perf report exited with {}",
+          name, output.status
+        ),
+        Err(e) => warn!("cannot summarize {}: {}", name, e),
+      }
+    }
+    Ok(ocalls)
+  }
+
+  /// Returns the untrusted loader run by `gramine-sgx`, found in `PATH`.
+  pub fn gramine_loader() -> Option<PathBuf> {
+    let script = env::split_paths(&env::var_os("PATH")?)
+      .map(|path| path.join("gramine-sgx"))
+      .find(|path| path.is_file())?;
+    parse_pal_cmd(&fs::read_to_string(script).ok()?)
+  }
+
+  /// Parses the loader (`PAL_CMD=<path>`) of a `gramine-<pal>` script.
+  pub fn parse_pal_cmd(script: &str) -> Option<PathBuf> {
+    script.lines().find_map(|line| {
+      let path = line.trim().strip_prefix("PAL_CMD=")?;
+      Some(PathBuf::from(path.trim_matches(['"', '\''])))
+    })
+  }
+
+  pub fn save_ocall_stats(
+    experiment_directory: &Path,
+    ocalls: &[OcallStats],
+  ) -> Result<(), std::io::Error> {
+    let mut file = File::create(experiment_directory.join("ocalls.csv"))?;
+    writeln!(file, "{}", OCALL_CSV_HEADER)?;
+    let csv_lines: Vec<String> = ocalls.iter().flat_map(|o| o.to_csv_rows()).collect();
+    file.write_all(csv_lines.join("\n").as_bytes())
+  }
+
+  pub fn save_perf_scaling(
+    experiment_directory: &Path,
+    perf_scaling: &[PerfScaling],
+  ) -> Result<(), std::io::Error> {
+    let mut file = File::create(experiment_directory.join("perf-scaling.csv"))?;
+    writeln!(file, "{}", PERF_SCALING_CSV_HEADER)?;
+    let csv_lines: Vec<String> = perf_scaling.iter().flat_map(|s| s.to_csv_rows()).collect();
+    file.write_all(csv_lines.join("\n").as_bytes())
+  }
+
   pub fn save_stdout_stderr(
     experiment_directory: &Path,
     stdout: &[u8],
@@ -659,13 +2828,34 @@ mod utils {
     std::fs::write(experiment_directory.join("stderr"), stderr)
   }
 
-  pub fn save_energy_data(
+  /// Saves the spawn time of the target process in both clocks as `clock.json`, so that time
+  /// series of different collectors can be aligned.
+  pub fn save_clock_reference(
     experiment_directory: &Path,
-    energy_stats: HashMap<String, Vec<EnergySample>>,
+    reference: &ClockReference,
+    relative_timestamps: bool,
   ) -> Result<(), std::io::Error> {
-    for (filename, samples) in energy_stats {
-      let mut file = File::create(experiment_directory.join(format!("{}.csv", filename)))?;
-      writeln!(file, "{}", ENERGY_CSV_HEADER)?;
+    let content = serde_json::json!({
+      // both clocks are JSON integers; UNIX time in nanoseconds fits in 64 bits until 2554
+      "realtime_ns": u64::try_from(reference.realtime_ns).unwrap_or(u64::MAX),
+      "monotonic_ns": reference.monotonic_ns,
+      "relative_timestamps": relative_timestamps,
+    });
+    fs::write(
+      experiment_directory.join("clock.json"),
+      serde_json::to_string_pretty(&content)?,
+    )
+  }
+
+  pub fn save_energy_data(
+    experiment_directory: &Path,
+    energy_stats: HashMap<String, Vec<EnergySample>>,
+  ) -> Result<(), std::io::Error> {
+    for (filename, samples) in energy_stats {
+      let mut file = open_csv(
+        &experiment_directory.join(format!("{}.csv", filename)),
+        ENERGY_CSV_HEADER,
+      )?;
       let csv_lines: Vec<String> = samples
         .iter()
         .flat_map(|sample| sample.to_csv_rows())
@@ -675,6 +2865,92 @@ mod utils {
     Ok(())
   }
 
+  pub fn save_pressure_data(
+    experiment_directory: &Path,
+    samples: &[PressureSample],
+  ) -> Result<(), std::io::Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    let mut file = open_csv(&experiment_directory.join("psi.csv"), PSI_CSV_HEADER)?;
+    let csv_lines: Vec<String> = samples
+      .iter()
+      .flat_map(|sample| sample.to_csv_rows())
+      .collect();
+    file.write_all(csv_lines.join("\n").as_bytes())?;
+    Ok(())
+  }
+
+  pub fn save_memory_data(
+    experiment_directory: &Path,
+    samples: &[MemorySample],
+  ) -> Result<(), std::io::Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    let mut file = open_csv(&experiment_directory.join("memory.csv"), MEMORY_CSV_HEADER)?;
+    let csv_lines: Vec<String> = samples
+      .iter()
+      .flat_map(|sample| sample.to_csv_rows())
+      .collect();
+    file.write_all(csv_lines.join("\n").as_bytes())?;
+    Ok(())
+  }
+
+  pub fn save_memory_bandwidth_data(
+    experiment_directory: &Path,
+    samples: &[BandwidthSample],
+  ) -> Result<(), std::io::Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    let mut file = open_csv(
+      &experiment_directory.join("memory-bandwidth.csv"),
+      MEMORY_BANDWIDTH_CSV_HEADER,
+    )?;
+    let csv_lines: Vec<String> = samples
+      .iter()
+      .flat_map(|sample| sample.to_csv_rows())
+      .collect();
+    file.write_all(csv_lines.join("\n").as_bytes())?;
+    Ok(())
+  }
+
+  pub fn save_cache_occupancy_data(
+    experiment_directory: &Path,
+    samples: &[CacheOccupancySample],
+  ) -> Result<(), std::io::Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    let mut file = open_csv(
+      &experiment_directory.join("cache-occupancy.csv"),
+      CACHE_OCCUPANCY_CSV_HEADER,
+    )?;
+    let csv_lines: Vec<String> = samples
+      .iter()
+      .flat_map(|sample| sample.to_csv_rows())
+      .collect();
+    file.write_all(csv_lines.join("\n").as_bytes())?;
+    Ok(())
+  }
+
+  pub fn save_cpu_data(
+    experiment_directory: &Path,
+    samples: &[CpuSample],
+  ) -> Result<(), std::io::Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    let mut file = open_csv(&experiment_directory.join("cpu.csv"), CPU_CSV_HEADER)?;
+    let csv_lines: Vec<String> = samples
+      .iter()
+      .flat_map(|sample| sample.to_csv_rows())
+      .collect();
+    file.write_all(csv_lines.join("\n").as_bytes())?;
+    Ok(())
+  }
+
   pub fn save_io_metrics(
     experiment_directory: &Path,
     metrics: &super::Metrics,
@@ -683,15 +2959,31 @@ mod utils {
     let mut file = File::create(&io_path)?;
     writeln!(file, "{}", IO_CSV_HEADER)?;
     if let Some(sgx) = &metrics.sgx_stats {
-      // Use the to_csv_rows method for the low-level SGX counters.
-      for row in sgx.counters.to_csv_rows() {
+      // statistics printed by Gramine, then the low-level SGX counters
+      for row in sgx.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    }
+    for stats in &metrics.syscall_stats {
+      for row in stats.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    }
+    for row in metrics.vfs_counters.to_csv_rows() {
+      writeln!(file, "{}", row)?;
+    }
+    for stats in &metrics.probe_stats {
+      for row in stats.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    }
+
+    // /proc/<pid>/io cross-checks the eBPF counters and includes buffered I/O
+    if let Some(proc_io) = &metrics.proc_io {
+      for row in proc_io.to_csv_rows() {
         writeln!(file, "{}", row)?;
       }
     }
-    writeln!(file, "sys_read,#,{},", metrics.sys_read_count)?;
-    writeln!(file, "sys_read,ns,{},", metrics.sys_read_avg)?;
-    writeln!(file, "sys_write,#,{},", metrics.sys_write_count)?;
-    writeln!(file, "sys_write,ns,{},", metrics.sys_write_avg)?;
 
     // Now use the DiskStats to_csv_rows method.
     for stats in &metrics.disk_stats {
@@ -702,13 +2994,149 @@ mod utils {
     Ok(())
   }
 
+  /// Writes the metrics of a sample to `summary.csv`; no file is written without any metric.
+  pub fn save_summary(
+    experiment_directory: &Path,
+    metrics: &super::Metrics,
+  ) -> Result<(), std::io::Error> {
+    // the rows are buffered to leave out a file with the header only
+    let mut file = Vec::new();
+    if let Some(efficiency) = &metrics.efficiency {
+      for row in efficiency.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    }
+    for row in metrics.socket_energy.to_csv_rows() {
+      writeln!(file, "{}", row)?;
+    }
+    if let Some(carbon) = &metrics.carbon {
+      for row in carbon.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    }
+    if let Some(min_counted) = metrics
+      .perf_scaling
+      .iter()
+      .map(|scaling| scaling.counted_percent)
+      .min_by(f64::total_cmp)
+    {
+      writeln!(
+        file,
+        "perf_min_counted,{},{:.2}",
+        Unit::Percent,
+        min_counted
+      )?;
+    }
+    if let Some(peak) = metrics.peak_pss_kb {
+      writeln!(file, "peak_pss,{},{}", Unit::Kilobytes, peak)?;
+    }
+    if let Some(peak) = metrics.peak_uss_kb {
+      writeln!(file, "peak_uss,{},{}", Unit::Kilobytes, peak)?;
+    }
+    if let Some(peak) = peak_llc_occupancy(&metrics.cache_occupancy) {
+      writeln!(file, "peak_llc_occupancy,{},{}", Unit::Bytes, peak)?;
+    }
+    if let Some(traffic) = memory_traffic(
+      &metrics.memory_bandwidth,
+      &metrics.socket_energy.workload_packages,
+    ) {
+      writeln!(file, "memory_traffic,{},{}", Unit::Bytes, traffic)?;
+      if let Some(runtime) = metrics.runtime_ns.filter(|&runtime| runtime > 0) {
+        writeln!(
+          file,
+          "memory_bandwidth,{},{:.0}",
+          Unit::BytesPerSecond,
+          traffic as f64 * 1e9 / runtime as f64
+        )?;
+      }
+    }
+    if let Some(hugepages) = &metrics.hugepages {
+      for row in hugepages.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    }
+    if let Some(fault) = &metrics.fault {
+      writeln!(
+        file,
+        "fault_injected_after,{},{}",
+        Unit::Nanoseconds,
+        fault.injected_after_ns
+      )?;
+      if let Some(stopped) = fault.stopped_ns {
+        writeln!(file, "fault_stopped,{},{}", Unit::Nanoseconds, stopped)?;
+      }
+    }
+    for metric in &metrics.workload_metrics {
+      for row in metric.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    }
+    for (phase, duration) in &metrics.phase_durations_ns {
+      writeln!(file, "phase_{},{},{}", phase, Unit::Nanoseconds, duration)?;
+    }
+    if let Some(rates) = &metrics.rates {
+      for row in rates.to_csv_rows() {
+        writeln!(file, "{}", row)?;
+      }
+    } else if let Some(runtime) = metrics.runtime_ns {
+      writeln!(file, "runtime,{},{}", Unit::Nanoseconds, runtime)?;
+      // the launch of an enclave waits on aesmd, which is not part of the enclave's work
+      if let Some(sgx) = &metrics.sgx_stats {
+        let aesm_wait = sgx.counters.aesm_wait_ns;
+        writeln!(file, "aesm_wait,{},{}", Unit::Nanoseconds, aesm_wait)?;
+        writeln!(
+          file,
+          "runtime_excluding_aesm,{},{}",
+          Unit::Nanoseconds,
+          runtime.saturating_sub(aesm_wait)
+        )?;
+      }
+    }
+    if let Some(ready_after) = metrics.ready_after_ns {
+      writeln!(file, "ready_after,{},{}", Unit::Nanoseconds, ready_after)?;
+    }
+    if let Some(session) = &metrics.key_provisioning {
+      writeln!(
+        file,
+        "key_provisioning_start,{},{}",
+        Unit::Nanoseconds,
+        session.connected_ns.saturating_sub(metrics.spawn_ns)
+      )?;
+      writeln!(
+        file,
+        "key_provisioning,{},{}",
+        Unit::Nanoseconds,
+        session.closed_ns.saturating_sub(session.connected_ns)
+      )?;
+      writeln!(
+        file,
+        "key_provisioning_traffic,{},{}",
+        Unit::Bytes,
+        session.bytes_sent + session.bytes_received
+      )?;
+    }
+    if let Some(access) = metrics.first_protected_access_ns {
+      writeln!(
+        file,
+        "first_protected_access,{},{}",
+        Unit::Nanoseconds,
+        access.saturating_sub(metrics.spawn_ns)
+      )?;
+    }
+    if file.is_empty() {
+      return Ok(());
+    }
+    let mut summary = File::create(experiment_directory.join("summary.csv"))?;
+    writeln!(summary, "{}", SUMMARY_CSV_HEADER)?;
+    summary.write_all(&file)
+  }
+
   pub fn save_deep_stats(
     experiment_directory: &Path,
     stats: Vec<DeepTraceEvent>,
   ) -> Result<(), std::io::Error> {
     let trace_path = experiment_directory.join("trace.csv");
-    let mut file = File::create(&trace_path)?;
-    writeln!(file, "{}", TRACE_CSV_HEADER)?;
+    let mut file = open_csv(&trace_path, TRACE_CSV_HEADER)?;
     // Use the new `to_csv_rows` method from our types.
     let csv_rows: Vec<String> = stats.iter().flat_map(|e| e.to_csv_rows()).collect();
 
@@ -716,56 +3144,1217 @@ mod utils {
     Ok(())
   }
 
-  pub fn run_command_with_args(cmd: &PathBuf, args: &[String]) -> Result<(), std::io::Error> {
-    let output = Command::new(cmd)
+  /// Returns the pid of a process and all of its descendants by scanning `/proc`.
+  pub fn process_tree(pid: u32) -> Vec<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    if let Ok(entries) = fs::read_dir("/proc") {
+      for entry in entries.flatten() {
+        let Ok(p) = entry.file_name().to_string_lossy().parse::<u32>() else {
+          continue;
+        };
+        // /proc/<pid>/stat looks like "<pid> (<comm>) <state> <ppid> ..." and comm may
+        // contain spaces or parenthesis
+        if let Ok(stat) = fs::read_to_string(entry.path().join("stat")) {
+          let ppid = stat
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+            .and_then(|v| v.parse::<u32>().ok());
+          if let Some(ppid) = ppid {
+            children.entry(ppid).or_default().push(p);
+          }
+        }
+      }
+    }
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while i < tree.len() {
+      if let Some(c) = children.get(&tree[i]) {
+        tree.extend(c);
+      }
+      i += 1;
+    }
+    tree
+  }
+
+  /// Opens a pidfd for `pid` (Linux 5.3+). Returns `None` if the kernel does not support it.
+  pub fn pidfd_open(pid: u32) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+      return None;
+    }
+    // Safety: the syscall returned a new file descriptor owned by the caller
+    Some(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+  }
+
+  /// Waits up to `timeout` for `fd` to become readable; a pidfd is readable once the process
+  /// has exited.
+  pub fn wait_readable(fd: &OwnedFd, timeout: Duration) -> bool {
+    let mut pollfd = libc::pollfd {
+      fd: fd.as_raw_fd(),
+      events: libc::POLLIN,
+      revents: 0,
+    };
+    let ret = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+    ret > 0 && pollfd.revents & libc::POLLIN != 0
+  }
+
+  /// Checks whether a child process has terminated without reaping it (`WNOWAIT`).
+  /// Returns `None` if the state cannot be determined.
+  pub fn peek_exited(pid: u32) -> Option<bool> {
+    // Safety: siginfo_t is a plain C struct and is only read after waitid succeeds.
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+      libc::waitid(
+        libc::P_PID,
+        pid as libc::id_t,
+        &mut info,
+        libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+      )
+    };
+    if ret != 0 {
+      return None;
+    }
+    // with WNOHANG si_pid is left to 0 when the child is still running
+    Some(unsafe { info.si_pid() } != 0)
+  }
+
+  /// Runs a hook (setup, pre_run, ...) with `extra_args` appended to its arguments and `env` added
+  /// to its environment, saving its
+  /// output as `<name>.stdout` and `<name>.stderr` in `directory`. A hook running longer than
+  /// `timeout` is killed together with its children. The exit code, duration and whether the hook
+  /// timed out are recorded in `hooks.json` in `directory`. A missing hook counts as successful.
+  pub fn run_hook(
+    name: &str,
+    hook: &Option<(PathBuf, Vec<String>)>,
+    extra_args: &[String],
+    env: &[(&str, String)],
+    directory: &Path,
+    timeout: Option<Duration>,
+  ) -> Result<HookOutcome, std::io::Error> {
+    let Some((cmd, args)) = hook else {
+      return Ok(HookOutcome::Succeeded);
+    };
+    let start = Instant::now();
+    // own process group, so that a timeout kills the processes spawned by the hook as well
+    let mut child = Command::new(cmd)
       .args(args)
+      .args(extra_args)
+      .envs(env.iter().map(|(key, value)| (key, value)))
       .stdout(Stdio::piped())
       .stderr(Stdio::piped())
-      .output()?;
+      .process_group(0)
+      .spawn()?;
+
+    let mut stdout_pipe = child.stdout.take().unwrap();
+    let mut stderr_pipe = child.stderr.take().unwrap();
+    let stdout_reader = thread::spawn(move || {
+      let mut buf = Vec::new();
+      let _ = stdout_pipe.read_to_end(&mut buf);
+      buf
+    });
+    let stderr_reader = thread::spawn(move || {
+      let mut buf = Vec::new();
+      let _ = stderr_pipe.read_to_end(&mut buf);
+      buf
+    });
+
+    let mut timed_out = false;
+    let status = loop {
+      if let Some(status) = child.try_wait()? {
+        break status;
+      }
+      if timeout.is_some_and(|t| start.elapsed() >= t) {
+        warn!(
+          "{} hook {:?} did not terminate within {}; killing it",
+          name,
+          cmd.to_string_lossy(),
+          timeout.unwrap().human_format()
+        );
+        unsafe {
+          libc::kill(-(child.id() as i32), libc::SIGKILL);
+        }
+        timed_out = true;
+        break child.wait()?;
+      }
+      thread::sleep(Duration::from_millis(10));
+    };
+    let duration = start.elapsed();
+
+    let stdout = stdout_reader.join().unwrap_or_default();
+    let stderr = stderr_reader.join().unwrap_or_default();
+    fs::write(directory.join(format!("{}.stdout", name)), stdout)?;
+    fs::write(directory.join(format!("{}.stderr", name)), stderr)?;
+    record_hook(
+      directory,
+      name,
+      HookRecord {
+        exit_code: status.code(),
+        duration_ms: duration.as_millis(),
+        timed_out,
+      },
+    )?;
 
-    if !output.status.success() {
-      let code = output
-        .status
+    if timed_out {
+      return Ok(HookOutcome::TimedOut);
+    }
+    if !status.success() {
+      let code = status
         .code()
         .map_or(String::from("unknown"), |c| c.to_string());
       warn!(
-        "command {:?} exited with status {}: {} {}",
+        "{} hook {:?} exited with status {}; output saved in {:?}",
+        name,
         cmd.to_string_lossy(),
         code,
-        String::from_utf8_lossy(&output.stdout),
-        String::from_utf8_lossy(&output.stderr)
+        directory
       );
-    } else {
-      trace!("command {:?} terminated with exit code 0", cmd)
+      return Ok(HookOutcome::Failed);
     }
-    Ok(())
+    trace!("{} hook {:?} terminated with exit code 0", name, cmd);
+    Ok(HookOutcome::Succeeded)
+  }
+
+  /// Outcome of a hook run by `run_hook`.
+  #[derive(Debug, Clone, Copy, PartialEq)]
+  pub enum HookOutcome {
+    Succeeded,
+    Failed,
+    TimedOut,
+  }
+
+  /// Outcome of a hook, stored in `hooks.json`.
+  #[derive(Serialize)]
+  struct HookRecord {
+    exit_code: Option<i32>,
+    duration_ms: u128,
+    timed_out: bool,
+  }
+
+  /// Adds the outcome of hook `name` to `hooks.json` in `directory`.
+  fn record_hook(directory: &Path, name: &str, record: HookRecord) -> Result<(), std::io::Error> {
+    let path = directory.join("hooks.json");
+    let mut hooks: serde_json::Map<String, serde_json::Value> = fs::read(&path)
+      .ok()
+      .and_then(|content| serde_json::from_slice(&content).ok())
+      .unwrap_or_default();
+    hooks.insert(name.to_string(), serde_json::to_value(record)?);
+    fs::write(path, serde_json::to_string_pretty(&hooks)?)
+  }
+
+  /// Opens a CSV file for appending, writing `header` if the file is new. Files can already hold
+  /// the rows appended by checkpoints.
+  pub fn open_csv(path: &Path, header: &str) -> Result<File, std::io::Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+      writeln!(file, "{}", header)?;
+    }
+    Ok(file)
+  }
+
+  /// Appends the rows of `samples` to a CSV file (see `open_csv`).
+  pub fn append_csv<T: ToCsv>(
+    path: &Path,
+    header: &str,
+    samples: &[T],
+  ) -> Result<(), std::io::Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    let mut file = BufWriter::new(open_csv(path, header)?);
+    for row in samples.iter().flat_map(|sample| sample.to_csv_rows()) {
+      writeln!(file, "{}", row)?;
+    }
+    file.flush()
+  }
+
+  /// Marks a sample as invalid; the aggregator skips such samples.
+  pub fn mark_invalid(experiment_directory: &Path, reason: &str) -> Result<(), std::io::Error> {
+    warn!("sample {:?} is invalid: {}", experiment_directory, reason);
+    fs::write(experiment_directory.join("invalid"), reason)
+  }
+
+  /// Extracts the metrics of `patterns` from the output of the workload: the first capture group
+  /// of the last match in stdout, or else in stderr. Thousands separators are ignored.
+  pub fn extract_workload_metrics(
+    patterns: &[MetricPattern],
+    stdout: &[u8],
+    stderr: &[u8],
+  ) -> Vec<WorkloadMetric> {
+    let stdout = String::from_utf8_lossy(stdout);
+    let stderr = String::from_utf8_lossy(stderr);
+    patterns
+      .iter()
+      .filter_map(|pattern| {
+        let capture = [&stdout, &stderr].into_iter().find_map(|output| {
+          pattern
+            .regex
+            .captures_iter(output)
+            .last()
+            .and_then(|captures| captures.get(1))
+        });
+        let Some(capture) = capture else {
+          warn!(
+            "metric {} not found in the output of the workload",
+            pattern.name
+          );
+          return None;
+        };
+        match capture.as_str().replace(',', "").trim().parse::<f64>() {
+          Ok(value) => Some(WorkloadMetric {
+            name: pattern.name.clone(),
+            unit: pattern.unit.clone(),
+            value,
+          }),
+          Err(e) => {
+            warn!(
+              "metric {} is not a number ({:?}): {}",
+              pattern.name,
+              capture.as_str(),
+              e
+            );
+            None
+          }
+        }
+      })
+      .collect()
+  }
+
+  /// Reads the metrics written by the workload to `path`, if any: a JSON object mapping metric
+  /// names to numbers, or to objects with a numeric `value` and a `unit`. Invalid entries are
+  /// skipped with a warning.
+  pub fn load_workload_metrics(path: &Path) -> Vec<WorkloadMetric> {
+    let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(e) => {
+        if e.kind() != std::io::ErrorKind::NotFound {
+          warn!("cannot read workload metrics {:?}: {}", path, e);
+        }
+        return Vec::new();
+      }
+    };
+    let metrics = match serde_json::from_str::<serde_json::Value>(&content) {
+      Ok(serde_json::Value::Object(metrics)) => metrics,
+      Ok(_) => {
+        warn!("workload metrics {:?} are not a JSON object", path);
+        return Vec::new();
+      }
+      Err(e) => {
+        warn!("cannot parse workload metrics {:?}: {}", path, e);
+        return Vec::new();
+      }
+    };
+    metrics
+      .into_iter()
+      .filter_map(|(name, value)| {
+        let (value, unit) = match &value {
+          serde_json::Value::Object(metric) => (
+            metric.get("value").and_then(serde_json::Value::as_f64),
+            metric
+              .get("unit")
+              .and_then(serde_json::Value::as_str)
+              .unwrap_or_default(),
+          ),
+          value => (value.as_f64(), ""),
+        };
+        match value.filter(|value| value.is_finite()) {
+          Some(value) if is_metric_name(&name) && is_metric_unit(unit) => Some(WorkloadMetric {
+            name,
+            unit: unit.to_string(),
+            value,
+          }),
+          _ => {
+            warn!("ignoring invalid workload metric {:?} in {:?}", name, path);
+            None
+          }
+        }
+      })
+      .collect()
+  }
+
+  /// Marks a sample as faulted by writing the injected action to a `fault` file.
+  pub fn save_fault(
+    experiment_directory: &Path,
+    fault: &FaultReport,
+  ) -> Result<(), std::io::Error> {
+    let action = match fault.action {
+      FaultAction::Kill => "kill",
+      FaultAction::Stop => "stop",
+    };
+    fs::write(experiment_directory.join("fault"), action)
+  }
+
+  /// Marks a sample as the recovery from the fault of sample `faulted` by writing a `recovery`
+  /// file.
+  pub fn mark_recovery(experiment_directory: &Path, faulted: u32) -> Result<(), std::io::Error> {
+    fs::write(experiment_directory.join("recovery"), faulted.to_string())
+  }
+
+  /// Creates an empty staging directory (`.<name>.partial`) next to `sample_directory`. A staging
+  /// directory left by a previous crashed run holds the checkpoints of its sample, so it is kept
+  /// as `.<name>.crashed-<unix time>` unless it is empty.
+  pub fn create_staging_directory(sample_directory: &Path) -> Result<PathBuf, std::io::Error> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(sample_directory.file_name().unwrap_or_default());
+    let mut staging_name = name.clone();
+    staging_name.push(".partial");
+    let staging_directory = sample_directory.with_file_name(staging_name);
+    if fs::read_dir(&staging_directory).is_ok_and(|mut entries| entries.next().is_some()) {
+      let since_epoch = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+      name.push(format!(".crashed-{}", since_epoch.as_secs()));
+      let crashed_directory = sample_directory.with_file_name(name);
+      warn!(
+        "keeping the data of a crashed run of {:?} in {:?}",
+        sample_directory, crashed_directory
+      );
+      fs::rename(&staging_directory, &crashed_directory)?;
+    }
+    match fs::remove_dir_all(&staging_directory) {
+      Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+      _ => {}
+    }
+    fs::create_dir_all(&staging_directory)?;
+    Ok(staging_directory)
+  }
+
+  /// Atomically moves a complete sample from its staging directory into place, replacing the
+  /// results of a previous run.
+  pub fn publish_sample(
+    staging_directory: &Path,
+    sample_directory: &Path,
+  ) -> Result<(), std::io::Error> {
+    match fs::remove_dir_all(sample_directory) {
+      Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+      _ => {}
+    }
+    fs::rename(staging_directory, sample_directory)
+  }
+
+  /// Restricts `cmd` to the logical CPUs in `cpus`; its threads and children inherit the affinity.
+  pub fn pin_on_spawn(cmd: &mut Command, cpus: &[usize]) {
+    let set = cpu_set(cpus);
+    // Safety: sched_setaffinity is async-signal-safe and the set is copied into the closure.
+    unsafe {
+      cmd.pre_exec(move || {
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+          return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+  }
+
+  /// Sets the scheduling policy and the nice level of `cmd`; its threads and children inherit
+  /// them. Spawning fails if the user is not allowed to set them.
+  pub fn schedule_on_spawn(
+    cmd: &mut Command,
+    nice: Option<i32>,
+    sched_policy: Option<SchedPolicy>,
+  ) {
+    // Safety: sched_setscheduler and setpriority are async-signal-safe.
+    unsafe {
+      cmd.pre_exec(move || {
+        if let Some(policy) = sched_policy {
+          let param = libc::sched_param {
+            sched_priority: policy.priority(),
+          };
+          if libc::sched_setscheduler(0, policy.policy(), &param) != 0 {
+            return Err(std::io::Error::last_os_error());
+          }
+        }
+        if let Some(nice) = nice {
+          if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+            return Err(std::io::Error::last_os_error());
+          }
+        }
+        Ok(())
+      });
+    }
+  }
+
+  /// Disables the randomization of the address space layout of `cmd`; its children inherit it.
+  pub fn disable_aslr_on_spawn(cmd: &mut Command) {
+    // Safety: personality is async-signal-safe.
+    unsafe {
+      cmd.pre_exec(|| {
+        // 0xffffffff queries the current persona
+        let persona = libc::personality(0xffffffff);
+        if persona == -1
+          || libc::personality((persona | libc::ADDR_NO_RANDOMIZE) as libc::c_ulong) == -1
+        {
+          return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+  }
+
+  /// Reads `pipe` to its end. The time of the first output is stored in `first_output_ns`, and
+  /// the time of the first complete line matching the pattern of a log ready check in its flag.
+  pub fn read_output(
+    mut pipe: impl Read,
+    first_output_ns: Option<&AtomicU64>,
+    ready: Option<(&Regex, &AtomicU64)>,
+  ) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut buf = [0u8; 8192];
+    // the output before this offset has been matched
+    let mut scanned = 0;
+    while let Ok(n) = pipe.read(&mut buf) {
+      if n == 0 {
+        break;
+      }
+      if output.is_empty() {
+        if let Some(first_output_ns) = first_output_ns {
+          first_output_ns.store(monotonic_ns(), Ordering::Relaxed);
+        }
+      }
+      output.extend_from_slice(&buf[..n]);
+      let Some((pattern, ready_ns)) =
+        ready.filter(|(_, ready_ns)| ready_ns.load(Ordering::Relaxed) == 0)
+      else {
+        continue;
+      };
+      if let Some(end) = output[scanned..].iter().rposition(|&b| b == b'\n') {
+        let lines = String::from_utf8_lossy(&output[scanned..scanned + end]);
+        if lines.lines().any(|line| pattern.is_match(line)) {
+          mark_ready(ready_ns);
+        }
+        scanned += end + 1;
+      }
+    }
+    output
+  }
+
+  /// Connects to `host:port` until a connection succeeds, storing its time in `ready_ns`, or until
+  /// `done` is set. Connections are made from `namespace` if any, which the calling thread enters.
+  pub fn probe_tcp(
+    host: &str,
+    port: u16,
+    namespace: Option<&NetworkNamespace>,
+    ready_ns: &AtomicU64,
+    done: &AtomicBool,
+  ) {
+    if let Some(namespace) = namespace {
+      if let Err(e) = namespace.enter() {
+        warn!("cannot enter the network namespace of the workload: {}", e);
+        return;
+      }
+    }
+    while !done.load(Ordering::Relaxed) {
+      let connected = (host, port)
+        .to_socket_addrs()
+        .into_iter()
+        .flatten()
+        .any(|address| TcpStream::connect_timeout(&address, TCP_PROBE_TIMEOUT).is_ok());
+      if connected {
+        mark_ready(ready_ns);
+        return;
+      }
+      thread::sleep(CHILD_POLL_INTERVAL);
+    }
+  }
+
+  /// Reads the phase markers appended to `file` every `PHASE_POLL_INTERVAL` until `done` is set,
+  /// timestamping them when read. Only complete, non-blank lines are markers; characters of the
+  /// name other than letters, digits and `_` are replaced with `_`.
+  pub fn watch_phases(file: &Path, done: &AtomicBool) -> Vec<PhaseMarker> {
+    let mut markers = Vec::new();
+    let Ok(mut file) = File::open(file) else {
+      return markers;
+    };
+    let mut pending = Vec::new();
+    loop {
+      // the flag is read first, so that the lines written before the workload exited are read
+      let finished = done.load(Ordering::Relaxed);
+      let now = ClockReference::now();
+      if let Err(e) = file.read_to_end(&mut pending) {
+        warn!("cannot read the phases of the workload: {}", e);
+        break;
+      }
+      while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = pending.drain(..=end).collect();
+        let phase: String = String::from_utf8_lossy(&line)
+          .trim()
+          .chars()
+          .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+              c
+            } else {
+              '_'
+            }
+          })
+          .collect();
+        if !phase.is_empty() {
+          markers.push(PhaseMarker {
+            timestamp: now.realtime_ns,
+            monotonic_ns: now.monotonic_ns,
+            phase,
+          });
+        }
+      }
+      if finished {
+        break;
+      }
+      thread::sleep(PHASE_POLL_INTERVAL);
+    }
+    markers
+  }
+
+  /// Returns the time spent in every phase, from its marker to the next one or to `end_ns`
+  /// (monotonic), summed over the markers of the same phase.
+  pub fn phase_durations(markers: &[PhaseMarker], end_ns: u64) -> BTreeMap<String, u64> {
+    let mut durations = BTreeMap::new();
+    for (i, marker) in markers.iter().enumerate() {
+      let until = markers.get(i + 1).map_or(end_ns, |next| next.monotonic_ns);
+      *durations.entry(marker.phase.clone()).or_default() +=
+        until.saturating_sub(marker.monotonic_ns);
+    }
+    durations
+  }
+
+  pub fn save_phases(
+    experiment_directory: &Path,
+    phases: &[PhaseMarker],
+  ) -> Result<(), std::io::Error> {
+    let mut file = File::create(experiment_directory.join("phases.csv"))?;
+    writeln!(file, "{}", PHASES_CSV_HEADER)?;
+    let csv_lines: Vec<String> = phases.iter().flat_map(|p| p.to_csv_rows()).collect();
+    file.write_all(csv_lines.join("\n").as_bytes())
+  }
+
+  fn mark_ready(ready_ns: &AtomicU64) {
+    let _ = ready_ns.compare_exchange(0, monotonic_ns(), Ordering::Relaxed, Ordering::Relaxed);
   }
 }
 
 #[cfg(test)]
 mod test {
-  use std::{path::PathBuf, sync::Arc, time::Duration};
+  use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+      atomic::{AtomicBool, AtomicU64, Ordering},
+      Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+  };
 
+  use regex::Regex;
   use tempfile::TempDir;
 
-  use crate::common::ExperimentConfig;
+  use crate::{
+    common::{
+      ExperimentConfig, Fault, FaultAction, FaultTrigger, MetricPattern, Network, PerfEvents,
+      PerfProfile, RunHealth, SchedPolicy, SgxProfile,
+    },
+    constants::{DEFAULT_PERF_EVENTS, MEMORY_PERF_EVENTS},
+    stats::{EnergySample, ToCsv},
+    tracer::types::{io_counter, latency_hist},
+  };
+
+  use super::{
+    utils::{
+      collect_sgx_profile_data, create_staging_directory, disable_aslr_on_spawn,
+      extract_workload_metrics, get_sgx_stats, load_workload_metrics, packages_energy_uj,
+      parse_pal_cmd, perf_event_supported, phase_durations, pidfd_open, probe_tcp,
+      process_mem_stats, publish_sample, read_output, resolve_kprobe_stats, save_summary,
+      schedule_on_spawn, sgx_profile_files, wait_readable, watch_phases,
+    },
+    DefaultCollector, FaultInjector, FaultReport, LowLevelSgxCounters, Metrics,
+  };
+
+  // a native experiment running `program` with nothing else set; tests override the fields they
+  // exercise
+  fn experiment_config(program: &str, args: &[&str], output_path: &Path) -> ExperimentConfig {
+    ExperimentConfig {
+      program: PathBuf::from(program),
+      args: args.iter().map(|arg| arg.to_string()).collect(),
+      pre_run: None,
+      post_run: None,
+      setup: None,
+      teardown: None,
+      on_fail: None,
+      hooks_must_succeed: false,
+      hook_timeout: None,
+      output_path: output_path.to_path_buf(),
+      env: None,
+      energy_sample_interval: None,
+      sgx_profile: SgxProfile::default(),
+      rpc_thread_num: 0,
+      log_level: None,
+      trusted_libraries: Vec::new(),
+      runtime: None,
+      script: None,
+      uprobes: Vec::new(),
+      usdt: Vec::new(),
+      network: Network::Host,
+      netem: None,
+      fault: None,
+      sample_duration: None,
+      metrics: Vec::new(),
+      perf_events: PerfEvents::default(),
+      systemd_scope: None,
+      cpu_affinity: Vec::new(),
+      transparent_hugepages: None,
+      nice: None,
+      sched_policy: None,
+      ready_check: None,
+      disable_aslr: false,
+      auxiliary_executables: Vec::new(),
+      cache_mask: None,
+      key_provisioning: None,
+    }
+  }
+
+  #[test]
+  fn summary_rows() {
+    let directory = TempDir::new().unwrap();
+    // a sample without any metric has no summary
+    save_summary(directory.path(), &Metrics::default()).unwrap();
+    assert!(!directory.path().join("summary.csv").exists());
+
+    let metrics = Metrics {
+      runtime_ns: Some(1000),
+      ..Default::default()
+    };
+    save_summary(directory.path(), &metrics).unwrap();
+    let summary = fs::read_to_string(directory.path().join("summary.csv")).unwrap();
+    assert_eq!(summary.lines().count(), 2);
+    assert!(summary.lines().nth(1).unwrap().starts_with("runtime,"));
+  }
+
+  #[test]
+  fn package_energy_wrap_around() {
+    let sample = |energy_uj: u64, range_uj: Option<u64>| EnergySample {
+      timestamp: 0,
+      monotonic_ns: 0,
+      energy_uj,
+      range_uj,
+      power_w: None,
+      gap: false,
+    };
+    // 2 J before the counter wraps around at 10 J, 3 J after it
+    let wrapped = HashMap::from([(
+      String::from("package-0"),
+      vec![
+        sample(6_000_000, Some(10_000_000)),
+        sample(8_000_000, Some(10_000_000)),
+        sample(1_000_000, Some(10_000_000)),
+      ],
+    )]);
+    assert_eq!(packages_energy_uj(&wrapped)[&0], 5_000_000);
+    // the interval is skipped when the range is unknown
+    let unknown = HashMap::from([(
+      String::from("package-0"),
+      vec![
+        sample(6_000_000, None),
+        sample(8_000_000, None),
+        sample(1_000_000, None),
+      ],
+    )]);
+    assert_eq!(packages_energy_uj(&unknown)[&0], 2_000_000);
+  }
+
+  #[test]
+  fn workload_scheduling() {
+    // lowering the priority is always permitted; the policy is field 41 of /proc/<pid>/stat
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", "nice; cut -d ' ' -f 41 /proc/$$/stat"]);
+    schedule_on_spawn(&mut cmd, Some(5), Some(SchedPolicy::Batch));
+    let output = cmd.output().unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines, vec!["5", libc::SCHED_BATCH.to_string().as_str()]);
+  }
+
+  #[test]
+  fn sgx_stats_from_stderr() {
+    let stderr = b"# of EENTERs:        139328\n\
+                   # of EEXITs:         139250\n\
+                   # of AEXs:           5377\n\
+                   # of sync signals:   72\n\
+                   # of async signals:  0\n";
+    let stats = get_sgx_stats(stderr, LowLevelSgxCounters::default(), true);
+    assert_eq!(
+      (stats.eenter, stats.aexit, stats.sync_signals),
+      (Some(139328), Some(5377), Some(72))
+    );
+    assert!(stats.parse_errors.is_empty());
+    assert_eq!(stats.to_csv_rows()[4], "sgx_async_signals,#,0,");
+
+    // an enclave which crashed while printing its statistics, and output of the workload
+    let stderr = b"# results\n# of EENTERs:        13\n# of EEXITs: 1x\nSegmentation fault\n";
+    let stats = get_sgx_stats(stderr, LowLevelSgxCounters::default(), true);
+    assert_eq!(stats.eenter, Some(13));
+    assert_eq!(stats.eexit, None);
+    assert_eq!(stats.to_csv_rows()[0], "sgx_enter,#,13,");
+    assert!(!stats.to_csv_rows()[1].starts_with("sgx_eexit"));
+    assert_eq!(
+      stats.parse_errors[0],
+      "stderr line 3: malformed statistic \"# of EEXITs: 1x\""
+    );
+    assert_eq!(stats.parse_errors.len(), 5);
+  }
+
+  #[test]
+  fn workload_phases() {
+    let directory = TempDir::new().unwrap();
+    let file = directory.path().join("phases");
+    fs::write(&file, "").unwrap();
+    let done = AtomicBool::new(false);
+    let markers = thread::scope(|s| {
+      let watcher = s.spawn(|| watch_phases(&file, &done));
+      // the last marker is written in two parts
+      let script = r#"
+        echo load >> "$EB_PHASES_FILE"; sleep 0.1
+        printf 'train step\nev' >> "$EB_PHASES_FILE"; sleep 0.1
+        echo al >> "$EB_PHASES_FILE"
+      "#;
+      let mut cmd = Command::new("sh");
+      cmd.args(["-c", script]).env("EB_PHASES_FILE", &file);
+      assert!(cmd.status().unwrap().success());
+      done.store(true, Ordering::Relaxed);
+      watcher.join().unwrap()
+    });
+    let phases: Vec<&str> = markers.iter().map(|m| m.phase.as_str()).collect();
+    assert_eq!(phases, vec!["load", "train_step", "eval"]);
+    assert!(markers[1].monotonic_ns - markers[0].monotonic_ns >= 50_000_000);
+
+    let end_ns = markers[2].monotonic_ns + 1000;
+    let durations = phase_durations(&markers, end_ns);
+    assert_eq!(durations["eval"], 1000);
+    assert_eq!(
+      durations.values().sum::<u64>(),
+      end_ns - markers[0].monotonic_ns
+    );
+  }
+
+  #[test]
+  fn workload_without_aslr() {
+    let personality = |disable_aslr: bool| {
+      let mut cmd = Command::new("cat");
+      cmd.arg("/proc/self/personality");
+      if disable_aslr {
+        disable_aslr_on_spawn(&mut cmd);
+      }
+      let output = String::from_utf8(cmd.output().unwrap().stdout).unwrap();
+      u32::from_str_radix(output.trim(), 16).unwrap()
+    };
+    assert_eq!(personality(false) & libc::ADDR_NO_RANDOMIZE as u32, 0);
+    assert_ne!(personality(true) & libc::ADDR_NO_RANDOMIZE as u32, 0);
+  }
+
+  #[test]
+  fn kprobe_stats_from_kallsyms() {
+    let kallsyms = "ffffffff81400000 T vfs_fsync_range\nffffffff81400100 T vfs_fsync\n\
+                    ffffffffc0a01000 t tcp_sendmsg\t[extra]\n";
+    let counters = [
+      (
+        0xffffffff81400104,
+        io_counter {
+          count: 3,
+          total_duration: 300,
+        },
+      ),
+      (
+        0xffffffff81400000,
+        io_counter {
+          count: 5,
+          total_duration: 50,
+        },
+      ),
+    ];
+    let symbols = vec![String::from("vfs_fsync"), String::from("tcp_sendmsg")];
+    let stats = resolve_kprobe_stats(&symbols, kallsyms, &counters);
+    assert_eq!(stats.len(), 2);
+    assert_eq!((stats[0].count, stats[0].total_ns), (3, 300));
+    assert_eq!(stats[1].count, 0);
+    assert_eq!(
+      stats[0].to_csv_rows(),
+      vec!["kprobe_vfs_fsync,#,3,", "kprobe_vfs_fsync,ns,100,"]
+    );
+  }
 
-  use super::DefaultCollector;
+  #[test]
+  fn fsync_latency_from_histograms() {
+    let mut slots = [0; 32];
+    slots[20] = 3;
+    let fsync = latency_hist {
+      count: 3,
+      total_duration: 3_000_000,
+      max: 1_200_000,
+      slots,
+    };
+    let stats = process_mem_stats(vec![(2, fsync)]);
+    let names: Vec<&str> = stats.iter().map(|s| s.name).collect();
+    assert_eq!(
+      names,
+      vec!["sys_read", "sys_write", "sys_fsync", "sys_fdatasync"]
+    );
+    assert_eq!(stats[0].count, 0);
+    assert_eq!(
+      (stats[2].count, stats[2].avg_ns, stats[2].p99_ns),
+      (3, 1_000_000, 1_200_000)
+    );
+  }
+
+  #[test]
+  fn fault_injection_schedule() {
+    let fault = Fault {
+      action: FaultAction::Stop,
+      trigger: FaultTrigger::After(Duration::from_secs(5)),
+      stop_for: Duration::from_secs(1),
+      every: 2,
+    };
+    let mut injector = FaultInjector::new(fault.clone(), 1, 1_000_000_000);
+    assert_eq!(injector.next_signal(5_900_000_000, None), None);
+    assert_eq!(
+      injector.next_signal(6_000_000_000, None),
+      Some(libc::SIGSTOP)
+    );
+    assert_eq!(injector.next_signal(6_500_000_000, None), None);
+    assert_eq!(
+      injector.next_signal(7_050_000_000, None),
+      Some(libc::SIGCONT)
+    );
+    assert_eq!(injector.next_signal(8_000_000_000, None), None);
+    assert_eq!(
+      injector.report(),
+      Some(FaultReport {
+        action: FaultAction::Stop,
+        injected_after_ns: 5_000_000_000,
+        stopped_ns: Some(1_050_000_000),
+      })
+    );
+
+    // the tracer injects faults triggered by system calls
+    let fault = Fault {
+      action: FaultAction::Kill,
+      trigger: FaultTrigger::Syscalls(100),
+      ..fault
+    };
+    let mut injector = FaultInjector::new(fault, 1, 1_000_000_000);
+    assert_eq!(injector.next_signal(9_000_000_000, None), None);
+    assert!(injector.report().is_none());
+    assert_eq!(
+      injector.next_signal(9_000_000_000, Some(2_000_000_000)),
+      Some(libc::SIGKILL)
+    );
+    assert_eq!(injector.next_signal(10_000_000_000, None), None);
+    assert_eq!(injector.report().unwrap().injected_after_ns, 1_000_000_000);
+  }
+
+  #[test]
+  fn workload_metrics_from_output() {
+    let pattern = |name: &str, regex: &str| MetricPattern {
+      name: name.to_string(),
+      regex: Regex::new(regex).unwrap(),
+      unit: String::from("1/s"),
+    };
+    let patterns = [
+      pattern("requests_per_second", r"Requests/sec:\s+([\d.,]+)"),
+      pattern("loss", r"loss=([\d.]+)"),
+      pattern("missing", r"images/sec: (\d+)"),
+    ];
+    let stdout = b"Running 10s test\nRequests/sec:  12,345.67\n";
+    let stderr = b"epoch 1 loss=0.9\nepoch 2 loss=0.25\n";
+
+    let metrics = extract_workload_metrics(&patterns, stdout, stderr);
+    let values: Vec<(&str, f64)> = metrics.iter().map(|m| (m.name.as_str(), m.value)).collect();
+    // the last match is kept
+    assert_eq!(
+      values,
+      vec![("requests_per_second", 12345.67), ("loss", 0.25)]
+    );
+    assert_eq!(metrics[1].to_csv_rows(), vec!["loss,1/s,0.25"]);
+  }
+
+  #[test]
+  fn ready_probes() {
+    // the matching line is split across reads
+    let first_output_ns = AtomicU64::new(0);
+    let ready_ns = AtomicU64::new(0);
+    let pattern = Regex::new(r"listening on \d+").unwrap();
+    let pipe = &b"starting\nlisten"[..];
+    let output = read_output(
+      pipe.chain(&b"ing on 8080\nserving\n"[..]),
+      Some(&first_output_ns),
+      Some((&pattern, &ready_ns)),
+    );
+    assert_eq!(output, b"starting\nlistening on 8080\nserving\n");
+    assert!(first_output_ns.load(Ordering::Relaxed) > 0);
+    assert!(ready_ns.load(Ordering::Relaxed) >= first_output_ns.load(Ordering::Relaxed));
+
+    // incomplete lines are not matched
+    let ready_ns = AtomicU64::new(0);
+    read_output(&b"listening on 8080"[..], None, Some((&pattern, &ready_ns)));
+    assert_eq!(ready_ns.load(Ordering::Relaxed), 0);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let ready_ns = AtomicU64::new(0);
+    probe_tcp("127.0.0.1", port, None, &ready_ns, &AtomicBool::new(false));
+    assert!(ready_ns.load(Ordering::Relaxed) > 0);
+
+    // stops once done
+    drop(listener);
+    let ready_ns = AtomicU64::new(0);
+    probe_tcp("127.0.0.1", port, None, &ready_ns, &AtomicBool::new(true));
+    assert_eq!(ready_ns.load(Ordering::Relaxed), 0);
+  }
+
+  #[test]
+  fn workload_metrics_from_file() {
+    let directory = TempDir::new().unwrap();
+    let path = directory.path().join("workload-metrics.json");
+    assert!(load_workload_metrics(&path).is_empty());
+
+    fs::write(
+      &path,
+      r#"{"images_per_second": {"value": 812.5, "unit": "1/s"}, "epochs": 3,
+          "bad name": 1, "label": "fast", "bad_unit": {"value": 1, "unit": "a,b"},
+          "tab_unit": {"value": 1, "unit": "a\tb"}}"#,
+    )
+    .unwrap();
+    let metrics = load_workload_metrics(&path);
+    let rows: Vec<String> = metrics.iter().flat_map(|m| m.to_csv_rows()).collect();
+    assert_eq!(rows, vec!["epochs,,3", "images_per_second,1/s,812.5"]);
+
+    fs::write(&path, "[1, 2]").unwrap();
+    assert!(load_workload_metrics(&path).is_empty());
+  }
+
+  #[test]
+  fn disk_devices_resolved_from_partitions() {
+    let collector = DefaultCollector::new(1, false, Duration::from_millis(100), None, None, false);
+    let partitions = collector.partitions.clone();
+    let names: Vec<String> = partitions.iter().take(1).map(|p| p.name.clone()).collect();
+
+    let collector = collector.with_disk_devices(&names).unwrap();
+    assert_eq!(
+      collector.disk_devices,
+      partitions
+        .iter()
+        .take(1)
+        .map(|p| p.dev)
+        .collect::<Vec<u32>>()
+    );
+
+    let err = collector
+      .with_disk_devices(&[String::from("no-such-disk")])
+      .err()
+      .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+  }
+
+  #[test]
+  fn perf_event_probe_output() {
+    assert!(perf_event_supported(
+      true,
+      b"1234567,,cpu-cycles,811734,100.00,,\n"
+    ));
+    assert!(!perf_event_supported(
+      true,
+      b"<not supported>,,stalled-cycles-frontend,0,100.00,,\n"
+    ));
+    // unknown events make perf fail
+    assert!(!perf_event_supported(
+      false,
+      b"event syntax error: 'no-such-event'\n"
+    ));
+  }
+
+  #[test]
+  fn perf_events_merged_per_task() {
+    let collector = DefaultCollector::new(
+      1,
+      false,
+      Duration::from_millis(100),
+      Some(vec![String::from("LLC-load-misses")]),
+      None,
+      false,
+    );
+    let events = collector.perf_events_for(&PerfEvents {
+      add: vec![
+        String::from("dTLB-load-misses"),
+        String::from("LLC-load-misses"),
+      ],
+      remove: vec![String::from("cpu-cycles")],
+      ..PerfEvents::default()
+    });
+    assert!(!events.contains(&String::from("cpu-cycles")));
+    assert!(events.contains(&String::from("instructions")));
+    assert_eq!(events.iter().filter(|e| *e == "LLC-load-misses").count(), 1);
+    assert_eq!(events.last().unwrap(), "dTLB-load-misses");
+    assert_eq!(
+      collector.perf_events_for(&PerfEvents::default()).len(),
+      collector.perf_events.len()
+    );
+
+    let events = collector.perf_events_for(&PerfEvents {
+      add: vec![String::from("dTLB-load-misses")],
+      groups: vec![vec![
+        String::from("instructions"),
+        String::from("dTLB-load-misses"),
+      ]],
+      ..PerfEvents::default()
+    });
+    assert!(!events.contains(&String::from("instructions")));
+    assert!(!events.contains(&String::from("dTLB-load-misses")));
+    assert_eq!(events.last().unwrap(), "{instructions,dTLB-load-misses}");
+  }
+
+  #[test]
+  fn perf_profiles() {
+    let collector = DefaultCollector::new(
+      1,
+      false,
+      Duration::from_millis(100),
+      Some(vec![String::from("cache-misses")]),
+      None,
+      false,
+    )
+    .with_perf_profile(PerfProfile::Memory);
+    let mut events = collector.perf_events.clone();
+    events.sort();
+    let mut expected: Vec<String> = MEMORY_PERF_EVENTS
+      .iter()
+      .map(|e| e.to_string())
+      .chain([String::from("cache-misses")])
+      .collect();
+    expected.sort();
+    assert_eq!(events, expected);
+    assert_eq!(collector.settings().perf_profile, PerfProfile::Memory);
+
+    // the hardware events are counted in the groups of the profile, those removed by a task or
+    // taken by its groups are left out of them
+    let events = collector.perf_events_for(&PerfEvents::default());
+    assert!(events.contains(&String::from(
+      "{cpu-cycles,dTLB-loads,dTLB-load-misses,dtlb_load_misses.walk_active}"
+    )));
+    assert!(events.contains(&String::from("page-faults")));
+    assert!(!events.contains(&String::from("LLC-loads")));
+    let events = collector.perf_events_for(&PerfEvents {
+      remove: vec![String::from("LLC-loads")],
+      groups: vec![vec![
+        String::from("dTLB-store-misses"),
+        String::from("cache-misses"),
+      ]],
+      ..PerfEvents::default()
+    });
+    assert!(events.contains(&String::from(
+      "{instructions,LLC-load-misses,LLC-store-misses}"
+    )));
+    // a group of one event is counted on its own
+    assert!(events.contains(&String::from("dtlb_store_misses.walk_active")));
+    assert_eq!(events.last().unwrap(), "{dTLB-store-misses,cache-misses}");
+    assert!(!events.iter().any(|e| e.contains("LLC-loads")));
+
+    let collector = collector.with_perf_profile(PerfProfile::Default);
+    assert_eq!(collector.perf_events.len(), DEFAULT_PERF_EVENTS.len());
+  }
+
+  #[test]
+  fn sgx_profile_files_of_sample() {
+    let experiment_directory = TempDir::new().unwrap();
+    fs::write(experiment_directory.path().join("sgx-perf.data"), "main").unwrap();
+    fs::write(
+      experiment_directory.path().join("sgx-perf-42.data"),
+      "child",
+    )
+    .unwrap();
+    fs::write(experiment_directory.path().join("other.data"), "").unwrap();
+
+    let files = sgx_profile_files(experiment_directory.path());
+    assert_eq!(
+      files,
+      vec![
+        experiment_directory.path().join("sgx-perf-42.data"),
+        experiment_directory.path().join("sgx-perf.data"),
+      ]
+    );
+    // the profile stays in the sample directory; summaries are written next to it if perf can
+    // read it
+    assert!(collect_sgx_profile_data(experiment_directory.path())
+      .unwrap()
+      .is_empty());
+    assert_eq!(sgx_profile_files(experiment_directory.path()), files);
+  }
+
+  #[test]
+  fn gramine_loader_of_script() {
+    let script = "#!/usr/bin/env bash\n\nPAL_CMD=/usr/lib/x86_64-linux-gnu/gramine/sgx/loader\nLIBPAL_PATH=/usr/lib/x86_64-linux-gnu/gramine/sgx/libpal.so\n";
+    assert_eq!(
+      parse_pal_cmd(script),
+      Some(PathBuf::from(
+        "/usr/lib/x86_64-linux-gnu/gramine/sgx/loader"
+      ))
+    );
+    assert_eq!(
+      parse_pal_cmd("PAL_CMD=\"/opt/gramine/sgx/loader\"\n"),
+      Some(PathBuf::from("/opt/gramine/sgx/loader"))
+    );
+    assert_eq!(parse_pal_cmd("#!/bin/sh\n"), None);
+  }
+
+  #[test]
+  fn staging_directory_publish() {
+    let output_directory = TempDir::new().unwrap();
+    let sample = output_directory.path().join("1");
+    // results of a previous run and a staging directory left by a crash
+    fs::create_dir_all(&sample).unwrap();
+    fs::write(sample.join("old"), "").unwrap();
+    let leftover = output_directory.path().join(".1.partial");
+    fs::create_dir_all(&leftover).unwrap();
+    fs::write(leftover.join("perf.csv"), "truncated").unwrap();
+
+    let staging = create_staging_directory(&sample).unwrap();
+    assert_eq!(staging, leftover);
+    assert_eq!(fs::read_dir(&staging).unwrap().count(), 0);
+    // the checkpoints of the crashed run are kept
+    let crashed = fs::read_dir(output_directory.path())
+      .unwrap()
+      .map(|entry| entry.unwrap().path())
+      .find(|path| path.to_string_lossy().contains("/.1.crashed-"))
+      .unwrap();
+    assert_eq!(
+      fs::read_to_string(crashed.join("perf.csv")).unwrap(),
+      "truncated"
+    );
+    // an empty staging directory is replaced
+    assert_eq!(create_staging_directory(&sample).unwrap(), staging);
+    fs::write(staging.join("perf.csv"), "complete").unwrap();
+
+    publish_sample(&staging, &sample).unwrap();
+    assert!(!staging.exists());
+    assert!(!sample.join("old").exists());
+    assert_eq!(
+      fs::read_to_string(sample.join("perf.csv")).unwrap(),
+      "complete"
+    );
+  }
 
   #[test]
   fn test_collector() {
     let output_directory = TempDir::new().unwrap();
     let sample_size = 1;
-    let collector = DefaultCollector::new(sample_size, false, Duration::from_micros(500), None);
+    let collector = DefaultCollector::new(
+      sample_size,
+      false,
+      Duration::from_micros(500),
+      None,
+      None,
+      false,
+    );
     let collector = Arc::new(collector);
-    let experiment_config = ExperimentConfig {
-      program: PathBuf::from("/bin/sleep"),
-      args: vec!["1".to_string()],
-      pre_run: None,
-      post_run: None,
-      output_path: output_directory.path().to_path_buf(),
-      env: None,
-    };
+    let experiment_config = experiment_config("/bin/sleep", &["1"], output_directory.path());
     collector.clone().attach(experiment_config).unwrap();
 
     for i in 1..sample_size + 1 {
@@ -774,9 +4363,360 @@ mod test {
       assert!(iter_directory.join("io.csv").is_file());
       assert!(iter_directory.join("stdout").is_file());
       assert!(iter_directory.join("stderr").is_file());
-      for (name, _) in &collector.rapl_paths {
-        assert!(iter_directory.join(format!("{}.csv", name)).is_file())
+      for counter in &collector.energy_counters {
+        assert!(iter_directory
+          .join(format!("{}.csv", counter.name))
+          .is_file())
       }
     }
   }
+
+  #[test]
+  fn test_collector_short_program() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(DefaultCollector::new(
+      1,
+      false,
+      Duration::from_micros(500),
+      None,
+      None,
+      false,
+    ));
+    let experiment_config = experiment_config("/bin/sleep", &["0.1"], output_directory.path());
+    collector.attach(experiment_config).unwrap();
+    assert!(output_directory.path().join("1/summary.csv").is_file());
+  }
+
+  #[test]
+  fn pidfd_readable_once_exited() {
+    let mut child = Command::new("cat")
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .spawn()
+      .unwrap();
+    let Some(pidfd) = pidfd_open(child.id()) else {
+      // kernels older than 5.3 poll instead
+      drop(child.stdin.take());
+      child.wait().unwrap();
+      return;
+    };
+    // the exit is notified by the pidfd itself, not by how long the wait took
+    assert!(!wait_readable(&pidfd, Duration::ZERO));
+    drop(child.stdin.take());
+    assert!(wait_readable(&pidfd, Duration::from_secs(60)));
+    assert!(child.try_wait().unwrap().is_some());
+  }
+
+  #[test]
+  fn test_collector_relative_timestamps() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(DefaultCollector::new(
+      1,
+      false,
+      Duration::from_micros(500),
+      None,
+      None,
+      true,
+    ));
+    let experiment_config = ExperimentConfig {
+      energy_sample_interval: Some(Duration::from_millis(100)),
+      ..experiment_config("/bin/sleep", &["1"], output_directory.path())
+    };
+    collector.attach(experiment_config).unwrap();
+
+    let sample_directory = output_directory.path().join("1");
+    let clock: serde_json::Value =
+      serde_json::from_str(&fs::read_to_string(sample_directory.join("clock.json")).unwrap())
+        .unwrap();
+    assert_eq!(clock["relative_timestamps"], true);
+    assert!(clock["realtime_ns"].is_u64() && clock["monotonic_ns"].is_u64());
+
+    let memory = fs::read_to_string(sample_directory.join("memory.csv")).unwrap();
+    for line in memory.lines().skip(1) {
+      let timestamp: u128 = line.split(',').next().unwrap().parse().unwrap();
+      assert!(timestamp < Duration::from_secs(60).as_nanos());
+    }
+  }
+
+  #[test]
+  fn test_collector_checkpoints() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(
+      DefaultCollector::new(1, false, Duration::from_micros(500), None, None, true)
+        .with_checkpoint_interval(Some(Duration::from_millis(200))),
+    );
+    let experiment_config = ExperimentConfig {
+      energy_sample_interval: Some(Duration::from_millis(20)),
+      ..experiment_config("/bin/sleep", &["1"], output_directory.path())
+    };
+    collector.attach(experiment_config).unwrap();
+
+    // rows appended by checkpoints and at the end of the sample form a single time series
+    let sample_directory = output_directory.path().join("1");
+    let memory = fs::read_to_string(sample_directory.join("memory.csv")).unwrap();
+    assert_eq!(memory.matches("timestamp").count(), 1);
+    let timestamps = memory
+      .lines()
+      .skip(1)
+      .map(|line| line.split(',').next().unwrap().parse::<u128>().unwrap())
+      .collect::<Vec<_>>();
+    assert!(timestamps.len() > 10);
+    assert!(timestamps.is_sorted());
+    assert!(timestamps
+      .iter()
+      .all(|&t| t < Duration::from_secs(60).as_nanos()));
+
+    let cpu = fs::read_to_string(sample_directory.join("cpu.csv")).unwrap();
+    assert_eq!(cpu.matches("timestamp").count(), 1);
+    assert!(cpu
+      .lines()
+      .skip(1)
+      .all(|line| line.starts_with(|c: char| c.is_ascii_digit())));
+
+    let summary = fs::read_to_string(sample_directory.join("summary.csv")).unwrap();
+    assert!(summary.contains("peak_pss,kB,"));
+    assert!(summary.contains("runtime,ns,"));
+  }
+
+  #[test]
+  fn test_collector_sample_duration() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(DefaultCollector::new(
+      1,
+      false,
+      Duration::from_millis(100),
+      None,
+      None,
+      false,
+    ));
+    let experiment_config = ExperimentConfig {
+      on_fail: Some((
+        PathBuf::from("/bin/sh"),
+        vec!["-c".to_string(), "touch \"$0/failed\"".to_string()],
+      )),
+      sample_duration: Some(Duration::from_millis(300)),
+      ..experiment_config("/bin/sleep", &["30"], output_directory.path())
+    };
+    let start = Instant::now();
+    collector.attach(experiment_config).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    // the terminated workload is not a failure and reports rates instead of its runtime
+    let sample_directory = output_directory.path().join("1");
+    assert!(!sample_directory.join("failed").exists());
+    let summary = fs::read_to_string(sample_directory.join("summary.csv")).unwrap();
+    assert!(summary.contains("duration,ns,"));
+    assert!(!summary.contains("runtime,ns,"));
+  }
+
+  #[test]
+  fn test_collector_workload_metrics() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(DefaultCollector::new(
+      1,
+      false,
+      Duration::from_millis(100),
+      None,
+      None,
+      false,
+    ));
+    let experiment_config = ExperimentConfig {
+      metrics: vec![MetricPattern {
+        name: String::from("throughput"),
+        regex: Regex::new(r"throughput: (\d+)").unwrap(),
+        unit: String::new(),
+      }],
+      ..experiment_config(
+        "/bin/sh",
+        &[
+          "-c",
+          r#"echo "throughput: 10"; echo '{"throughput": 42, "latency": {"value": 3.5, "unit": "ms"}}' > "$EB_METRICS_FILE""#,
+        ],
+        output_directory.path(),
+      )
+    };
+    collector.attach(experiment_config).unwrap();
+
+    // the file written by the workload is kept and overrides its output
+    let sample_directory = output_directory.path().join("1");
+    assert!(sample_directory.join("workload-metrics.json").is_file());
+    let summary = fs::read_to_string(sample_directory.join("summary.csv")).unwrap();
+    assert!(summary.contains("latency,ms,3.5"));
+    assert!(summary.contains("throughput,,42"));
+    assert!(!summary.contains("throughput,,10"));
+  }
+
+  #[test]
+  fn test_collector_hooks() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(DefaultCollector::new(
+      2,
+      false,
+      Duration::from_micros(500),
+      None,
+      None,
+      false,
+    ));
+    let touch = |file: &str| {
+      Some((
+        PathBuf::from("/bin/sh"),
+        vec!["-c".to_string(), format!("touch \"$0/{}\"", file)],
+      ))
+    };
+    let hook = |file: &str| {
+      touch(file).map(|(cmd, mut args)| {
+        args.push(output_directory.path().to_string_lossy().into_owned());
+        (cmd, args)
+      })
+    };
+    let experiment_config = ExperimentConfig {
+      setup: hook("setup"),
+      teardown: hook("teardown"),
+      // the sample directory is appended by the collector
+      on_fail: touch("failed"),
+      ..experiment_config("/bin/false", &[], output_directory.path())
+    };
+    collector.clone().attach(experiment_config).unwrap();
+    assert_eq!(collector.health().failed_samples, 2);
+
+    assert!(output_directory.path().join("setup").is_file());
+    assert!(output_directory.path().join("teardown").is_file());
+    for i in 1..=2 {
+      assert!(output_directory
+        .path()
+        .join(i.to_string())
+        .join("failed")
+        .is_file());
+    }
+  }
+
+  #[test]
+  fn test_collector_hooks_must_succeed() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(DefaultCollector::new(
+      1,
+      false,
+      Duration::from_micros(500),
+      None,
+      None,
+      false,
+    ));
+    let experiment_config = ExperimentConfig {
+      pre_run: Some((
+        PathBuf::from("/bin/sh"),
+        vec!["-c".to_string(), "echo preparing; exit 1".to_string()],
+      )),
+      hooks_must_succeed: true,
+      ..experiment_config("/bin/true", &[], output_directory.path())
+    };
+    collector.clone().attach(experiment_config).unwrap();
+    assert_eq!(
+      collector.health(),
+      RunHealth {
+        partial_samples: 1,
+        ..RunHealth::default()
+      }
+    );
+
+    let sample_directory = output_directory.path().join("1");
+    assert!(sample_directory.join("invalid").is_file());
+    assert_eq!(
+      fs::read_to_string(sample_directory.join("pre_run.stdout")).unwrap(),
+      "preparing\n"
+    );
+    // the workload is skipped after a failed pre_run hook
+    assert!(!sample_directory.join("stdout").exists());
+  }
+
+  #[test]
+  fn test_collector_hook_timeout() {
+    let output_directory = TempDir::new().unwrap();
+    let collector = Arc::new(DefaultCollector::new(
+      1,
+      false,
+      Duration::from_micros(500),
+      None,
+      None,
+      false,
+    ));
+    let experiment_config = ExperimentConfig {
+      post_run: Some((
+        PathBuf::from("/bin/sh"),
+        vec!["-c".to_string(), "sleep 30".to_string()],
+      )),
+      hook_timeout: Some(Duration::from_millis(200)),
+      ..experiment_config("/bin/true", &[], output_directory.path())
+    };
+    let start = Instant::now();
+    collector.clone().attach(experiment_config).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(30));
+    assert_eq!(collector.health().timed_out_hooks, 1);
+
+    let hooks: serde_json::Value = serde_json::from_str(
+      &fs::read_to_string(output_directory.path().join("1").join("hooks.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(hooks["post_run"]["timed_out"], true);
+  }
+
+  #[test]
+  fn test_collector_stop_and_interrupt() {
+    // the workload announces that it runs, so that the stop or the interrupt is requested in the
+    // middle of the first sample whatever the load of the machine
+    let markers = TempDir::new().unwrap();
+    let started = markers.path().join("started");
+    let script = format!("touch {}; exec sleep $0", started.display());
+    let experiment_config = |output_path: &Path, seconds: &str| {
+      experiment_config("/bin/sh", &["-c", &script, seconds], output_path)
+    };
+    let request_when_started = |request: fn(Arc<DefaultCollector>), collector| {
+      let started = started.clone();
+      thread::spawn(move || {
+        while !started.exists() {
+          thread::sleep(Duration::from_millis(10));
+        }
+        request(collector);
+      })
+    };
+    let new_collector = || {
+      Arc::new(DefaultCollector::new(
+        3,
+        false,
+        Duration::from_micros(500),
+        None,
+        None,
+        false,
+      ))
+    };
+
+    // a stop completes the running sample and skips the others
+    let output_directory = TempDir::new().unwrap();
+    let collector = new_collector();
+    let stopper = request_when_started(DefaultCollector::stop, collector.clone());
+    collector
+      .attach(experiment_config(output_directory.path(), "0.5"))
+      .unwrap();
+    stopper.join().unwrap();
+    let sample = output_directory.path().join("1");
+    assert!(sample.join("summary.csv").is_file());
+    assert!(!sample.join("invalid").exists());
+    assert!(!output_directory.path().join("2").exists());
+
+    // an interrupt kills the workload and marks the partial sample as invalid
+    let output_directory = TempDir::new().unwrap();
+    fs::remove_file(&started).unwrap();
+    let collector = new_collector();
+    let interrupter = request_when_started(DefaultCollector::interrupt, collector.clone());
+    let start = Instant::now();
+    collector
+      .attach(experiment_config(output_directory.path(), "60"))
+      .unwrap();
+    interrupter.join().unwrap();
+    // the workload did not run to completion
+    assert!(start.elapsed() < Duration::from_secs(60));
+    let sample = output_directory.path().join("1");
+    assert!(sample.join("summary.csv").is_file());
+    assert!(sample.join("invalid").is_file());
+    assert!(!output_directory.path().join("2").exists());
+  }
 }
@@ -1,7 +1,21 @@
-use duration_str::deserialize_duration;
-use std::{collections::HashMap, fmt::Display, path::PathBuf, time::Duration};
+use duration_str::{deserialize_duration, deserialize_option_duration};
+use std::{
+  collections::{BTreeMap, HashMap},
+  env,
+  fmt::Display,
+  fs,
+  path::{Path, PathBuf},
+  time::Duration,
+};
 
-use serde::Deserialize;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::constants::{
+  DEFAULT_PERF_EVENTS, EXIT_FAILED_SAMPLES, EXIT_PARTIAL_SAMPLES, EXIT_TIMED_OUT_HOOKS,
+  FRONTEND_PERF_EVENTS, INDEX_FILE, MEMORY_PERF_EVENTS, MEMORY_PERF_GROUPS, PROVENANCE_ENV,
+};
 
 /// GlobalParams holds the configuration parameters for the global settings of the application.
 ///
@@ -13,11 +27,27 @@ use serde::Deserialize;
 /// - **debug** - A boolean flag for enabling debug logging for more detailed output. Defaults to false.
 /// - **deep_trace** - A boolean flag for enabling an extra experiment with tracing enabled. This can be very slow. Defaults to false.
 /// - **energy_sample_interval** - The interval for energy sampling, deserialized using deserialize_duration. Must be specified with a time unit (e.g., "250ms" for 250 milliseconds). Defaults to 500 milliseconds.
+/// - **carbon_intensity_g_per_kwh** - An optional grid carbon intensity (grams of CO2e per kWh) used to convert measured energy into CO2e estimates.
+/// - **relative_timestamps** - A boolean flag for writing time series timestamps relative to the spawn of the target process instead of absolute clock values. Defaults to false.
+/// - **baseline_cache** - An optional directory where native experiments are stored and reused by later campaigns with the same native parameters.
+/// - **extra_kprobes** - An optional list of kernel functions whose calls by the target process are counted and timed by a generic eBPF program.
+/// - **disk_devices** - An optional list of block devices (names of `/proc/partitions`, e.g. "nvme0n1p3") whose traffic is accounted in the disk statistics; a partition accounts the traffic of its whole disk. Defaults to every device.
+/// - **checkpoint_interval** - An optional interval, deserialized using deserialize_option_duration, at which time series collected during a sample are appended to their files instead of being held in memory until the workload exits.
+/// - **retention** - Cleanup applied to results at the end of a run (see **Retention**). Defaults to keeping everything.
+/// - **signing_key** - The key signing the enclaves of the campaign (see **SigningKey**). Defaults to a generated RSA-3072 key with exponent 3.
+/// - **failure_policy** - How failed samples, timed out hooks and partial metrics affect the exit status of a run (see **FailurePolicy**). Defaults to logging them as warnings, with the exit status unchanged.
+/// - **energy_source** - Where the RAPL energy counters are read from (see **EnergySource**). Defaults to the powercap interface.
+/// - **memory_bandwidth** - An optional source of the memory bandwidth of the workload, sampled every `energy_sample_interval` (see **MemoryBandwidthSource**). With `imc` the traffic is counted per socket, not per core: other processes on the sockets of the workload are counted too. Not measured by default.
+/// - **perf_profile** - The curated set of perf events counted before `extra_perf_events` and the `perf_events` of the tasks apply (see **PerfProfile**). Defaults to `default`.
+/// - **layout** - An optional handlebars template of the path of every experiment directory relative to `output_directory`, rendered with the parameters of the experiment (e.g. "{{storage_type}}/{{program}}/{{id}}"). Defaults to "{{program}}/{{kind}}/{{id}}".
+/// - **isolated_cpus** - An optional list of CPUs in the list format of the kernel (e.g. "2-7"), deserialized using deserialize_option_cpu_list, reserved to the workloads during a run: IRQs and the threads of the benchmark are moved to the other CPUs and workloads are restricted to the isolated ones (see **isolation::Isolation**).
 #[derive(Deserialize, Debug)]
 pub struct GlobalParams {
   pub sample_size: u32,
   pub output_directory: PathBuf,
   pub extra_perf_events: Option<Vec<String>>,
+  pub extra_kprobes: Option<Vec<String>>,
+  pub disk_devices: Option<Vec<String>>,
 
   #[serde(default)]
   pub debug: bool,
@@ -30,6 +60,456 @@ pub struct GlobalParams {
     default = "default_energy_sample_interval"
   )]
   pub energy_sample_interval: Duration,
+
+  pub carbon_intensity_g_per_kwh: Option<f64>,
+
+  #[serde(default)]
+  pub relative_timestamps: bool,
+
+  pub baseline_cache: Option<PathBuf>,
+
+  #[serde(default, deserialize_with = "deserialize_option_duration")]
+  pub checkpoint_interval: Option<Duration>,
+
+  #[serde(default)]
+  pub retention: Retention,
+
+  #[serde(default)]
+  pub signing_key: SigningKey,
+
+  #[serde(default)]
+  pub failure_policy: FailurePolicy,
+
+  #[serde(default)]
+  pub energy_source: EnergySource,
+
+  pub memory_bandwidth: Option<MemoryBandwidthSource>,
+
+  #[serde(default)]
+  pub perf_profile: PerfProfile,
+
+  #[serde(default, deserialize_with = "deserialize_option_cpu_list")]
+  pub isolated_cpus: Option<Vec<usize>>,
+
+  pub layout: Option<String>,
+}
+
+/// Problems of the samples of a run, which make a campaign unhealthy.
+///
+/// # Fields
+///
+/// - **failed_samples** - Samples whose workload could not start or exited with a non-zero status.
+/// - **timed_out_hooks** - Hooks killed after `hook_timeout`.
+/// - **partial_samples** - Samples marked as invalid (failed hook with `hooks_must_succeed`,
+///   interrupted run), whose metrics are partial.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RunHealth {
+  pub failed_samples: usize,
+  pub timed_out_hooks: usize,
+  pub partial_samples: usize,
+}
+
+/// Severity defines how a kind of problem of a run is reported.
+///
+/// # Variants
+///
+/// - **Ignore** - Not reported.
+/// - **Warn** - Logged as a warning; the exit status is unchanged.
+/// - **Error** - Logged as an error, and the run exits with the status of the problem.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+  Ignore,
+  Warn,
+  Error,
+}
+
+/// EnergySource defines where the energy monitor reads the RAPL energy counters from.
+///
+/// # Variants
+///
+/// - **Powercap** - The `energy_uj` files of the powercap interface of the kernel (`/sys/devices/virtual/powercap/intel-rapl`).
+/// - **Msr** - The RAPL MSRs, read through `/dev/cpu/<N>/msr` (or `msr_safe` of the msr-safe module) of the first CPU of every package and scaled with the energy unit of the CPU. For hosts where the powercap interface is disabled but MSR access is permitted (`msr` module loaded).
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EnergySource {
+  #[default]
+  Powercap,
+  Msr,
+}
+
+impl Display for EnergySource {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Powercap => write!(f, "powercap"),
+      Self::Msr => write!(f, "msr"),
+    }
+  }
+}
+
+/// MemoryBandwidthSource defines how the memory bandwidth of the workload is measured.
+///
+/// # Variants
+///
+/// - **Resctrl** - The memory bandwidth monitoring (MBM) counters of Intel RDT, read through a monitoring group of resctrl (mounted on `/sys/fs/resctrl`) holding the tasks of the workload: only its own traffic is counted, by L3 domain.
+/// - **Imc** - The CAS counters of the integrated memory controllers (`uncore_imc` perf events), counted by socket. The traffic of the sockets the workload is allowed to run on is attributed to it, including the one of other processes on those sockets.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryBandwidthSource {
+  Resctrl,
+  Imc,
+}
+
+impl Display for MemoryBandwidthSource {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Resctrl => write!(f, "resctrl"),
+      Self::Imc => write!(f, "imc"),
+    }
+  }
+}
+
+/// PerfProfile selects the events counted by perf when a task does not change them.
+///
+/// # Variants
+///
+/// - **Default** - General counters: cycles, instructions, cache and branch misses and frontend stalls (`DEFAULT_PERF_EVENTS`).
+/// - **Memory** - Counters of SGX paging studies: data TLB and last level cache misses, page faults and page-walk cycles (`MEMORY_PERF_EVENTS`), counted in groups which fit the hardware counters (`MEMORY_PERF_GROUPS`).
+/// - **Frontend** - Counters of the instruction supply: instruction TLB and L1 instruction cache misses, frontend stalls, branch misses and page-walk cycles (`FRONTEND_PERF_EVENTS`).
+///
+/// Events of the profile the host cannot count are skipped with a warning.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PerfProfile {
+  #[default]
+  Default,
+  Memory,
+  Frontend,
+}
+
+impl PerfProfile {
+  pub fn events(&self) -> &'static [&'static str] {
+    match self {
+      Self::Default => &DEFAULT_PERF_EVENTS,
+      Self::Memory => &MEMORY_PERF_EVENTS,
+      Self::Frontend => &FRONTEND_PERF_EVENTS,
+    }
+  }
+
+  /// Events of the profile counted together as perf groups, so that they fit the hardware
+  /// counters.
+  pub fn groups(&self) -> &'static [&'static [&'static str]] {
+    match self {
+      Self::Memory => &MEMORY_PERF_GROUPS,
+      Self::Default | Self::Frontend => &[],
+    }
+  }
+}
+
+impl Display for PerfProfile {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Default => write!(f, "default"),
+      Self::Memory => write!(f, "memory"),
+      Self::Frontend => write!(f, "frontend"),
+    }
+  }
+}
+
+/// FailurePolicy sets the severity of each kind of problem of a run (see **RunHealth**), so that
+/// wrapper scripts and CI can tell unhealthy campaigns from the exit status: `EXIT_FAILED_SAMPLES`,
+/// `EXIT_TIMED_OUT_HOOKS` or `EXIT_PARTIAL_SAMPLES`, the first in this order with severity
+/// **Error**.
+///
+/// # Fields
+///
+/// - **failed_samples** - Defaults to **Warn**.
+/// - **timed_out_hooks** - Defaults to **Warn**.
+/// - **partial_samples** - Defaults to **Warn**.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FailurePolicy {
+  #[serde(default = "default_severity")]
+  pub failed_samples: Severity,
+
+  #[serde(default = "default_severity")]
+  pub timed_out_hooks: Severity,
+
+  #[serde(default = "default_severity")]
+  pub partial_samples: Severity,
+}
+
+impl Default for FailurePolicy {
+  fn default() -> Self {
+    FailurePolicy {
+      failed_samples: default_severity(),
+      timed_out_hooks: default_severity(),
+      partial_samples: default_severity(),
+    }
+  }
+}
+
+impl FailurePolicy {
+  /// Reports the problems of `health` with their severity. Returns the exit status of the run, if
+  /// any problem has severity **Error**.
+  pub fn check(&self, health: &RunHealth) -> Option<i32> {
+    let mut exit_code = None;
+    for (count, severity, description, code) in [
+      (
+        health.failed_samples,
+        self.failed_samples,
+        "samples failed",
+        EXIT_FAILED_SAMPLES,
+      ),
+      (
+        health.timed_out_hooks,
+        self.timed_out_hooks,
+        "hooks timed out",
+        EXIT_TIMED_OUT_HOOKS,
+      ),
+      (
+        health.partial_samples,
+        self.partial_samples,
+        "samples have partial metrics",
+        EXIT_PARTIAL_SAMPLES,
+      ),
+    ] {
+      if count == 0 {
+        continue;
+      }
+      match severity {
+        Severity::Ignore => {}
+        Severity::Warn => warn!("{} {}", count, description),
+        Severity::Error => {
+          error!("{} {}", count, description);
+          exit_code = exit_code.or(Some(code));
+        }
+      }
+    }
+    exit_code
+  }
+}
+
+// runs exit successfully whatever the health of their samples unless asked otherwise, as they
+// did before the failure policy
+fn default_severity() -> Severity {
+  Severity::Warn
+}
+
+/// SigningKey describes the key signing the enclaves of a campaign.
+///
+/// # Fields
+///
+/// - **path** - An optional existing private key (PKCS#1 or PKCS#8 PEM) used as is, e.g. a key
+///   shared by several hosts. No key is generated and the key is not copied to the output
+///   directory.
+/// - **bits** - The size of the generated key. Defaults to 3072.
+/// - **exponent** - The public exponent of the generated key. Defaults to 3.
+/// - **scheme** - The signature scheme (see **SigningScheme**). Defaults to RSA.
+///
+/// SGX only launches enclaves signed with RSA-3072 keys with exponent 3, which is what Gramine
+/// currently accepts; other sizes and exponents are meant for newer signing schemes.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SigningKey {
+  pub path: Option<PathBuf>,
+
+  #[serde(default = "default_signing_key_bits")]
+  pub bits: usize,
+
+  #[serde(default = "default_signing_key_exponent")]
+  pub exponent: u32,
+
+  #[serde(default)]
+  pub scheme: SigningScheme,
+}
+
+impl Default for SigningKey {
+  fn default() -> Self {
+    SigningKey {
+      path: None,
+      bits: default_signing_key_bits(),
+      exponent: default_signing_key_exponent(),
+      scheme: SigningScheme::default(),
+    }
+  }
+}
+
+impl SigningKey {
+  /// Whether SGX launches enclaves signed with a key generated from these parameters.
+  pub fn sgx_compatible(&self) -> bool {
+    match self.scheme {
+      SigningScheme::Rsa => self.bits == 3072 && self.exponent == 3,
+    }
+  }
+}
+
+fn default_signing_key_bits() -> usize {
+  3072
+}
+
+fn default_signing_key_exponent() -> u32 {
+  3
+}
+
+/// SigningScheme defines the signature schemes of the enclave signing key.
+///
+/// # Variants
+///
+/// - **Rsa** - RSA with PKCS#1 v1.5 padding, as in the SGX SIGSTRUCT.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningScheme {
+  #[default]
+  Rsa,
+}
+
+/// Retention limits the disk space used by results on long-lived benchmark hosts. It is applied
+/// at the end of a run.
+///
+/// # Fields
+///
+/// - **keep_deep_traces** - Raw deep traces are kept only for the last N campaigns (result
+///   directories next to `output_directory` with an `index.json`, including the current one).
+/// - **max_stdout_size** - `stdout` files of samples larger than this size (e.g. "10M") are deleted.
+#[derive(Deserialize, Debug, Default)]
+pub struct Retention {
+  pub keep_deep_traces: Option<usize>,
+
+  #[serde(default, deserialize_with = "deserialize_option_size")]
+  pub max_stdout_size: Option<u64>,
+}
+
+impl Retention {
+  pub fn apply(&self, output_directory: &Path) -> Result<(), std::io::Error> {
+    if let Some(keep) = self.keep_deep_traces {
+      for campaign in campaigns(output_directory)?.iter().skip(keep) {
+        for experiment in experiment_directories(campaign)? {
+          let deep_trace = experiment.join("deep-trace");
+          if deep_trace.is_dir() {
+            info!("retention: removing {:?}", deep_trace);
+            fs::remove_dir_all(deep_trace)?;
+          }
+        }
+      }
+    }
+
+    if let Some(max_size) = self.max_stdout_size {
+      for experiment in experiment_directories(output_directory)? {
+        for sample in fs::read_dir(experiment)?.flatten() {
+          let stdout = sample.path().join("stdout");
+          match fs::metadata(&stdout) {
+            Ok(metadata) if metadata.len() > max_size => {
+              info!(
+                "retention: removing {:?} ({} bytes)",
+                stdout,
+                metadata.len()
+              );
+              fs::remove_file(stdout)?;
+            }
+            _ => {}
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+// campaigns are the result directories next to `output_directory` with an index written by the
+// benchmark, newest first; anything else there is not ours to clean
+fn campaigns(output_directory: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+  let parent = match output_directory.parent() {
+    Some(p) if !p.as_os_str().is_empty() => p,
+    _ => Path::new("."),
+  };
+  let mut campaigns = Vec::new();
+  for entry in fs::read_dir(parent)?.flatten() {
+    let path = entry.path();
+    if indexed_experiments(&path).is_none() {
+      continue;
+    }
+    if let Ok(created) = path.join(INDEX_FILE).metadata().and_then(|m| m.modified()) {
+      campaigns.push((created, path));
+    }
+  }
+  campaigns.sort_by_key(|(created, _)| std::cmp::Reverse(*created));
+  Ok(campaigns.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Directories of the experiments of the campaign in `output_directory` which exist, in the order
+/// of the plan, as listed in its index. `None` for results of older versions, without an index.
+pub fn indexed_experiments(output_directory: &Path) -> Option<Vec<PathBuf>> {
+  #[derive(Deserialize)]
+  struct Entry {
+    directory: PathBuf,
+  }
+  let index = fs::read_to_string(output_directory.join(INDEX_FILE)).ok()?;
+  let entries: Vec<Entry> = serde_json::from_str(&index).ok()?;
+  Some(
+    entries
+      .into_iter()
+      .map(|entry| output_directory.join(entry.directory))
+      .filter(|directory| directory.is_dir())
+      .collect(),
+  )
+}
+
+// the directories of the index, or <campaign>/<program>/{gramine-sgx,no-gramine-sgx}/<experiment>
+// for results without an index
+fn experiment_directories(campaign: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+  if let Some(experiments) = indexed_experiments(campaign) {
+    // symlinks point to native baselines shared with other campaigns and are left alone
+    return Ok(
+      experiments
+        .into_iter()
+        .filter(|path| path.symlink_metadata().is_ok_and(|m| m.is_dir()))
+        .collect(),
+    );
+  }
+  let mut experiments = Vec::new();
+  for program in fs::read_dir(campaign)?.flatten() {
+    for kind in ["gramine-sgx", "no-gramine-sgx"] {
+      let Ok(entries) = fs::read_dir(program.path().join(kind)) else {
+        continue;
+      };
+      // symlinks point to native baselines shared with other campaigns and are left alone
+      experiments.extend(
+        entries
+          .flatten()
+          .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+          .map(|e| e.path()),
+      );
+    }
+  }
+  Ok(experiments)
+}
+
+fn deserialize_option_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  Option::<String>::deserialize(deserializer)?
+    .map(|v| {
+      parse_size(&v).ok_or_else(|| serde::de::Error::custom(format!("invalid size {:?}", v)))
+    })
+    .transpose()
+}
+
+fn deserialize_option_cpu_list<'de, D>(deserializer: D) -> Result<Option<Vec<usize>>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  Option::<String>::deserialize(deserializer)?
+    .map(|v| {
+      parse_cpu_list(&v)
+        .filter(|cpus| !cpus.is_empty())
+        .ok_or_else(|| {
+          serde::de::Error::custom(format!(
+            "invalid CPU list {:?}: expected CPUs and ranges separated by commas (e.g. \"2-7,10\")",
+            v
+          ))
+        })
+    })
+    .transpose()
 }
 
 /// Task represents a task to be executed, including its configuration and associated scripts.
@@ -38,14 +518,49 @@ pub struct GlobalParams {
 ///
 /// * **executable** - The path to the executable file for the task.
 /// * **args** - A vector of arguments to be passed to the executable. Defaults to an empty vector.
-/// - **num_threads** - A vector specifying the number of threads to be used in each experiment.
-/// - **enclave_size** - A vector of strings representing the possible enclave memory sizes. Each experiment will be run with every listed size.
-/// * **custom_manifest_path** - An optional path to a custom manifest file.
+/// * **args_matrix** - A map from an argument to a list of values. The task runs once for each combination of values (cartesian product), appending `<argument> <value>` to **args**. Defaults to an empty map.
+/// - **num_threads** - A vector specifying the number of threads to be used in each experiment. Can also be a range like `{ from = 1, to = 32, step = "x2" }`, deserialized using **deserialize_num_threads**.
+/// - **enclave_size** - A vector of strings representing the possible enclave memory sizes. Each experiment will be run with every listed size. Can also be a range like `{ from = "64M", to = "4G", factor = 2 }`, deserialized using **deserialize_enclave_size**.
+/// * **custom_manifest_path** - An optional custom manifest template, for every storage type or by storage type (see **CustomManifest**).
 /// * **storage_type** - A vector of storage types, deserialized using **deserialize_storage_type**. Defaults to **[StorageType::Untrusted]**.
 /// * **pre_run_executable** - An optional path to an executable to run before the main task.
 /// * **pre_run_args** - A vector of arguments for the pre-run executable. Defaults to an empty vector.
 /// * **post_run_executable** - An optional path to an executable to run after the main task.
 /// * **post_run_args** - A vector of arguments for the post-run executable. Defaults to an empty vector.
+/// * **setup_executable** - An optional path to an executable to run once before the samples of each experiment.
+/// * **setup_args** - A vector of arguments for the setup executable. Defaults to an empty vector.
+/// * **teardown_executable** - An optional path to an executable to run once after the samples of each experiment.
+/// * **teardown_args** - A vector of arguments for the teardown executable. Defaults to an empty vector.
+/// * **on_fail_executable** - An optional path to an executable to run when a sample fails. The sample directory is passed as last argument.
+/// * **on_fail_args** - A vector of arguments for the on-fail executable. Defaults to an empty vector.
+/// * **hook_timeout** - An optional time limit for every hook, deserialized using deserialize_option_duration. A hook running longer is killed and counts as failed.
+/// * **hooks_must_succeed** - If true, a failing setup or teardown aborts the experiment and a failing pre_run or post_run marks the sample as invalid. Defaults to false.
+/// * **energy_sample_interval** - An optional override of `globals.energy_sample_interval` for this task, deserialized using deserialize_option_duration.
+/// * **rpc_thread_num** - A vector of values for `sgx.insecure__rpc_thread_num` (exitless OCALLs). Each SGX experiment is run with every listed value; 0 disables exitless calls. Defaults to **[0]**.
+/// * **log_level** - A vector of Gramine log levels (`loader.log_level`, see **GramineLogLevel**). Each SGX experiment is run with every listed level, e.g. `["none", "error", "debug"]` to measure the probe effect of the logging of Gramine. Defaults to empty: the level follows `globals.debug`.
+/// * **runtime** - An optional interpreter running **executable** as a script (`python3` or `java`). The generated manifest uses the interpreter as entrypoint and mounts and trusts its library paths.
+/// * **discover_libraries** - If true, the shared libraries discovered with `ldd` are added one by one to `sgx.trusted_files` instead of whole library directories. Defaults to false.
+/// * **auxiliary_executables** - Absolute paths of the executables and scripts started by the workload (e.g. with exec or from a shell script). The generated manifest mounts and trusts them with their shared libraries, and the processes the workload starts are traced with it. Defaults to an empty vector.
+/// * **sgx_profile** - Gramine SGX profiler settings (`sgx.profile.enable` and `sgx.profile.mode`). Defaults to profiling disabled with mode `ocall_outer`.
+/// * **omp_places** - An optional value for `OMP_PLACES` set for both SGX and native runs.
+/// * **omp_proc_bind** - An optional value for `OMP_PROC_BIND` set for both SGX and native runs.
+/// * **uprobes** - Functions of the workload whose calls are counted and timed, and marked in the deep trace. Defaults to an empty vector.
+/// * **usdt** - Static tracepoints (USDT) of the workload or of Gramine whose hits are marked in the deep trace. Defaults to an empty vector.
+/// * **network** - The network namespace the workload runs in (`host`, `loopback` or `veth`, see **Network**). Defaults to `host`.
+/// * **netem** - Optional traffic shaping (latency, jitter, bandwidth) applied to the network of the workload during every sample (see **Netem**).
+/// * **fault** - An optional fault (kill or stop) injected into the workload in some samples to measure its recovery (see **Fault**).
+/// * **metrics** - Application-level metrics (requests/sec, final loss, ...) extracted from the output of the workload, by name (see **MetricPattern**). Defaults to none.
+/// * **perf_events** - Changes to the perf events of `globals` for this task: events to **add** and to **remove**, event **groups** and the **min_counted** share of the run time (see **PerfEvents**). Defaults to none.
+/// * **sample_duration** - An optional fixed duration of every sample, deserialized using deserialize_option_duration, for workloads with no natural end (servers, loops). The workload is then terminated with SIGTERM and rates are reported instead of its runtime.
+/// * **systemd_scope** - If set, the workload runs in a transient systemd scope with resource limits (see **SystemdScope**).
+/// * **pinning** - A vector of CPU pinning modes (see **Pinning**). Each experiment is run with every listed mode, e.g. `["none", "cores"]` to compare pinned and unpinned runs. Defaults to **["none"]**.
+/// * **transparent_hugepages** - If set, the system-wide transparent huge page mode during the experiments of the task (see **TransparentHugePages**). The previous mode is restored afterwards.
+/// * **nice** - An optional nice level of the workload, from -20 (highest priority) to 19. Negative levels require `CAP_SYS_NICE` (or `RLIMIT_NICE`).
+/// * **sched_policy** - An optional scheduling policy of the workload (see **SchedPolicy**).
+/// * **ready_check** - If set, measurement and the `pre_run` hook of every sample wait until the workload is ready (see **ReadyCheck**).
+/// * **disable_aslr** - If true, the address space layout of the workload is not randomized (`ADDR_NO_RANDOMIZE`, and `loader.insecure__disable_aslr` in the enclave). Defaults to false.
+/// * **cache_allocation** - If set, the L3 cache ways the workload can fill during the experiments of the task (see **CacheAllocation**).
+/// * **key_provisioning** - If set, SGX experiments are also run with the key of their encrypted files provisioned by a secret provisioning server after remote attestation, instead of the insecure key embedded in the manifest (see **KeyProvisioning**).
 #[derive(Deserialize, Clone, Debug)]
 pub struct Task {
   pub executable: PathBuf,
@@ -53,11 +568,18 @@ pub struct Task {
   #[serde(default)]
   pub args: Vec<String>,
 
-  #[serde(default = "default_num_threads")]
+  #[serde(default)]
+  pub args_matrix: BTreeMap<String, Vec<String>>,
+
+  #[serde(
+    default = "default_num_threads",
+    deserialize_with = "deserialize_num_threads"
+  )]
   pub num_threads: Vec<usize>,
+  #[serde(deserialize_with = "deserialize_enclave_size")]
   pub enclave_size: Vec<String>,
 
-  pub custom_manifest_path: Option<PathBuf>,
+  pub custom_manifest_path: Option<CustomManifest>,
   #[serde(
     default = "default_storage_type",
     deserialize_with = "deserialize_storage_type"
@@ -72,51 +594,1239 @@ pub struct Task {
   #[serde(default)]
   pub post_run_args: Vec<String>,
 
+  pub setup_executable: Option<PathBuf>,
+  #[serde(default)]
+  pub setup_args: Vec<String>,
+
+  pub teardown_executable: Option<PathBuf>,
+  #[serde(default)]
+  pub teardown_args: Vec<String>,
+
+  pub on_fail_executable: Option<PathBuf>,
+  #[serde(default)]
+  pub on_fail_args: Vec<String>,
+
+  #[serde(default)]
+  pub hooks_must_succeed: bool,
+  #[serde(default, deserialize_with = "deserialize_option_duration")]
+  pub hook_timeout: Option<Duration>,
+
   pub env: Option<HashMap<String, String>>,
+
+  #[serde(default = "default_rpc_thread_num")]
+  pub rpc_thread_num: Vec<usize>,
+
+  #[serde(default)]
+  pub log_level: Vec<GramineLogLevel>,
+
+  pub runtime: Option<Runtime>,
+
+  #[serde(default)]
+  pub discover_libraries: bool,
+
+  #[serde(default)]
+  pub auxiliary_executables: Vec<PathBuf>,
+
+  #[serde(default)]
+  pub sgx_profile: SgxProfile,
+
+  pub omp_places: Option<String>,
+  pub omp_proc_bind: Option<String>,
+
+  #[serde(default, deserialize_with = "deserialize_option_duration")]
+  pub energy_sample_interval: Option<Duration>,
+
+  #[serde(default)]
+  pub uprobes: Vec<Uprobe>,
+
+  #[serde(default)]
+  pub usdt: Vec<Usdt>,
+
+  #[serde(default)]
+  pub network: Network,
+
+  pub netem: Option<Netem>,
+
+  pub fault: Option<Fault>,
+
+  #[serde(default, deserialize_with = "deserialize_option_duration")]
+  pub sample_duration: Option<Duration>,
+
+  #[serde(default, deserialize_with = "deserialize_metric_patterns")]
+  pub metrics: Vec<MetricPattern>,
+
+  #[serde(default)]
+  pub perf_events: PerfEvents,
+
+  pub systemd_scope: Option<SystemdScope>,
+
+  #[serde(default = "default_pinning")]
+  pub pinning: Vec<Pinning>,
+
+  pub transparent_hugepages: Option<TransparentHugePages>,
+
+  pub nice: Option<i32>,
+
+  pub sched_policy: Option<SchedPolicy>,
+
+  pub ready_check: Option<ReadyCheck>,
+
+  #[serde(default)]
+  pub disable_aslr: bool,
+
+  pub cache_allocation: Option<CacheAllocation>,
+
+  pub key_provisioning: Option<KeyProvisioning>,
+}
+
+impl Task {
+  /// Returns the number of experiments (SGX and native) this task runs. Each experiment runs
+  /// `globals.sample_size` samples.
+  pub fn experiment_count(&self) -> usize {
+    let combinations: usize = self
+      .args_matrix
+      .values()
+      .filter(|values| !values.is_empty())
+      .map(Vec::len)
+      .product();
+    let storage: usize = self
+      .storage_type
+      .iter()
+      .map(|storage_type| self.key_provisioning_modes(storage_type).len())
+      .sum();
+    let sgx =
+      self.enclave_size.len() * storage * self.rpc_thread_num.len() * self.log_level.len().max(1);
+    self.num_threads.len() * self.pinning.len() * combinations * (sgx + 1)
+  }
+
+  /// Returns whether the SGX experiments of the task with `storage_type` get the key of their
+  /// encrypted files from the provisioning server, in the order they are run: with the embedded
+  /// key first. Only the encrypted storage has a key to provision.
+  pub fn key_provisioning_modes(&self, storage_type: &StorageType) -> Vec<bool> {
+    match &self.key_provisioning {
+      _ if *storage_type != StorageType::Encrypted => vec![false],
+      None => vec![false],
+      Some(provisioning) if provisioning.embedded_key => vec![false, true],
+      Some(_) => vec![true],
+    }
+  }
+
+  /// Returns whether the SGX experiments of the task enable EDMM (`EDMM = "1"` in **env**).
+  pub fn edmm(&self) -> bool {
+    self
+      .env
+      .as_ref()
+      .is_some_and(|env| env.get("EDMM").is_some_and(|v| v == "1"))
+  }
+
+  /// Checks the parameters of the task which are only rejected by Gramine or the kernel, so that a
+  /// mistake fails when the configuration is loaded instead of in the middle of a campaign:
+  /// without EDMM, enclave sizes must be powers of two, the nice level and real-time priority
+  /// must be in the ranges of Linux, auxiliary executables must be absolute paths, perf groups must
+  /// not be empty, `min_counted` must be a percentage, a cache allocation must have either
+  /// ways or a contiguous mask and key provisioning needs a server address, an absolute CA
+  /// chain, the encrypted storage and the network of the host (the namespace of an experiment has
+  /// no route to the server).
+  pub fn validate(&self) -> Result<(), String> {
+    if let Some(provisioning) = &self.key_provisioning {
+      provisioning
+        .validate()
+        .map_err(|e| format!("task {:?}: {}", self.executable, e))?;
+      if !self.storage_type.contains(&StorageType::Encrypted) {
+        return Err(format!(
+          "task {:?}: key_provisioning needs the encrypted storage_type",
+          self.executable
+        ));
+      }
+      if self.network != Network::Host {
+        return Err(format!(
+          "task {:?}: key_provisioning needs the host network",
+          self.executable
+        ));
+      }
+    }
+    if let Some(allocation) = &self.cache_allocation {
+      allocation
+        .validate()
+        .map_err(|e| format!("task {:?}: {}", self.executable, e))?;
+      // systemd starts the workload from its own process
+      if self.systemd_scope.is_some() {
+        return Err(format!(
+          "task {:?}: cache_allocation cannot be used with systemd_scope",
+          self.executable
+        ));
+      }
+    }
+    if self.perf_events.groups.iter().any(Vec::is_empty) {
+      return Err(format!(
+        "task {:?}: empty perf event group",
+        self.executable
+      ));
+    }
+    if let Some(min_counted) = self
+      .perf_events
+      .min_counted
+      .filter(|min_counted| !(*min_counted > 0.0 && *min_counted <= 100.0))
+    {
+      return Err(format!(
+        "task {:?}: perf min_counted {} is not between 0 and 100",
+        self.executable, min_counted
+      ));
+    }
+    if let Some(executable) = self
+      .auxiliary_executables
+      .iter()
+      .find(|executable| !executable.is_absolute())
+    {
+      return Err(format!(
+        "task {:?}: auxiliary executable {:?} is not an absolute path",
+        self.executable, executable
+      ));
+    }
+    if let Some(nice) = self.nice.filter(|nice| !(-20..=19).contains(nice)) {
+      return Err(format!(
+        "task {:?}: nice {} is not between -20 and 19",
+        self.executable, nice
+      ));
+    }
+    if let Some(policy) = self.sched_policy.filter(|policy| {
+      matches!(policy, SchedPolicy::Fifo { .. } | SchedPolicy::Rr { .. })
+        && !(1..=99).contains(&policy.priority())
+    }) {
+      return Err(format!(
+        "task {:?}: the priority of sched_policy {} is not between 1 and 99",
+        self.executable, policy
+      ));
+    }
+    if self.edmm() {
+      return Ok(());
+    }
+    for size in &self.enclave_size {
+      if !parse_size(size).is_some_and(u64::is_power_of_two) {
+        return Err(format!(
+          "task {:?}: enclave size {:?} is not a power of two, which Gramine requires without EDMM",
+          self.executable, size
+        ));
+      }
+    }
+    Ok(())
+  }
 }
 
 pub fn default_num_threads() -> Vec<usize> {
   vec![1]
 }
 
-/// StorageType defines the types of storage that can be used.
+pub fn default_rpc_thread_num() -> Vec<usize> {
+  vec![0]
+}
+
+pub fn default_pinning() -> Vec<Pinning> {
+  vec![Pinning::None]
+}
+
+/// GramineLogLevel defines the verbosity of Gramine in the enclave (`loader.log_level`). Logging
+/// is done by the enclave itself, so more verbose levels add OCALLs (writes to stderr) to the
+/// measurements of the workload.
 ///
 /// # Variants
 ///
-/// - **Encrypted** - Represents encrypted storage.
-/// - **Tmpfs** - Represents temporary file system storage.
-/// - **Untrusted** - Represents untrusted storage.
-#[derive(Deserialize, Clone, Debug, PartialEq)]
+/// - **None** - Nothing is logged.
+/// - **Error**, **Warning**, **Debug**, **Trace** - Messages up to this severity are logged.
+/// - **All** - Every message is logged.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
-pub enum StorageType {
-  Encrypted,
-  Untrusted,
+pub enum GramineLogLevel {
+  None,
+  Error,
+  Warning,
+  Debug,
+  Trace,
+  All,
 }
 
-impl Display for StorageType {
+impl Display for GramineLogLevel {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
-      Self::Encrypted => write!(f, "encrypted"),
-      Self::Untrusted => write!(f, "untrusted"),
+      Self::None => write!(f, "none"),
+      Self::Error => write!(f, "error"),
+      Self::Warning => write!(f, "warning"),
+      Self::Debug => write!(f, "debug"),
+      Self::Trace => write!(f, "trace"),
+      Self::All => write!(f, "all"),
     }
   }
 }
-// ensure storage type is not empty
-// could happen if the user writes storage_type = []
-fn deserialize_storage_type<'de, D>(deserializer: D) -> Result<Vec<StorageType>, D::Error>
-where
-  D: serde::Deserializer<'de>,
-{
-  let v: Option<Vec<StorageType>> = Option::deserialize(deserializer)?;
-  Ok(if let Some(array) = v {
-    if array.is_empty() {
-      default_storage_type()
-    } else {
-      array
-    }
-  } else {
-    default_storage_type()
-  })
+
+/// Pinning defines how the workload is placed on the CPUs of the host.
+///
+/// # Variants
+///
+/// - **None** - The scheduler places the workload anywhere (no CPU affinity).
+/// - **Cores** - The workload is restricted to one logical CPU per thread, on distinct physical cores first and then on their hyper-threads (see **system::pinned_cpus**).
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Pinning {
+  #[default]
+  None,
+  Cores,
+}
+
+impl Display for Pinning {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::None => write!(f, "none"),
+      Self::Cores => write!(f, "cores"),
+    }
+  }
+}
+
+/// SchedPolicy defines the Linux scheduling policy of the workload, set with `sched_setscheduler`
+/// before it is executed and inherited by its threads and children. Real-time policies keep the
+/// workload from being preempted by the threads of the benchmark and by background daemons, and
+/// require `CAP_SYS_NICE` (or `RLIMIT_RTPRIO`).
+///
+/// # Variants
+///
+/// - **Other** - The default time-sharing policy (`SCHED_OTHER`), weighted by the nice level.
+/// - **Batch** - Time-sharing for CPU-bound, non-interactive work (`SCHED_BATCH`).
+/// - **Idle** - Runs only when nothing else does (`SCHED_IDLE`).
+/// - **Fifo** - Real-time first-in first-out (`SCHED_FIFO`) with a **priority** from 1 to 99.
+/// - **Rr** - Real-time round-robin (`SCHED_RR`) with a **priority** from 1 to 99.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum SchedPolicy {
+  Other,
+  Batch,
+  Idle,
+  Fifo { priority: i32 },
+  Rr { priority: i32 },
+}
+
+impl SchedPolicy {
+  /// Returns the `SCHED_*` constant of the policy.
+  pub fn policy(&self) -> i32 {
+    match self {
+      Self::Other => libc::SCHED_OTHER,
+      Self::Batch => libc::SCHED_BATCH,
+      Self::Idle => libc::SCHED_IDLE,
+      Self::Fifo { .. } => libc::SCHED_FIFO,
+      Self::Rr { .. } => libc::SCHED_RR,
+    }
+  }
+
+  /// Returns the static priority of the policy; 0 for the policies which are not real-time.
+  pub fn priority(&self) -> i32 {
+    match self {
+      Self::Fifo { priority } | Self::Rr { priority } => *priority,
+      _ => 0,
+    }
+  }
+}
+
+impl Display for SchedPolicy {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Other => write!(f, "other"),
+      Self::Batch => write!(f, "batch"),
+      Self::Idle => write!(f, "idle"),
+      Self::Fifo { priority } => write!(f, "fifo (priority {})", priority),
+      Self::Rr { priority } => write!(f, "rr (priority {})", priority),
+    }
+  }
+}
+
+/// CacheAllocation restricts the L3 cache ways the workload can fill, with the cache allocation
+/// technology (CAT) of Intel RDT: a control group of resctrl (mounted on `/sys/fs/resctrl`) is
+/// created for every experiment and the workload is moved to it before it executes. The same
+/// ways are allocated in every cache domain; other processes of the host keep every way.
+///
+/// # Fields
+///
+/// - **ways** - The number of ways allocated, the lowest ones of the cache.
+/// - **mask** - A capacity bitmask in hexadecimal (e.g. "0x0f0"), instead of **ways**. Its bits must be contiguous.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CacheAllocation {
+  pub ways: Option<u32>,
+  pub mask: Option<String>,
+}
+
+impl CacheAllocation {
+  /// Returns the value of **mask**, if it is a non-empty hexadecimal mask of contiguous bits.
+  pub fn parsed_mask(&self) -> Option<u64> {
+    let mask = self.mask.as_deref()?;
+    let digits = mask
+      .strip_prefix("0x")
+      .or_else(|| mask.strip_prefix("0X"))
+      .unwrap_or(mask);
+    // without its trailing zeros, a mask of contiguous bits is one less than a power of two
+    u64::from_str_radix(digits, 16)
+      .ok()
+      .filter(|&mask| mask != 0)
+      .filter(|&mask| {
+        let bits = mask >> mask.trailing_zeros();
+        bits & bits.wrapping_add(1) == 0
+      })
+  }
+
+  fn validate(&self) -> Result<(), String> {
+    match (self.ways, &self.mask) {
+      (Some(0), None) => Err(String::from("cache_allocation needs at least one way")),
+      (Some(_), None) => Ok(()),
+      (None, Some(mask)) if self.parsed_mask().is_none() => Err(format!(
+        "cache mask {:?} is not a hexadecimal mask of contiguous bits",
+        mask
+      )),
+      (None, Some(_)) => Ok(()),
+      _ => Err(String::from("cache_allocation needs either ways or a mask")),
+    }
+  }
+}
+
+/// KeyProvisioning replaces the insecure key of the encrypted files of an enclave
+/// (`fs.insecure__keys.default`) with a key provisioned after remote attestation: the secret
+/// provisioning library of Gramine (`libsecret_prov_attest.so`) connects to the server before the
+/// workload starts, proves the identity of the enclave with an RA-TLS certificate (DCAP quote)
+/// and receives the key. The server is not started by the benchmark, e.g. the
+/// `secret_prov_server_dcap` of the examples of Gramine, which must accept debug enclaves.
+///
+/// # Fields
+///
+/// - **server** - Address of the secret provisioning server (`host:port`).
+/// - **ca_chain** - Absolute path of the CA certificate the server certificate is verified with. It is mounted and trusted in the enclave.
+/// - **embedded_key** - If true, every SGX experiment is run with the embedded key as well, as the reference of the provisioned one. Defaults to true.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct KeyProvisioning {
+  pub server: String,
+  pub ca_chain: PathBuf,
+  #[serde(default = "default_embedded_key")]
+  pub embedded_key: bool,
+}
+
+fn default_embedded_key() -> bool {
+  true
+}
+
+impl KeyProvisioning {
+  fn validate(&self) -> Result<(), String> {
+    if !self
+      .server
+      .rsplit_once(':')
+      .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+    {
+      return Err(format!(
+        "key provisioning server {:?} is not a host:port address",
+        self.server
+      ));
+    }
+    if !self.ca_chain.is_absolute() {
+      return Err(format!(
+        "key provisioning CA chain {:?} is not an absolute path",
+        self.ca_chain
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// TransparentHugePages defines the transparent huge page mode of the host
+/// (`/sys/kernel/mm/transparent_hugepage/enabled`). The mode is system-wide: setting it requires
+/// root and affects every process of the host, Gramine included.
+///
+/// # Variants
+///
+/// - **Always** - Anonymous memory is backed by huge pages whenever possible.
+/// - **Madvise** - Only memory marked with `madvise(MADV_HUGEPAGE)` is backed by huge pages.
+/// - **Never** - Anonymous memory is never backed by transparent huge pages.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransparentHugePages {
+  Always,
+  Madvise,
+  Never,
+}
+
+impl Display for TransparentHugePages {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Always => write!(f, "always"),
+      Self::Madvise => write!(f, "madvise"),
+      Self::Never => write!(f, "never"),
+    }
+  }
+}
+
+/// StorageType defines the types of storage that can be used.
+///
+/// # Variants
+///
+/// - **Encrypted** - Represents encrypted storage.
+/// - **Tmpfs** - Represents temporary file system storage, in the memory of the enclave (`/tmp/`).
+/// - **Untrusted** - Represents untrusted storage.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageType {
+  Encrypted,
+  Tmpfs,
+  Untrusted,
+}
+
+impl Display for StorageType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Encrypted => write!(f, "encrypted"),
+      Self::Tmpfs => write!(f, "tmpfs"),
+      Self::Untrusted => write!(f, "untrusted"),
+    }
+  }
+}
+/// CustomManifest is the manifest template of the SGX experiments of a task, instead of the default
+/// one.
+///
+/// # Variants
+///
+/// - **Path** - A template used for every storage type.
+/// - **ByStorageType** - A template by storage type (e.g. `{ encrypted = "enc.manifest.template" }`),
+///   for storage types needing structurally different manifests. Storage types which are not
+///   listed use the default template.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum CustomManifest {
+  Path(PathBuf),
+  ByStorageType(BTreeMap<StorageType, PathBuf>),
+}
+
+impl CustomManifest {
+  /// Returns the template of the experiments with `storage_type`, if not the default one.
+  pub fn for_storage_type(&self, storage_type: &StorageType) -> Option<&Path> {
+    match self {
+      CustomManifest::Path(path) => Some(path),
+      CustomManifest::ByStorageType(paths) => paths.get(storage_type).map(PathBuf::as_path),
+    }
+  }
+}
+
+/// Runtime is an interpreter that runs the task executable as a script.
+///
+/// # Variants
+///
+/// - **Python3** - Runs the executable with `python3`.
+/// - **Java** - Runs the executable with `java` (with `-jar` for `.jar` files).
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Runtime {
+  Python3,
+  Java,
+}
+
+impl Runtime {
+  /// Returns the path of the interpreter looked up in `PATH`, or its bare name if it cannot be
+  /// found (so that spawning it reports the error).
+  pub fn interpreter(&self) -> PathBuf {
+    let name = self.to_string();
+    env::var_os("PATH")
+      .and_then(|paths| {
+        env::split_paths(&paths)
+          .map(|path| path.join(&name))
+          .find(|path| path.is_file())
+      })
+      .unwrap_or_else(|| PathBuf::from(name))
+  }
+
+  /// Returns the interpreter arguments that run `script`.
+  pub fn script_args(&self, script: &Path) -> Vec<String> {
+    let script_arg = script.to_string_lossy().into_owned();
+    match self {
+      Self::Java if script.extension().is_some_and(|ext| ext == "jar") => {
+        vec![String::from("-jar"), script_arg]
+      }
+      Self::Python3 | Self::Java => vec![script_arg],
+    }
+  }
+}
+
+impl Display for Runtime {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Python3 => write!(f, "python3"),
+      Self::Java => write!(f, "java"),
+    }
+  }
+}
+
+/// Uprobe is a function of the workload traced at every call.
+///
+/// # Fields
+///
+/// - **binary** - The executable or shared library defining the function. Only code outside of the enclave can be traced.
+/// - **symbol** - The name of the function in the symbol table of **binary**.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Uprobe {
+  pub binary: PathBuf,
+  pub symbol: String,
+}
+
+/// MetricPattern extracts an application-level metric from the output of the workload: the value
+/// is the first capture group of the last match in stdout, or else in stderr. Patterns are given
+/// by metric name in `[tasks.metrics]`, either as a regular expression or as a table with a
+/// `pattern` and a `unit`.
+///
+/// # Fields
+///
+/// - **name** - The name of the metric in `summary.csv` (letters, digits and underscores).
+/// - **regex** - The regular expression; it must have a capture group.
+/// - **unit** - The unit of the metric in `summary.csv`; it cannot hold commas, quotes or control
+///   characters. Defaults to an empty string.
+#[derive(Serialize, Clone, Debug)]
+pub struct MetricPattern {
+  pub name: String,
+  #[serde(serialize_with = "serialize_regex")]
+  pub regex: Regex,
+  pub unit: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MetricPatternSpec {
+  Pattern(String),
+  Table {
+    pattern: String,
+    #[serde(default)]
+    unit: String,
+  },
+}
+
+fn deserialize_metric_patterns<'de, D>(deserializer: D) -> Result<Vec<MetricPattern>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  BTreeMap::<String, MetricPatternSpec>::deserialize(deserializer)?
+    .into_iter()
+    .map(|(name, spec)| {
+      let (pattern, unit) = match spec {
+        MetricPatternSpec::Pattern(pattern) => (pattern, String::new()),
+        MetricPatternSpec::Table { pattern, unit } => (pattern, unit),
+      };
+      if !is_metric_name(&name) {
+        return Err(serde::de::Error::custom(format!(
+          "invalid metric name {:?}",
+          name
+        )));
+      }
+      let regex = Regex::new(&pattern).map_err(|e| {
+        serde::de::Error::custom(format!("invalid pattern of metric {:?}: {}", name, e))
+      })?;
+      if !is_metric_unit(&unit) {
+        return Err(serde::de::Error::custom(format!(
+          "invalid unit {:?} of metric {:?}",
+          unit, name
+        )));
+      }
+      if regex.captures_len() < 2 {
+        return Err(serde::de::Error::custom(format!(
+          "pattern of metric {:?} has no capture group",
+          name
+        )));
+      }
+      Ok(MetricPattern { name, regex, unit })
+    })
+    .collect()
+}
+
+/// PerfEvents changes the perf events counted for a task, e.g. LLC misses for a memory-bound
+/// workload or dTLB misses for a page-heavy one.
+///
+/// # Fields
+///
+/// - **add** - Events counted in addition to the default and global extra ones. Defaults to none.
+/// - **remove** - Default or global extra events not counted. Defaults to none.
+/// - **groups** - Events counted together as a perf group (`{a,b}`): they are scheduled on the
+///   hardware counters at the same time, so ratios between them stay exact when the other events
+///   are multiplexed. An event of a group is not counted outside of it. Defaults to none.
+/// - **min_counted** - Optional share of the run time (in percent) every event must have been
+///   counted for; a sample with a more multiplexed event is marked invalid.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub struct PerfEvents {
+  #[serde(default)]
+  pub add: Vec<String>,
+  #[serde(default)]
+  pub remove: Vec<String>,
+  #[serde(default)]
+  pub groups: Vec<Vec<String>>,
+  pub min_counted: Option<f64>,
+}
+
+// regular expressions are serialized as their pattern
+fn serialize_regex<S>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error>
+where
+  S: serde::Serializer,
+{
+  serializer.serialize_str(regex.as_str())
+}
+
+/// Returns true if `name` can name a metric of `summary.csv`: letters, digits and underscores.
+pub fn is_metric_name(name: &str) -> bool {
+  !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns true if `unit` can be the unit of a metric of `summary.csv`: no commas, quotes or
+/// control characters, which would break its row.
+pub fn is_metric_unit(unit: &str) -> bool {
+  unit
+    .chars()
+    .all(|c| !c.is_control() && c != ',' && c != '"')
+}
+
+/// Usdt is a static tracepoint compiled into a binary, e.g. the PAL events of Gramine builds
+/// with USDT support.
+///
+/// # Fields
+///
+/// - **binary** - The executable or shared library defining the tracepoint.
+/// - **provider** - The provider of the tracepoint (e.g. `gramine`).
+/// - **name** - The name of the tracepoint (e.g. `ocall_start`).
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Usdt {
+  pub binary: PathBuf,
+  pub provider: String,
+  pub name: String,
+}
+
+/// Network is the network namespace a workload runs in. Outside of `host`, every experiment gets
+/// a fresh namespace, so that servers find their ports free and network metrics are not
+/// contaminated by host traffic. Hooks keep running in the namespace of the host.
+///
+/// # Variants
+///
+/// - **Host** - The namespace of the host.
+/// - **Loopback** - A namespace with only the loopback interface up.
+/// - **Veth** - Like **Loopback**, plus a veth pair linking the namespace to the host on a /30
+///   subnet of `10.200.0.0/16` not in use on the host, so that hooks can reach the workload (at
+///   `$EB_WORKLOAD_ADDRESS`).
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+  #[default]
+  Host,
+  Loopback,
+  Veth,
+}
+
+/// Netem shapes the egress traffic of the workload with `tc netem` from the `pre_run` hook to the
+/// `post_run` hook of every sample, e.g. to evaluate remote attestation under realistic network
+/// conditions.
+///
+/// # Fields
+///
+/// - **latency** - An optional delay added to every packet, deserialized using deserialize_option_duration (e.g. "20ms").
+/// - **jitter** - An optional random variation of **latency**, deserialized using deserialize_option_duration.
+/// - **bandwidth** - An optional rate limit in `tc` units (e.g. "100mbit").
+/// - **device** - The interface to shape, in the network namespace of the workload. Defaults to the interfaces created by **network** (`lo` for `loopback`, both ends of the veth pair for `veth`); required with the `host` network.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub struct Netem {
+  #[serde(default, deserialize_with = "deserialize_option_duration")]
+  pub latency: Option<Duration>,
+  #[serde(default, deserialize_with = "deserialize_option_duration")]
+  pub jitter: Option<Duration>,
+  pub bandwidth: Option<String>,
+  pub device: Option<String>,
+}
+
+/// SystemdScope runs every sample of the workload in a transient systemd scope
+/// (`systemd-run --scope`) with resource limits, for isolation and cleanup on hosts where raw
+/// cgroup manipulation is restricted. `systemd-run` executes the workload in place, so the traced
+/// process is the workload itself. Hooks run outside of the scope.
+///
+/// # Fields
+///
+/// - **cpu_quota** - An optional `CPUQuota` of the scope (e.g. "200%" for two CPUs).
+/// - **memory_max** - An optional `MemoryMax` of the scope (e.g. "4G").
+/// - **allowed_cpus** - An optional `AllowedCPUs` of the scope (e.g. "0-3").
+/// - **properties** - Other properties of the scope, by name (e.g. `{ IOWeight = "50" }`). Defaults to none.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub struct SystemdScope {
+  pub cpu_quota: Option<String>,
+  pub memory_max: Option<String>,
+  pub allowed_cpus: Option<String>,
+  #[serde(default)]
+  pub properties: BTreeMap<String, String>,
+}
+
+impl SystemdScope {
+  /// Returns the arguments of `systemd-run` starting the command which follows them in the scope.
+  pub fn systemd_run_args(&self) -> Vec<String> {
+    // without --quiet, systemd-run reports the name of the unit on the stderr of the workload
+    let mut args = vec![
+      String::from("--scope"),
+      String::from("--quiet"),
+      String::from("--collect"),
+    ];
+    let properties = [
+      ("CPUQuota", &self.cpu_quota),
+      ("MemoryMax", &self.memory_max),
+      ("AllowedCPUs", &self.allowed_cpus),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value.as_ref()?)))
+    .chain(
+      self
+        .properties
+        .iter()
+        .map(|(name, value)| (name.as_str(), value)),
+    );
+    for (name, value) in properties {
+      args.push(format!("--property={}={}", name, value));
+    }
+    args.push(String::from("--"));
+    args
+  }
+}
+
+/// Fault kills or stops the workload in some samples, e.g. to evaluate checkpoint/restart
+/// strategies for enclaves. Every **every**-th sample is faulted; the sample after it restarts the
+/// workload (rebuilding the enclave) and is marked as a recovery sample. Neither is part of the
+/// regular aggregation.
+///
+/// # Fields
+///
+/// - **action** - `kill` (SIGKILL) or `stop` (SIGSTOP, then SIGCONT after **stop_for**).
+/// - **after** - Time from the spawn of the workload to the fault, deserialized using deserialize_duration (e.g. "5s"). Checked every 50ms.
+/// - **after_syscalls** - Number of system calls of the workload after which the tracer injects the fault. Exactly one of **after** and **after_syscalls** must be set.
+/// - **stop_for** - How long a stopped workload stays stopped, deserialized using deserialize_duration. Defaults to 1s.
+/// - **every** - Period of the faulted samples, at least 2 (2 faults samples 2, 4, ...). Defaults to 2.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "FaultSpec")]
+pub struct Fault {
+  pub action: FaultAction,
+  pub trigger: FaultTrigger,
+  pub stop_for: Duration,
+  pub every: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultAction {
+  Kill,
+  Stop,
+}
+
+impl FaultAction {
+  /// Returns the signal injecting the fault.
+  pub fn signal(&self) -> i32 {
+    match self {
+      FaultAction::Kill => libc::SIGKILL,
+      FaultAction::Stop => libc::SIGSTOP,
+    }
+  }
+}
+
+/// When a fault is injected.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum FaultTrigger {
+  /// Time from the spawn of the workload.
+  After(Duration),
+  /// Number of system calls of the workload.
+  Syscalls(u64),
+}
+
+#[derive(Deserialize)]
+struct FaultSpec {
+  action: FaultAction,
+  #[serde(default, deserialize_with = "deserialize_option_duration")]
+  after: Option<Duration>,
+  after_syscalls: Option<u64>,
+  #[serde(
+    default = "default_fault_stop_for",
+    deserialize_with = "deserialize_duration"
+  )]
+  stop_for: Duration,
+  #[serde(default = "default_fault_every")]
+  every: u32,
+}
+
+impl TryFrom<FaultSpec> for Fault {
+  type Error = String;
+
+  fn try_from(spec: FaultSpec) -> Result<Self, Self::Error> {
+    let trigger = match (spec.after, spec.after_syscalls) {
+      (Some(after), None) => FaultTrigger::After(after),
+      (None, Some(syscalls)) if syscalls > 0 => FaultTrigger::Syscalls(syscalls),
+      (None, Some(_)) => return Err(String::from("fault after_syscalls must be positive")),
+      _ => {
+        return Err(String::from(
+          "fault needs exactly one of after and after_syscalls",
+        ))
+      }
+    };
+    if spec.every < 2 {
+      return Err(String::from("fault every must be at least 2"));
+    }
+    Ok(Self {
+      action: spec.action,
+      trigger,
+      stop_for: spec.stop_for,
+      every: spec.every,
+    })
+  }
+}
+
+fn default_fault_stop_for() -> Duration {
+  Duration::from_secs(1)
+}
+
+fn default_fault_every() -> u32 {
+  2
+}
+
+/// ReadyCheck tells when a long-running workload (e.g. a server) is ready to serve: measurement
+/// and the `pre_run` hook, which usually starts the clients, only start once the check passes, so
+/// that the variable startup time (e.g. building the enclave) is not part of the measured window.
+///
+/// # Fields
+///
+/// - **type** - `tcp` (a TCP connection to **port** succeeds) or `log` (a line of the output of
+///   the workload matches **pattern**).
+/// - **host** - The host the `tcp` check connects to, from the network namespace of the workload. Defaults to 127.0.0.1.
+/// - **port** - The port of the `tcp` check.
+/// - **pattern** - The regular expression of the `log` check, matched on stdout and stderr.
+/// - **timeout** - How long to wait for the workload to be ready, deserialized using deserialize_duration. The sample fails and the workload is killed afterwards. Defaults to 60s.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(try_from = "ReadyCheckSpec")]
+pub struct ReadyCheck {
+  pub probe: ReadyProbe,
+  pub timeout: Duration,
+}
+
+/// How the readiness of a workload is checked.
+#[derive(Serialize, Clone, Debug)]
+pub enum ReadyProbe {
+  /// A TCP connection to `host:port` succeeds.
+  Tcp { host: String, port: u16 },
+  /// A line of the output of the workload matches.
+  Log(#[serde(serialize_with = "serialize_regex")] Regex),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ReadyProbeKind {
+  Tcp,
+  Log,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadyCheckSpec {
+  #[serde(rename = "type")]
+  kind: ReadyProbeKind,
+  host: Option<String>,
+  port: Option<u16>,
+  pattern: Option<String>,
+  #[serde(
+    default = "default_ready_timeout",
+    deserialize_with = "deserialize_duration"
+  )]
+  timeout: Duration,
+}
+
+impl TryFrom<ReadyCheckSpec> for ReadyCheck {
+  type Error = String;
+
+  fn try_from(spec: ReadyCheckSpec) -> Result<Self, Self::Error> {
+    let probe = match (spec.kind, spec.port, spec.pattern) {
+      (ReadyProbeKind::Tcp, Some(port), None) => ReadyProbe::Tcp {
+        host: spec.host.unwrap_or_else(|| String::from("127.0.0.1")),
+        port,
+      },
+      (ReadyProbeKind::Log, None, Some(pattern)) if spec.host.is_none() => ReadyProbe::Log(
+        Regex::new(&pattern).map_err(|e| format!("invalid ready_check pattern: {}", e))?,
+      ),
+      (ReadyProbeKind::Tcp, ..) => {
+        return Err(String::from(
+          "ready_check of type tcp needs a port (and no pattern)",
+        ))
+      }
+      (ReadyProbeKind::Log, ..) => {
+        return Err(String::from(
+          "ready_check of type log needs a pattern (and no host or port)",
+        ))
+      }
+    };
+    Ok(Self {
+      probe,
+      timeout: spec.timeout,
+    })
+  }
+}
+
+fn default_ready_timeout() -> Duration {
+  Duration::from_secs(60)
+}
+
+/// SgxProfile configures the Gramine SGX profiler for a task.
+///
+/// # Fields
+///
+/// - **enable** - Which threads are profiled (`sgx.profile.enable`): `none`, `main` or `all`.
+/// - **mode** - What is sampled (`sgx.profile.mode`): `aex`, `ocall_inner` or `ocall_outer`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct SgxProfile {
+  #[serde(default)]
+  pub enable: SgxProfileEnable,
+  #[serde(default)]
+  pub mode: SgxProfileMode,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SgxProfileEnable {
+  #[default]
+  None,
+  Main,
+  All,
+}
+
+impl Display for SgxProfileEnable {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::None => write!(f, "none"),
+      Self::Main => write!(f, "main"),
+      Self::All => write!(f, "all"),
+    }
+  }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SgxProfileMode {
+  Aex,
+  OcallInner,
+  #[default]
+  OcallOuter,
+}
+
+impl Display for SgxProfileMode {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Aex => write!(f, "aex"),
+      Self::OcallInner => write!(f, "ocall_inner"),
+      Self::OcallOuter => write!(f, "ocall_outer"),
+    }
+  }
+}
+
+// ensure storage type is not empty
+// could happen if the user writes storage_type = []
+fn deserialize_storage_type<'de, D>(deserializer: D) -> Result<Vec<StorageType>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  let v: Option<Vec<StorageType>> = Option::deserialize(deserializer)?;
+  Ok(if let Some(array) = v {
+    if array.is_empty() {
+      default_storage_type()
+    } else {
+      array
+    }
+  } else {
+    default_storage_type()
+  })
+}
+
+/// Thread counts can be listed explicitly or expressed as a range.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumThreadsSpec {
+  List(Vec<usize>),
+  Range {
+    from: usize,
+    to: usize,
+    step: Option<ThreadStep>,
+  },
+}
+
+/// The step of a num_threads range: a number (or "+N") for linear ranges, "xN" for geometric ones.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ThreadStep {
+  Linear(usize),
+  Expr(String),
+}
+
+// expands num_threads = { from = 1, to = 32, step = "x2" } into [1, 2, 4, 8, 16, 32]
+fn deserialize_num_threads<'de, D>(deserializer: D) -> Result<Vec<usize>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  let (from, to, step) = match NumThreadsSpec::deserialize(deserializer)? {
+    NumThreadsSpec::List(threads) => return Ok(threads),
+    NumThreadsSpec::Range { from, to, step } => (from, to, step),
+  };
+  if from == 0 || from > to {
+    return Err(serde::de::Error::custom(
+      "num_threads range must satisfy 0 < from <= to",
+    ));
+  }
+  let invalid_step =
+    |v: &str| serde::de::Error::custom(format!("invalid num_threads step {:?}", v));
+  let next: Box<dyn Fn(usize) -> Option<usize>> = match step {
+    None => Box::new(|n| n.checked_add(1)),
+    Some(ThreadStep::Linear(0)) => return Err(invalid_step("0")),
+    Some(ThreadStep::Linear(step)) => Box::new(move |n| n.checked_add(step)),
+    Some(ThreadStep::Expr(expr)) => {
+      let trimmed = expr.trim();
+      if let Some(factor) = trimmed.strip_prefix('x') {
+        match factor.parse::<usize>() {
+          Ok(factor) if factor >= 2 => Box::new(move |n| n.checked_mul(factor)),
+          _ => return Err(invalid_step(&expr)),
+        }
+      } else {
+        match trimmed.trim_start_matches('+').parse::<usize>() {
+          Ok(step) if step > 0 => Box::new(move |n| n.checked_add(step)),
+          _ => return Err(invalid_step(&expr)),
+        }
+      }
+    }
+  };
+  let mut threads = Vec::new();
+  let mut n = Some(from);
+  while let Some(v) = n.filter(|v| *v <= to) {
+    threads.push(v);
+    n = next(v);
+  }
+  Ok(threads)
+}
+
+/// Enclave sizes can be listed explicitly or expressed as a geometric range.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EnclaveSizeSpec {
+  List(Vec<String>),
+  Range {
+    from: String,
+    to: String,
+    #[serde(default = "default_enclave_size_factor")]
+    factor: u64,
+  },
+}
+
+fn default_enclave_size_factor() -> u64 {
+  2
+}
+
+// expands enclave_size = { from = "64M", to = "4G", factor = 2 } into
+// ["64M", "128M", ..., "4G"]
+fn deserialize_enclave_size<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  let invalid = |v: &str| {
+    serde::de::Error::custom(format!(
+      "invalid enclave size {:?}: expected a positive whole number with an optional K, M or G suffix (e.g. \"256M\")",
+      v
+    ))
+  };
+  match EnclaveSizeSpec::deserialize(deserializer)? {
+    EnclaveSizeSpec::List(sizes) => {
+      if let Some(size) = sizes
+        .iter()
+        .find(|size| parse_size(size).is_none_or(|size| size == 0))
+      {
+        return Err(invalid(size));
+      }
+      Ok(sizes)
+    }
+    EnclaveSizeSpec::Range { from, to, factor } => {
+      let from = parse_size(&from).ok_or_else(|| invalid(&from))?;
+      let to = parse_size(&to).ok_or_else(|| invalid(&to))?;
+      if factor < 2 {
+        return Err(serde::de::Error::custom(
+          "enclave_size factor must be at least 2",
+        ));
+      }
+      if from == 0 || from > to {
+        return Err(serde::de::Error::custom(
+          "enclave_size range must satisfy 0 < from <= to",
+        ));
+      }
+      let mut sizes = Vec::new();
+      let mut size = from;
+      while size <= to {
+        sizes.push(format_size(size));
+        size = match size.checked_mul(factor) {
+          Some(v) => v,
+          None => break,
+        };
+      }
+      Ok(sizes)
+    }
+  }
+}
+
+/// Parses a size with an optional `K`, `M` or `G` suffix (as accepted by Gramine) into bytes.
+pub fn parse_size(value: &str) -> Option<u64> {
+  let value = value.trim();
+  let (number, multiplier) = match value.chars().last()? {
+    'K' => (&value[..value.len() - 1], 1 << 10),
+    'M' => (&value[..value.len() - 1], 1 << 20),
+    'G' => (&value[..value.len() - 1], 1 << 30),
+    _ => (value, 1),
+  };
+  number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Formats a size in bytes using the largest `K`, `M` or `G` suffix that represents it exactly.
+pub fn format_size(bytes: u64) -> String {
+  for (suffix, multiplier) in [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)] {
+    if bytes != 0 && bytes.is_multiple_of(multiplier) {
+      return format!("{}{}", bytes / multiplier, suffix);
+    }
+  }
+  bytes.to_string()
+}
+
+/// Parses a list of CPUs in the format of the kernel (e.g. "0-3,8") into sorted CPU numbers.
+pub fn parse_cpu_list(value: &str) -> Option<Vec<usize>> {
+  let mut cpus = Vec::new();
+  for range in value.trim().split(',').filter(|range| !range.is_empty()) {
+    let (first, last): (usize, usize) = match range.split_once('-') {
+      Some((first, last)) => (first.trim().parse().ok()?, last.trim().parse().ok()?),
+      None => {
+        let cpu = range.trim().parse().ok()?;
+        (cpu, cpu)
+      }
+    };
+    if first > last {
+      return None;
+    }
+    cpus.extend(first..=last);
+  }
+  cpus.sort_unstable();
+  cpus.dedup();
+  Some(cpus)
+}
+
+/// Formats sorted CPU numbers in the list format of the kernel, with ranges of consecutive CPUs.
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+  let mut ranges: Vec<(usize, usize)> = Vec::new();
+  for &cpu in cpus {
+    match ranges.last_mut() {
+      Some((_, last)) if *last + 1 == cpu => *last = cpu,
+      _ => ranges.push((cpu, cpu)),
+    }
+  }
+  ranges
+    .iter()
+    .map(|&(first, last)| {
+      if first == last {
+        first.to_string()
+      } else {
+        format!("{}-{}", first, last)
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(",")
 }
 
 pub fn default_energy_sample_interval() -> Duration {
@@ -132,6 +1842,482 @@ pub struct ExperimentConfig {
   pub args: Vec<String>,
   pub pre_run: Option<(PathBuf, Vec<String>)>,
   pub post_run: Option<(PathBuf, Vec<String>)>,
+  pub setup: Option<(PathBuf, Vec<String>)>,
+  pub teardown: Option<(PathBuf, Vec<String>)>,
+  pub on_fail: Option<(PathBuf, Vec<String>)>,
+  pub hooks_must_succeed: bool,
+  pub hook_timeout: Option<Duration>,
   pub output_path: PathBuf,
   pub env: Option<HashMap<String, String>>,
+  pub energy_sample_interval: Option<Duration>,
+  pub sgx_profile: SgxProfile,
+  pub rpc_thread_num: usize,
+  /// `loader.log_level` of the enclave; `None` to follow `globals.debug`.
+  pub log_level: Option<GramineLogLevel>,
+  pub trusted_libraries: Vec<PathBuf>,
+  pub runtime: Option<Runtime>,
+  pub script: Option<PathBuf>,
+  pub uprobes: Vec<Uprobe>,
+  pub usdt: Vec<Usdt>,
+  pub network: Network,
+  pub netem: Option<Netem>,
+  pub fault: Option<Fault>,
+  pub sample_duration: Option<Duration>,
+  pub metrics: Vec<MetricPattern>,
+  pub perf_events: PerfEvents,
+  pub systemd_scope: Option<SystemdScope>,
+  /// Logical CPUs the workload is restricted to; empty if it is not restricted.
+  pub cpu_affinity: Vec<usize>,
+  pub transparent_hugepages: Option<TransparentHugePages>,
+  pub nice: Option<i32>,
+  pub sched_policy: Option<SchedPolicy>,
+  pub ready_check: Option<ReadyCheck>,
+  pub disable_aslr: bool,
+  pub auxiliary_executables: Vec<PathBuf>,
+  /// Capacity bitmask of the L3 ways the workload can fill; `None` if it is not restricted.
+  pub cache_mask: Option<u64>,
+  /// Secret provisioning server of the key of the encrypted files of an enclave; `None` for the
+  /// key embedded in the manifest and for native experiments.
+  pub key_provisioning: Option<KeyProvisioning>,
+}
+
+/// Parameters identifying an experiment. Stored as `metadata.json` in the experiment directory.
+#[derive(Serialize, Debug, Clone)]
+pub struct ExperimentMetadata {
+  pub program: PathBuf,
+  pub sgx: bool,
+  pub num_threads: usize,
+  pub enclave_size: Option<String>,
+  pub storage_type: Option<String>,
+  pub rpc_thread_num: Option<usize>,
+  /// `loader.log_level` of the enclave; `None` for native experiments.
+  pub log_level: Option<GramineLogLevel>,
+  pub args_matrix: BTreeMap<String, String>,
+  pub pinning: Pinning,
+  /// Logical CPUs the workload was restricted to (pinned or isolated); empty if it was not
+  /// restricted.
+  pub cpu_affinity: Vec<usize>,
+  /// The transparent huge page mode set for the experiment; `None` if the mode of the host (see
+  /// `system.json`) was kept.
+  pub transparent_hugepages: Option<TransparentHugePages>,
+  /// Address space layout randomization was disabled for the workload; otherwise the setting of
+  /// the host (see `system.json`) applied.
+  pub disable_aslr: bool,
+  /// Capacity bitmask of the L3 ways allocated to the workload (e.g. "0xf"); `None` if it could
+  /// fill the whole cache.
+  pub cache_mask: Option<String>,
+  /// Secret provisioning server the key of the encrypted files came from; `None` for the key
+  /// embedded in the manifest and for native experiments.
+  pub key_provisioning: Option<String>,
+  /// Shared libraries listed one by one in `sgx.trusted_files` (see `Task::discover_libraries`);
+  /// empty when library directories are trusted and for native experiments.
+  pub trusted_libraries: Vec<PathBuf>,
+  /// Gramine release the enclave was built with, as reported by `graminelibos`; `None` for
+  /// native experiments or if it cannot be detected.
+  pub gramine_version: Option<String>,
+  /// The storage stack backing the storage directory of the workload.
+  pub storage: Option<StorageStack>,
+  /// Bytes written under the experiment directory; set once the experiment completes.
+  pub disk_usage_bytes: Option<u64>,
+}
+
+/// File system and block device backing a directory. They determine the disk metrics reported by
+/// the tracer as much as the workload does.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StorageStack {
+  pub mount_point: PathBuf,
+  pub device: String,
+  pub filesystem: String,
+  pub mount_options: String,
+  pub superblock_options: String,
+  /// Active I/O scheduler of the block device (e.g. `mq-deadline`); `None` for file systems not
+  /// backed by a block device, like `tmpfs`.
+  pub scheduler: Option<String>,
+}
+
+impl StorageStack {
+  /// Looks up the mount containing `path` in `/proc/self/mountinfo`.
+  pub fn probe(path: &Path) -> Option<Self> {
+    let path = path.canonicalize().ok()?;
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let (mut stack, dev) = Self::from_mountinfo(&mountinfo, &path)?;
+    // partitions have no queue: the scheduler is the one of the parent disk
+    stack.scheduler = [
+      format!("/sys/dev/block/{}/queue/scheduler", dev),
+      format!("/sys/dev/block/{}/../queue/scheduler", dev),
+    ]
+    .iter()
+    .find_map(|p| fs::read_to_string(p).ok())
+    .and_then(|schedulers| active_scheduler(&schedulers));
+    Some(stack)
+  }
+
+  /// Returns the stack of the mount with the longest mount point containing `path`, with its
+  /// `major:minor` device number. Lines of `mountinfo` look like
+  ///
+  /// 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+  ///
+  /// (see proc(5)); the optional fields end with `-`.
+  pub fn from_mountinfo(mountinfo: &str, path: &Path) -> Option<(Self, String)> {
+    mountinfo
+      .lines()
+      .filter_map(|line| {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        let mut mount = mount.split_whitespace();
+        let dev = mount.nth(2)?;
+        let mount_point = PathBuf::from(unescape_mountinfo(mount.nth(1)?));
+        let mount_options = mount.next()?;
+        let mut filesystem = filesystem.split_whitespace();
+        Some((
+          Self {
+            filesystem: filesystem.next()?.to_string(),
+            device: unescape_mountinfo(filesystem.next()?),
+            superblock_options: filesystem.next().unwrap_or_default().to_string(),
+            mount_point,
+            mount_options: mount_options.to_string(),
+            scheduler: None,
+          },
+          dev.to_string(),
+        ))
+      })
+      // with stacked mounts on the same mount point the last one is visible
+      .filter(|(stack, _)| path.starts_with(&stack.mount_point))
+      .max_by_key(|(stack, _)| stack.mount_point.components().count())
+  }
+}
+
+// spaces, tabs, newlines and backslashes are escaped as octal sequences (e.g. `\040`)
+fn unescape_mountinfo(value: &str) -> String {
+  let mut result = String::with_capacity(value.len());
+  let mut rest = value;
+  while let Some(i) = rest.find('\\') {
+    result.push_str(&rest[..i]);
+    let code = rest
+      .get(i + 1..i + 4)
+      .and_then(|c| u8::from_str_radix(c, 8).ok());
+    match code {
+      Some(c) => {
+        result.push(c as char);
+        rest = &rest[i + 4..];
+      }
+      None => {
+        result.push('\\');
+        rest = &rest[i + 1..];
+      }
+    }
+  }
+  result.push_str(rest);
+  result
+}
+
+// the active scheduler is the one in brackets, e.g. "mq-deadline kyber [bfq] none"
+fn active_scheduler(schedulers: &str) -> Option<String> {
+  schedulers
+    .split_whitespace()
+    .find_map(|s| s.strip_prefix('[')?.strip_suffix(']'))
+    .map(String::from)
+}
+
+impl ExperimentMetadata {
+  pub fn save(&self, experiment_path: &Path) -> Result<(), std::io::Error> {
+    fs::create_dir_all(experiment_path)?;
+    fs::write(
+      experiment_path.join("metadata.json"),
+      serde_json::to_string_pretty(self)?,
+    )
+  }
+
+  /// Stores the bytes written under `experiment_path` in `metadata.json`.
+  pub fn record_disk_usage(&mut self, experiment_path: &Path) -> Result<(), std::io::Error> {
+    let bytes = disk_usage(experiment_path)?;
+    info!("{:?} uses {} bytes", experiment_path, bytes);
+    self.disk_usage_bytes = Some(bytes);
+    self.save(experiment_path)
+  }
+}
+
+/// What an experiment ran, so that it can be reproduced without reading the code. Stored as
+/// `provenance.json` in the experiment directory.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Provenance {
+  /// Program and arguments of every sample.
+  pub command: Vec<String>,
+  /// Environment of the workload which affects its behavior: the variables of the benchmark listed
+  /// in `PROVENANCE_ENV` (secrets and session details are left out) and the task `env`. Samples get
+  /// `EB_METRICS_FILE` as well.
+  pub env: BTreeMap<String, String>,
+  /// Custom manifest template of the task; `None` with the default template.
+  pub manifest_template_path: Option<PathBuf>,
+  /// Template the manifest was rendered from (SGX experiments only).
+  pub manifest_template: Option<String>,
+  /// Variables the template was rendered with (SGX experiments only).
+  pub manifest_variables: Option<serde_json::Value>,
+}
+
+impl Provenance {
+  /// Describes a workload started as `program` with `args` and the variables of `env` on top of
+  /// the environment of the benchmark.
+  pub fn new(program: &Path, args: &[String], env: Option<&HashMap<String, String>>) -> Self {
+    let mut command = vec![program.to_string_lossy().into_owned()];
+    command.extend(args.iter().cloned());
+    let mut variables: BTreeMap<String, String> = PROVENANCE_ENV
+      .iter()
+      .filter_map(|name| {
+        let value = env::var_os(name)?;
+        Some((name.to_string(), value.to_string_lossy().into_owned()))
+      })
+      .collect();
+    variables.extend(env.cloned().unwrap_or_default());
+    Self {
+      command,
+      env: variables,
+      manifest_template_path: None,
+      manifest_template: None,
+      manifest_variables: None,
+    }
+  }
+
+  pub fn save(&self, experiment_path: &Path) -> Result<(), std::io::Error> {
+    fs::write(
+      experiment_path.join("provenance.json"),
+      serde_json::to_string_pretty(self)?,
+    )
+  }
+}
+
+/// Returns the number of bytes used by the files under `path`, without following symlinks.
+pub fn disk_usage(path: &Path) -> Result<u64, std::io::Error> {
+  let metadata = fs::symlink_metadata(path)?;
+  if !metadata.is_dir() {
+    return Ok(metadata.len());
+  }
+  let mut total = 0;
+  for entry in fs::read_dir(path)? {
+    total += disk_usage(&entry?.path())?;
+  }
+  Ok(total)
+}
+
+/// Returns the bytes available to unprivileged users on the file system containing `path`. If
+/// `path` does not exist yet, its closest existing ancestor is used.
+pub fn available_space(path: &Path) -> Result<u64, std::io::Error> {
+  let existing = path
+    .ancestors()
+    .find(|p| p.exists())
+    .filter(|p| !p.as_os_str().is_empty())
+    .unwrap_or(Path::new("."));
+  let c_path = std::ffi::CString::new(existing.as_os_str().as_encoded_bytes())?;
+  let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::Path,
+    time::{Duration, SystemTime},
+  };
+
+  use tempfile::TempDir;
+
+  use crate::constants::{INDEX_FILE, PROVENANCE_ENV};
+
+  use super::{
+    active_scheduler, format_cpu_list, parse_cpu_list, FailurePolicy, Provenance, Retention,
+    RunHealth, Severity, StorageStack, StorageType, SystemdScope, Task,
+  };
+
+  #[test]
+  fn cpu_lists() {
+    assert_eq!(parse_cpu_list("0-3,8"), Some(vec![0, 1, 2, 3, 8]));
+    assert_eq!(parse_cpu_list(" 10,2-3,2\n"), Some(vec![2, 3, 10]));
+    assert_eq!(parse_cpu_list(""), Some(vec![]));
+    assert!(parse_cpu_list("3-1").is_none());
+    assert!(parse_cpu_list("a").is_none());
+    assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8, 10, 11]), "0-3,8,10-11");
+    assert_eq!(format_cpu_list(&[]), "");
+  }
+
+  fn create_campaign(path: &Path, age: Duration) {
+    let sample = path.join("prog/no-gramine-sgx/prog-1/1");
+    fs::create_dir_all(&sample).unwrap();
+    fs::create_dir_all(path.join("prog/no-gramine-sgx/prog-1/deep-trace")).unwrap();
+    fs::write(sample.join("stdout"), "0123456789").unwrap();
+    fs::write(
+      path.join(INDEX_FILE),
+      r#"[{"directory": "prog/no-gramine-sgx/prog-1"}]"#,
+    )
+    .unwrap();
+    File::options()
+      .write(true)
+      .open(path.join(INDEX_FILE))
+      .unwrap()
+      .set_modified(SystemTime::now() - age)
+      .unwrap();
+  }
+
+  #[test]
+  fn systemd_scope_arguments() {
+    let scope: SystemdScope = toml::from_str(
+      r#"
+        cpu_quota = "200%"
+        allowed_cpus = "0-3"
+        properties = { IOWeight = "50" }
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+      scope.systemd_run_args(),
+      vec![
+        "--scope",
+        "--quiet",
+        "--collect",
+        "--property=CPUQuota=200%",
+        "--property=AllowedCPUs=0-3",
+        "--property=IOWeight=50",
+        "--",
+      ]
+    );
+  }
+
+  #[test]
+  fn failure_policy_exit_code() {
+    let health = RunHealth {
+      timed_out_hooks: 1,
+      partial_samples: 2,
+      ..RunHealth::default()
+    };
+    // problems are only logged by default
+    let policy = FailurePolicy::default();
+    assert_eq!(policy.check(&RunHealth::default()), None);
+    assert_eq!(policy.check(&health), None);
+
+    let policy: FailurePolicy =
+      toml::from_str("timed_out_hooks = \"error\"\npartial_samples = \"error\"").unwrap();
+    assert_eq!(policy.failed_samples, Severity::Warn);
+    assert_eq!(policy.check(&health), Some(4));
+    let policy = FailurePolicy {
+      timed_out_hooks: Severity::Warn,
+      ..policy
+    };
+    assert_eq!(policy.check(&health), Some(5));
+    let policy = FailurePolicy {
+      partial_samples: Severity::Ignore,
+      ..policy
+    };
+    assert_eq!(policy.check(&health), None);
+  }
+
+  #[test]
+  fn custom_manifest_by_storage_type() {
+    let task: Task = toml::from_str(
+      r#"
+        executable = "/bin/ls"
+        enclave_size = ["64M"]
+        custom_manifest_path = { encrypted = "encrypted.manifest.template" }
+        "#,
+    )
+    .unwrap();
+    let manifest = task.custom_manifest_path.unwrap();
+    assert_eq!(
+      manifest.for_storage_type(&StorageType::Encrypted),
+      Some(Path::new("encrypted.manifest.template"))
+    );
+    // falls back to the default template
+    assert_eq!(manifest.for_storage_type(&StorageType::Untrusted), None);
+
+    let task: Task = toml::from_str(
+      r#"
+        executable = "/bin/ls"
+        enclave_size = ["64M"]
+        custom_manifest_path = "ls.manifest.template"
+        "#,
+    )
+    .unwrap();
+    let manifest = task.custom_manifest_path.unwrap();
+    assert_eq!(
+      manifest.for_storage_type(&StorageType::Untrusted),
+      Some(Path::new("ls.manifest.template"))
+    );
+  }
+
+  #[test]
+  fn provenance_environment() {
+    let env = HashMap::from([(String::from("EB_PROVENANCE_TEST"), String::from("1"))]);
+    let provenance = Provenance::new(Path::new("/bin/ls"), &[String::from("-l")], Some(&env));
+    assert_eq!(provenance.command, vec!["/bin/ls", "-l"]);
+    assert_eq!(provenance.env["EB_PROVENANCE_TEST"], "1");
+    // only the allowed variables of the environment of the benchmark are recorded
+    assert!(provenance.env.contains_key("PATH"));
+    assert!(provenance
+      .env
+      .keys()
+      .all(|name| name == "EB_PROVENANCE_TEST" || PROVENANCE_ENV.contains(&name.as_str())));
+    assert!(provenance.manifest_variables.is_none());
+  }
+
+  #[test]
+  fn retention_apply() {
+    let parent = TempDir::new().unwrap();
+    let old = parent.path().join("old");
+    let current = parent.path().join("current");
+    create_campaign(&old, Duration::from_secs(3600));
+    create_campaign(&current, Duration::ZERO);
+    // a directory next to the results which was not written by the benchmark
+    let foreign = parent.path().join("foreign");
+    create_campaign(&foreign, Duration::from_secs(7200));
+    fs::write(foreign.join(INDEX_FILE), "{}").unwrap();
+
+    Retention {
+      keep_deep_traces: Some(1),
+      max_stdout_size: Some(5),
+    }
+    .apply(&current)
+    .unwrap();
+
+    assert!(!old.join("prog/no-gramine-sgx/prog-1/deep-trace").exists());
+    assert!(foreign
+      .join("prog/no-gramine-sgx/prog-1/deep-trace")
+      .exists());
+    assert!(current
+      .join("prog/no-gramine-sgx/prog-1/deep-trace")
+      .exists());
+    // stdout is only capped in the current campaign
+    assert!(old.join("prog/no-gramine-sgx/prog-1/1/stdout").exists());
+    assert!(!current.join("prog/no-gramine-sgx/prog-1/1/stdout").exists());
+  }
+
+  #[test]
+  fn storage_stack_from_mountinfo() {
+    let mountinfo = r"22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw,errors=remount-ro
+35 22 0:32 / /tmp rw,nosuid,nodev shared:14 - tmpfs tmpfs rw,size=8G
+40 22 8:17 / /mnt/data\040disk rw,noatime - xfs /dev/sdb1 rw,attr2,inode64
+";
+    let (stack, dev) =
+      StorageStack::from_mountinfo(mountinfo, Path::new("/home/user/results/storage")).unwrap();
+    assert_eq!(dev, "259:2");
+    assert_eq!(stack.filesystem, "ext4");
+    assert_eq!(stack.device, "/dev/nvme0n1p2");
+    assert_eq!(stack.mount_options, "rw,relatime");
+
+    let (stack, dev) =
+      StorageStack::from_mountinfo(mountinfo, Path::new("/mnt/data disk/storage")).unwrap();
+    assert_eq!(dev, "8:17");
+    assert_eq!(stack.mount_point, Path::new("/mnt/data disk"));
+    assert_eq!(stack.superblock_options, "rw,attr2,inode64");
+
+    let (stack, _) = StorageStack::from_mountinfo(mountinfo, Path::new("/tmp/x")).unwrap();
+    assert_eq!(stack.filesystem, "tmpfs");
+
+    assert_eq!(
+      active_scheduler("mq-deadline kyber [bfq] none\n"),
+      Some("bfq".to_string())
+    );
+    assert_eq!(active_scheduler("none"), None);
+  }
 }
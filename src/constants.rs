@@ -22,6 +22,8 @@
 ///
 /// - `{{ untrusted_path }}`: The path to the directory containing untrusted files, mounted at `/untrusted/`.
 ///
+/// - `{{ output_path }}`: The experiment directory; its `workload-metrics` directory is mounted at `/workload-metrics/`.
+///
 /// - `{{ enclave_size }}`: The size of the enclave, specified in bytes.
 ///
 /// - `{{ num_threads_sgx }}`: The maximum number of threads that the SGX enclave can support.
@@ -32,24 +34,45 @@
 ///
 /// - `libos.entrypoint`: Specifies the entry point executable for the application.
 ///
-/// - `loader.log_level`: Sets the logging level for the Gramine loader.
+/// - `loader.log_level`: Sets the logging level for the Gramine loader: the `log_level` of the experiment, or "debug" with `globals.debug` and "none" otherwise.
 ///
 /// - `loader.env.LD_LIBRARY_PATH`: Sets the library path for dynamic linking within the enclave.
 ///
 /// - `loader.insecure__use_cmdline_argv`: Allows the use of command-line arguments in an insecure manner.
 ///
+/// - `loader.insecure__disable_aslr`: Disables the randomization of the enclave memory layout with the task `disable_aslr`.
+///
+/// - `loader.env.EB_METRICS_FILE`: Where the workload may write its own metrics; the collector moves the file to the sample directory.
+///
+/// - `loader.env.EB_PHASES_FILE`: Where the workload may announce its phases; the collector timestamps them while it runs.
+///
 /// - `fs.mounts`: Defines the file system mounts for the enclave, including paths for libraries, executables, tmpfs, encrypted, and untrusted files.
+///   With the `python3` runtime, the script directory and the interpreter's `sys.path` are mounted (and trusted) as well.
+///   With the `java` runtime, the script directory and `{{ java_home }}` (the `java.home` reported by the JVM) are mounted (and trusted) as well.
+///   The `auxiliary_executables` of the task are mounted one by one at the path the workload runs
+///   them with (`path`), from their target once symbolic links are resolved (`canonical`, trusted).
+///
+/// - `sys.stack.size`, `JAVA_TOOL_OPTIONS`: With the `java` runtime, the JVM gets larger stacks and
+///   a heap (`-Xmx`) of half the enclave size (`{{ java_heap_size }}`), unless `JAVA_TOOL_OPTIONS` is set in the task env.
 ///
 /// - `fs.insecure__keys.default`: Specifies the default encryption key for accessing encrypted files.
+///   With the `key_provisioning` of the task (`{{ key_provisioning_ca_chain }}` set), the key is
+///   instead provisioned by the server of the task (`{{ key_provisioning_server }}`, as configured so that
+///   its certificate is verified against its name) to `libsecret_prov_attest.so`, preloaded from the runtime directory, after DCAP remote
+///   attestation (`sgx.remote_attestation`); the CA chain of the server is mounted and trusted.
 ///
 /// - `sgx.debug`: Enables or disables debug mode for the SGX enclave.
 ///
+/// - `sgx.profile.enable`: Selects which threads are profiled by the SGX profiler ("none", "main" or "all").
+///
 /// - `sgx.profile.mode`: Sets the profiling mode for the SGX enclave, such as "ocall_outer".
 ///
 /// - `sgx.enable_stats`: Enables the collection of statistics within the SGX enclave.
 ///
 /// - `sys.enable_sigterm_injection`: Allows the injection of SIGTERM signals into the enclave.
 ///
+/// - `sgx.insecure__rpc_thread_num`: Number of untrusted RPC threads serving exitless OCALLs (0 disables them).
+///
 /// - `sgx.enclave_size`: Specifies the size of the SGX enclave.
 ///
 /// - `sgx.max_threads`: Sets the maximum number of threads for the SGX enclave.
@@ -57,14 +80,34 @@
 /// - `sgx.edmm_enable`: Enables or disables Enhanced Dynamic Memory Management (EDMM) for the SGX enclave.
 ///
 /// - `sgx.trusted_files`: Lists the files that are trusted and can be accessed securely within the enclave.
+///   Library directories are trusted as a whole, unless the task sets `discover_libraries`: the shared libraries
+///   discovered with `ldd` are then listed one by one.
 ///
 /// - `sgx.allowed_files`: Lists the files that are allowed to be accessed, but are not necessarily trusted.
 pub const MANIFEST: &str = r#"
 libos.entrypoint = "{{ executable }}"
 loader.log_level = "{{ debug }}"
 
-loader.env.LD_LIBRARY_PATH = "/lib:{{ arch_libdir }}:/usr/lib"
+loader.env.LD_LIBRARY_PATH = "/lib:{{ arch_libdir }}:/usr/lib{{ ':' ~ java_home ~ '/lib' if runtime == 'java' else '' }}"
 loader.insecure__use_cmdline_argv = true
+loader.insecure__disable_aslr = {{ 'true' if disable_aslr else 'false' }}
+loader.env.EB_METRICS_FILE = "/workload-metrics/workload-metrics.json"
+loader.env.EB_PHASES_FILE = "/workload-metrics/phases"
+
+{% if key_provisioning_ca_chain %}
+loader.env.LD_PRELOAD = "/lib/libsecret_prov_attest.so"
+loader.env.SECRET_PROVISION_CONSTRUCTOR = "1"
+loader.env.SECRET_PROVISION_SET_KEY = "default"
+loader.env.SECRET_PROVISION_CA_CHAIN_PATH = "{{ key_provisioning_ca_chain }}"
+loader.env.SECRET_PROVISION_SERVERS = "{{ key_provisioning_server }}"
+{% endif %}
+
+{% if runtime == "java" %}
+sys.stack.size = "8M"
+{% if 'JAVA_TOOL_OPTIONS' not in env %}
+loader.env.JAVA_TOOL_OPTIONS = "-Xmx{{ java_heap_size }} -XX:+UseSerialGC"
+{% endif %}
+{% endif %}
 
 {% for key, val in env.items() %}
     loader.env.{{ key }} = "{{ val }}"
@@ -78,15 +121,39 @@ fs.mounts = [
   { type = "tmpfs", path = "/tmp/" },
   { type = "encrypted", path = "/encrypted/", uri = "file:{{ encrypted_path }}/", key_name = "default" },
   { path = "/untrusted/", uri = "file:{{ untrusted_path }}/" },
+  { path = "/workload-metrics/", uri = "file:{{ output_path }}/workload-metrics/" },
+{% if runtime != "native" %}
+  { path = "{{ executable_path }}", uri = "file:{{ executable_path }}" },
+{% endif %}
+{% if runtime == "python3" %}
+{% for path in python.get_sys_path(executable) %}
+  { path = "{{ path }}", uri = "file:{{ path }}" },
+{% endfor %}
+{% endif %}
+{% if runtime == "java" %}
+  { path = "{{ java_home }}", uri = "file:{{ java_home }}" },
+{% endif %}
+{% for auxiliary in auxiliary_executables %}
+  { path = "{{ auxiliary.path }}", uri = "file:{{ auxiliary.canonical }}" },
+{% endfor %}
+{% if key_provisioning_ca_chain %}
+  { path = "{{ key_provisioning_ca_chain }}", uri = "file:{{ key_provisioning_ca_chain }}" },
+{% endif %}
   { path = "/etc/passwd", uri = "file:/etc/passwd" }
 ]
 
+{% if key_provisioning_ca_chain %}
+sgx.remote_attestation = "dcap"
+{% else %}
 fs.insecure__keys.default = "ffeeddccbbaa99887766554433221100"
+{% endif %}
 
 sgx.debug = true
-sgx.profile.mode = "ocall_outer"
+sgx.profile.enable = "{{ profile_enable }}"
+sgx.profile.mode = "{{ profile_mode }}"
 sgx.enable_stats = true
 sys.enable_sigterm_injection = true
+sgx.insecure__rpc_thread_num = {{ rpc_thread_num }}
 sgx.enclave_size = "{{ enclave_size }}"
 sgx.max_threads = {{ num_threads_sgx }}
 sgx.edmm_enable = {{ 'true' if env.get('EDMM', '0') == '1' else 'false' }}
@@ -95,19 +162,207 @@ sgx.trusted_files = [
   "file:{{ executable }}",
   "file:{{ gramine.runtimedir( libc ) }}/",
   "file:{{ executable_path }}/",
+{% if trusted_libraries %}
+{% for library in trusted_libraries %}
+  "file:{{ library }}",
+{% endfor %}
+{% else %}
   "file:{{ arch_libdir }}/",
   "file:/usr/{{ arch_libdir }}/",
+{% endif %}
+{% if runtime == "python3" %}
+{% for path in python.get_sys_path(executable) %}
+  "file:{{ path }}{{ '/' if path.is_dir() else '' }}",
+{% endfor %}
+{% endif %}
+{% if runtime == "java" %}
+  "file:{{ java_home }}/",
+{% endif %}
+{% for auxiliary in auxiliary_executables %}
+  "file:{{ auxiliary.canonical }}",
+{% endfor %}
+{% if key_provisioning_ca_chain %}
+  "file:{{ key_provisioning_ca_chain }}",
+{% endif %}
   "file:/etc/passwd"
 ]
 
 sgx.allowed_files = [
   "file:{{ untrusted_path }}/",
+  "file:{{ output_path }}/workload-metrics/",
 ]
 "#;
 
-pub const ENERGY_CSV_HEADER: &str = "timestamp (ns),energy (microjoule)";
+pub const ENERGY_CSV_HEADER: &str =
+  "timestamp (ns),energy (microjoule),power (W),monotonic (ns),gap";
+/// An energy reading later than this many sampling intervals after the previous one is flagged as
+/// a gap in the energy series.
+pub const ENERGY_GAP_FACTOR: u32 = 2;
 pub const IO_CSV_HEADER: &str = "dimension,unit,value,description";
-pub const TRACE_CSV_HEADER: &str = "timestamp (ns),event";
+pub const TRACE_CSV_HEADER: &str = "timestamp (ns),event,bytes";
+pub const SUMMARY_CSV_HEADER: &str = "metric,unit,value";
+pub const PSI_CSV_HEADER: &str =
+  "timestamp (ns),resource,some avg10 (%),some total (us),full avg10 (%),full total (us)";
+pub const OCALL_CSV_HEADER: &str = "ocall,count,share (%),time (ns)";
+pub const PERF_SCALING_CSV_HEADER: &str = "event,counted (%),scaling factor";
+pub const PHASES_CSV_HEADER: &str = "timestamp (ns),monotonic (ns),phase";
+pub const MEMORY_CSV_HEADER: &str =
+  "timestamp (ns),processes,rss (kB),pss (kB),uss (kB),anon hugepages (kB)";
+pub const CPU_CSV_HEADER: &str = "timestamp (ns),cpu,user (%),system (%),iowait (%),busy (%)";
+pub const MEMORY_BANDWIDTH_CSV_HEADER: &str = "timestamp (ns),domain,counter,bytes";
+pub const CACHE_OCCUPANCY_CSV_HEADER: &str = "timestamp (ns),domain,bytes";
+pub const TIMELINE_CSV_HEADER: &str = "sample,offset (ns),event,bytes";
+
+/// Time buckets of the rows of the HTML timeline.
+pub const TIMELINE_HTML_BUCKETS: usize = 500;
+
+/// Root of the resctrl filesystem, where the cache allocation groups of the workloads and the
+/// monitoring groups of the memory bandwidth monitor are created.
+pub const RESCTRL_DIRECTORY: &str = "/sys/fs/resctrl";
+
+/// Environment variable telling the workload where to write its own metrics.
+pub const METRICS_FILE_ENV: &str = "EB_METRICS_FILE";
+
+/// JSON file of the metrics written by the workload, in its sample directory.
+pub const WORKLOAD_METRICS_FILE: &str = "workload-metrics.json";
+
+/// Variables of the environment of the benchmark recorded in `provenance.json`: those which change
+/// the behavior of a workload. Anything else (tokens, credentials, session details) is left out.
+pub const PROVENANCE_ENV: &[&str] = &[
+  "PATH",
+  "LD_LIBRARY_PATH",
+  "LD_PRELOAD",
+  "LANG",
+  "LANGUAGE",
+  "LC_ALL",
+  "LC_CTYPE",
+  "LC_NUMERIC",
+  "TZ",
+  "TMPDIR",
+  "OMP_NUM_THREADS",
+  "OMP_PROC_BIND",
+  "OMP_PLACES",
+  "MALLOC_ARENA_MAX",
+  "JAVA_HOME",
+  "JAVA_TOOL_OPTIONS",
+  "_JAVA_OPTIONS",
+  "PYTHONPATH",
+  "PYTHONHASHSEED",
+  "SGX_AESM_ADDR",
+  "AZDCAP_DEBUG_LOG_LEVEL",
+];
+
+/// Environment variables telling the hooks the address of the host and of the workload on the
+/// veth pair of `Network::Veth`.
+pub const HOST_ADDRESS_ENV: &str = "EB_HOST_ADDRESS";
+pub const WORKLOAD_ADDRESS_ENV: &str = "EB_WORKLOAD_ADDRESS";
+
+/// Environment variable telling the workload where to announce its phases, one name per line.
+pub const PHASES_FILE_ENV: &str = "EB_PHASES_FILE";
+
+/// File the workload writes its phase markers to, in its sample directory (or in
+/// `WORKLOAD_METRICS_DIRECTORY` for an enclave). The collector saves them to `phases.csv`.
+pub const PHASES_FILE: &str = "phases";
+
+/// Directory of the experiment mounted in the enclave for `WORKLOAD_METRICS_FILE` and
+/// `PHASES_FILE`.
+pub const WORKLOAD_METRICS_DIRECTORY: &str = "workload-metrics";
+
+/// Copy of the configuration of a campaign (with its includes expanded) in the output directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Layout of the experiment directories in the output directory (see `GlobalParams`).
+pub const DEFAULT_LAYOUT: &str = "{{program}}/{{kind}}/{{id}}";
+
+/// Lookup table of the experiments of a campaign in the output directory: the entries of `plan
+/// --json`, with the id, name and directory of every experiment and its parameters.
+pub const INDEX_FILE: &str = "index.json";
+
+/// Throughput report of `io-sweep`, in its output directory.
+pub const IO_SWEEP_FILE: &str = "io-sweep.csv";
+
+/// Checksums of the files of an export archive, in the format of `sha256sum`.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Index of the archives imported into a combined results tree.
+pub const SOURCES_FILE: &str = "sources.json";
+
+/// Exit status of `run` when samples failed (see `FailurePolicy`).
+pub const EXIT_FAILED_SAMPLES: i32 = 3;
+
+/// Exit status of `run` when hooks timed out (see `FailurePolicy`).
+pub const EXIT_TIMED_OUT_HOOKS: i32 = 4;
+
+/// Exit status of `run` when samples have partial metrics (see `FailurePolicy`).
+pub const EXIT_PARTIAL_SAMPLES: i32 = 5;
+
+/// Threads Gramine starts in an enclave next to those of the workload (IPC, asynchronous events,
+/// TLS handshake and the main thread of the PAL), counted in `sgx.max_threads` and in the CPUs
+/// of a pinned SGX experiment.
+pub const GRAMINE_HELPER_THREADS: usize = 4;
+
+/// OCALLs of Gramine whose handlers (`sgx_ocall_<name>` in the untrusted loader) are timed with
+/// uprobes. `exit` is left out: its handler does not return.
+pub const GRAMINE_OCALLS: &[&str] = &[
+  "mmap_untrusted",
+  "munmap_untrusted",
+  "cpuid",
+  "open",
+  "close",
+  "read",
+  "write",
+  "pread",
+  "pwrite",
+  "fstat",
+  "fionread",
+  "fsetnonblock",
+  "fchmod",
+  "fsync",
+  "ftruncate",
+  "mkdir",
+  "getdents",
+  "resume_thread",
+  "sched_setaffinity",
+  "sched_getaffinity",
+  "clone_thread",
+  "create_process",
+  "futex",
+  "socket",
+  "bind",
+  "listen_simple",
+  "listen",
+  "accept",
+  "connect",
+  "connect_simple",
+  "recv",
+  "send",
+  "setsockopt",
+  "shutdown",
+  "gettime",
+  "sched_yield",
+  "poll",
+  "rename",
+  "delete",
+  "eventfd",
+  "ioctl",
+  "get_quote",
+  "debug_map_add",
+  "debug_map_remove",
+  "debug_describe_location",
+];
+
+/// Extra SGX threads reserved for the JVM (GC and JIT compiler threads) with the `java` runtime.
+pub const JVM_EXTRA_THREADS: usize = 32;
+
+/// Estimated size of a sample directory (CSV files, stdout/stderr, profiler output) used to check
+/// the free space before starting.
+pub const ESTIMATED_SAMPLE_BYTES: u64 = 4 << 20;
+
+/// Estimated size of a deep-trace run, dominated by the traced events.
+pub const ESTIMATED_DEEP_TRACE_BYTES: u64 = 1 << 30;
+
+/// Resources sampled from `/proc/pressure` (Pressure Stall Information).
+pub const PSI_RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
 
 /// Default performance events to be monitored.
 ///
@@ -136,3 +391,79 @@ pub const DEFAULT_PERF_EVENTS: [&str; 8] = [
   "system_time",
   "user_time",
 ];
+
+/// Performance events of the `memory` perf profile, for SGX paging studies: EPC evictions show up
+/// as TLB misses, page walks and last level cache misses.
+///
+/// # Events
+///
+/// - `cpu-cycles`, `instructions`, `duration_time`, `user_time`, `system_time`: As in
+///   `DEFAULT_PERF_EVENTS`.
+/// - `dTLB-loads`, `dTLB-load-misses`, `dTLB-store-misses`: Data TLB accesses and misses.
+/// - `LLC-loads`, `LLC-load-misses`, `LLC-store-misses`: Last level cache accesses and misses.
+/// - `page-faults`: Page faults of the workload (software event).
+/// - `dtlb_load_misses.walk_active`, `dtlb_store_misses.walk_active`: Cycles with a page walk
+///   in progress after a data TLB miss (Intel).
+pub const MEMORY_PERF_EVENTS: [&str; 14] = [
+  "cpu-cycles",
+  "instructions",
+  "duration_time",
+  "user_time",
+  "system_time",
+  "dTLB-loads",
+  "dTLB-load-misses",
+  "dTLB-store-misses",
+  "LLC-loads",
+  "LLC-load-misses",
+  "LLC-store-misses",
+  "page-faults",
+  "dtlb_load_misses.walk_active",
+  "dtlb_store_misses.walk_active",
+];
+
+/// Perf groups of `MEMORY_PERF_EVENTS`. The profile has more hardware events than a core has
+/// general-purpose counters (four with hyper-threading), so perf multiplexes them; each group
+/// fits the counters (cycles and instructions have fixed ones) and keeps the ratios of its events
+/// exact: load miss rate and walk cycles per cycle, store walks per miss, LLC misses per
+/// instruction.
+pub const MEMORY_PERF_GROUPS: [&[&str]; 3] = [
+  &[
+    "cpu-cycles",
+    "dTLB-loads",
+    "dTLB-load-misses",
+    "dtlb_load_misses.walk_active",
+  ],
+  &["dTLB-store-misses", "dtlb_store_misses.walk_active"],
+  &[
+    "instructions",
+    "LLC-loads",
+    "LLC-load-misses",
+    "LLC-store-misses",
+  ],
+];
+
+/// Performance events of the `frontend` perf profile, for the instruction supply of enclave code.
+///
+/// # Events
+///
+/// - `cpu-cycles`, `instructions`, `duration_time`, `user_time`, `system_time`: As in
+///   `DEFAULT_PERF_EVENTS`.
+/// - `iTLB-loads`, `iTLB-load-misses`: Instruction TLB accesses and misses.
+/// - `L1-icache-load-misses`: L1 instruction cache misses.
+/// - `stalled-cycles-frontend`: Cycles where the frontend is stalled.
+/// - `branch-misses`: Number of branch misses.
+/// - `itlb_misses.walk_active`: Cycles with a page walk in progress after an instruction TLB
+///   miss (Intel).
+pub const FRONTEND_PERF_EVENTS: [&str; 11] = [
+  "cpu-cycles",
+  "instructions",
+  "duration_time",
+  "user_time",
+  "system_time",
+  "iTLB-loads",
+  "iTLB-load-misses",
+  "L1-icache-load-misses",
+  "stalled-cycles-frontend",
+  "branch-misses",
+  "itlb_misses.walk_active",
+];
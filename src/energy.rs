@@ -0,0 +1,341 @@
+//! Energy counters read by the energy monitor (see `EnergyCounter`): the powercap interface of
+//! the kernel or, where it is disabled, the RAPL MSRs.
+use std::{
+  fs::{self, File},
+  os::unix::fs::FileExt,
+  path::{Path, PathBuf},
+};
+
+use tracing::warn;
+
+use crate::common::EnergySource;
+
+/// Reports the units of the RAPL counters; bits 12:8 are the energy unit as a power of 1/2 J.
+const MSR_RAPL_POWER_UNIT: u64 = 0x606;
+
+/// Energy status registers of the RAPL domains, by domain name (as named by powercap).
+const MSR_ENERGY_STATUS: [(&str, u64); 4] = [
+  ("package", 0x611),
+  ("core", 0x639),
+  ("uncore", 0x641),
+  ("dram", 0x619),
+];
+
+/// Models (family 6) of server CPUs whose DRAM domain counts in a fixed unit of 2^-16 J (15.3
+/// uJ) instead of the unit of `MSR_RAPL_POWER_UNIT`, as listed by the powercap driver.
+const FIXED_DRAM_UNIT_MODELS: [u32; 11] = [
+  0x3f, // Haswell-X
+  0x4f, // Broadwell-X
+  0x56, // Broadwell-D
+  0x55, // Skylake-X, Cascade Lake-X, Cooper Lake-X
+  0x57, // Xeon Phi (Knights Landing)
+  0x85, // Xeon Phi (Knights Mill)
+  0x6a, // Ice Lake-X
+  0x6c, // Ice Lake-D
+  0x8f, // Sapphire Rapids-X
+  0xcf, // Emerald Rapids-X
+  0xad, // Granite Rapids-X
+];
+
+const FIXED_DRAM_UNIT_EXPONENT: u64 = 16;
+
+/// A cumulative energy counter of a RAPL domain. `name` is `package-<N>` for packages and
+/// `package-<N>-<domain>` for their subdomains (`core`, `uncore`, `dram`), for both sources.
+pub struct EnergyCounter {
+  pub name: String,
+  /// The value the counter wraps around at, in microjoules, if known.
+  pub range_uj: Option<u64>,
+  source: CounterSource,
+}
+
+enum CounterSource {
+  /// The `energy_uj` file of a powercap zone.
+  Powercap(PathBuf),
+  /// An energy status MSR, read through the MSR device of a CPU of the package. The counter is
+  /// 32 bits wide and wraps around like `energy_uj`.
+  Msr {
+    device: File,
+    path: PathBuf,
+    register: u64,
+    unit_uj: f64,
+  },
+}
+
+impl EnergyCounter {
+  /// Reads the counter in microjoules.
+  pub fn read(&self) -> Option<u64> {
+    match &self.source {
+      CounterSource::Powercap(path) => fs::read_to_string(path).ok()?.trim().parse().ok(),
+      CounterSource::Msr {
+        device,
+        register,
+        unit_uj,
+        ..
+      } => {
+        let raw = read_msr(device, *register)? & 0xffff_ffff;
+        Some((raw as f64 * unit_uj).round() as u64)
+      }
+    }
+  }
+
+  /// The file the counter is read from.
+  pub fn path(&self) -> &Path {
+    match &self.source {
+      CounterSource::Powercap(path) => path,
+      CounterSource::Msr { path, .. } => path,
+    }
+  }
+}
+
+/// Discovers the energy counters of `source`. Returns no counter (with a warning) if the host
+/// does not expose it.
+pub fn discover(source: &EnergySource) -> Vec<EnergyCounter> {
+  let counters = match source {
+    EnergySource::Powercap => {
+      // https://www.kernel.org/doc/html/next/power/powercap/powercap.html
+      powercap_counters(Path::new("/sys/devices/virtual/powercap/intel-rapl"))
+    }
+    EnergySource::Msr => {
+      let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+      let fixed_dram_unit = cpu_model(&cpuinfo)
+        .is_some_and(|(family, model)| family == 6 && FIXED_DRAM_UNIT_MODELS.contains(&model));
+      msr_counters(
+        Path::new("/sys/devices/system/cpu"),
+        Path::new("/dev/cpu"),
+        fixed_dram_unit,
+      )
+    }
+  };
+  if counters.is_empty() {
+    warn!(
+      "system does not support the {} energy interface; skipping",
+      source
+    );
+  }
+  counters
+}
+
+// packages are the zones intel-rapl:<N> and their subdomains the zones intel-rapl:<N>:<M>
+fn powercap_counters(base_path: &Path) -> Vec<EnergyCounter> {
+  let mut counters = Vec::new();
+  let Ok(entries) = base_path.read_dir() else {
+    return counters;
+  };
+  for entry in entries.flatten() {
+    if let Some((domain_name, path)) = powercap_zone(&entry.path()) {
+      counters.push(EnergyCounter {
+        name: domain_name.clone(),
+        range_uj: powercap_range(&path),
+        source: CounterSource::Powercap(path),
+      });
+      for subentry in entry.path().read_dir().into_iter().flatten().flatten() {
+        if let Some((name, path)) = powercap_zone(&subentry.path()) {
+          counters.push(EnergyCounter {
+            name: format!("{}-{}", domain_name, name),
+            range_uj: powercap_range(&path),
+            source: CounterSource::Powercap(path),
+          });
+        }
+      }
+    }
+  }
+  counters
+}
+
+fn powercap_zone(path: &Path) -> Option<(String, PathBuf)> {
+  if !path
+    .file_name()?
+    .to_string_lossy()
+    .starts_with("intel-rapl:")
+    || !path.is_dir()
+  {
+    return None;
+  }
+  let name = fs::read_to_string(path.join("name"))
+    .ok()?
+    .trim()
+    .to_owned();
+  Some((name, path.join("energy_uj")))
+}
+
+// the range of the `energy_uj` file of a zone, next to it
+fn powercap_range(energy_path: &Path) -> Option<u64> {
+  fs::read_to_string(energy_path.with_file_name("max_energy_range_uj"))
+    .ok()?
+    .trim()
+    .parse()
+    .ok()
+}
+
+// the domains of every package are read through the first CPU of the package; domains the CPU
+// does not implement read as 0 or fail
+fn msr_counters(
+  cpu_directory: &Path,
+  device_directory: &Path,
+  fixed_dram_unit: bool,
+) -> Vec<EnergyCounter> {
+  let mut packages: Vec<(u32, usize)> = Vec::new();
+  for entry in fs::read_dir(cpu_directory).into_iter().flatten().flatten() {
+    let Some(cpu) = entry
+      .file_name()
+      .to_str()
+      .and_then(|name| name.strip_prefix("cpu"))
+      .and_then(|cpu| cpu.parse::<usize>().ok())
+    else {
+      continue;
+    };
+    let Some(package) = fs::read_to_string(entry.path().join("topology/physical_package_id"))
+      .ok()
+      .and_then(|id| id.trim().parse::<u32>().ok())
+    else {
+      continue;
+    };
+    match packages.iter_mut().find(|(id, _)| *id == package) {
+      Some((_, first)) => *first = (*first).min(cpu),
+      None => packages.push((package, cpu)),
+    }
+  }
+  packages.sort_unstable();
+
+  let mut counters = Vec::new();
+  for (package, cpu) in packages {
+    // msr-safe exposes an allowlisted copy of the MSRs to unprivileged users
+    let Some((path, device)) = ["msr", "msr_safe"].iter().find_map(|name| {
+      let path = device_directory.join(cpu.to_string()).join(name);
+      File::open(&path).ok().map(|device| (path, device))
+    }) else {
+      continue;
+    };
+    let Some(units) = read_msr(&device, MSR_RAPL_POWER_UNIT) else {
+      continue;
+    };
+    for (domain, register) in MSR_ENERGY_STATUS {
+      if read_msr(&device, register).is_none_or(|value| value & 0xffff_ffff == 0) {
+        continue;
+      }
+      let exponent = if domain == "dram" && fixed_dram_unit {
+        FIXED_DRAM_UNIT_EXPONENT
+      } else {
+        (units >> 8) & 0x1f
+      };
+      let name = if domain == "package" {
+        format!("package-{}", package)
+      } else {
+        format!("package-{}-{}", package, domain)
+      };
+      let Ok(device) = device.try_clone() else {
+        continue;
+      };
+      let unit_uj = energy_unit_uj(exponent);
+      counters.push(EnergyCounter {
+        name,
+        // the counter is 32 bits wide
+        range_uj: Some(((1u64 << 32) as f64 * unit_uj).round() as u64),
+        source: CounterSource::Msr {
+          device,
+          path: path.clone(),
+          register,
+          unit_uj,
+        },
+      });
+    }
+  }
+  counters
+}
+
+// an energy unit of 1/2^exponent J, in microjoules
+fn energy_unit_uj(exponent: u64) -> f64 {
+  1e6 / (1u64 << exponent) as f64
+}
+
+// MSRs are read at the offset of their address in the MSR device of a CPU
+fn read_msr(device: &File, register: u64) -> Option<u64> {
+  let mut value = [0u8; 8];
+  device.read_exact_at(&mut value, register).ok()?;
+  Some(u64::from_le_bytes(value))
+}
+
+// returns the `cpu family` and `model` of the first CPU in /proc/cpuinfo
+fn cpu_model(cpuinfo: &str) -> Option<(u32, u32)> {
+  let field = |name: &str| {
+    cpuinfo.lines().find_map(|line| {
+      let (key, value) = line.split_once(':')?;
+      (key.trim() == name)
+        .then(|| value.trim().parse::<u32>().ok())
+        .flatten()
+    })
+  };
+  Some((field("cpu family")?, field("model")?))
+}
+
+#[cfg(test)]
+mod test {
+  use std::{fs, os::unix::fs::FileExt};
+
+  use tempfile::TempDir;
+
+  use super::{cpu_model, energy_unit_uj, msr_counters, powercap_counters};
+
+  #[test]
+  fn powercap_zones() {
+    let directory = TempDir::new().unwrap();
+    for (zone, name, energy) in [
+      ("intel-rapl:0", "package-0", "1000"),
+      ("intel-rapl:0/intel-rapl:0:0", "core", "400"),
+      ("intel-rapl:1", "package-1", "2000"),
+    ] {
+      let zone = directory.path().join(zone);
+      fs::create_dir_all(&zone).unwrap();
+      fs::write(zone.join("name"), format!("{}\n", name)).unwrap();
+      fs::write(zone.join("energy_uj"), format!("{}\n", energy)).unwrap();
+      fs::write(zone.join("max_energy_range_uj"), "262143328850\n").unwrap();
+    }
+
+    let mut counters = powercap_counters(directory.path());
+    counters.sort_by(|a, b| a.name.cmp(&b.name));
+    let names: Vec<&str> = counters.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["package-0", "package-0-core", "package-1"]);
+    assert_eq!(counters[1].read(), Some(400));
+    assert_eq!(counters[1].range_uj, Some(262143328850));
+    assert!(powercap_counters(&directory.path().join("missing")).is_empty());
+  }
+
+  #[test]
+  fn msr_units() {
+    // 2^-14 J, the most common unit on client CPUs
+    assert!((energy_unit_uj(14) - 61.03515625).abs() < 1e-9);
+    // the fixed DRAM unit of server CPUs
+    assert!((energy_unit_uj(16) - 15.2587890625).abs() < 1e-9);
+
+    let cpuinfo =
+      "processor\t: 0\ncpu family\t: 6\nmodel\t\t: 85\nmodel name\t: Intel(R) Xeon(R)\n";
+    assert_eq!(cpu_model(cpuinfo), Some((6, 85)));
+    assert_eq!(cpu_model("processor\t: 0\n"), None);
+
+    // one package of 2 CPUs, read through cpu0
+    let directory = TempDir::new().unwrap();
+    for cpu in ["cpu0", "cpu1"] {
+      let topology = directory.path().join(cpu).join("topology");
+      fs::create_dir_all(&topology).unwrap();
+      fs::write(topology.join("physical_package_id"), "0\n").unwrap();
+    }
+    let devices = directory.path().join("dev");
+    assert!(msr_counters(directory.path(), &devices, false).is_empty());
+
+    // a regular file stands for the MSR device: registers are at the offset of their address
+    fs::create_dir_all(devices.join("0")).unwrap();
+    let device = fs::File::create(devices.join("0/msr")).unwrap();
+    let write =
+      |register: u64, value: u64| device.write_all_at(&value.to_le_bytes(), register).unwrap();
+    // energy unit 2^-14 J
+    write(0x606, 0xe << 8);
+    write(0x611, 1000);
+    write(0x619, (1 << 32) | 2000);
+    let counters = msr_counters(directory.path(), &devices, true);
+    let names: Vec<&str> = counters.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["package-0", "package-0-dram"]);
+    assert_eq!(counters[0].read(), Some(61035));
+    // the upper bits are reserved; DRAM counts in 2^-16 J
+    assert_eq!(counters[1].read(), Some(30518));
+  }
+}
@@ -0,0 +1,619 @@
+//! Export of the results of a campaign as a single archive for sharing (see `export`), optionally
+//! anonymized (see `Anonymizer`), and import of archives from several hosts into a combined
+//! results tree (see `import`).
+use std::{
+  collections::BTreeMap,
+  env,
+  fs::{self, File, Metadata},
+  io::{self, Read},
+  os::unix::fs::{MetadataExt, PermissionsExt},
+  path::{Path, PathBuf},
+};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tar::{Builder, EntryType, Header};
+use tracing::{info, warn};
+
+use crate::constants::{CHECKSUMS_FILE, CONFIG_FILE, SOURCES_FILE};
+
+/// Files never exported: the key signing the enclaves of the campaign.
+const EXCLUDED_FILES: [&str; 1] = ["private_key.pem"];
+
+/// Top-level directories whose paths are the same on every host, kept by `Anonymizer`.
+const SYSTEM_DIRECTORIES: [&str; 13] = [
+  "bin", "boot", "dev", "etc", "lib", "lib32", "lib64", "proc", "run", "sbin", "sys", "tmp", "usr",
+];
+
+/// Rewrites the paths, host name and user names of a host to stable placeholders: the results
+/// directory becomes `<results>`, the home directory `<home>`, the host name `<hostname>` and
+/// every user name `<user>`. The directory of any other absolute path outside of the system
+/// directories becomes `<path-N>`, numbered in order of appearance, so that the same directory
+/// gets the same placeholder in every file.
+#[derive(Debug)]
+pub struct Anonymizer {
+  /// Directories with their placeholder, longest first.
+  prefixes: Vec<(String, String)>,
+  words: Vec<(Regex, &'static str)>,
+  path: Regex,
+  directories: BTreeMap<String, String>,
+}
+
+impl Anonymizer {
+  pub fn new(
+    results: &Path,
+    home: Option<&Path>,
+    hostname: Option<&str>,
+    users: &[String],
+  ) -> Self {
+    let mut prefixes = vec![(path_string(results), String::from("<results>"))];
+    if let Some(home) = home.filter(|home| home.parent().is_some()) {
+      prefixes.push((path_string(home), String::from("<home>")));
+    }
+    prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+    let word = |word: &str| Regex::new(&format!(r"\b{}\b", regex::escape(word))).unwrap();
+    let mut words = Vec::new();
+    if let Some(hostname) = hostname.filter(|hostname| !hostname.is_empty()) {
+      words.push((word(hostname), "<hostname>"));
+    }
+    // root is the same everywhere and a common word (e.g. the root qdisc)
+    for user in users
+      .iter()
+      .filter(|user| !user.is_empty() && *user != "root")
+    {
+      words.push((word(user), "<user>"));
+    }
+
+    Self {
+      prefixes,
+      words,
+      // a path starts a line or follows a character which cannot be part of a path, a URL or a
+      // placeholder
+      path: Regex::new(r"(^|[^\w./:>~-])(/[\w.+@-]+(?:/[\w.+@-]+)*/?)").unwrap(),
+      directories: BTreeMap::new(),
+    }
+  }
+
+  /// Anonymizes the paths of `results`, the home directory, the host name and the users of this
+  /// host (`USER`, `LOGNAME` and `SUDO_USER`).
+  pub fn for_host(results: &Path) -> Result<Self, io::Error> {
+    let results = results.canonicalize()?;
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+      .ok()
+      .map(|hostname| hostname.trim().to_string());
+    let users: Vec<String> = ["USER", "LOGNAME", "SUDO_USER"]
+      .iter()
+      .filter_map(|name| env::var(name).ok())
+      .collect();
+    Ok(Self::new(
+      &results,
+      home.as_deref(),
+      hostname.as_deref(),
+      &users,
+    ))
+  }
+
+  pub fn anonymize(&mut self, text: &str) -> String {
+    let mut directories = std::mem::take(&mut self.directories);
+    let text = self
+      .path
+      .replace_all(text, |captures: &Captures| {
+        format!(
+          "{}{}",
+          &captures[1],
+          self.anonymize_path(&captures[2], &mut directories)
+        )
+      })
+      .into_owned();
+    self.directories = directories;
+    self.words.iter().fold(text, |text, (word, placeholder)| {
+      word.replace_all(&text, *placeholder).into_owned()
+    })
+  }
+
+  fn anonymize_path(&self, path: &str, directories: &mut BTreeMap<String, String>) -> String {
+    for (prefix, placeholder) in &self.prefixes {
+      if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+        if rest.is_empty() || rest.starts_with('/') {
+          return format!("{}{}", placeholder, rest);
+        }
+      }
+    }
+    let top = path[1..].split('/').next().unwrap_or_default();
+    if SYSTEM_DIRECTORIES.contains(&top) {
+      return path.to_string();
+    }
+    let trimmed = path.trim_end_matches('/');
+    let (directory, name) = match trimmed.rfind('/') {
+      Some(0) | None => (trimmed, ""),
+      Some(i) => (&trimmed[..i], &trimmed[i..]),
+    };
+    let next = directories.len() + 1;
+    let placeholder = directories
+      .entry(directory.to_string())
+      .or_insert_with(|| format!("<path-{}>", next));
+    format!("{}{}{}", placeholder, name, &path[trimmed.len()..])
+  }
+}
+
+fn path_string(path: &Path) -> String {
+  path.to_string_lossy().trim_end_matches('/').to_string()
+}
+
+/// Packs the results of a campaign in `results` into the zstd-compressed tar archive `output`,
+/// under a directory named after the results. The archive holds everything the campaign wrote
+/// (configuration, manifests, `system.json`, samples) except the key signing its enclaves, and a
+/// `SHA256SUMS` file of the archived files. `config` replaces the configuration saved by the
+/// campaign. With an `anonymizer`, text files are anonymized and binary files (e.g. `perf.data`
+/// profiles, which record the host name) are left out.
+pub fn export(
+  results: &Path,
+  config: Option<&Path>,
+  output: &Path,
+  anonymizer: Option<&mut Anonymizer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+  if output.exists() {
+    return Err(format!("{:?} already exists", output).into());
+  }
+  let results = results.canonicalize()?;
+  if env::current_dir()?.join(output).starts_with(&results) {
+    return Err(format!("{:?} is inside the results {:?}", output, results).into());
+  }
+  let root = PathBuf::from(
+    results
+      .file_name()
+      .ok_or_else(|| format!("cannot export {:?}", results))?,
+  );
+  if config.is_none() && !results.join(CONFIG_FILE).is_file() {
+    warn!(
+      "{:?} has no {}; the archive does not include the configuration",
+      results, CONFIG_FILE
+    );
+  }
+
+  let mut archive = Archive {
+    builder: Builder::new(zstd::Encoder::new(File::create_new(output)?, 0)?),
+    anonymizer,
+    checksums: String::new(),
+    skipped: Vec::new(),
+  };
+  let result = (|| -> Result<Vec<PathBuf>, io::Error> {
+    let mut excluded = EXCLUDED_FILES.to_vec();
+    excluded.extend(config.map(|_| CONFIG_FILE));
+    archive.add_directory(&results, &root, Path::new(""), &excluded)?;
+    if let Some(config) = config {
+      archive.add_file(config, &root, Path::new(CONFIG_FILE))?;
+    }
+    archive.finish(&root)
+  })();
+  let skipped = match result {
+    Ok(skipped) => skipped,
+    Err(e) => {
+      // a truncated archive must not be mistaken for a complete one
+      let _ = fs::remove_file(output);
+      return Err(e.into());
+    }
+  };
+  if !skipped.is_empty() {
+    warn!(
+      "{} binary files cannot be anonymized and were not exported: {}",
+      skipped.len(),
+      skipped
+        .iter()
+        .map(|path| path.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(", ")
+    );
+  }
+  info!("exported {:?} to {:?}", results, output);
+  Ok(())
+}
+
+/// An export archive being written.
+struct Archive<'a> {
+  builder: Builder<zstd::Encoder<'static, File>>,
+  anonymizer: Option<&'a mut Anonymizer>,
+  /// Lines of `SHA256SUMS`, in the order files are added.
+  checksums: String,
+  /// Binary files left out by the anonymizer.
+  skipped: Vec<PathBuf>,
+}
+
+impl Archive<'_> {
+  /// Adds the directory `source` as `root`/`name`, except the entries named in `excluded`.
+  fn add_directory(
+    &mut self,
+    source: &Path,
+    root: &Path,
+    name: &Path,
+    excluded: &[&str],
+  ) -> Result<(), io::Error> {
+    let metadata = fs::metadata(source)?;
+    let mut header = entry_header(EntryType::Directory, &metadata, 0);
+    self
+      .builder
+      .append_data(&mut header, root.join(name), io::empty())?;
+
+    let mut entries = fs::read_dir(source)?.collect::<Result<Vec<_>, _>>()?;
+    // placeholders are numbered in order of appearance, so the order must not depend on the file
+    // system
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+      if excluded
+        .iter()
+        .any(|excluded| entry.file_name() == *excluded)
+      {
+        continue;
+      }
+      let path = entry.path();
+      let entry_name = name.join(entry.file_name());
+      let file_type = entry.file_type()?;
+      if file_type.is_dir() {
+        self.add_directory(&path, root, &entry_name, &[])?;
+      } else if file_type.is_symlink() {
+        let link = fs::read_link(&path)?;
+        let link = match &mut self.anonymizer {
+          Some(anonymizer) => PathBuf::from(anonymizer.anonymize(&link.to_string_lossy())),
+          None => link,
+        };
+        let mut header = entry_header(EntryType::Symlink, &fs::symlink_metadata(&path)?, 0);
+        self
+          .builder
+          .append_link(&mut header, root.join(&entry_name), link)?;
+      } else {
+        self.add_file(&path, root, &entry_name)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Adds the file `source` as `root`/`name`, recording its checksum.
+  fn add_file(&mut self, source: &Path, root: &Path, name: &Path) -> Result<(), io::Error> {
+    let metadata = fs::metadata(source)?;
+    match &mut self.anonymizer {
+      Some(anonymizer) => match String::from_utf8(fs::read(source)?) {
+        Ok(text) => {
+          let text = anonymizer.anonymize(&text);
+          self.append(root, name, &metadata, text.as_bytes(), text.len() as u64)
+        }
+        Err(_) => {
+          self.skipped.push(source.to_path_buf());
+          Ok(())
+        }
+      },
+      None => self.append(root, name, &metadata, File::open(source)?, metadata.len()),
+    }
+  }
+
+  fn append(
+    &mut self,
+    root: &Path,
+    name: &Path,
+    metadata: &Metadata,
+    data: impl Read,
+    size: u64,
+  ) -> Result<(), io::Error> {
+    let mut header = entry_header(EntryType::Regular, metadata, size);
+    let mut data = HashingReader {
+      inner: data,
+      hasher: Sha256::new(),
+    };
+    self
+      .builder
+      .append_data(&mut header, root.join(name), &mut data)?;
+    self.checksums.push_str(&format!(
+      "{:x}  {}\n",
+      data.hasher.finalize(),
+      name.display()
+    ));
+    Ok(())
+  }
+
+  /// Adds `SHA256SUMS` and completes the archive. Returns the files left out by the anonymizer.
+  fn finish(mut self, root: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_size(self.checksums.len() as u64);
+    self.builder.append_data(
+      &mut header,
+      root.join(CHECKSUMS_FILE),
+      self.checksums.as_bytes(),
+    )?;
+    self.builder.into_inner()?.finish()?;
+    Ok(self.skipped)
+  }
+}
+
+// owners are left out: user ids and names identify the host as well
+fn entry_header(entry_type: EntryType, metadata: &Metadata, size: u64) -> Header {
+  let mut header = Header::new_gnu();
+  header.set_entry_type(entry_type);
+  header.set_mode(metadata.permissions().mode() & 0o7777);
+  header.set_mtime(metadata.mtime().max(0) as u64);
+  header.set_size(size);
+  header
+}
+
+/// Computes the SHA-256 of the data read through it.
+struct HashingReader<R> {
+  inner: R,
+  hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.hasher.update(&buf[..n]);
+    Ok(n)
+  }
+}
+
+/// An archive imported into a combined results tree by `import`, listed in `sources.json` at the
+/// top of the tree.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImportedSource {
+  /// Directory of the results in the tree, `<host>/<run>`.
+  pub tag: String,
+  /// Host name recorded in `system.json`.
+  pub host: String,
+  /// Name of the exported results directory.
+  pub run: String,
+  pub archive: PathBuf,
+}
+
+/// Unpacks archives written by `export` into the combined results tree `output`, each in a
+/// `<host>/<run>` directory named after the host in its `system.json` and its results directory
+/// (with a numeric suffix if taken). An archive is added to the tree and to `sources.json` only
+/// once its checksums are verified.
+pub fn import(
+  archives: &[PathBuf],
+  output: &Path,
+) -> Result<Vec<ImportedSource>, Box<dyn std::error::Error>> {
+  fs::create_dir_all(output)?;
+  let index = output.join(SOURCES_FILE);
+  let mut sources: Vec<ImportedSource> = match fs::read_to_string(&index) {
+    Ok(content) => serde_json::from_str(&content)?,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+    Err(e) => return Err(e.into()),
+  };
+
+  let mut imported = Vec::new();
+  for archive in archives {
+    // unpacked next to the tree, so that it is moved into place with a rename
+    let staging = tempfile::Builder::new()
+      .prefix(".import-")
+      .tempdir_in(output)?;
+    tar::Archive::new(zstd::Decoder::new(File::open(archive)?)?).unpack(staging.path())?;
+    let root = single_directory(staging.path())?
+      .ok_or_else(|| format!("{:?} is not an export archive", archive))?;
+    verify_checksums(&root).map_err(|e| format!("{:?}: {}", archive, e))?;
+
+    let host = fs::read_to_string(root.join("system.json"))
+      .ok()
+      .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+      .and_then(|system| system["hostname"].as_str().map(tag_component))
+      .filter(|host| !host.is_empty())
+      .unwrap_or_else(|| String::from("unknown-host"));
+    let run = tag_component(&root.file_name().unwrap_or_default().to_string_lossy());
+    let mut tag = format!("{}/{}", host, run);
+    for n in 2.. {
+      if !output.join(&tag).exists() {
+        break;
+      }
+      tag = format!("{}/{}-{}", host, run, n);
+    }
+    fs::create_dir_all(output.join(&host))?;
+    fs::rename(&root, output.join(&tag))?;
+    info!("imported {:?} as {}", archive, tag);
+
+    let source = ImportedSource {
+      tag,
+      host,
+      run,
+      archive: archive.clone(),
+    };
+    sources.push(source.clone());
+    imported.push(source);
+    // written after every archive, so that a failing one does not drop the ones before it
+    fs::write(&index, serde_json::to_string_pretty(&sources)?)?;
+  }
+  Ok(imported)
+}
+
+// keeps the characters of a host or results name which are safe in a directory name
+fn tag_component(name: &str) -> String {
+  name
+    .chars()
+    .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    .collect::<String>()
+    .trim_start_matches('.')
+    .to_string()
+}
+
+// returns the only entry of `directory` if it is a directory
+fn single_directory(directory: &Path) -> Result<Option<PathBuf>, io::Error> {
+  let entries = fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+  match entries.as_slice() {
+    [entry] if entry.file_type()?.is_dir() => Ok(Some(entry.path())),
+    _ => Ok(None),
+  }
+}
+
+/// Checks the files of an unpacked archive against its `SHA256SUMS`.
+fn verify_checksums(root: &Path) -> Result<(), String> {
+  let checksums = fs::read_to_string(root.join(CHECKSUMS_FILE))
+    .map_err(|e| format!("cannot read {}: {}", CHECKSUMS_FILE, e))?;
+  for line in checksums.lines() {
+    let (checksum, name) = line
+      .split_once("  ")
+      .ok_or_else(|| format!("invalid line in {}: {}", CHECKSUMS_FILE, line))?;
+    let mut hasher = Sha256::new();
+    File::open(root.join(name))
+      .and_then(|mut file| io::copy(&mut file, &mut hasher))
+      .map_err(|e| format!("cannot read {}: {}", name, e))?;
+    if format!("{:x}", hasher.finalize()) != checksum {
+      return Err(format!("checksum mismatch for {}", name));
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Read,
+    path::Path,
+  };
+
+  use tempfile::TempDir;
+
+  use crate::constants::{CONFIG_FILE, SOURCES_FILE};
+
+  use super::{export, import, Anonymizer, ImportedSource};
+
+  #[test]
+  fn anonymize_text() {
+    let mut anonymizer = Anonymizer::new(
+      Path::new("/srv/bench/results"),
+      Some(Path::new("/home/alice")),
+      Some("lab-node3"),
+      &[String::from("alice"), String::from("root")],
+    );
+    assert_eq!(
+      anonymizer.anonymize(
+        "output_path = \"/srv/bench/results/ls/gramine-sgx\"\n\
+         loader = \"/home/alice/gramine/loader\"\nrunning on lab-node3 as alice (root qdisc)"
+      ),
+      "output_path = \"<results>/ls/gramine-sgx\"\n\
+       loader = \"<home>/gramine/loader\"\nrunning on <hostname> as <user> (root qdisc)"
+    );
+    // system paths, relative paths and URLs are kept
+    let kept = "/usr/bin/ls -> ls/gramine-sgx and https://example.com/a/b in /proc/self/maps";
+    assert_eq!(anonymizer.anonymize(kept), kept);
+    // other directories get stable placeholders
+    assert_eq!(
+      anonymizer.anonymize("/data/sets/a.bin /opt/app /data/sets/b.bin /srv/bench/resultsX"),
+      "<path-1>/a.bin <path-2>/app <path-1>/b.bin <path-3>/resultsX"
+    );
+    assert_eq!(anonymizer.anonymize("/opt/tool"), "<path-2>/tool");
+  }
+
+  #[test]
+  fn export_results() {
+    let tmp_dir = TempDir::new().unwrap();
+    let results = tmp_dir.path().join("results");
+    let sample = results.join("ls/no-gramine-sgx/ls-1/1");
+    fs::create_dir_all(&sample).unwrap();
+    fs::write(results.join("private_key.pem"), "key").unwrap();
+    fs::write(results.join(CONFIG_FILE), "[globals]").unwrap();
+    fs::write(sample.join("summary.csv"), "0123456789").unwrap();
+    fs::write(
+      sample.join("stdout"),
+      format!("{}/ls/no-gramine-sgx", results.display()),
+    )
+    .unwrap();
+    fs::write(sample.join("perf.data"), [0xff, 0xfe, 0x00]).unwrap();
+
+    let archive = tmp_dir.path().join("results.tar.zst");
+    export(&results, None, &archive, None).unwrap();
+    let files = unpack(&archive);
+    assert!(!files.contains_key("results/private_key.pem"));
+    assert_eq!(files["results/config.toml"], b"[globals]");
+    assert_eq!(
+      files["results/ls/no-gramine-sgx/ls-1/1/perf.data"],
+      [0xff, 0xfe, 0x00]
+    );
+    let checksums = String::from_utf8(files["results/SHA256SUMS"].clone()).unwrap();
+    assert!(checksums.contains(
+      "84d89877f0d4041efb6bf91a16f0248f2fd573e6af05c19f96bedb9f882f7882  \
+       ls/no-gramine-sgx/ls-1/1/summary.csv\n"
+    ));
+    assert_eq!(checksums.lines().count(), 4);
+    assert!(export(&results, None, &archive, None).is_err());
+    assert!(export(&results, None, &results.join("export.tar.zst"), None).is_err());
+
+    // the configuration given replaces the saved one
+    let config = tmp_dir.path().join("benchmark.toml");
+    fs::write(&config, "[[tasks]]").unwrap();
+    let anonymized = tmp_dir.path().join("anonymized.tar.zst");
+    let mut anonymizer = Anonymizer::new(&results, None, None, &[]);
+    export(&results, Some(&config), &anonymized, Some(&mut anonymizer)).unwrap();
+    let files = unpack(&anonymized);
+    assert_eq!(files["results/config.toml"], b"[[tasks]]");
+    assert_eq!(
+      files["results/ls/no-gramine-sgx/ls-1/1/stdout"],
+      b"<results>/ls/no-gramine-sgx"
+    );
+    assert!(!files.contains_key("results/ls/no-gramine-sgx/ls-1/1/perf.data"));
+  }
+
+  #[test]
+  fn import_archives() {
+    let tmp_dir = TempDir::new().unwrap();
+    let results = tmp_dir.path().join("run-1");
+    fs::create_dir_all(results.join("ls/no-gramine-sgx/ls-1/1")).unwrap();
+    fs::write(results.join("system.json"), r#"{"hostname": "lab-node3"}"#).unwrap();
+    let archive = tmp_dir.path().join("run-1.tar.zst");
+    export(&results, None, &archive, None).unwrap();
+
+    let combined = tmp_dir.path().join("combined");
+    let imported = import(&[archive.clone(), archive.clone()], &combined).unwrap();
+    assert_eq!(imported[0].tag, "lab-node3/run-1");
+    assert_eq!(imported[1].tag, "lab-node3/run-1-2");
+    assert!(combined
+      .join("lab-node3/run-1/ls/no-gramine-sgx/ls-1/1")
+      .is_dir());
+    let sources: Vec<ImportedSource> =
+      serde_json::from_str(&fs::read_to_string(combined.join(SOURCES_FILE)).unwrap()).unwrap();
+    assert_eq!(sources, imported);
+
+    // an archive whose files do not match their checksums is rejected
+    let tampered = tmp_dir.path().join("tampered.tar.zst");
+    let mut builder = tar::Builder::new(
+      zstd::Encoder::new(File::create(&tampered).unwrap(), 0)
+        .unwrap()
+        .auto_finish(),
+    );
+    for (name, content) in [
+      ("run-2/summary.csv", "0123456789"),
+      ("run-2/SHA256SUMS", "0000  summary.csv\n"),
+    ] {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(content.len() as u64);
+      header.set_mode(0o644);
+      builder
+        .append_data(&mut header, name, content.as_bytes())
+        .unwrap();
+    }
+    builder.into_inner().unwrap();
+    assert!(import(&[tampered], &combined).is_err());
+    assert!(!combined.join("unknown-host").exists());
+    assert_eq!(
+      fs::read_to_string(combined.join(SOURCES_FILE)).unwrap(),
+      serde_json::to_string_pretty(&imported).unwrap()
+    );
+  }
+
+  // returns the content of the files of an archive by path
+  fn unpack(archive: &Path) -> BTreeMap<String, Vec<u8>> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(archive).unwrap()).unwrap());
+    archive
+      .entries()
+      .unwrap()
+      .map(Result::unwrap)
+      .filter(|entry| entry.header().entry_type().is_file())
+      .map(|mut entry| {
+        let path = entry.path().unwrap().to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        (path, data)
+      })
+      .collect()
+  }
+}
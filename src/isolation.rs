@@ -0,0 +1,174 @@
+//! Isolation of the CPUs running the workloads (see `Isolation`) from interrupts and from the
+//! threads of the benchmark, reducing the variance between runs without external tuning scripts.
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use tracing::{info, trace, warn};
+
+use crate::{
+  common::format_cpu_list,
+  system::{affinity, cpu_set, online_cpus},
+};
+
+/// Moves the IRQs of the host and the threads of the benchmark to the housekeeping CPUs (the
+/// online CPUs which are not isolated) while it is alive. Threads started afterwards inherit the
+/// affinity of the benchmark, so workloads must be restricted to the isolated CPUs explicitly.
+/// Dropping it restores the previous affinities.
+#[derive(Debug)]
+pub struct Isolation {
+  /// `smp_affinity_list` files of the moved IRQs, with their previous content.
+  irqs: Vec<(PathBuf, String)>,
+  /// CPUs the benchmark was allowed to run on before.
+  affinity: Vec<usize>,
+}
+
+impl Isolation {
+  /// Isolates `isolated`. Requires root to move the IRQs.
+  pub fn apply(isolated: &[usize]) -> Result<Self, io::Error> {
+    let online = online_cpus();
+    if let Some(cpu) = isolated.iter().find(|cpu| !online.contains(cpu)) {
+      return Err(io::Error::other(format!(
+        "isolated CPU {} is not online (online CPUs: {})",
+        cpu,
+        format_cpu_list(&online)
+      )));
+    }
+    let housekeeping: Vec<usize> = online
+      .into_iter()
+      .filter(|cpu| !isolated.contains(cpu))
+      .collect();
+    if housekeeping.is_empty() {
+      return Err(io::Error::other(
+        "every online CPU is isolated: no CPU is left for interrupts and the benchmark",
+      ));
+    }
+
+    // on failure the threads and the IRQs moved so far are restored when dropped
+    let mut isolation = Self {
+      irqs: Vec::new(),
+      affinity: affinity(0)?,
+    };
+    for thread in threads() {
+      // threads may exit in the meantime
+      if let Err(e) = set_thread_affinity(thread, &housekeeping) {
+        trace!("cannot move thread {}: {}", thread, e);
+      }
+    }
+    let skipped = isolation.move_irqs(Path::new("/proc/irq"), &housekeeping)?;
+    info!(
+      "isolated CPUs {}: moved {} IRQs and the benchmark to CPUs {} ({} IRQs cannot be moved)",
+      format_cpu_list(isolated),
+      isolation.irqs.len(),
+      format_cpu_list(&housekeeping),
+      skipped
+    );
+    Ok(isolation)
+  }
+
+  // moves every IRQ of `directory` to `cpus`; returns the number of IRQs the kernel does not
+  // allow to move (per-CPU and managed interrupts)
+  fn move_irqs(&mut self, directory: &Path, cpus: &[usize]) -> Result<usize, io::Error> {
+    let affinity = format_cpu_list(cpus);
+    let mut skipped = 0;
+    for entry in fs::read_dir(directory)?.flatten() {
+      let path = entry.path().join("smp_affinity_list");
+      let Ok(previous) = fs::read_to_string(&path) else {
+        continue;
+      };
+      match fs::write(&path, &affinity) {
+        Ok(()) => self.irqs.push((path, previous.trim().to_string())),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+          return Err(io::Error::new(
+            e.kind(),
+            format!(
+              "cannot move the IRQs to CPUs {} (requires root): {}",
+              affinity, e
+            ),
+          ));
+        }
+        Err(e) => {
+          trace!("cannot move IRQ {:?}: {}", entry.file_name(), e);
+          skipped += 1;
+        }
+      }
+    }
+    Ok(skipped)
+  }
+}
+
+impl Drop for Isolation {
+  fn drop(&mut self) {
+    for (path, previous) in &self.irqs {
+      if let Err(e) = fs::write(path, previous) {
+        warn!("cannot restore the affinity of {:?}: {}", path, e);
+      }
+    }
+    for thread in threads() {
+      if let Err(e) = set_thread_affinity(thread, &self.affinity) {
+        trace!("cannot restore the affinity of thread {}: {}", thread, e);
+      }
+    }
+  }
+}
+
+// the threads of the benchmark
+fn threads() -> Vec<libc::pid_t> {
+  fs::read_dir("/proc/self/task")
+    .into_iter()
+    .flatten()
+    .flatten()
+    .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+    .collect()
+}
+
+fn set_thread_affinity(tid: libc::pid_t, cpus: &[usize]) -> Result<(), io::Error> {
+  let set = cpu_set(cpus);
+  // Safety: the set outlives the call.
+  if unsafe { libc::sched_setaffinity(tid, std::mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs;
+
+  use tempfile::TempDir;
+
+  use super::Isolation;
+  use crate::system::affinity;
+
+  #[test]
+  fn irq_affinities() {
+    let directory = TempDir::new().unwrap();
+    for (irq, affinity) in [("1", "0-3\n"), ("24", "2\n")] {
+      fs::create_dir(directory.path().join(irq)).unwrap();
+      fs::write(
+        directory.path().join(irq).join("smp_affinity_list"),
+        affinity,
+      )
+      .unwrap();
+    }
+    // not an IRQ
+    fs::write(directory.path().join("default_smp_affinity"), "f\n").unwrap();
+
+    let mut isolation = Isolation {
+      irqs: Vec::new(),
+      affinity: affinity(0).unwrap(),
+    };
+    let skipped = isolation.move_irqs(directory.path(), &[0, 1]).unwrap();
+    assert_eq!(skipped, 0);
+    assert_eq!(isolation.irqs.len(), 2);
+    let affinity =
+      |irq: &str| fs::read_to_string(directory.path().join(irq).join("smp_affinity_list")).unwrap();
+    assert_eq!(affinity("1"), "0-1");
+    assert_eq!(affinity("24"), "0-1");
+
+    drop(isolation);
+    assert_eq!(affinity("1"), "0-3");
+    assert_eq!(affinity("24"), "2");
+  }
+}
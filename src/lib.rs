@@ -0,0 +1,1221 @@
+//! Runs benchmarks of applications with and without Gramine-SGX, collecting performance
+//! counters, energy, I/O and SGX metrics for every experiment.
+//!
+//! The `enclave-benchmark` binary is a thin wrapper around this crate; other tools can drive a
+//! campaign in-process:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use enclave_benchmark::{run_campaign, Config};
+//!
+//! let config = Config::load(Path::new("examples/simple.toml")).unwrap();
+//! run_campaign(config).unwrap();
+//! ```
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+pub mod bandwidth;
+pub mod cache;
+pub mod collector;
+pub mod common;
+pub mod constants;
+pub mod energy;
+pub mod export;
+pub mod isolation;
+pub mod manifest;
+pub mod network;
+pub mod profiler;
+pub mod progress;
+pub mod provisioning;
+pub mod stats;
+pub mod summary;
+pub mod sweep;
+pub mod system;
+pub mod timeline;
+pub mod trend;
+pub mod wizard;
+
+mod tracer {
+  include!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/bpf/tracer.skel.rs"
+  ));
+}
+
+pub use collector::DefaultCollector;
+use common::{available_space, GlobalParams, RunHealth, Task};
+use constants::{DEFAULT_LAYOUT, ESTIMATED_DEEP_TRACE_BYTES, ESTIMATED_SAMPLE_BYTES, INDEX_FILE};
+use isolation::Isolation;
+pub use profiler::{plan, plan_with_layout, PlannedExperiment, Profiler};
+use progress::Progress;
+use system::{check_enclave_sizes, memory_and_swap, sgx_warnings, SgxPlatform, SystemInfo};
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+  pub globals: GlobalParams,
+  pub tasks: Vec<Task>,
+}
+
+impl Config {
+  /// Reads a configuration file, appending the tasks of the files listed in its `include` array
+  /// (relative to the including file) before its own tasks.
+  pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+    let mut config = load_with_includes(path, &mut Vec::new())?;
+    config.remove("include");
+    let config: Self = config.try_into()?;
+    for task in &config.tasks {
+      task.validate()?;
+    }
+    config.try_plan()?;
+    Ok(config)
+  }
+
+  /// Returns the configuration file at `path` with the tasks of its includes inlined, as saved in
+  /// the output directory of a campaign.
+  pub fn expand(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(toml::to_string(&load_with_includes(
+      path,
+      &mut Vec::new(),
+    )?)?)
+  }
+
+  /// Returns all experiments of the configuration in the order they are run.
+  pub fn plan(&self) -> Vec<PlanEntry> {
+    self
+      .try_plan()
+      .expect("the layout is checked by Config::load")
+  }
+
+  // the plan with the directories of `globals.layout`, which must give every experiment its own
+  // directory
+  fn try_plan(&self) -> Result<Vec<PlanEntry>, String> {
+    let mut entries = Vec::new();
+    for (task, t) in self.tasks.iter().enumerate() {
+      entries.extend(
+        plan_with_layout(t, self.layout())?
+          .into_iter()
+          .map(|experiment| PlanEntry {
+            task,
+            pinning_suffix: profiler::pinning_suffix(experiment.pinning),
+            experiment,
+          }),
+      );
+    }
+    if let Some(layout) = &self.globals.layout {
+      // sorted by component, a directory comes right before the ones it contains
+      let mut directories: Vec<&Path> = entries
+        .iter()
+        .map(|entry| Path::new(&entry.experiment.directory))
+        .collect();
+      directories.sort();
+      if let Some(pair) = directories
+        .windows(2)
+        .find(|pair| pair[1].starts_with(pair[0]))
+      {
+        return Err(format!(
+          "layout {:?} puts several experiments in {:?}",
+          layout, pair[0]
+        ));
+      }
+    }
+    Ok(entries)
+  }
+
+  fn layout(&self) -> &str {
+    self.globals.layout.as_deref().unwrap_or(DEFAULT_LAYOUT)
+  }
+}
+
+fn load_with_includes(
+  path: &Path,
+  stack: &mut Vec<PathBuf>,
+) -> Result<toml::Table, Box<dyn std::error::Error>> {
+  let canonical = path.canonicalize()?;
+  if stack.contains(&canonical) {
+    return Err(format!("include cycle detected at {:?}", path).into());
+  }
+  let mut table = fs::read_to_string(path)?.parse::<toml::Table>()?;
+
+  let includes = match table.remove("include") {
+    None => Vec::new(),
+    Some(toml::Value::Array(includes)) => includes,
+    Some(_) => return Err(format!("include must be an array of paths in {:?}", path).into()),
+  };
+
+  stack.push(canonical);
+  let mut tasks = Vec::new();
+  for include in includes {
+    let include = include
+      .as_str()
+      .ok_or_else(|| format!("include must be an array of paths in {:?}", path))?;
+    let included = load_with_includes(&path.parent().unwrap().join(include), stack)?;
+    if let Some(toml::Value::Array(included_tasks)) = included.get("tasks") {
+      tasks.extend(included_tasks.iter().cloned());
+    }
+  }
+  stack.pop();
+
+  if let Some(toml::Value::Array(own_tasks)) = table.remove("tasks") {
+    tasks.extend(own_tasks);
+  }
+  table.insert(String::from("tasks"), toml::Value::Array(tasks));
+  Ok(table)
+}
+
+/// An experiment of the plan, with the index of its task in the configuration.
+#[derive(Serialize, Debug)]
+pub struct PlanEntry {
+  pub task: usize,
+  /// The suffix the pinning adds to the name of the experiment (e.g. `-pinned`), so that scripts
+  /// reading the index do not hard-code it.
+  pub pinning_suffix: &'static str,
+  #[serde(flatten)]
+  pub experiment: PlannedExperiment,
+}
+
+/// A shard of the experiment plan (`i/n`, 1-based), used to split a campaign across machines.
+/// Experiments are assigned round-robin by their position in the plan, so identical
+/// configurations are always partitioned in the same way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shard {
+  pub index: usize,
+  pub count: usize,
+}
+
+impl Shard {
+  /// Returns whether the experiment at `position` in the plan belongs to this shard.
+  pub fn contains(&self, position: usize) -> bool {
+    position % self.count == self.index - 1
+  }
+}
+
+impl FromStr for Shard {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (index, count) = s
+      .split_once('/')
+      .ok_or_else(|| format!("invalid shard {:?}: expected i/n", s))?;
+    let index = index
+      .trim()
+      .parse::<usize>()
+      .map_err(|e| format!("invalid shard index {:?}: {}", index, e))?;
+    let count = count
+      .trim()
+      .parse::<usize>()
+      .map_err(|e| format!("invalid shard count {:?}: {}", count, e))?;
+    if index == 0 || index > count {
+      return Err(format!(
+        "invalid shard {}/{}: expected 1 <= i <= n",
+        index, count
+      ));
+    }
+    Ok(Self { index, count })
+  }
+}
+
+/// A benchmark campaign: the tasks of a configuration with the profiler and the collector running
+/// them.
+pub struct Campaign {
+  tasks: Vec<Task>,
+  shard: Option<Shard>,
+  profiler: Arc<Profiler>,
+  collector: Arc<DefaultCollector>,
+  stop: Arc<AtomicBool>,
+  isolated_cpus: Option<Vec<usize>>,
+  layout: String,
+  /// Set while the campaign runs with `isolated_cpus`.
+  isolation: Arc<Mutex<Option<Isolation>>>,
+}
+
+impl Campaign {
+  /// Prepares a campaign, checking that the output directory has enough free space and that the
+  /// enclaves fit on the host.
+  pub fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+    check_free_space(&config)?;
+    // the whole plan, also with --shard, so that the index of merged shards is complete
+    let index = serde_json::to_string_pretty(&config.plan())?;
+    let platform = SgxPlatform::probe();
+    for warning in sgx_warnings(platform.as_ref(), &config.tasks) {
+      warn!("{}", warning);
+    }
+    if let (Some(platform), Some(memory)) = (&platform, memory_and_swap()) {
+      check_enclave_sizes(platform, memory, &config.tasks)?;
+    }
+
+    let task_perf_events: Vec<String> = config
+      .tasks
+      .iter()
+      .flat_map(|task| {
+        let groups = task.perf_events.groups.iter().flatten();
+        task.perf_events.add.iter().chain(groups).cloned()
+      })
+      .collect();
+    let collector = Arc::new(
+      DefaultCollector::new(
+        config.globals.sample_size,
+        config.globals.deep_trace,
+        config.globals.energy_sample_interval,
+        config.globals.extra_perf_events,
+        config.globals.carbon_intensity_g_per_kwh,
+        config.globals.relative_timestamps,
+      )
+      .with_energy_source(config.globals.energy_source)
+      .with_perf_profile(config.globals.perf_profile)
+      .with_memory_bandwidth(config.globals.memory_bandwidth)
+      .with_checkpoint_interval(config.globals.checkpoint_interval)
+      .with_extra_kprobes(config.globals.extra_kprobes.unwrap_or_default())
+      .with_disk_devices(&config.globals.disk_devices.unwrap_or_default())?
+      .with_supported_perf_events(&task_perf_events)?,
+    );
+
+    let output_directory = config.globals.output_directory.clone();
+    let profiler = Arc::new(
+      Profiler::new(
+        config.globals.output_directory,
+        config.globals.debug,
+        collector.clone(),
+        &config.globals.signing_key,
+      )?
+      .with_baseline_cache(config.globals.baseline_cache)
+      .with_isolated_cpus(config.globals.isolated_cpus.clone().unwrap_or_default()),
+    );
+    SystemInfo {
+      unsupported_perf_events: collector.unsupported_perf_events().to_vec(),
+      ..SystemInfo::probe()
+    }
+    .save(&output_directory)?;
+    fs::write(output_directory.join(INDEX_FILE), index)?;
+
+    Ok(Self {
+      tasks: config.tasks,
+      shard: None,
+      profiler,
+      collector,
+      stop: Arc::new(AtomicBool::new(false)),
+      isolated_cpus: config.globals.isolated_cpus,
+      layout: config
+        .globals
+        .layout
+        .unwrap_or_else(|| DEFAULT_LAYOUT.to_string()),
+      isolation: Arc::new(Mutex::new(None)),
+    })
+  }
+
+  /// Returns a function stopping the campaign, which can be called from another thread (e.g. a
+  /// signal handler). The running sample completes and no further sample or task is started.
+  pub fn stopper(&self) -> impl Fn() + Send + Sync + 'static {
+    let profiler = self.profiler.clone();
+    let collector = self.collector.clone();
+    let stop = self.stop.clone();
+    move || {
+      profiler.stop();
+      collector.clone().stop();
+      stop.store(true, Ordering::Relaxed);
+    }
+  }
+
+  /// Like [`Campaign::stopper`], but the running workload is killed; the partial results of the
+  /// sample are saved and the sample is marked as invalid.
+  pub fn interrupter(&self) -> impl Fn() + Send + Sync + 'static {
+    let profiler = self.profiler.clone();
+    let collector = self.collector.clone();
+    let stop = self.stop.clone();
+    move || {
+      profiler.stop();
+      collector.clone().interrupt();
+      stop.store(true, Ordering::Relaxed);
+    }
+  }
+
+  /// Returns a function killing the running workload immediately, to be called right before
+  /// exiting the process. The isolation of the CPUs, if any, is undone.
+  pub fn aborter(&self) -> impl Fn() + Send + Sync + 'static {
+    let collector = self.collector.clone();
+    let isolation = self.isolation.clone();
+    move || {
+      collector.abort();
+      drop(isolation.lock().unwrap().take());
+    }
+  }
+
+  /// Returns the problems of the samples run so far (see `FailurePolicy`).
+  pub fn health(&self) -> RunHealth {
+    self.collector.health()
+  }
+
+  /// Reports the progress of the campaign as NDJSON records on `writer` (see `Progress`).
+  pub fn with_progress(self, writer: Box<dyn std::io::Write + Send>) -> Self {
+    self.collector.set_progress(Progress::new(
+      writer,
+      self.collector.samples_per_experiment(),
+    ));
+    self
+  }
+
+  /// Restricts the campaign to the experiments of `shard`.
+  pub fn with_shard(mut self, shard: Shard) -> Self {
+    self.shard = Some(shard);
+    self
+  }
+
+  /// Runs all the tasks in order.
+  pub fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+    let mut position = 0;
+    let mut plans = Vec::with_capacity(self.tasks.len());
+    for task in &self.tasks {
+      let experiments = plan_with_layout(task, &self.layout)?;
+      let first = position;
+      position += experiments.len();
+      let experiments: Vec<PlannedExperiment> = experiments
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| self.shard.is_none_or(|shard| shard.contains(first + i)))
+        .map(|(_, experiment)| experiment)
+        .collect();
+      plans.push((task, experiments));
+    }
+
+    if let Some(isolated_cpus) = &self.isolated_cpus {
+      *self.isolation.lock().unwrap() = Some(Isolation::apply(isolated_cpus)?);
+    }
+    let progress = self.collector.progress();
+    if let Some(progress) = progress {
+      progress.campaign_started(plans.iter().map(|(_, experiments)| experiments.len()).sum());
+    }
+    let result = (|| {
+      for (task, experiments) in plans {
+        if self.stop.load(Ordering::Relaxed) {
+          break;
+        }
+        if experiments.is_empty() {
+          continue;
+        }
+        self
+          .profiler
+          .profile_experiments(task.clone(), experiments)?;
+      }
+      Ok(())
+    })();
+    if let Some(progress) = progress {
+      progress.campaign_finished(
+        &self.collector.health(),
+        result.as_ref().err().map(ToString::to_string),
+      );
+    }
+    drop(self.isolation.lock().unwrap().take());
+    result
+  }
+}
+
+/// Runs all the tasks of `config`, writing results in `globals.output_directory`.
+pub fn run_campaign(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+  Campaign::new(config)?.run()
+}
+
+/// Estimates the bytes the configured experiments will write in the output directory.
+fn estimate_output_bytes(config: &Config) -> u64 {
+  let experiments: u64 = config
+    .tasks
+    .iter()
+    .map(|task| task.experiment_count() as u64)
+    .sum();
+  let per_experiment = config.globals.sample_size as u64 * ESTIMATED_SAMPLE_BYTES
+    + if config.globals.deep_trace {
+      ESTIMATED_DEEP_TRACE_BYTES
+    } else {
+      0
+    };
+  experiments * per_experiment
+}
+
+// fail early instead of dying with ENOSPC in the middle of a campaign
+fn check_free_space(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+  let required = estimate_output_bytes(config);
+  let available = available_space(&config.globals.output_directory)?;
+  info!(
+    "estimated output size {} bytes; {} bytes available",
+    required, available
+  );
+  if required > available {
+    return Err(
+      format!(
+        "not enough space for {:?}: about {} bytes required, {} bytes available",
+        config.globals.output_directory, required, available
+      )
+      .into(),
+    );
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use std::{fs, path::PathBuf, time::Duration};
+
+  use tempfile::TempDir;
+
+  use crate::{
+    common::{
+      FaultAction, FaultTrigger, Network, PerfEvents, ReadyProbe, SchedPolicy, SgxProfile,
+      SgxProfileEnable, SgxProfileMode, StorageType, Uprobe,
+    },
+    constants::{ESTIMATED_DEEP_TRACE_BYTES, ESTIMATED_SAMPLE_BYTES},
+    estimate_output_bytes, Config, Shard,
+  };
+
+  #[test]
+  fn example_configs() {
+    let examples = [
+      "examples/full.toml",
+      "examples/simple.toml",
+      "examples/iobound.toml",
+      "examples/minimal.toml",
+      "examples/demo.toml",
+    ];
+    for file in examples {
+      let content = fs::read_to_string(file).unwrap();
+      toml::from_str::<Config>(&content).unwrap();
+    }
+  }
+
+  #[test]
+  fn parse_config() {
+    let config = toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            debug = true
+            deep_trace = true
+            retention = { keep_deep_traces = 2, max_stdout_size = "10M" }
+            signing_key = { path = "/keys/enclave.pem" }
+            extra_kprobes = ["vfs_fsync"]
+            disk_devices = ["nvme0n1p3"]
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M", "128M"]
+            num_threads = [1]
+            [[tasks]]
+            executable = "/bin/ls"
+            args = ["-l", "-a"]
+            storage_type = ["encrypted"] 
+            enclave_size = ["64M", "128M"]
+            num_threads = [1]
+            energy_sample_interval = "10ms"
+            sgx_profile = { enable = "main", mode = "aex" }
+            rpc_thread_num = [0, 2]
+            uprobes = [{ binary = "/bin/ls", symbol = "main" }]
+            usdt = [{ binary = "/bin/ls", provider = "gramine", name = "ocall_start" }]
+            network = "veth"
+            netem = { latency = "20ms", bandwidth = "100mbit" }
+            sample_duration = "60s"
+            perf_events = { add = ["dTLB-load-misses"], remove = ["cache-misses"], groups = [["instructions", "cpu-cycles"]], min_counted = 50.0 }
+            [tasks.metrics]
+            requests_per_second = { pattern = 'Requests/sec:\s+([\d.]+)', unit = "1/s" }
+            loss = 'loss=([\d.]+)'
+            "#,
+    )
+    .unwrap();
+    assert!(config.globals.debug);
+    assert_eq!(config.globals.retention.keep_deep_traces, Some(2));
+    assert_eq!(config.globals.retention.max_stdout_size, Some(10 << 20));
+    assert_eq!(
+      config.globals.signing_key.path,
+      Some(PathBuf::from("/keys/enclave.pem"))
+    );
+    assert!(config.globals.signing_key.sgx_compatible());
+    assert_eq!(2, config.tasks.len());
+    assert_eq!(3, config.globals.sample_size);
+    let args = config.tasks[1].clone().args;
+    assert_eq!(2, args.len());
+    assert!(config.tasks[0].energy_sample_interval.is_none());
+    assert_eq!(config.tasks[0].sgx_profile, SgxProfile::default());
+    assert_eq!(config.tasks[1].sgx_profile.enable, SgxProfileEnable::Main);
+    assert_eq!(config.tasks[1].sgx_profile.mode, SgxProfileMode::Aex);
+    assert_eq!(config.tasks[0].rpc_thread_num, vec![0]);
+    assert_eq!(config.tasks[1].rpc_thread_num, vec![0, 2]);
+    assert_eq!(
+      config.globals.extra_kprobes,
+      Some(vec![String::from("vfs_fsync")])
+    );
+    assert_eq!(
+      config.globals.disk_devices,
+      Some(vec![String::from("nvme0n1p3")])
+    );
+    assert_eq!(config.tasks[0].network, Network::Host);
+    assert_eq!(config.tasks[1].network, Network::Veth);
+    let netem = config.tasks[1].netem.clone().unwrap();
+    assert_eq!(netem.latency, Some(Duration::from_millis(20)));
+    assert_eq!(netem.bandwidth.as_deref(), Some("100mbit"));
+    assert!(config.tasks[0].sample_duration.is_none());
+    assert!(config.tasks[0].metrics.is_empty());
+    assert_eq!(config.tasks[0].perf_events, PerfEvents::default());
+    assert_eq!(
+      config.tasks[1].perf_events,
+      PerfEvents {
+        add: vec![String::from("dTLB-load-misses")],
+        remove: vec![String::from("cache-misses")],
+        groups: vec![vec![
+          String::from("instructions"),
+          String::from("cpu-cycles")
+        ]],
+        min_counted: Some(50.0),
+      }
+    );
+    let metrics = &config.tasks[1].metrics;
+    assert_eq!(metrics[0].name, "loss");
+    assert_eq!(metrics[0].unit, "");
+    assert_eq!(metrics[1].name, "requests_per_second");
+    assert_eq!(metrics[1].unit, "1/s");
+    assert!(metrics[1].regex.is_match("Requests/sec:  123.4"));
+    assert_eq!(
+      config.tasks[1].sample_duration,
+      Some(Duration::from_secs(60))
+    );
+    assert!(config.tasks[0].uprobes.is_empty());
+    assert_eq!(
+      config.tasks[1].uprobes,
+      vec![Uprobe {
+        binary: PathBuf::from("/bin/ls"),
+        symbol: String::from("main"),
+      }]
+    );
+    assert_eq!(config.tasks[1].usdt[0].provider, "gramine");
+    assert_eq!(config.tasks[1].usdt[0].name, "ocall_start");
+    assert_eq!(
+      config.tasks[1].energy_sample_interval,
+      Some(Duration::from_millis(10))
+    );
+  }
+
+  #[test]
+  fn default_storage_type() {
+    let config = toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            storage_type = []
+            enclave_size = ["64M", "128M"]
+            [[tasks]]
+            executable = "/bin/ls"
+            args = ["-l", "-a"]
+            storage_type = ["encrypted"] 
+            enclave_size = ["64M", "128M"]
+            "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.tasks.len(), 2);
+    assert_eq!(config.tasks[0].storage_type.len(), 1);
+    assert_eq!(config.tasks[0].storage_type[0], StorageType::Untrusted);
+  }
+
+  #[test]
+  #[should_panic]
+  fn invalid_storage_type() {
+    toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M", "128M"]
+            [[tasks]]
+            executable = "/bin/ls"
+            args = ["-l", "-a"]
+            storage_type = ["invalid_storage_type", "encrypted"]
+            enclave_size = ["64M", "128M"]
+            "#,
+    )
+    .unwrap();
+  }
+
+  #[test]
+  fn enclave_size_range() {
+    let config = toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = { from = "64M", to = "1G" }
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = { from = "512K", to = "8M", factor = 4 }
+            "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+      config.tasks[0].enclave_size,
+      vec!["64M", "128M", "256M", "512M", "1G"]
+    );
+    assert_eq!(config.tasks[1].enclave_size, vec!["512K", "2M", "8M"]);
+  }
+
+  #[test]
+  #[should_panic]
+  fn invalid_enclave_size_range() {
+    toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = { from = "4G", to = "64M" }
+            "#,
+    )
+    .unwrap();
+  }
+
+  #[test]
+  fn enclave_size_validation() {
+    let config = |enclave_size: &str, env: &str| {
+      format!(
+        r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = {}
+            env = {}
+            "#,
+        enclave_size, env
+      )
+    };
+    for size in ["\"256MB\"", "\"1.5G\"", "\"0\""] {
+      let error = toml::from_str::<Config>(&config(&format!("[{}]", size), "{}")).unwrap_err();
+      assert!(error.to_string().contains("invalid enclave size"));
+    }
+
+    let directory = TempDir::new().unwrap();
+    let path = directory.path().join("config.toml");
+    fs::write(&path, config("[\"64M\", \"96M\"]", "{}")).unwrap();
+    let error = Config::load(&path).unwrap_err();
+    assert!(error.to_string().contains("\"96M\" is not a power of two"));
+    fs::write(&path, config("[\"64M\", \"96M\"]", "{ EDMM = \"1\" }")).unwrap();
+    assert!(Config::load(&path).is_ok());
+  }
+
+  #[test]
+  fn scheduling_validation() {
+    let config = |scheduling: &str| {
+      format!(
+        r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            {}
+            "#,
+        scheduling
+      )
+    };
+    let task = |scheduling: &str| {
+      toml::from_str::<Config>(&config(scheduling))
+        .unwrap()
+        .tasks
+        .remove(0)
+    };
+    let fifo = task("nice = -5\nsched_policy = { policy = \"fifo\", priority = 50 }");
+    assert_eq!(fifo.nice, Some(-5));
+    assert_eq!(fifo.sched_policy, Some(SchedPolicy::Fifo { priority: 50 }));
+    assert_eq!(fifo.sched_policy.unwrap().policy(), libc::SCHED_FIFO);
+    assert!(fifo.validate().is_ok());
+    assert!(task("sched_policy = { policy = \"batch\" }")
+      .validate()
+      .is_ok());
+
+    let error = task("nice = 20").validate().unwrap_err();
+    assert!(error.contains("nice 20 is not between -20 and 19"));
+    let error = task("sched_policy = { policy = \"rr\", priority = 0 }")
+      .validate()
+      .unwrap_err();
+    assert!(error.contains("priority of sched_policy rr (priority 0) is not between 1 and 99"));
+    assert!(toml::from_str::<Config>(&config("sched_policy = { policy = \"fifo\" }")).is_err());
+  }
+
+  #[test]
+  fn cache_allocation_validation() {
+    let task = |allocation: &str| {
+      toml::from_str::<Config>(&format!(
+        r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            cache_allocation = {}
+            "#,
+        allocation
+      ))
+      .unwrap()
+      .tasks
+      .remove(0)
+    };
+    assert!(task("{ ways = 4 }").validate().is_ok());
+    let masked = task("{ mask = \"0x0f0\" }");
+    assert_eq!(masked.cache_allocation.unwrap().parsed_mask(), Some(0xf0));
+
+    let error = task("{ mask = \"0x0f1\" }").validate().unwrap_err();
+    assert!(error.contains("is not a hexadecimal mask of contiguous bits"));
+    let error = task("{ ways = 2, mask = \"0x3\" }").validate().unwrap_err();
+    assert!(error.contains("either ways or a mask"));
+    assert!(task("{ ways = 0 }").validate().is_err());
+    assert!(task("{}").validate().is_err());
+  }
+
+  #[test]
+  fn key_provisioning() {
+    let task_with_storage = |provisioning: &str, storage: &str| {
+      toml::from_str::<Config>(&format!(
+        r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            storage_type = {}
+            key_provisioning = {}
+            "#,
+        storage, provisioning
+      ))
+      .unwrap()
+      .tasks
+      .remove(0)
+    };
+    let task = |provisioning: &str| task_with_storage(provisioning, r#"["encrypted"]"#);
+    let provisioned = task(r#"{ server = "localhost:4433", ca_chain = "/etc/ca.crt" }"#);
+    assert!(provisioned.validate().is_ok());
+    assert_eq!(
+      provisioned.key_provisioning_modes(&StorageType::Encrypted),
+      vec![false, true]
+    );
+    // SGX experiments with both keys, and the native one
+    assert_eq!(provisioned.experiment_count(), 3);
+    let names: Vec<String> = crate::profiler::plan(&provisioned)
+      .into_iter()
+      .map(|experiment| experiment.name)
+      .collect();
+    assert_eq!(
+      names,
+      vec![
+        "ls-1-64M-encrypted",
+        "ls-1-64M-encrypted-provisioned",
+        "ls-1"
+      ]
+    );
+    let only =
+      task(r#"{ server = "localhost:4433", ca_chain = "/etc/ca.crt", embedded_key = false }"#);
+    assert_eq!(
+      only.key_provisioning_modes(&StorageType::Encrypted),
+      vec![true]
+    );
+
+    // the untrusted storage has no key to provision
+    let mixed = task_with_storage(
+      r#"{ server = "localhost:4433", ca_chain = "/etc/ca.crt" }"#,
+      r#"["encrypted", "untrusted"]"#,
+    );
+    assert_eq!(
+      mixed.key_provisioning_modes(&StorageType::Untrusted),
+      vec![false]
+    );
+    assert_eq!(mixed.experiment_count(), 4);
+    let names: Vec<String> = crate::profiler::plan(&mixed)
+      .into_iter()
+      .map(|experiment| experiment.name)
+      .collect();
+    assert_eq!(
+      names,
+      vec![
+        "ls-1-64M-encrypted",
+        "ls-1-64M-encrypted-provisioned",
+        "ls-1-64M-untrusted",
+        "ls-1"
+      ]
+    );
+    let error = task_with_storage(
+      r#"{ server = "localhost:4433", ca_chain = "/etc/ca.crt" }"#,
+      r#"["untrusted"]"#,
+    )
+    .validate()
+    .unwrap_err();
+    assert!(error.contains("needs the encrypted storage_type"));
+
+    let error = task(r#"{ server = "localhost", ca_chain = "/etc/ca.crt" }"#)
+      .validate()
+      .unwrap_err();
+    assert!(error.contains("is not a host:port address"));
+    let error = task(r#"{ server = "localhost:4433", ca_chain = "ca.crt" }"#)
+      .validate()
+      .unwrap_err();
+    assert!(error.contains("is not an absolute path"));
+    // enclaves in their own network namespace have no route to the server
+    let mut isolated = provisioned.clone();
+    isolated.network = Network::Loopback;
+    let error = isolated.validate().unwrap_err();
+    assert!(error.contains("needs the host network"));
+  }
+
+  #[test]
+  fn auxiliary_executables() {
+    let task = |executables: &str| {
+      toml::from_str::<Config>(&format!(
+        r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/sh"
+            args = ["run.sh"]
+            enclave_size = ["64M"]
+            auxiliary_executables = {}
+            "#,
+        executables
+      ))
+      .unwrap()
+      .tasks
+      .remove(0)
+    };
+    let task_with_helpers = task(r#"["/bin/ls", "/usr/bin/env"]"#);
+    assert_eq!(
+      task_with_helpers.auxiliary_executables,
+      vec![PathBuf::from("/bin/ls"), PathBuf::from("/usr/bin/env")]
+    );
+    assert!(task_with_helpers.validate().is_ok());
+    let error = task(r#"["bin/helper"]"#).validate().unwrap_err();
+    assert!(error.contains("auxiliary executable \"bin/helper\" is not an absolute path"));
+  }
+
+  #[test]
+  fn fault_triggers() {
+    let config = toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            fault = { action = "kill", after = "5s" }
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            fault = { action = "stop", after_syscalls = 1000, stop_for = "2s", every = 3 }
+            "#,
+    )
+    .unwrap();
+
+    let kill = config.tasks[0].fault.clone().unwrap();
+    assert_eq!(kill.action, FaultAction::Kill);
+    assert_eq!(kill.trigger, FaultTrigger::After(Duration::from_secs(5)));
+    assert_eq!(kill.stop_for, Duration::from_secs(1));
+    assert_eq!(kill.every, 2);
+    let stop = config.tasks[1].fault.clone().unwrap();
+    assert_eq!(stop.action, FaultAction::Stop);
+    assert_eq!(stop.trigger, FaultTrigger::Syscalls(1000));
+    assert_eq!(stop.stop_for, Duration::from_secs(2));
+    assert_eq!(stop.every, 3);
+
+    // exactly one trigger
+    assert!(toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            fault = { action = "kill", after = "5s", after_syscalls = 10 }
+            "#,
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn ready_checks() {
+    let config = |ready_check: &str| {
+      format!(
+        r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            ready_check = {}
+            "#,
+        ready_check
+      )
+    };
+    let tcp = toml::from_str::<Config>(&config(r#"{ type = "tcp", port = 8080 }"#)).unwrap();
+    let tcp = tcp.tasks[0].ready_check.clone().unwrap();
+    assert!(matches!(&tcp.probe, ReadyProbe::Tcp { host, port: 8080 } if host == "127.0.0.1"));
+    assert_eq!(tcp.timeout, Duration::from_secs(60));
+    let log = toml::from_str::<Config>(&config(
+      r#"{ type = "log", pattern = 'listening on \d+', timeout = "10s" }"#,
+    ))
+    .unwrap();
+    let log = log.tasks[0].ready_check.clone().unwrap();
+    assert!(matches!(&log.probe, ReadyProbe::Log(pattern) if pattern.is_match("listening on 80")));
+    assert_eq!(log.timeout, Duration::from_secs(10));
+
+    for invalid in [
+      r#"{ type = "tcp" }"#,
+      r#"{ type = "tcp", port = 8080, pattern = "ready" }"#,
+      r#"{ type = "log", pattern = "ready", port = 8080 }"#,
+      r#"{ type = "log", pattern = "(" }"#,
+      r#"{ type = "http", port = 8080 }"#,
+    ] {
+      assert!(toml::from_str::<Config>(&config(invalid)).is_err());
+    }
+  }
+
+  #[test]
+  fn invalid_metric_patterns() {
+    for metrics in [
+      "loss = 'loss=[0-9.]+'",
+      "loss = 'loss=([0-9.]+'",
+      "'a,b' = '([0-9]+)'",
+      "loss = { pattern = 'loss=([0-9.]+)', unit = \"a,b\" }",
+      "loss = { pattern = 'loss=([0-9.]+)', unit = 'a\"b' }",
+    ] {
+      let config = format!(
+        r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            [tasks.metrics]
+            {}
+            "#,
+        metrics
+      );
+      assert!(toml::from_str::<Config>(&config).is_err(), "{}", metrics);
+    }
+  }
+
+  #[test]
+  fn num_threads_range() {
+    let config = toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            num_threads = { from = 1, to = 32, step = "x2" }
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            num_threads = { from = 2, to = 9, step = 3 }
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            num_threads = { from = 1, to = 3 }
+            "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.tasks[0].num_threads, vec![1, 2, 4, 8, 16, 32]);
+    assert_eq!(config.tasks[1].num_threads, vec![2, 5, 8]);
+    assert_eq!(config.tasks[2].num_threads, vec![1, 2, 3]);
+  }
+
+  #[test]
+  fn output_size_estimate() {
+    let config = toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 3
+            output_directory = "/test"
+            deep_trace = true
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M", "128M"]
+            num_threads = [1, 2]
+            storage_type = ["encrypted", "untrusted"]
+            args_matrix = { "--size" = ["1", "2", "3"] }
+            "#,
+    )
+    .unwrap();
+
+    // 2 threads * 3 combinations * (2 sizes * 2 storage types + native)
+    assert_eq!(config.tasks[0].experiment_count(), 30);
+    assert_eq!(
+      estimate_output_bytes(&config),
+      30 * (3 * ESTIMATED_SAMPLE_BYTES + ESTIMATED_DEEP_TRACE_BYTES)
+    );
+  }
+
+  #[test]
+  fn config_includes() {
+    let directory = TempDir::new().unwrap();
+    fs::create_dir(directory.path().join("tasks")).unwrap();
+    fs::write(
+      directory.path().join("tasks/common.toml"),
+      r#"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            "#,
+    )
+    .unwrap();
+    fs::write(
+      directory.path().join("config.toml"),
+      r#"
+            include = ["tasks/common.toml"]
+            [globals]
+            sample_size = 1
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/dd"
+            enclave_size = ["128M"]
+            "#,
+    )
+    .unwrap();
+
+    let config = Config::load(&directory.path().join("config.toml")).unwrap();
+    assert_eq!(config.tasks.len(), 2);
+    assert_eq!(config.tasks[0].executable, PathBuf::from("/bin/ls"));
+    assert_eq!(config.tasks[1].executable, PathBuf::from("/bin/dd"));
+
+    // the expanded configuration stands on its own
+    let expanded = Config::expand(&directory.path().join("config.toml")).unwrap();
+    assert!(!expanded.contains("include"));
+    let config = toml::from_str::<Config>(&expanded).unwrap();
+    assert_eq!(config.tasks.len(), 2);
+    assert_eq!(config.tasks[0].executable, PathBuf::from("/bin/ls"));
+
+    // a file including itself
+    fs::write(
+      directory.path().join("tasks/common.toml"),
+      r#"include = ["common.toml"]"#,
+    )
+    .unwrap();
+    assert!(Config::load(&directory.path().join("config.toml")).is_err());
+  }
+
+  #[test]
+  fn plan_shards() {
+    let config = toml::from_str::<Config>(
+      r#"
+            [globals]
+            sample_size = 1
+            output_directory = "/test"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M", "128M"]
+            num_threads = [1, 2]
+            [[tasks]]
+            executable = "/bin/dd"
+            enclave_size = ["64M"]
+            "#,
+    )
+    .unwrap();
+    let plan = config.plan();
+    assert_eq!(plan.len(), 8);
+    assert_eq!(plan[0].task, 0);
+    assert_eq!(plan[7].task, 1);
+
+    let shards: Vec<Shard> = ["1/3", "2/3", "3/3"]
+      .iter()
+      .map(|s| s.parse().unwrap())
+      .collect();
+    // every experiment belongs to exactly one shard
+    for position in 0..plan.len() {
+      assert_eq!(shards.iter().filter(|s| s.contains(position)).count(), 1);
+    }
+    assert!("0/3".parse::<Shard>().is_err());
+    assert!("4/3".parse::<Shard>().is_err());
+    assert!("1-3".parse::<Shard>().is_err());
+  }
+
+  #[test]
+  fn plan_layout() {
+    let config = |layout: &str| {
+      toml::from_str::<Config>(&format!(
+        r#"
+            [globals]
+            sample_size = 1
+            output_directory = "/test"
+            layout = "{}"
+            [[tasks]]
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            storage_type = ["encrypted", "untrusted"]
+            args_matrix = {{ "--size" = ["1", "2"] }}
+            "#,
+        layout
+      ))
+      .unwrap()
+    };
+    let plan = config("{{storage_type}}/{{program}}/{{name}}")
+      .try_plan()
+      .unwrap();
+    let directories: Vec<&str> = plan
+      .iter()
+      .map(|entry| entry.experiment.directory.as_str())
+      .collect();
+    assert_eq!(
+      directories,
+      vec![
+        "encrypted/ls/ls-1-64M-encrypted-size=1",
+        "encrypted/ls/ls-1-64M-encrypted-size=2",
+        "untrusted/ls/ls-1-64M-untrusted-size=1",
+        "untrusted/ls/ls-1-64M-untrusted-size=2",
+        // native experiments have no storage type
+        "ls/ls-1-size=1",
+        "ls/ls-1-size=2",
+      ]
+    );
+
+    let flat = config("{{id}}").try_plan().unwrap();
+    assert_eq!(flat[0].experiment.directory, flat[0].experiment.id);
+    // the default layout is not changed by an explicit one with the same template
+    assert_eq!(
+      config("{{program}}/{{kind}}/{{id}}").plan()[0]
+        .experiment
+        .directory,
+      format!("ls/gramine-sgx/{}", flat[0].experiment.id)
+    );
+
+    assert!(config("{{program}}/{{storage_type}}").try_plan().is_err());
+    let by_value = config("{{mode}}/size-{{args_matrix.[--size]}}/{{id}}")
+      .try_plan()
+      .unwrap();
+    assert!(by_value[1]
+      .experiment
+      .directory
+      .starts_with("sgx/size-2/ls-"));
+    assert!(config("../{{id}}").try_plan().is_err());
+    assert!(config("{{#if}}").try_plan().is_err());
+  }
+}
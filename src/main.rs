@@ -1,49 +1,58 @@
-use collector::DefaultCollector;
-use common::{GlobalParams, Task};
-use profiler::Profiler;
+use enclave_benchmark::{
+  common::{parse_size, GlobalParams},
+  constants::{CONFIG_FILE, IO_SWEEP_FILE},
+  export::{export, import, Anonymizer},
+  manifest::{diff_manifests, load_manifest},
+  summary::{format_table, summarize},
+  sweep::{self, format_report, run_worker, IoSweep},
+  timeline,
+  trend::{format_trend, track, trend},
+  wizard::{self, MachineInfo},
+  Campaign, Config, PlanEntry, Shard,
+};
 use pyo3::{
   ffi::c_str,
-  types::{PyAnyMethods, PyModule},
-  Py, PyAny, PyResult, Python,
+  types::{PyAnyMethods, PyDict, PyDictMethods, PyModule},
+  Bound, Py, PyAny, PyResult, Python,
 };
-use serde::Deserialize;
 use std::{
   env,
-  fmt::Debug,
   fs::{self, remove_dir_all},
-  path::PathBuf,
+  io::{self, Read, Write},
+  os::fd::{FromRawFd, RawFd},
+  path::{Path, PathBuf},
   sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicI32, AtomicUsize, Ordering},
     Arc,
   },
+  thread,
 };
 
-use clap::{arg, command, Parser};
+use clap::{arg, command, Parser, Subcommand};
 use tracing::{info, warn, Level};
 
-mod collector;
-mod common;
-mod constants;
-mod profiler;
-mod stats;
-
-mod tracer {
-  include!(concat!(
-    env!("CARGO_MANIFEST_DIR"),
-    "/src/bpf/tracer.skel.rs"
-  ));
-}
-
 #[derive(Parser)]
 #[command(version = env!("CARGO_PKG_VERSION"), about = "A cli app to run benchmarks for Gramine application", long_about = None)]
 #[command(name = "enclave-benchmark")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+  #[command(subcommand)]
+  command: Option<Commands>,
+
   /// Turn debugging information on
   #[arg(short, action = clap::ArgAction::Count)]
   verbose: u8,
 
-  #[arg(short, long, help = "Path to configuration file")]
-  config: PathBuf,
+  #[arg(
+    short,
+    long,
+    default_value = "false",
+    help = "Print only errors and the summary table at the end of the run"
+  )]
+  quiet: bool,
+
+  #[arg(short, long, required = true, help = "Path to configuration file")]
+  config: Option<PathBuf>,
 
   #[arg(
     long,
@@ -58,12 +67,269 @@ struct Cli {
     help = "Aggregate results from samples. Creates an <output_directory>/aggregated"
   )]
   aggregate: bool,
+
+  #[arg(long, help = "Override globals.output_directory")]
+  output_dir: Option<PathBuf>,
+
+  #[arg(long, help = "Override globals.sample_size")]
+  sample_size: Option<u32>,
+
+  #[arg(
+    long,
+    default_value = "false",
+    help = "Enable globals.deep_trace regardless of the configuration file"
+  )]
+  deep_trace: bool,
+
+  #[arg(
+    long,
+    help = "Run only the i-th of n shards of the experiment plan (e.g. 1/4)"
+  )]
+  shard: Option<Shard>,
+
+  #[arg(
+    long,
+    value_name = "FILE|fd:N",
+    value_parser = parse_progress_target,
+    help = "Write progress records as NDJSON to a file or an open file descriptor (fd:3)"
+  )]
+  progress_json: Option<ProgressTarget>,
 }
 
-#[derive(Deserialize, Debug)]
-struct Config {
-  pub globals: GlobalParams,
-  pub tasks: Vec<Task>,
+#[derive(Subcommand)]
+enum Commands {
+  /// Print the expanded experiment matrix without running it
+  Plan {
+    #[arg(short, long, help = "Path to configuration file")]
+    config: PathBuf,
+
+    #[arg(long, default_value = "false", help = "Print the plan as JSON")]
+    json: bool,
+
+    #[arg(long, help = "Print only the i-th of n shards of the plan (e.g. 1/4)")]
+    shard: Option<Shard>,
+  },
+  /// Interactively create a configuration file with values suggested for this machine
+  Init {
+    #[arg(
+      short,
+      long,
+      default_value = "benchmark.toml",
+      help = "Path of the configuration file to create"
+    )]
+    output: PathBuf,
+  },
+  /// Pack the results of a campaign into a tar.zst archive for sharing, without the enclave
+  /// signing key
+  Export {
+    #[arg(help = "Results directory (globals.output_directory)")]
+    results: PathBuf,
+
+    #[arg(
+      short,
+      long,
+      help = "Configuration file to include instead of the one saved with the results"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(short, long, help = "Archive to create [default: <results>.tar.zst]")]
+    output: Option<PathBuf>,
+
+    #[arg(
+      long,
+      default_value = "false",
+      help = "Replace paths, host name and user names with placeholders"
+    )]
+    anonymize: bool,
+  },
+  /// Show the settings which differ between the Gramine manifests of two experiments
+  ManifestDiff {
+    #[arg(help = "Experiment directory or manifest file")]
+    old: PathBuf,
+
+    #[arg(
+      required_unless_present = "default",
+      help = "Experiment directory or manifest file"
+    )]
+    new: Option<PathBuf>,
+
+    #[arg(
+      long,
+      default_value = "false",
+      conflicts_with = "new",
+      help = "Compare the default template rendered for the experiment with its custom manifest"
+    )]
+    default: bool,
+
+    #[arg(
+      long,
+      default_value = "false",
+      help = "Ignore the sha256 of trusted files"
+    )]
+    ignore_checksums: bool,
+  },
+  /// Unpack exported archives into a combined results tree, one directory per host and run
+  Import {
+    #[arg(required = true, help = "Archives created by export")]
+    archives: Vec<PathBuf>,
+
+    #[arg(short, long, help = "Combined results directory")]
+    output: PathBuf,
+
+    #[arg(
+      long,
+      default_value = "false",
+      help = "Aggregate every run and their summaries. Creates an <output>/aggregated"
+    )]
+    aggregate: bool,
+  },
+  /// Append the summary of a campaign to a trend database, keyed by host, configuration and
+  /// workload version
+  Track {
+    #[arg(help = "Results directory (globals.output_directory)")]
+    results: PathBuf,
+
+    #[arg(long, help = "SQLite database, created if missing")]
+    db: PathBuf,
+  },
+  /// Show how a metric changed across the campaigns of a trend database
+  Trends {
+    #[arg(long, help = "SQLite database filled by track")]
+    db: PathBuf,
+
+    #[arg(
+      short,
+      long,
+      default_value = "runtime",
+      help = "Metric of summary.csv, or sgx_overhead"
+    )]
+    metric: String,
+
+    #[arg(long, help = "Show only the experiments of this task")]
+    task: Option<String>,
+
+    #[arg(long, help = "Show only the campaigns of this host")]
+    host: Option<String>,
+
+    #[arg(long, help = "Plot the trend of every experiment to this PNG file")]
+    plot: Option<PathBuf>,
+  },
+  /// Combine the deep traces of several samples into a timeline aligned on the start of their
+  /// process, exported as CSV and HTML
+  Timeline {
+    #[arg(
+      required = true,
+      help = "Experiment directories (their deep-trace sample) or sample directories with a trace.csv"
+    )]
+    directories: Vec<PathBuf>,
+
+    #[arg(
+      short,
+      long,
+      default_value = "timeline",
+      help = "Output path, without extension: writes <output>.csv and <output>.html"
+    )]
+    output: PathBuf,
+  },
+  /// Characterize the storage of this machine: run a builtin I/O workload across block sizes,
+  /// file sizes and storage types and report its throughput
+  IoSweep {
+    #[arg(short, long, default_value = "io-sweep", help = "Output directory")]
+    output: PathBuf,
+
+    #[arg(
+      long,
+      value_delimiter = ',',
+      value_parser = parse_size_arg,
+      default_values = ["4K", "64K", "1M"],
+      help = "Block sizes of the writes and reads"
+    )]
+    block_sizes: Vec<u64>,
+
+    #[arg(
+      long,
+      value_delimiter = ',',
+      value_parser = parse_size_arg,
+      default_values = ["16M", "256M"],
+      help = "Sizes of the written file"
+    )]
+    file_sizes: Vec<u64>,
+
+    #[arg(
+      long,
+      default_value = "1G",
+      help = "Enclave size; tmpfs files are kept in the enclave, so it must fit the largest file"
+    )]
+    enclave_size: String,
+
+    #[arg(long, default_value = "3", help = "Samples per experiment")]
+    sample_size: u32,
+
+    #[arg(
+      long,
+      default_value = "false",
+      help = "Remove previous results directory (if exists)"
+    )]
+    force: bool,
+  },
+  /// Run the I/O workload of io-sweep
+  #[command(hide = true)]
+  IoWorker {
+    #[arg(long)]
+    path: PathBuf,
+
+    #[arg(long, value_parser = parse_size_arg)]
+    block_size: u64,
+
+    #[arg(long, value_parser = parse_size_arg)]
+    file_size: u64,
+  },
+}
+
+/// Where `--progress-json` writes its records.
+#[derive(Debug, Clone, PartialEq)]
+enum ProgressTarget {
+  /// A file descriptor inherited from the caller, given as `fd:N`.
+  Fd(i32),
+  File(PathBuf),
+}
+
+// `fd:N` names an inherited file descriptor, anything else a file (`./fd:3` for a file named so);
+// stdin, stdout and stderr are refused, since the records would mix with the logs or be lost
+fn parse_progress_target(value: &str) -> Result<ProgressTarget, String> {
+  let Some(fd) = value.strip_prefix("fd:") else {
+    return Ok(ProgressTarget::File(PathBuf::from(value)));
+  };
+  match fd.parse::<i32>() {
+    Ok(fd) if fd > 2 => Ok(ProgressTarget::Fd(fd)),
+    Ok(fd) if fd >= 0 => Err(format!(
+      "file descriptor {} is stdin, stdout or stderr; use 3 or above",
+      fd
+    )),
+    _ => Err(format!("invalid file descriptor {:?}", fd)),
+  }
+}
+
+// sizes with an optional K, M or G suffix, as in the configuration file
+fn parse_size_arg(value: &str) -> Result<u64, String> {
+  parse_size(value)
+    .filter(|&size| size > 0)
+    .ok_or_else(|| format!("invalid size {:?}", value))
+}
+
+impl Cli {
+  /// Applies the command line overrides to the global parameters read from the configuration file.
+  fn apply_overrides(&self, globals: &mut GlobalParams) {
+    if let Some(output_dir) = &self.output_dir {
+      globals.output_directory = output_dir.clone();
+    }
+    if let Some(sample_size) = self.sample_size {
+      globals.sample_size = sample_size;
+    }
+    if self.deep_trace {
+      globals.deep_trace = true;
+    }
+  }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -71,6 +337,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
   // You can see how many times a particular flag or argument occurred
   // Note, only flags can have multiple occurrences
   let log_level = match cli.verbose {
+    _ if cli.quiet => Level::ERROR,
     0 => Level::WARN,
     1 => Level::INFO,
     2 => Level::DEBUG,
@@ -84,14 +351,143 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     )
     .init();
 
+  // without a subcommand the campaign of --config is run; io-sweep runs a campaign on a generated
+  // configuration file, removed at the end, and the other subcommands return here
+  let mut sweep_config = None;
+  let (config_path, force) = match &cli.command {
+    Some(Commands::Plan {
+      config,
+      json,
+      shard,
+    }) => {
+      return print_plan(config, *json, *shard);
+    }
+    Some(Commands::Init { output }) => {
+      return init_config(output);
+    }
+    Some(Commands::Export {
+      results,
+      config,
+      output,
+      anonymize,
+    }) => {
+      return export_results(results, config.as_deref(), output.as_deref(), *anonymize);
+    }
+    Some(Commands::ManifestDiff {
+      old,
+      new,
+      default,
+      ignore_checksums,
+    }) => {
+      return print_manifest_diff(old, new.as_deref(), *default, *ignore_checksums);
+    }
+    Some(Commands::Import {
+      archives,
+      output,
+      aggregate,
+    }) => {
+      return import_results(archives, output, *aggregate);
+    }
+    Some(Commands::Track { results, db }) => {
+      let campaign = track(db, results)?;
+      if campaign.added {
+        println!(
+          "Tracked {} metrics of {:?} (host {}, config {})",
+          campaign.metrics,
+          results,
+          campaign.host,
+          &campaign.config_hash[..12]
+        );
+      } else {
+        println!("{:?} is already tracked in {:?}", results, db);
+      }
+      return Ok(());
+    }
+    Some(Commands::Trends {
+      db,
+      metric,
+      task,
+      host,
+      plot,
+    }) => {
+      return print_trend(
+        db,
+        metric,
+        task.as_deref(),
+        host.as_deref(),
+        plot.as_deref(),
+      );
+    }
+    Some(Commands::Timeline {
+      directories,
+      output,
+    }) => {
+      // extensions are appended, so that a dot in the name (e.g. run-1.5) is kept
+      let with_extension = |extension: &str| {
+        let mut path = output.clone().into_os_string();
+        path.push(extension);
+        PathBuf::from(path)
+      };
+      let (csv, html) = (with_extension(".csv"), with_extension(".html"));
+      let samples = timeline::load(directories)?;
+      timeline::write_csv(&samples, &csv)?;
+      timeline::write_html(&samples, &html)?;
+      println!(
+        "Timeline of {} samples written to {:?} and {:?}",
+        samples.len(),
+        csv,
+        html
+      );
+      return Ok(());
+    }
+    Some(Commands::IoWorker {
+      path,
+      block_size,
+      file_size,
+    }) => {
+      return Ok(run_worker(
+        path,
+        *block_size,
+        *file_size,
+        &mut io::stdout(),
+      )?);
+    }
+    Some(Commands::IoSweep {
+      output,
+      block_sizes,
+      file_sizes,
+      enclave_size,
+      sample_size,
+      force,
+    }) => {
+      let content = IoSweep {
+        block_sizes: block_sizes.clone(),
+        file_sizes: file_sizes.clone(),
+        enclave_size: enclave_size.clone(),
+        sample_size: *sample_size,
+        output_directory: output.clone(),
+      }
+      .config(&env::current_exe()?)?;
+      let mut file = tempfile::NamedTempFile::new()?;
+      file.write_all(content.as_bytes())?;
+      let path = file.path().to_path_buf();
+      sweep_config = Some(file);
+      (path, *force)
+    }
+    None => (
+      cli.config.clone().expect("--config is required by clap"),
+      cli.force,
+    ),
+  };
+
   if env::var_os("EB_SKIP_SGX").is_some_and(|v| v == "1") {
     warn!("EB_SKIP_SGX is set; skipping SGX execution");
   }
-  let config = fs::read_to_string(&cli.config)?;
-  let config = toml::from_str::<Config>(&config)?;
+  let mut config = Config::load(&config_path)?;
+  cli.apply_overrides(&mut config.globals);
   let output_directory = config.globals.output_directory.clone();
 
-  if cli.force {
+  if force {
     warn!("force specified; deleting previous results directory...");
     match remove_dir_all(&config.globals.output_directory) {
       Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
@@ -99,161 +495,474 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
   }
 
-  let collector = Arc::new(DefaultCollector::new(
-    config.globals.sample_size,
-    config.globals.deep_trace,
-    config.globals.energy_sample_interval,
-    config.globals.extra_perf_events,
-  ));
-
-  let profiler = Arc::new(Profiler::new(
-    config.globals.output_directory,
-    config.globals.debug,
-    collector.clone(),
-  )?);
-
-  let collector = collector.clone();
-  let stop = Arc::new(AtomicBool::new(false));
-  {
-    let stop = stop.clone();
-    let collector = collector.clone();
-    let profiler = profiler.clone();
-    ctrlc::set_handler(move || {
-      info!("Received stop signal. Closing existing threads... ");
-      profiler.stop();
-      collector.clone().stop();
-      stop.store(true, Ordering::Relaxed);
-    })
-    .expect("Cannot set SIGTERM handler");
-  }
+  let retention = std::mem::take(&mut config.globals.retention);
+  let failure_policy = config.globals.failure_policy;
+  let sample_size = config.globals.sample_size;
+  let deep_trace = config.globals.deep_trace;
 
-  for task in config.tasks {
-    if stop.clone().load(Ordering::Relaxed) {
-      break;
-    }
-    profiler.profile(task)?;
+  let mut campaign = Campaign::new(config)?;
+  // kept with the results, so that they can be reproduced and exported with the configuration
+  fs::write(
+    output_directory.join(CONFIG_FILE),
+    Config::expand(&config_path)?,
+  )?;
+  if let Some(shard) = cli.shard {
+    info!("running shard {}/{}", shard.index, shard.count);
+    campaign = campaign.with_shard(shard);
   }
+  if let Some(target) = &cli.progress_json {
+    campaign = campaign.with_progress(Box::new(progress_writer(target)?));
+  }
+  {
+    // each signal escalates: complete the running sample, kill the workload, exit. SIGTERM and
+    // SIGHUP (systemd stopping the unit, tmux closing the session) start from killing the
+    // workload, since the sender does not wait for a whole sample before killing the benchmark
+    let stop = campaign.stopper();
+    let interrupt = campaign.interrupter();
+    let abort = campaign.aborter();
+    let received = AtomicUsize::new(0);
+    let escalate = Arc::new(move |minimum: usize| {
+      let level = received
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |level| {
+          Some(level.max(minimum) + 1)
+        })
+        .unwrap_or_default()
+        .max(minimum);
+      match level {
+        0 => {
+          warn!(
+            "Received stop signal. Completing the running sample (press Ctrl-C again to kill it)"
+          );
+          stop();
+        }
+        1 => {
+          warn!("Killing the running workload and saving partial results (press Ctrl-C again to exit immediately)");
+          interrupt();
+        }
+        _ => {
+          warn!("Exiting immediately");
+          abort();
+          std::process::exit(130);
+        }
+      }
+    });
+    let on_interrupt = escalate.clone();
+    ctrlc::set_handler(move || on_interrupt(0)).expect("Cannot set SIGINT handler");
+    on_termination(move || escalate(1))?;
+  }
+  campaign.run()?;
 
   if cli.aggregate {
     Python::with_gil(|py| -> PyResult<()> {
-      let aggregate_script = c_str!(include_str!(concat!(
-        env!("CARGO_MANIFEST_DIR"),
-        "/dev/aggregate.py"
-      )));
+      // the script reads the configuration file, so command line overrides are passed along
+      let globals = PyDict::new(py);
+      globals.set_item("output_directory", &output_directory)?;
+      globals.set_item("sample_size", sample_size)?;
+      globals.set_item("deep_trace", deep_trace)?;
+      if cli.quiet {
+        // the script reports its progress on stdout
+        let devnull = py.import("io")?.getattr("StringIO")?.call0()?;
+        py.import("sys")?.setattr("stdout", devnull)?;
+      }
 
       let output_directory = output_directory.join("aggregated");
       info!(
         "aggregating results in {:?}. This may take some time...",
         output_directory
       );
-      let aggregate_fn: Py<PyAny> =
-        PyModule::from_code(py, aggregate_script, c_str!(""), c_str!(""))?
-          .getattr("aggregate")?
-          .into();
+      let aggregate_fn: Py<PyAny> = aggregate_script(py)?.getattr("aggregate")?.into();
 
-      aggregate_fn.call1(py, (cli.config, output_directory))?;
+      aggregate_fn.call1(py, (&config_path, output_directory, globals))?;
 
       Ok(())
     })
     .unwrap();
   }
 
+  match summarize(&output_directory) {
+    Ok(rows) if sweep_config.is_some() => {
+      let report = sweep::report(&rows);
+      fs::write(
+        output_directory.join(IO_SWEEP_FILE),
+        format_report(&report, true),
+      )?;
+      print!("{}", format_report(&report, false));
+    }
+    Ok(rows) if !rows.is_empty() => print!("{}", format_table(&rows)),
+    Ok(_) => {}
+    Err(e) => warn!("cannot summarize the results: {}", e),
+  }
+
+  // aggregated results are copied first so that raw data can be safely removed
+  retention.apply(&output_directory)?;
+
+  if let Some(code) = failure_policy.check(&campaign.health()) {
+    std::process::exit(code);
+  }
+
   Ok(())
 }
 
-#[cfg(test)]
-mod test {
-  use std::fs;
+/// Opens the target of `--progress-json`: a file descriptor inherited from the caller (e.g.
+/// `fd:3` with `3>progress.ndjson`, or a pipe to a scheduler) or a file, truncated.
+fn progress_writer(target: &ProgressTarget) -> io::Result<fs::File> {
+  match *target {
+    ProgressTarget::Fd(fd) => {
+      if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          format!("--progress-json: {} is not an open file descriptor", fd),
+        ));
+      }
+      Ok(unsafe { fs::File::from_raw_fd(fd) })
+    }
+    ProgressTarget::File(ref path) => fs::File::create(path),
+  }
+}
 
-  use crate::{common::StorageType, Config};
+/// Loads `dev/aggregate.py`, embedded in the binary.
+fn aggregate_script(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+  let script = c_str!(include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/dev/aggregate.py"
+  )));
+  PyModule::from_code(py, script, c_str!(""), c_str!(""))
+}
 
-  #[test]
-  fn example_configs() {
-    let examples = [
-      "examples/full.toml",
-      "examples/simple.toml",
-      "examples/iobound.toml",
-      "examples/minimal.toml",
-      "examples/demo.toml",
-    ];
-    for file in examples {
-      let content = fs::read_to_string(file).unwrap();
-      toml::from_str::<Config>(&content).unwrap();
+/// Runs the configuration wizard on the terminal and writes its result to `output`.
+fn init_config(output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+  if output.exists() {
+    return Err(format!("{:?} already exists", output).into());
+  }
+  let content = wizard::run(
+    &mut io::stdin().lock(),
+    &mut io::stdout(),
+    &MachineInfo::probe(),
+  )?;
+  fs::write(output, content)?;
+  println!(
+    "Configuration written to {:?}. Run it with: enclave-benchmark -c {:?}",
+    output, output
+  );
+  Ok(())
+}
+
+/// Exports `results` to the archive `output`, next to the results unless given.
+fn export_results(
+  results: &Path,
+  config: Option<&Path>,
+  output: Option<&Path>,
+  anonymize: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let output = match output {
+    Some(output) => output.to_path_buf(),
+    None => {
+      let results = results.canonicalize()?;
+      let mut name = results
+        .file_name()
+        .ok_or_else(|| format!("cannot export {:?}", results))?
+        .to_owned();
+      name.push(".tar.zst");
+      results.with_file_name(name)
     }
+  };
+  let mut anonymizer = anonymize
+    .then(|| Anonymizer::for_host(results))
+    .transpose()?;
+  export(results, config, &output, anonymizer.as_mut())?;
+  println!("Results exported to {:?}", output);
+  Ok(())
+}
+
+/// Imports `archives` into `output`, aggregating all the runs of the tree if requested.
+fn import_results(
+  archives: &[PathBuf],
+  output: &Path,
+  aggregate: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+  for source in import(archives, output)? {
+    println!("Imported {:?} as {}", source.archive, source.tag);
+  }
+  if aggregate {
+    let aggregated = output.join("aggregated");
+    info!(
+      "aggregating results in {:?}. This may take some time...",
+      aggregated
+    );
+    Python::with_gil(|py| -> PyResult<()> {
+      aggregate_script(py)?
+        .getattr("aggregate_imports")?
+        .call1((output, aggregated))?;
+      Ok(())
+    })?;
+  }
+  Ok(())
+}
+
+/// Prints the values of `metric` in the campaigns of `db`, plotting them if requested.
+fn print_trend(
+  db: &Path,
+  metric: &str,
+  task: Option<&str>,
+  host: Option<&str>,
+  plot: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let points = trend(db, metric, task, host)?;
+  if points.is_empty() {
+    return Err(format!("no values of {:?} in {:?}", metric, db).into());
+  }
+  print!("{}", format_trend(&points));
+  if let Some(plot) = plot {
+    let points = serde_json::to_string(&points)?;
+    Python::with_gil(|py| -> PyResult<()> {
+      aggregate_script(py)?
+        .getattr("plot_trend")?
+        .call1((points, metric, plot))?;
+      Ok(())
+    })?;
+    println!("Trend plotted to {:?}", plot);
   }
+  Ok(())
+}
 
-  #[test]
-  fn parse_config() {
-    let config = toml::from_str::<Config>(
-      r#"
-            [globals]
-            sample_size = 3
-            output_directory = "/test"
-            debug = true
-            deep_trace = true
-            [[tasks]]
-            executable = "/bin/ls"
-            enclave_size = ["64M", "128M"]
-            num_threads = [1]
-            [[tasks]]
-            executable = "/bin/ls"
-            args = ["-l", "-a"]
-            storage_type = ["encrypted"] 
-            enclave_size = ["64M", "128M"]
-            num_threads = [1]
-            "#,
-    )
-    .unwrap();
-    assert!(config.globals.debug);
-    assert_eq!(2, config.tasks.len());
-    assert_eq!(3, config.globals.sample_size);
-    let args = config.tasks[1].clone().args;
-    assert_eq!(2, args.len());
+/// Prints the differences from the manifest `old` to `new`, or from the default template rendered
+/// for the experiment `old` to its manifest.
+fn print_manifest_diff(
+  old: &Path,
+  new: Option<&Path>,
+  default: bool,
+  ignore_checksums: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let (old_manifest, new_manifest) = match new {
+    Some(new) => (load_manifest(old, false)?, load_manifest(new, false)?),
+    None => (load_manifest(old, true)?, load_manifest(old, false)?),
+  };
+  // the default template is rendered without expanding the trusted files
+  let changes = diff_manifests(&old_manifest, &new_manifest, ignore_checksums || default);
+  if changes.is_empty() {
+    println!("No differences");
+  }
+  for change in changes {
+    println!("{}", change);
+  }
+  Ok(())
+}
+
+/// Prints the experiments of a configuration file, one id per line or as a JSON array.
+fn print_plan(
+  config: &Path,
+  json: bool,
+  shard: Option<Shard>,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let plan: Vec<PlanEntry> = Config::load(config)?
+    .plan()
+    .into_iter()
+    .enumerate()
+    .filter(|(position, _)| shard.is_none_or(|shard| shard.contains(*position)))
+    .map(|(_, entry)| entry)
+    .collect();
+  if json {
+    println!("{}", serde_json::to_string_pretty(&plan)?);
+  } else {
+    for entry in plan {
+      println!("{}", entry.experiment.directory);
+    }
+  }
+  Ok(())
+}
+
+/// Write end of the pipe notifying SIGTERM and SIGHUP to the thread started by `on_termination`.
+static TERMINATION_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn notify_termination(_: libc::c_int) {
+  // only async-signal-safe calls are allowed here; the write end does not block, and a full pipe
+  // (EAGAIN) means a notification is already pending
+  unsafe {
+    libc::write(
+      TERMINATION_PIPE.load(Ordering::Relaxed),
+      [0u8].as_ptr().cast(),
+      1,
+    );
+  }
+}
+
+/// Creates a pipe for `notify_termination` and a thread calling `handler` on every notification.
+/// Returns the write end, which does not block.
+fn termination_pipe(handler: impl Fn() + Send + 'static) -> io::Result<RawFd> {
+  let mut fds = [0; 2];
+  if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+    return Err(io::Error::last_os_error());
+  }
+  let mut reader = unsafe { fs::File::from_raw_fd(fds[0]) };
+  // a signal arriving while the pipe is full must not block the thread it interrupts
+  if unsafe { libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK) } != 0 {
+    return Err(io::Error::last_os_error());
   }
 
+  thread::spawn(move || {
+    let mut buf = [0u8; 1];
+    while reader.read(&mut buf).is_ok_and(|n| n > 0) {
+      handler();
+    }
+  });
+  Ok(fds[1])
+}
+
+/// Calls `handler` from a dedicated thread on every SIGTERM and SIGHUP.
+fn on_termination(handler: impl Fn() + Send + 'static) -> io::Result<()> {
+  TERMINATION_PIPE.store(termination_pipe(handler)?, Ordering::Relaxed);
+
+  for signal in [libc::SIGTERM, libc::SIGHUP] {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = notify_termination as extern "C" fn(libc::c_int) as usize;
+    action.sa_flags = libc::SA_RESTART;
+    if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+      return Err(io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    path::PathBuf,
+    sync::{atomic::Ordering, mpsc},
+    time::Duration,
+  };
+
+  use clap::Parser;
+  use enclave_benchmark::{Config, Shard};
+
+  use crate::{
+    notify_termination, termination_pipe, Cli, Commands, ProgressTarget, TERMINATION_PIPE,
+  };
+
   #[test]
-  fn default_storage_type() {
-    let config = toml::from_str::<Config>(
+  fn cli_overrides() {
+    let mut config = toml::from_str::<Config>(
       r#"
             [globals]
             sample_size = 3
             output_directory = "/test"
             [[tasks]]
             executable = "/bin/ls"
-            storage_type = []
-            enclave_size = ["64M", "128M"]
-            [[tasks]]
-            executable = "/bin/ls"
-            args = ["-l", "-a"]
-            storage_type = ["encrypted"] 
-            enclave_size = ["64M", "128M"]
+            enclave_size = ["64M"]
             "#,
     )
     .unwrap();
 
-    assert_eq!(config.tasks.len(), 2);
-    assert_eq!(config.tasks[0].storage_type.len(), 1);
-    assert_eq!(config.tasks[0].storage_type[0], StorageType::Untrusted);
+    Cli::parse_from(["enclave-benchmark", "-c", "config.toml"])
+      .apply_overrides(&mut config.globals);
+    assert_eq!(config.globals.output_directory, PathBuf::from("/test"));
+    assert_eq!(config.globals.sample_size, 3);
+    assert!(!config.globals.deep_trace);
+
+    Cli::parse_from([
+      "enclave-benchmark",
+      "-c",
+      "config.toml",
+      "--output-dir",
+      "/other",
+      "--sample-size",
+      "1",
+      "--deep-trace",
+    ])
+    .apply_overrides(&mut config.globals);
+    assert_eq!(config.globals.output_directory, PathBuf::from("/other"));
+    assert_eq!(config.globals.sample_size, 1);
+    assert!(config.globals.deep_trace);
+
+    let cli = Cli::parse_from(["enclave-benchmark", "-c", "config.toml", "--shard", "2/4"]);
+    assert_eq!(cli.shard, Some(Shard { index: 2, count: 4 }));
+
+    let progress = |target: &str| {
+      Cli::try_parse_from([
+        "enclave-benchmark",
+        "-c",
+        "c.toml",
+        "--progress-json",
+        target,
+      ])
+      .map(|cli| cli.progress_json.unwrap())
+    };
+    assert_eq!(progress("fd:3").unwrap(), ProgressTarget::Fd(3));
+    assert_eq!(
+      progress("3").unwrap(),
+      ProgressTarget::File(PathBuf::from("3"))
+    );
+    for target in ["fd:0", "fd:1", "fd:2", "fd:-1", "fd:x"] {
+      assert!(progress(target).is_err(), "{}", target);
+    }
+
+    let cli = Cli::parse_from(["enclave-benchmark", "plan", "-c", "config.toml", "--json"]);
+    assert!(matches!(
+      cli.command,
+      Some(Commands::Plan { json: true, .. })
+    ));
+    let cli = Cli::parse_from([
+      "enclave-benchmark",
+      "import",
+      "a.tar.zst",
+      "b.tar.zst",
+      "-o",
+      "combined",
+    ]);
+    assert!(matches!(
+      cli.command,
+      Some(Commands::Import { ref archives, aggregate: false, .. }) if archives.len() == 2
+    ));
+    assert!(Cli::try_parse_from(["enclave-benchmark", "import", "-o", "combined"]).is_err());
+    let cli = Cli::parse_from(["enclave-benchmark", "manifest-diff", "a", "--default"]);
+    assert!(matches!(
+      cli.command,
+      Some(Commands::ManifestDiff {
+        new: None,
+        default: true,
+        ..
+      })
+    ));
+    assert!(Cli::try_parse_from(["enclave-benchmark", "manifest-diff", "a"]).is_err());
+    assert!(
+      Cli::try_parse_from(["enclave-benchmark", "manifest-diff", "a", "b", "--default"]).is_err()
+    );
+    let cli = Cli::parse_from(["enclave-benchmark", "export", "results", "--anonymize"]);
+    assert!(matches!(
+      cli.command,
+      Some(Commands::Export {
+        output: None,
+        anonymize: true,
+        ..
+      })
+    ));
+    let cli = Cli::parse_from(["enclave-benchmark", "io-sweep", "--block-sizes", "4K,1M"]);
+    assert!(matches!(
+      cli.command,
+      Some(Commands::IoSweep { ref block_sizes, ref file_sizes, .. })
+        if *block_sizes == [4 << 10, 1 << 20] && *file_sizes == [16 << 20, 256 << 20]
+    ));
+    assert!(Cli::try_parse_from(["enclave-benchmark", "io-sweep", "--file-sizes", "0"]).is_err());
+    assert!(Cli::try_parse_from(["enclave-benchmark"]).is_err());
   }
 
   #[test]
-  #[should_panic]
-  fn invalid_storage_type() {
-    toml::from_str::<Config>(
-      r#"
-            [globals]
-            sample_size = 3
-            output_directory = "/test"
-            [[tasks]]
-            executable = "/bin/ls"
-            enclave_size = ["64M", "128M"]
-            [[tasks]]
-            executable = "/bin/ls"
-            args = ["-l", "-a"]
-            storage_type = ["invalid_storage_type", "encrypted"]
-            enclave_size = ["64M", "128M"]
-            "#,
-    )
+  fn termination_notifications() {
+    let (sender, receiver) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    let pipe = termination_pipe(move || {
+      let _ = sender.send(());
+      let _ = released.recv();
+    })
     .unwrap();
+    TERMINATION_PIPE.store(pipe, Ordering::Relaxed);
+
+    notify_termination(libc::SIGTERM);
+    receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    // while the handler runs, notifications fill the pipe without blocking the signal handler
+    for _ in 0..100_000 {
+      notify_termination(libc::SIGHUP);
+    }
+    release.send(()).unwrap();
+    receiver.recv_timeout(Duration::from_secs(5)).unwrap();
   }
 }
@@ -0,0 +1,429 @@
+//! Structured comparison of rendered Gramine manifests (see `diff_manifests`), to check which
+//! settings differ between experiments or between a custom template and the default one, and
+//! adaptation of rendered manifests to the installed Gramine release (see `adapt_manifest`).
+use std::{
+  collections::BTreeMap,
+  fmt::{self, Display},
+  fs,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use toml::{Table, Value};
+
+/// Suffix of the manifest of an SGX experiment, as signed.
+pub const MANIFEST_SUFFIX: &str = ".manifest.sgx";
+
+/// Suffix of the default template rendered for an experiment with a custom manifest.
+pub const DEFAULT_MANIFEST_SUFFIX: &str = ".default.manifest";
+
+/// Oldest and newest Gramine releases the manifest adaptations are known for.
+pub const SUPPORTED_GRAMINE_VERSIONS: (GramineVersion, GramineVersion) =
+  (GramineVersion::new(1, 4), GramineVersion::new(1, 7));
+
+/// Manifest settings renamed by Gramine: the release of the rename, the old key and the new key.
+/// The default template uses the new keys.
+const RENAMED_SETTINGS: [(GramineVersion, &str, &str); 3] = [
+  (
+    GramineVersion::new(1, 4),
+    "sgx.edmm_enable_heap",
+    "sgx.edmm_enable",
+  ),
+  (
+    GramineVersion::new(1, 5),
+    "sgx.thread_num",
+    "sgx.max_threads",
+  ),
+  (
+    GramineVersion::new(1, 5),
+    "sgx.rpc_thread_num",
+    "sgx.insecure__rpc_thread_num",
+  ),
+];
+
+/// A Gramine release, as reported by `graminelibos.__version__` (e.g. `1.6.1` or
+/// `1.7post~UNRELEASED`); only the major and minor versions change the manifest syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GramineVersion {
+  pub major: u32,
+  pub minor: u32,
+}
+
+impl GramineVersion {
+  pub const fn new(major: u32, minor: u32) -> Self {
+    Self { major, minor }
+  }
+}
+
+impl FromStr for GramineVersion {
+  type Err = String;
+
+  fn from_str(version: &str) -> Result<Self, Self::Err> {
+    let mut parts = version.trim().trim_start_matches('v').split('.');
+    let mut number = || -> Option<u32> {
+      let digits: String = parts
+        .next()?
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+      digits.parse().ok()
+    };
+    match (number(), number()) {
+      (Some(major), Some(minor)) => Ok(Self::new(major, minor)),
+      _ => Err(format!("invalid Gramine version {:?}", version)),
+    }
+  }
+}
+
+impl Display for GramineVersion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}", self.major, self.minor)
+  }
+}
+
+/// A setting which differs between two manifests, by dotted key. Entries of arrays of files and
+/// mounts are compared by their `uri` or `path` (e.g. `sgx.trusted_files[file:/usr/lib/]`), with
+/// the rest of their fields as value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManifestChange {
+  Added { key: String, value: Value },
+  Removed { key: String, value: Value },
+  Changed { key: String, old: Value, new: Value },
+}
+
+impl ManifestChange {
+  pub fn key(&self) -> &str {
+    match self {
+      ManifestChange::Added { key, .. }
+      | ManifestChange::Removed { key, .. }
+      | ManifestChange::Changed { key, .. } => key,
+    }
+  }
+}
+
+impl Display for ManifestChange {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ManifestChange::Added { key, value } => write!(f, "+ {} = {}", key, value),
+      ManifestChange::Removed { key, value } => write!(f, "- {} = {}", key, value),
+      ManifestChange::Changed { key, old, new } => write!(f, "~ {} = {} -> {}", key, old, new),
+    }
+  }
+}
+
+/// Reads a manifest from a file or from the experiment directory holding it. With `default`, the
+/// default template rendered for the experiment is read instead.
+pub fn load_manifest(path: &Path, default: bool) -> Result<Table, Box<dyn std::error::Error>> {
+  let suffix = if default {
+    DEFAULT_MANIFEST_SUFFIX
+  } else {
+    MANIFEST_SUFFIX
+  };
+  let file = if path.is_dir() {
+    let manifests: Vec<PathBuf> = fs::read_dir(path)?
+      .flatten()
+      .map(|entry| entry.path())
+      .filter(|file| file.to_string_lossy().ends_with(suffix))
+      .collect();
+    match manifests.as_slice() {
+      [manifest] => manifest.clone(),
+      [] => return Err(format!("no *{} file in {:?}", suffix, path).into()),
+      _ => return Err(format!("more than one *{} file in {:?}", suffix, path).into()),
+    }
+  } else {
+    path.to_path_buf()
+  };
+  Ok(fs::read_to_string(&file)?.parse::<Table>()?)
+}
+
+/// Returns the settings which differ from `old` to `new`, sorted by key. With `ignore_checksums`,
+/// the `sha256` of trusted files is left out, e.g. to compare with a manifest whose trusted files
+/// are not expanded.
+pub fn diff_manifests(old: &Table, new: &Table, ignore_checksums: bool) -> Vec<ManifestChange> {
+  let mut old_settings = BTreeMap::new();
+  flatten(
+    "",
+    &Value::Table(old.clone()),
+    ignore_checksums,
+    &mut old_settings,
+  );
+  let mut new_settings = BTreeMap::new();
+  flatten(
+    "",
+    &Value::Table(new.clone()),
+    ignore_checksums,
+    &mut new_settings,
+  );
+
+  let mut changes = Vec::new();
+  for (key, old) in &old_settings {
+    match new_settings.get(key) {
+      None => changes.push(ManifestChange::Removed {
+        key: key.clone(),
+        value: old.clone(),
+      }),
+      Some(new) if new != old => changes.push(ManifestChange::Changed {
+        key: key.clone(),
+        old: old.clone(),
+        new: new.clone(),
+      }),
+      Some(_) => {}
+    }
+  }
+  for (key, new) in new_settings {
+    if !old_settings.contains_key(&key) {
+      changes.push(ManifestChange::Added { key, value: new });
+    }
+  }
+  changes.sort_by(|a, b| a.key().cmp(b.key()));
+  changes
+}
+
+/// Adapts a rendered manifest to the settings of Gramine `version`: settings renamed after
+/// `version` get their old key, and settings renamed up to `version` their new key, so that the
+/// default template and custom templates written for other releases keep working. A setting
+/// already present under the target key is kept. Returns the renames, as `old -> new`.
+pub fn adapt_manifest(manifest: &mut Table, version: GramineVersion) -> Vec<String> {
+  let mut renames = Vec::new();
+  for (since, old, new) in RENAMED_SETTINGS {
+    let (from, to) = if version >= since {
+      (old, new)
+    } else {
+      (new, old)
+    };
+    let Some(value) = remove_setting(manifest, from) else {
+      continue;
+    };
+    if !has_setting(manifest, to) {
+      insert_setting(manifest, to, value);
+    }
+    renames.push(format!("{} -> {}", from, to));
+  }
+  renames
+}
+
+fn has_setting(manifest: &Table, key: &str) -> bool {
+  let mut value = None;
+  let mut table = Some(manifest);
+  for part in key.split('.') {
+    value = table.and_then(|table| table.get(part));
+    table = value.and_then(Value::as_table);
+  }
+  value.is_some()
+}
+
+fn remove_setting(manifest: &mut Table, key: &str) -> Option<Value> {
+  let (parent, name) = key.rsplit_once('.').unwrap_or(("", key));
+  let mut table = manifest;
+  for part in parent.split('.').filter(|part| !part.is_empty()) {
+    table = table.get_mut(part)?.as_table_mut()?;
+  }
+  table.remove(name)
+}
+
+// tables missing on the way are created; a setting in the way is left untouched
+fn insert_setting(manifest: &mut Table, key: &str, value: Value) {
+  let (parent, name) = key.rsplit_once('.').unwrap_or(("", key));
+  let mut table = manifest;
+  for part in parent.split('.').filter(|part| !part.is_empty()) {
+    table = match table
+      .entry(part)
+      .or_insert_with(|| Value::Table(Table::new()))
+    {
+      Value::Table(table) => table,
+      _ => return,
+    };
+  }
+  table.insert(name.to_string(), value);
+}
+
+// maps every leaf setting of `value` to its dotted key
+fn flatten(
+  key: &str,
+  value: &Value,
+  ignore_checksums: bool,
+  settings: &mut BTreeMap<String, Value>,
+) {
+  let child = |name: &str| {
+    if key.is_empty() {
+      name.to_string()
+    } else {
+      format!("{}.{}", key, name)
+    }
+  };
+  match value {
+    Value::Table(table) => {
+      for (name, value) in table {
+        flatten(&child(name), value, ignore_checksums, settings);
+      }
+    }
+    Value::Array(array) => match keyed_entries(array) {
+      Some(entries) => {
+        for (id, mut entry) in entries {
+          if ignore_checksums {
+            entry.remove("sha256");
+          }
+          settings.insert(format!("{}[{}]", key, id), Value::Table(entry));
+        }
+      }
+      None => {
+        settings.insert(key.to_string(), value.clone());
+      }
+    },
+    _ => {
+      settings.insert(key.to_string(), value.clone());
+    }
+  }
+}
+
+// Splits the entries of an array of files or mounts into their `uri` or `path` and their other
+// fields. Trusted files are plain `file:` URIs before Gramine expands them into tables with a
+// checksum, so both forms are accepted.
+fn keyed_entries(array: &[Value]) -> Option<Vec<(String, Table)>> {
+  if array.is_empty() {
+    return None;
+  }
+  array
+    .iter()
+    .map(|entry| match entry {
+      Value::String(uri) if uri.starts_with("file:") => Some((uri.clone(), Table::new())),
+      Value::Table(table) => ["uri", "path"].iter().find_map(|id| {
+        let value = table.get(*id)?.as_str()?.to_string();
+        let mut fields = table.clone();
+        fields.remove(*id);
+        Some((value, fields))
+      }),
+      _ => None,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs;
+
+  use tempfile::TempDir;
+  use toml::{Table, Value};
+
+  use super::{adapt_manifest, diff_manifests, load_manifest, GramineVersion, ManifestChange};
+
+  #[test]
+  fn manifest_changes() {
+    let old: Table = r#"
+      loader.log_level = "none"
+      sgx.enclave_size = "64M"
+      sgx.trusted_files = ["file:/usr/lib/", "file:/bin/ls"]
+      fs.mounts = [{ path = "/lib", uri = "file:/usr/lib" }]
+      "#
+    .parse()
+    .unwrap();
+    let new: Table = r#"
+      sgx.enclave_size = "128M"
+      sgx.edmm_enable = true
+      sgx.trusted_files = [
+        { uri = "file:/usr/lib/", sha256 = "00" },
+        { uri = "file:/bin/cat", sha256 = "11" },
+      ]
+      fs.mounts = [
+        { path = "/lib", uri = "file:/usr/lib" },
+        { path = "/tmp", type = "tmpfs" },
+      ]
+      "#
+    .parse()
+    .unwrap();
+
+    let changes: Vec<String> = diff_manifests(&old, &new, false)
+      .iter()
+      .map(ToString::to_string)
+      .collect();
+    assert_eq!(
+      changes,
+      vec![
+        "+ fs.mounts[/tmp] = { type = \"tmpfs\" }",
+        "- loader.log_level = \"none\"",
+        "+ sgx.edmm_enable = true",
+        "~ sgx.enclave_size = \"64M\" -> \"128M\"",
+        "+ sgx.trusted_files[file:/bin/cat] = { sha256 = \"11\" }",
+        "- sgx.trusted_files[file:/bin/ls] = {}",
+        "~ sgx.trusted_files[file:/usr/lib/] = {} -> { sha256 = \"00\" }",
+      ]
+    );
+    assert!(diff_manifests(&new, &new, false).is_empty());
+    assert_eq!(
+      diff_manifests(&old, &new, true)[3],
+      ManifestChange::Changed {
+        key: String::from("sgx.enclave_size"),
+        old: Value::from("64M"),
+        new: Value::from("128M"),
+      }
+    );
+    // without checksums only the list of trusted files is compared
+    assert_eq!(diff_manifests(&old, &new, true).len(), 6);
+  }
+
+  #[test]
+  fn manifest_for_gramine_version() {
+    assert_eq!("1.6.1".parse(), Ok(GramineVersion::new(1, 6)));
+    assert_eq!("1.7post~UNRELEASED".parse(), Ok(GramineVersion::new(1, 7)));
+    assert_eq!("v1.4".parse(), Ok(GramineVersion::new(1, 4)));
+    assert!("unknown".parse::<GramineVersion>().is_err());
+    assert!(GramineVersion::new(1, 10) > GramineVersion::new(1, 7));
+
+    let rendered: Table = r#"
+      sgx.enclave_size = "64M"
+      sgx.max_threads = 8
+      sgx.insecure__rpc_thread_num = 0
+      sgx.edmm_enable = false
+      "#
+    .parse()
+    .unwrap();
+    let mut manifest = rendered.clone();
+    assert!(adapt_manifest(&mut manifest, GramineVersion::new(1, 7)).is_empty());
+    assert_eq!(manifest, rendered);
+
+    let renames = adapt_manifest(&mut manifest, GramineVersion::new(1, 4));
+    assert_eq!(
+      renames,
+      vec![
+        "sgx.max_threads -> sgx.thread_num",
+        "sgx.insecure__rpc_thread_num -> sgx.rpc_thread_num",
+      ]
+    );
+    assert_eq!(manifest["sgx"]["thread_num"].as_integer(), Some(8));
+    assert!(manifest["sgx"].get("max_threads").is_none());
+    assert_eq!(manifest["sgx"]["edmm_enable"].as_bool(), Some(false));
+
+    // a template written for an older release; the explicit new key wins
+    let mut manifest: Table = r#"
+      sgx.edmm_enable_heap = true
+      sgx.thread_num = 4
+      sgx.max_threads = 16
+      "#
+    .parse()
+    .unwrap();
+    adapt_manifest(&mut manifest, GramineVersion::new(1, 6));
+    assert_eq!(manifest["sgx"]["edmm_enable"].as_bool(), Some(true));
+    assert_eq!(manifest["sgx"]["max_threads"].as_integer(), Some(16));
+    assert!(manifest["sgx"].get("thread_num").is_none());
+  }
+
+  #[test]
+  fn manifest_from_experiment_directory() {
+    let directory = TempDir::new().unwrap();
+    assert!(load_manifest(directory.path(), false).is_err());
+    fs::write(
+      directory.path().join("ls.manifest.sgx"),
+      "sgx.enclave_size = \"64M\"",
+    )
+    .unwrap();
+    fs::write(
+      directory.path().join("ls.default.manifest"),
+      "sgx.enclave_size = \"128M\"",
+    )
+    .unwrap();
+    let manifest = load_manifest(directory.path(), false).unwrap();
+    assert_eq!(manifest["sgx"]["enclave_size"].as_str(), Some("64M"));
+    let default = load_manifest(directory.path(), true).unwrap();
+    assert_eq!(default["sgx"]["enclave_size"].as_str(), Some("128M"));
+  }
+}
@@ -0,0 +1,484 @@
+//! Network namespaces isolating workloads from the network of the host (see `Network`) and
+//! traffic shaping of their interfaces (see `Netem`).
+use std::{
+  fs::{self, File},
+  io,
+  net::Ipv4Addr,
+  os::{fd::AsRawFd, unix::process::CommandExt},
+  path::Path,
+  process::{self, Command},
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Once,
+  },
+};
+
+use tracing::{trace, warn};
+
+use crate::{
+  common::{Netem, Network},
+  constants::{HOST_ADDRESS_ENV, WORKLOAD_ADDRESS_ENV},
+};
+
+/// The veth pairs of `Network::Veth` get a /30 subnet each in `10.200.0.0/16`.
+const VETH_SUBNETS: Ipv4Addr = Ipv4Addr::new(10, 200, 0, 0);
+const VETH_SUBNET_COUNT: u32 = 1 << 14;
+
+static NAMESPACES: AtomicU32 = AtomicU32::new(0);
+static STALE_REMOVED: Once = Once::new();
+
+/// A named network namespace created with `ip netns`. Dropping it deletes the namespace together
+/// with its interfaces.
+#[derive(Debug)]
+pub struct NetworkNamespace {
+  name: String,
+  file: File,
+  /// Interfaces created for the workload, shaped by `Netem` unless it names a device.
+  interfaces: Vec<Interface>,
+  veth: Option<Veth>,
+}
+
+/// The veth pair of a namespace of `Network::Veth`. Its interfaces are named after the pid of the
+/// benchmark and the index of the namespace, so that concurrent benchmarks do not clash and the
+/// pairs left behind by a killed one can be told apart.
+#[derive(Debug, Clone, PartialEq)]
+struct Veth {
+  host: String,
+  peer: String,
+  subnet: Ipv4Addr,
+}
+
+impl Veth {
+  // interface names are limited to 15 characters: `eb` and the pid and index in hex fit
+  fn new(pid: u32, index: u32, subnet: Ipv4Addr) -> Self {
+    Self {
+      host: format!("eb{:x}-{:x}h", pid, index),
+      peer: format!("eb{:x}-{:x}n", pid, index),
+      subnet,
+    }
+  }
+
+  fn host_address(&self) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(self.subnet) + 1)
+  }
+
+  fn namespace_address(&self) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(self.subnet) + 2)
+  }
+}
+
+/// A network interface; `namespace` is `None` for the namespace of the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+  namespace: Option<String>,
+  name: String,
+}
+
+impl NetworkNamespace {
+  /// Creates a fresh namespace set up for `network`. Returns `None` for `Network::Host`.
+  pub fn create(network: &Network) -> Result<Option<Self>, io::Error> {
+    if *network == Network::Host {
+      return Ok(None);
+    }
+    STALE_REMOVED.call_once(remove_stale);
+    let pid = process::id();
+    let index = NAMESPACES.fetch_add(1, Ordering::Relaxed);
+    let name = format!("eb-{}-{}", pid, index);
+    let veth = match network {
+      Network::Veth => {
+        let used = host_addresses(&run("ip", &["-o", "-4", "addr", "show"].map(String::from))?);
+        let subnet = free_subnet(pid.wrapping_mul(16).wrapping_add(index), &used)
+          .ok_or_else(|| io::Error::other("no free subnet for the veth pair in 10.200.0.0/16"))?;
+        Some(Veth::new(pid, index, subnet))
+      }
+      _ => None,
+    };
+    let mut commands = setup_commands(&name, veth.as_ref()).into_iter();
+
+    ip(&commands.next().unwrap())?;
+    let inside = |interface: String| Interface {
+      namespace: Some(name.clone()),
+      name: interface,
+    };
+    let interfaces = match &veth {
+      Some(veth) => vec![
+        Interface {
+          namespace: None,
+          name: veth.host.clone(),
+        },
+        inside(veth.peer.clone()),
+      ],
+      None => vec![inside(String::from("lo"))],
+    };
+    let namespace = Self {
+      file: File::open(format!("/run/netns/{}", name))?,
+      name,
+      interfaces,
+      veth,
+    };
+    // on failure the namespace is deleted when dropped
+    for args in commands {
+      ip(&args)?;
+    }
+    trace!("created network namespace {}", namespace.name);
+    Ok(Some(namespace))
+  }
+
+  /// Returns the environment telling the hooks the addresses of the veth pair (empty without one).
+  pub fn hook_env(&self) -> Vec<(&'static str, String)> {
+    self
+      .veth
+      .iter()
+      .flat_map(|veth| {
+        [
+          (HOST_ADDRESS_ENV, veth.host_address().to_string()),
+          (WORKLOAD_ADDRESS_ENV, veth.namespace_address().to_string()),
+        ]
+      })
+      .collect()
+  }
+
+  /// Makes `cmd` run in the namespace.
+  pub fn enter_on_spawn(&self, cmd: &mut Command) {
+    let fd = self.file.as_raw_fd();
+    // Safety: setns is async-signal-safe and the file outlives the spawn of `cmd`.
+    unsafe {
+      cmd.pre_exec(move || {
+        if libc::setns(fd, libc::CLONE_NEWNET) != 0 {
+          return Err(io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+  }
+
+  /// Moves the calling thread into the namespace, e.g. to probe the workload from the inside. The
+  /// thread stays there, so it must be dedicated to it.
+  pub fn enter(&self) -> Result<(), io::Error> {
+    if unsafe { libc::setns(self.file.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(())
+  }
+}
+
+impl Drop for NetworkNamespace {
+  fn drop(&mut self) {
+    if let Err(e) = ip(&["netns", "delete", &self.name].map(String::from)) {
+      warn!("cannot delete network namespace {}: {}", self.name, e);
+    }
+  }
+}
+
+/// Traffic shaping applied with `tc netem` to the interfaces of a workload. Dropping it removes
+/// the shaping.
+#[derive(Debug)]
+pub struct Shaping {
+  interfaces: Vec<Interface>,
+}
+
+impl Shaping {
+  /// Returns the interfaces shaped by `netem`: its device in the namespace of the workload, or
+  /// the interfaces created for the namespace. Fails if there is none.
+  pub fn interfaces(
+    netem: &Netem,
+    namespace: Option<&NetworkNamespace>,
+  ) -> Result<Vec<Interface>, io::Error> {
+    let interfaces = match &netem.device {
+      Some(device) => vec![Interface {
+        namespace: namespace.map(|n| n.name.clone()),
+        name: device.clone(),
+      }],
+      None => namespace.map(|n| n.interfaces.clone()).unwrap_or_default(),
+    };
+    if interfaces.is_empty() {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "netem needs a device when the workload runs in the network of the host",
+      ));
+    }
+    Ok(interfaces)
+  }
+
+  /// Shapes the egress traffic of every interface.
+  pub fn apply(netem: &Netem, interfaces: &[Interface]) -> Result<Self, io::Error> {
+    let mut shaping = Self {
+      interfaces: Vec::new(),
+    };
+    // on failure the interfaces shaped so far are restored when dropped
+    for interface in interfaces {
+      tc(&qdisc_args(interface, "add", Some(netem)))?;
+      shaping.interfaces.push(interface.clone());
+    }
+    Ok(shaping)
+  }
+}
+
+impl Drop for Shaping {
+  fn drop(&mut self) {
+    for interface in &self.interfaces {
+      if let Err(e) = tc(&qdisc_args(interface, "del", None)) {
+        warn!("cannot remove netem from {}: {}", interface.name, e);
+      }
+    }
+  }
+}
+
+/// Returns the arguments of `tc` adding or deleting the root qdisc of `interface`.
+fn qdisc_args(interface: &Interface, action: &str, netem: Option<&Netem>) -> Vec<String> {
+  let mut args = Vec::new();
+  if let Some(namespace) = &interface.namespace {
+    args.extend([String::from("-n"), namespace.clone()]);
+  }
+  args.extend(["qdisc", action, "dev", &interface.name, "root"].map(String::from));
+  if let Some(netem) = netem {
+    args.push(String::from("netem"));
+    if netem.latency.is_some() || netem.jitter.is_some() {
+      args.push(String::from("delay"));
+      args.push(format!(
+        "{}us",
+        netem.latency.unwrap_or_default().as_micros()
+      ));
+      if let Some(jitter) = netem.jitter {
+        args.push(format!("{}us", jitter.as_micros()));
+      }
+    }
+    if let Some(bandwidth) = &netem.bandwidth {
+      args.extend([String::from("rate"), bandwidth.clone()]);
+    }
+  }
+  args
+}
+
+/// Returns the arguments of the `ip` commands creating the namespace `name`, linked to the host
+/// by `veth` if any; the first one adds the namespace.
+fn setup_commands(name: &str, veth: Option<&Veth>) -> Vec<Vec<String>> {
+  let mut commands: Vec<Vec<String>> = vec![
+    vec!["netns", "add", name],
+    vec!["-n", name, "link", "set", "lo", "up"],
+  ]
+  .into_iter()
+  .map(|args| args.into_iter().map(String::from).collect())
+  .collect();
+  if let Some(veth) = veth {
+    let host_address = format!("{}/30", veth.host_address());
+    let namespace_address = format!("{}/30", veth.namespace_address());
+    commands.extend(
+      [
+        vec![
+          "link", "add", &veth.host, "type", "veth", "peer", "name", &veth.peer, "netns", name,
+        ],
+        vec!["addr", "add", &host_address, "dev", &veth.host],
+        vec!["link", "set", &veth.host, "up"],
+        vec![
+          "-n",
+          name,
+          "addr",
+          "add",
+          &namespace_address,
+          "dev",
+          &veth.peer,
+        ],
+        vec!["-n", name, "link", "set", &veth.peer, "up"],
+      ]
+      .into_iter()
+      .map(|args| args.into_iter().map(String::from).collect()),
+    );
+  }
+  commands
+}
+
+/// Returns the first /30 subnet of `10.200.0.0/16` from the `start`-th one (wrapping around)
+/// without any of the `used` addresses.
+fn free_subnet(start: u32, used: &[Ipv4Addr]) -> Option<Ipv4Addr> {
+  (0..VETH_SUBNET_COUNT)
+    .map(|i| u32::from(VETH_SUBNETS) + (start.wrapping_add(i) % VETH_SUBNET_COUNT) * 4)
+    .find(|subnet| {
+      !used
+        .iter()
+        .any(|address| u32::from(*address) & !3 == *subnet)
+    })
+    .map(Ipv4Addr::from)
+}
+
+/// Parses the IPv4 addresses of the output of `ip -o -4 addr show`.
+fn host_addresses(output: &str) -> Vec<Ipv4Addr> {
+  output
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace();
+      fields.find(|field| *field == "inet")?;
+      fields.next()?.split('/').next()?.parse().ok()
+    })
+    .collect()
+}
+
+/// Returns the pid of the benchmark which created a namespace (`eb-<pid>-<index>`) or a veth
+/// interface (`eb<pid>-<index>h` in hex) named by this module.
+fn owner(name: &str) -> Option<u32> {
+  if let Some(rest) = name.strip_prefix("eb-") {
+    let (pid, index) = rest.split_once('-')?;
+    index.parse::<u32>().ok()?;
+    return pid.parse().ok();
+  }
+  let (pid, index) = name.strip_prefix("eb")?.split_once('-')?;
+  u32::from_str_radix(index.strip_suffix(['h', 'n'])?, 16).ok()?;
+  u32::from_str_radix(pid, 16).ok()
+}
+
+// deletes the namespaces and veth pairs left behind by benchmarks which did not exit cleanly
+fn remove_stale() {
+  let alive = |pid: u32| Path::new("/proc").join(pid.to_string()).exists();
+  let stale = |directory: &str| -> Vec<String> {
+    fs::read_dir(directory)
+      .into_iter()
+      .flatten()
+      .flatten()
+      .filter_map(|entry| entry.file_name().into_string().ok())
+      .filter(|name| owner(name).is_some_and(|pid| !alive(pid)))
+      .collect()
+  };
+  // deleting a namespace deletes the end of its veth pair on the host too
+  for name in stale("/run/netns") {
+    warn!("deleting the stale network namespace {}", name);
+    if let Err(e) = ip(&["netns", "delete", &name].map(String::from)) {
+      warn!("cannot delete network namespace {}: {}", name, e);
+    }
+  }
+  for name in stale("/sys/class/net") {
+    warn!("deleting the stale veth interface {}", name);
+    if let Err(e) = ip(&["link", "delete", &name].map(String::from)) {
+      warn!("cannot delete interface {}: {}", name, e);
+    }
+  }
+}
+
+fn ip(args: &[String]) -> Result<(), io::Error> {
+  run("ip", args).map(|_| ())
+}
+
+fn tc(args: &[String]) -> Result<(), io::Error> {
+  run("tc", args).map(|_| ())
+}
+
+// returns the standard output of the command
+fn run(program: &str, args: &[String]) -> Result<String, io::Error> {
+  let output = Command::new(program).args(args).output()?;
+  if !output.status.success() {
+    return Err(io::Error::other(format!(
+      "{} {} failed: {}",
+      program,
+      args.join(" "),
+      String::from_utf8_lossy(&output.stderr).trim()
+    )));
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+  use std::{net::Ipv4Addr, time::Duration};
+
+  use crate::common::{Netem, Network};
+
+  use super::{
+    free_subnet, host_addresses, owner, qdisc_args, setup_commands, Interface, NetworkNamespace,
+    Shaping, Veth,
+  };
+
+  #[test]
+  fn host_network_has_no_namespace() {
+    assert!(NetworkNamespace::create(&Network::Host).unwrap().is_none());
+    assert_eq!(setup_commands("eb-1-0", None).len(), 2);
+  }
+
+  #[test]
+  fn netem_qdisc() {
+    let interface = Interface {
+      namespace: Some(String::from("eb-1-0")),
+      name: String::from("eb0n"),
+    };
+    let netem = Netem {
+      latency: Some(Duration::from_millis(20)),
+      jitter: Some(Duration::from_millis(2)),
+      bandwidth: Some(String::from("100mbit")),
+      device: None,
+    };
+    assert_eq!(
+      qdisc_args(&interface, "add", Some(&netem)).join(" "),
+      "-n eb-1-0 qdisc add dev eb0n root netem delay 20000us 2000us rate 100mbit"
+    );
+    assert_eq!(
+      qdisc_args(&interface, "del", None).join(" "),
+      "-n eb-1-0 qdisc del dev eb0n root"
+    );
+
+    // on the host network the device must be given
+    assert!(Shaping::interfaces(&netem, None).is_err());
+    let netem = Netem {
+      device: Some(String::from("eth0")),
+      ..netem
+    };
+    assert_eq!(
+      Shaping::interfaces(&netem, None).unwrap(),
+      vec![Interface {
+        namespace: None,
+        name: String::from("eth0"),
+      }]
+    );
+  }
+
+  #[test]
+  fn veth_commands() {
+    let veth = Veth::new(0x3fffff, 3, Ipv4Addr::new(10, 200, 0, 4));
+    assert!(veth.host.len() <= 15);
+    let commands = setup_commands("eb-4194303-3", Some(&veth));
+    assert_eq!(commands[0], vec!["netns", "add", "eb-4194303-3"]);
+    assert_eq!(
+      commands[1],
+      vec!["-n", "eb-4194303-3", "link", "set", "lo", "up"]
+    );
+    assert_eq!(
+      commands[2].join(" "),
+      "link add eb3fffff-3h type veth peer name eb3fffff-3n netns eb-4194303-3"
+    );
+    assert_eq!(
+      commands[3].join(" "),
+      "addr add 10.200.0.5/30 dev eb3fffff-3h"
+    );
+    assert_eq!(
+      commands[5].join(" "),
+      "-n eb-4194303-3 addr add 10.200.0.6/30 dev eb3fffff-3n"
+    );
+  }
+
+  #[test]
+  fn veth_subnets() {
+    let used = host_addresses(
+      "1: lo    inet 127.0.0.1/8 scope host lo\\       valid_lft forever preferred_lft forever\n\
+       7: eb1-0h    inet 10.200.0.9/30 scope global eb1-0h\\       valid_lft forever\n",
+    );
+    assert_eq!(
+      used,
+      vec![Ipv4Addr::new(127, 0, 0, 1), Ipv4Addr::new(10, 200, 0, 9)]
+    );
+    assert_eq!(free_subnet(1, &used), Some(Ipv4Addr::new(10, 200, 0, 4)));
+    // the subnet in use is skipped
+    assert_eq!(free_subnet(2, &used), Some(Ipv4Addr::new(10, 200, 0, 12)));
+    // the start wraps around the range
+    assert_eq!(
+      free_subnet(1 << 14, &used),
+      Some(Ipv4Addr::new(10, 200, 0, 0))
+    );
+  }
+
+  #[test]
+  fn stale_owners() {
+    assert_eq!(owner("eb-1234-0"), Some(1234));
+    assert_eq!(owner("eb4d2-a"), None);
+    assert_eq!(owner("eb4d2-ah"), Some(1234));
+    assert_eq!(owner("eb4d2-an"), Some(1234));
+    assert_eq!(owner("eth0"), None);
+    assert_eq!(owner("eb-x-0"), None);
+  }
+}
@@ -1,11 +1,14 @@
 use std::{
-  collections::HashMap,
+  collections::{BTreeMap, HashMap},
   fs::{self, create_dir, create_dir_all},
+  os::unix::fs::symlink,
   path::{Path, PathBuf},
+  process::Command,
   sync::{
     atomic::{AtomicBool, Ordering},
-    Arc,
+    Arc, OnceLock,
   },
+  time::{Duration, SystemTime},
 };
 
 use handlebars::Handlebars;
@@ -14,15 +17,26 @@ use pyo3::{
   Bound, PyAny, PyResult, Python,
 };
 use rsa::{
-  pkcs1::{self, EncodeRsaPrivateKey},
+  pkcs1::{self, DecodeRsaPrivateKey, EncodeRsaPrivateKey},
+  pkcs8::DecodePrivateKey,
+  traits::PublicKeyParts,
   BigUint, RsaPrivateKey,
 };
-use tracing::{span, Level};
+use serde::Serialize;
+use tracing::{info, span, trace, warn, Level};
 
 use crate::{
+  cache::resolve_mask,
   collector::DefaultCollector,
-  common::{ExperimentConfig, StorageType, Task},
-  constants::MANIFEST,
+  common::{
+    format_size, parse_size, CacheAllocation, ExperimentConfig, ExperimentMetadata, Fault,
+    GramineLogLevel, MetricPattern, Netem, Network, PerfEvents, Pinning, Provenance, ReadyCheck,
+    Runtime, SchedPolicy, SigningKey, SigningScheme, StorageStack, StorageType, SystemdScope, Task,
+    TransparentHugePages, Uprobe, Usdt,
+  },
+  constants::{DEFAULT_LAYOUT, GRAMINE_HELPER_THREADS, JVM_EXTRA_THREADS, MANIFEST},
+  manifest::{adapt_manifest, GramineVersion, DEFAULT_MANIFEST_SUFFIX, SUPPORTED_GRAMINE_VERSIONS},
+  system::pinned_cpus,
 };
 
 /// A `Profiler` is responsible for managing the benchmarking of tasks within an SGX enclave environment.
@@ -33,10 +47,13 @@ use crate::{
 ///
 /// # Fields
 ///
-/// * `private_key_path` - The file path where the RSA private key is stored.
+/// * `private_key_path` - The file path where the RSA private key is stored: the external key of
+///   the `SigningKey` if any, otherwise the key generated in the output directory.
 /// * `output_directory` - The directory where profiling results and other output files are stored.
 /// * `collector` - An `Arc` wrapped `DefaultCollector` used for collecting profiling data.
 /// * `debug` - A boolean flag indicating whether debugging is enabled.
+/// * `baseline_cache` - An optional directory where native experiments are cached and reused
+///   across campaigns.
 ///
 /// # Methods
 ///
@@ -48,6 +65,11 @@ pub struct Profiler {
   output_directory: PathBuf,
   collector: Arc<DefaultCollector>,
   debug: bool,
+  baseline_cache: Option<PathBuf>,
+  /// CPUs reserved to the workloads (see `Isolation`); empty if the CPUs are not isolated.
+  isolated_cpus: Vec<usize>,
+  /// Gramine release reported by `graminelibos`, detected with the first enclave.
+  gramine_version: OnceLock<Option<String>>,
   stop: AtomicBool,
 }
 
@@ -56,33 +78,97 @@ impl Profiler {
     output_directory: PathBuf,
     debug: bool,
     collector: Arc<DefaultCollector>,
+    signing_key: &SigningKey,
   ) -> Result<Self, std::io::Error> {
     create_dir(&output_directory)?;
 
-    let private_key_path = output_directory.join("private_key.pem");
-    let mut rng = rand::thread_rng();
-    let private_key = RsaPrivateKey::new_with_exp(&mut rng, 3072, &BigUint::new([3].into()))
-      .expect("failed to generate a key");
+    let private_key_path = match &signing_key.path {
+      Some(path) => {
+        check_signing_key(path)?;
+        path.clone()
+      }
+      None => {
+        if !signing_key.sgx_compatible() {
+          warn!(
+            "SGX only launches enclaves signed with RSA-3072 keys with exponent 3 (got {} bits, exponent {})",
+            signing_key.bits, signing_key.exponent
+          );
+        }
+        let private_key_path = output_directory.join("private_key.pem");
+        let private_key = match signing_key.scheme {
+          SigningScheme::Rsa => RsaPrivateKey::new_with_exp(
+            &mut rand::thread_rng(),
+            signing_key.bits,
+            &BigUint::from(signing_key.exponent),
+          ),
+        }
+        .map_err(|e| {
+          std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("cannot generate the signing key: {}", e),
+          )
+        })?;
 
-    private_key
-      .write_pkcs1_pem_file(&private_key_path, pkcs1::LineEnding::default())
-      .unwrap();
+        private_key
+          .write_pkcs1_pem_file(&private_key_path, pkcs1::LineEnding::default())
+          .map_err(|e| std::io::Error::other(e.to_string()))?;
+        private_key_path
+      }
+    };
 
     Ok(Profiler {
       private_key_path,
       output_directory,
       debug,
       collector,
+      baseline_cache: None,
+      isolated_cpus: Vec::new(),
+      gramine_version: OnceLock::new(),
       stop: AtomicBool::new(false),
     })
   }
 
+  /// Reuses native experiments stored in `baseline_cache` instead of running them again, and
+  /// stores the ones that are run.
+  pub fn with_baseline_cache(mut self, baseline_cache: Option<PathBuf>) -> Self {
+    self.baseline_cache = baseline_cache;
+    self
+  }
+
+  /// Restricts the workloads to `isolated_cpus`, pinned ones included.
+  pub fn with_isolated_cpus(mut self, isolated_cpus: Vec<usize>) -> Self {
+    self.isolated_cpus = isolated_cpus;
+    self
+  }
+
+  /// Returns the log level of an enclave: `log_level` if the task sweeps it, otherwise "debug"
+  /// with `globals.debug` and "none" without.
+  fn log_level(&self, log_level: Option<GramineLogLevel>) -> GramineLogLevel {
+    log_level.unwrap_or(if self.debug {
+      GramineLogLevel::Debug
+    } else {
+      GramineLogLevel::None
+    })
+  }
+
+  /// Renders, signs and writes the manifest of an experiment. Returns the variables the template
+  /// was rendered with, as JSON.
   fn build_and_sign_enclave(
     &self,
     ExperimentConfig {
       program,
       output_path,
       env,
+      sgx_profile,
+      rpc_thread_num,
+      log_level,
+      trusted_libraries,
+      runtime,
+      script,
+      sample_duration,
+      disable_aslr,
+      auxiliary_executables,
+      key_provisioning,
       ..
     }: &ExperimentConfig,
     threads: usize,
@@ -90,11 +176,12 @@ impl Profiler {
     encrypted_path: &Path,
     untrusted_path: &Path,
     custom_manifest_path: Option<PathBuf>,
-  ) -> PyResult<()> {
+  ) -> PyResult<String> {
     Python::with_gil(|py| {
       // variables
       let executable_name = program.file_name().unwrap().to_str().unwrap();
-      let executable_path = program.parent().unwrap();
+      // with a runtime the workload lives next to the script, not the interpreter
+      let executable_path = script.as_ref().unwrap_or(program).parent().unwrap();
       let manifest_path =
         output_path.join(PathBuf::from(format!("{ }.manifest.sgx", executable_name)));
       let signature_path = output_path.join(format!("{}.sig", executable_name));
@@ -114,11 +201,33 @@ impl Profiler {
       let libpal = gramine.getattr("SGX_LIBPAL")?;
       let get_tbssigstruct = gramine.getattr("get_tbssigstruct")?;
       let sign_with_local_key = gramine.getattr("sign_with_local_key")?;
+      let gramine_version = self
+        .gramine_version
+        .get_or_init(|| {
+          let version: Option<String> = gramine
+            .getattr("__version__")
+            .and_then(|version| version.extract())
+            .ok();
+          let (oldest, newest) = SUPPORTED_GRAMINE_VERSIONS;
+          match version.as_deref().map(str::parse::<GramineVersion>) {
+            Some(Ok(parsed)) if parsed < oldest || parsed > newest => warn!(
+              "Gramine {} is not supported (supported releases: {} to {}); the manifest may need changes",
+              parsed, oldest, newest
+            ),
+            Some(Ok(parsed)) => info!("building enclaves for Gramine {}", parsed),
+            Some(Err(e)) => warn!("{}; the manifest is not adapted", e),
+            None => warn!("cannot detect the Gramine version; the manifest is not adapted"),
+          }
+          version
+        })
+        .as_deref()
+        .and_then(|version| version.parse::<GramineVersion>().ok());
 
       let args = PyDict::new(py);
       args.set_item("env", py_env)?;
       args.set_item("encrypted_path", encrypted_path)?;
       args.set_item("untrusted_path", untrusted_path)?;
+      args.set_item("output_path", output_path.canonicalize()?)?;
       args.set_item(
         "arch_libdir",
         if cfg!(target_env = "musl") {
@@ -130,9 +239,70 @@ impl Profiler {
       args.set_item("executable", program.canonicalize()?)?;
       args.set_item("enclave_size", size)?;
       args.set_item("num_threads", threads)?;
-      args.set_item("num_threads_sgx", threads + 4)?;
+      // the JVM starts its own GC and JIT compiler threads
+      let jvm_threads = if *runtime == Some(Runtime::Java) {
+        JVM_EXTRA_THREADS
+      } else {
+        0
+      };
+      args.set_item(
+        "num_threads_sgx",
+        threads + GRAMINE_HELPER_THREADS + jvm_threads,
+      )?;
+      args.set_item("rpc_thread_num", rpc_thread_num)?;
+      if *runtime == Some(Runtime::Java) {
+        args.set_item("java_home", java_home(program))?;
+        // leave half of the enclave to the JVM code cache, metaspace and thread stacks
+        args.set_item(
+          "java_heap_size",
+          parse_size(size).map_or(size.to_string(), |bytes| format_size(bytes / 2)),
+        )?;
+      }
+      args.set_item(
+        "runtime",
+        runtime
+          .as_ref()
+          .map_or(String::from("native"), |runtime| runtime.to_string()),
+      )?;
+      args.set_item(
+        "trusted_libraries",
+        trusted_libraries
+          .iter()
+          .map(|library| library.to_string_lossy().into_owned())
+          .collect::<Vec<_>>(),
+      )?;
       args.set_item("executable_path", executable_path)?;
-      args.set_item("debug", if self.debug { "debug" } else { "none" })?;
+      args.set_item(
+        "auxiliary_executables",
+        auxiliary_executables
+          .iter()
+          .map(|executable| {
+            // the workload runs the configured path, which may be a link (e.g. /bin/sh)
+            executable.canonicalize().map(|canonical| {
+              HashMap::from([
+                ("path", executable.to_string_lossy().into_owned()),
+                ("canonical", canonical.to_string_lossy().into_owned()),
+              ])
+            })
+          })
+          .collect::<Result<Vec<_>, _>>()?,
+      )?;
+      args.set_item("debug", self.log_level(*log_level).to_string())?;
+      args.set_item("disable_aslr", disable_aslr)?;
+      args.set_item(
+        "key_provisioning_ca_chain",
+        key_provisioning
+          .as_ref()
+          .map(|provisioning| provisioning.ca_chain.to_string_lossy().into_owned()),
+      )?;
+      args.set_item(
+        "key_provisioning_server",
+        key_provisioning
+          .as_ref()
+          .map(|provisioning| provisioning.server.clone()),
+      )?;
+      args.set_item("profile_enable", sgx_profile.enable.to_string())?;
+      args.set_item("profile_mode", sgx_profile.mode.to_string())?;
       args.set_item(
         "libc",
         if cfg!(target_env = "musl") {
@@ -141,16 +311,44 @@ impl Profiler {
           "glibc"
         },
       )?;
-      let manifest: Bound<'_, PyAny> = match custom_manifest_path {
+      let render = |template: &str| -> PyResult<Bound<'_, PyAny>> {
+        let rendered = manifest.call_method1("from_template", (template, &args))?;
+        // fixed-duration samples end with a SIGTERM, which Gramine ignores unless injection is
+        // enabled; custom manifests may not enable it
+        if sample_duration.is_some() {
+          rendered
+            .get_item("sys")?
+            .set_item("enable_sigterm_injection", true)?;
+        }
+        let Some(version) = gramine_version else {
+          return Ok(rendered);
+        };
+        let content: String = rendered.call_method0("dumps")?.extract()?;
+        let mut settings: toml::Table = content.parse().map_err(std::io::Error::other)?;
+        let renames = adapt_manifest(&mut settings, version);
+        if renames.is_empty() {
+          return Ok(rendered);
+        }
+        trace!(
+          "manifest adapted to Gramine {}: {}",
+          version,
+          renames.join(", ")
+        );
+        let content = toml::to_string(&settings).map_err(std::io::Error::other)?;
+        manifest.call1((content,))
+      };
+      let manifest = match custom_manifest_path {
         Some(p) => {
-          let f = fs::read_to_string(p)?;
-          manifest
-            .call_method1("from_template", (f, args))?
-            .extract()?
+          // the default template rendered with the same variables shows what the custom one
+          // changes (see `manifest-diff --default`)
+          let default: String = render(MANIFEST.trim())?.call_method0("dumps")?.extract()?;
+          fs::write(
+            output_path.join(format!("{}{}", executable_name, DEFAULT_MANIFEST_SUFFIX)),
+            default,
+          )?;
+          render(&fs::read_to_string(p)?)?
         }
-        None => manifest
-          .call_method1("from_template", (MANIFEST.trim(), args))?
-          .extract()?,
+        None => render(MANIFEST.trim())?,
       };
 
       manifest.call_method0("check")?;
@@ -178,107 +376,296 @@ impl Profiler {
         .extract()?;
 
       std::fs::write(&signature_path, sig_bytes)?;
-      Ok(())
+
+      // paths are not JSON values
+      let kwargs = PyDict::new(py);
+      kwargs.set_item("default", PyModule::import(py, "builtins")?.getattr("str")?)?;
+      PyModule::import(py, "json")?
+        .getattr("dumps")?
+        .call((&args,), Some(&kwargs))?
+        .extract()
     })
   }
 
   #[tracing::instrument(skip(self), level = "info", err)]
   pub fn profile(&self, task: Task) -> Result<(), Box<dyn std::error::Error>> {
-    let program_name = task.executable.clone();
-    let program_name = program_name.file_name().unwrap().to_str().unwrap();
-    let task_path = self.output_directory.join(program_name);
-
-    'outer: for threads in task.num_threads.clone() {
-      for enclave_size in &task.enclave_size {
-        for storage_type in &task.storage_type {
-          if self.stop.load(Ordering::Relaxed) {
-            break 'outer;
-          }
-          let span = span!(
-            Level::TRACE,
-            "sgx_execution",
-            program = program_name,
-            threads = threads,
-            enclave_size = enclave_size,
-            storage_type = storage_type.to_string()
-          );
-          let _enter = span.enter();
-          let experiment_path = task_path.join(format!(
-            "gramine-sgx/{}-{}-{}-{}",
-            program_name, threads, enclave_size, storage_type
-          ));
-
-          // storage
-          let paths: Vec<PathBuf> = [
-            experiment_path.join(StorageType::Encrypted.to_string()),
-            experiment_path.join(StorageType::Untrusted.to_string()),
-          ]
-          .iter()
-          .map(|path| {
-            create_dir_all(path).or_else(|e| {
-              if e.kind() != std::io::ErrorKind::AlreadyExists {
-                return Err(e);
-              }
-              Ok(())
-            })?;
-            path.canonicalize()
-          })
-          .collect::<Result<Vec<_>, _>>()?;
-
-          let correct_storage_path = match storage_type {
-            StorageType::Encrypted => PathBuf::from("/encrypted/"),
-            StorageType::Untrusted => PathBuf::from("/untrusted/"),
-          };
-
-          let mut experiment_config = build_experiment(
-            task.clone(),
-            threads,
-            &experiment_path,
-            &correct_storage_path,
-          );
+    let experiments = plan(&task);
+    self.profile_experiments(task, experiments)
+  }
 
-          self.build_and_sign_enclave(
-            &experiment_config,
-            threads,
-            enclave_size,
-            &paths[0],
-            &paths[1],
-            task.custom_manifest_path.clone(),
-          )?;
-          // since this is a Gramine enclave
-          // we need to run the application like gramine-sgx <path-to-manifest>.manifest.sgx <args>
-          // for some reasons gramine expects the application name without the .manifest.sgx
-          // extension
-          let manifest_path = experiment_path
-            .join(program_name)
-            .to_str()
-            .unwrap()
-            .to_string();
-          experiment_config.args.insert(0, manifest_path);
-          experiment_config.program = PathBuf::from("gramine-sgx");
-          self.collector.clone().attach(experiment_config)?;
+  /// Runs a subset of the experiments of `task` (as returned by `plan`).
+  pub fn profile_experiments(
+    &self,
+    task: Task,
+    experiments: Vec<PlannedExperiment>,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let entrypoint = task
+      .runtime
+      .as_ref()
+      .map_or(task.executable.clone(), Runtime::interpreter);
+    let mut trusted_libraries = if task.discover_libraries {
+      discover_libraries(&entrypoint)
+    } else {
+      Vec::new()
+    };
+    // an empty list trusts the library directories, which cover auxiliary executables too
+    if !trusted_libraries.is_empty() {
+      for executable in &task.auxiliary_executables {
+        for library in discover_libraries(executable) {
+          if !trusted_libraries.contains(&library) {
+            trusted_libraries.push(library);
+          }
         }
       }
     }
 
-    for threads in task.num_threads.clone() {
+    for experiment in experiments {
       if self.stop.load(Ordering::Relaxed) {
         break;
       }
-      let span = span!(
-        Level::TRACE,
-        "non_sgx_execution",
-        program = program_name,
-        threads = threads,
-      );
-      let _enter = span.enter();
-      let experiment_path = task_path.join(format!("no-gramine-sgx/{}-{}", program_name, threads));
-      let storage_path = experiment_path.join("storage");
-      // ensure storage exists
-      create_dir_all(&storage_path)?;
-      let experiment_config =
-        build_experiment(task.clone(), threads, &experiment_path, &storage_path);
-      self.collector.clone().attach(experiment_config)?;
+      let progress = self.collector.progress();
+      if let Some(progress) = progress {
+        progress.experiment_started(&experiment.id);
+      }
+      let result = if experiment.sgx {
+        self.profile_sgx(&task, &experiment, &trusted_libraries)
+      } else {
+        self.profile_native(&task, &experiment)
+      };
+      if let Some(progress) = progress {
+        progress.experiment_finished(
+          &experiment.id,
+          result.as_ref().err().map(ToString::to_string),
+        );
+      }
+      result?;
+    }
+    Ok(())
+  }
+
+  fn profile_sgx(
+    &self,
+    task: &Task,
+    experiment: &PlannedExperiment,
+    trusted_libraries: &[PathBuf],
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let threads = experiment.num_threads;
+    let enclave_size = experiment.enclave_size.clone().unwrap_or_default();
+    let storage_type = experiment
+      .storage_type
+      .clone()
+      .unwrap_or(StorageType::Untrusted);
+    let rpc_thread_num = experiment.rpc_thread_num.unwrap_or_default();
+    let log_level = self.log_level(experiment.log_level);
+    let key_provisioning = task
+      .key_provisioning
+      .clone()
+      .filter(|_| experiment.key_provisioning);
+    let combination: Vec<(String, String)> = experiment.args_matrix.clone().into_iter().collect();
+    let cpu_affinity = cpu_affinity(experiment, &self.isolated_cpus);
+    let cache_mask = cache_mask(task)?;
+    let span = span!(
+      Level::TRACE,
+      "sgx_execution",
+      program = experiment.name,
+      threads = threads,
+      enclave_size = enclave_size,
+      storage_type = storage_type.to_string(),
+      rpc_thread_num = rpc_thread_num,
+      log_level = log_level.to_string(),
+      args_matrix = format!("{:?}", combination),
+      pinning = experiment.pinning.to_string()
+    );
+    let _enter = span.enter();
+    let experiment_path = self.output_directory.join(&experiment.directory);
+
+    // storage
+    let paths: Vec<PathBuf> = [
+      experiment_path.join(StorageType::Encrypted.to_string()),
+      experiment_path.join(StorageType::Untrusted.to_string()),
+    ]
+    .iter()
+    .map(|path| {
+      create_dir_all(path).or_else(|e| {
+        if e.kind() != std::io::ErrorKind::AlreadyExists {
+          return Err(e);
+        }
+        Ok(())
+      })?;
+      path.canonicalize()
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let correct_storage_path = match storage_type {
+      StorageType::Encrypted => PathBuf::from("/encrypted/"),
+      StorageType::Tmpfs => PathBuf::from("/tmp/"),
+      StorageType::Untrusted => PathBuf::from("/untrusted/"),
+    };
+
+    let mut metadata = ExperimentMetadata {
+      program: task.executable.clone(),
+      sgx: true,
+      num_threads: threads,
+      enclave_size: Some(enclave_size.clone()),
+      storage_type: Some(storage_type.to_string()),
+      rpc_thread_num: Some(rpc_thread_num),
+      log_level: Some(log_level),
+      args_matrix: experiment.args_matrix.clone(),
+      pinning: experiment.pinning,
+      cpu_affinity: cpu_affinity.clone(),
+      transparent_hugepages: task.transparent_hugepages,
+      disable_aslr: task.disable_aslr,
+      cache_mask: cache_mask.map(|mask| format!("{:#x}", mask)),
+      key_provisioning: key_provisioning.as_ref().map(|p| p.server.clone()),
+      trusted_libraries: trusted_libraries.to_vec(),
+      gramine_version: None,
+      // tmpfs lives in the memory of the enclave, not on a host file system
+      storage: match storage_type {
+        StorageType::Encrypted => StorageStack::probe(&paths[0]),
+        StorageType::Tmpfs => None,
+        StorageType::Untrusted => StorageStack::probe(&paths[1]),
+      },
+      disk_usage_bytes: None,
+    };
+    metadata.save(&experiment_path)?;
+
+    let mut experiment_config = build_experiment(
+      with_matrix_args(task, &combination),
+      threads,
+      &experiment_path,
+      &correct_storage_path,
+    );
+    experiment_config.rpc_thread_num = rpc_thread_num;
+    experiment_config.log_level = Some(log_level);
+    experiment_config.trusted_libraries = trusted_libraries.to_vec();
+    experiment_config.cpu_affinity = cpu_affinity;
+    experiment_config.cache_mask = cache_mask;
+    experiment_config.key_provisioning = key_provisioning;
+
+    let custom_manifest_path = task
+      .custom_manifest_path
+      .as_ref()
+      .and_then(|manifest| manifest.for_storage_type(&storage_type))
+      .map(Path::to_path_buf);
+    let manifest_variables = self.build_and_sign_enclave(
+      &experiment_config,
+      threads,
+      &enclave_size,
+      &paths[0],
+      &paths[1],
+      custom_manifest_path.clone(),
+    )?;
+    metadata.gramine_version = self.gramine_version.get().cloned().flatten();
+    metadata.save(&experiment_path)?;
+    // since this is a Gramine enclave
+    // we need to run the application like gramine-sgx <path-to-manifest>.manifest.sgx <args>
+    // for some reasons gramine expects the application name without the .manifest.sgx
+    // extension
+    // the enclave runs in its sample directory, so the path is absolute
+    let manifest_path = experiment_path
+      .canonicalize()?
+      .join(experiment_config.program.file_name().unwrap())
+      .to_str()
+      .unwrap()
+      .to_string();
+    experiment_config.args.insert(0, manifest_path);
+    experiment_config.program = PathBuf::from("gramine-sgx");
+    let manifest_template = match &custom_manifest_path {
+      Some(path) => fs::read_to_string(path)?,
+      None => MANIFEST.trim().to_string(),
+    };
+    Provenance {
+      manifest_template_path: custom_manifest_path,
+      manifest_template: Some(manifest_template),
+      manifest_variables: Some(serde_json::from_str(&manifest_variables)?),
+      ..Provenance::new(
+        &experiment_config.program,
+        &experiment_config.args,
+        experiment_config.env.as_ref(),
+      )
+    }
+    .save(&experiment_path)?;
+    self.collector.clone().attach(experiment_config)?;
+    metadata.record_disk_usage(&experiment_path)?;
+    Ok(())
+  }
+
+  fn profile_native(
+    &self,
+    task: &Task,
+    experiment: &PlannedExperiment,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let threads = experiment.num_threads;
+    let combination: Vec<(String, String)> = experiment.args_matrix.clone().into_iter().collect();
+    let span = span!(
+      Level::TRACE,
+      "non_sgx_execution",
+      program = experiment.name,
+      threads = threads,
+      args_matrix = format!("{:?}", combination),
+      pinning = experiment.pinning.to_string()
+    );
+    let _enter = span.enter();
+    let experiment_path = self.output_directory.join(&experiment.directory);
+
+    let cached = self.baseline_cache.as_ref().map(|cache| {
+      // isolation changes the results as much as the settings of the collector
+      let settings = (self.collector.settings(), &self.isolated_cpus);
+      cache.join(baseline_key(task, experiment, &settings))
+    });
+    if let Some(cached) = cached.as_ref().filter(|cached| cached.is_dir()) {
+      info!("reusing native baseline {:?}", cached);
+      link_baseline(cached, &experiment_path)?;
+      return Ok(());
+    }
+
+    let storage_path = experiment_path.join("storage");
+    // ensure storage exists
+    create_dir_all(&storage_path)?;
+    let cache_mask = cache_mask(task)?;
+
+    let mut metadata = ExperimentMetadata {
+      program: task.executable.clone(),
+      sgx: false,
+      num_threads: threads,
+      enclave_size: None,
+      storage_type: None,
+      rpc_thread_num: None,
+      log_level: None,
+      args_matrix: experiment.args_matrix.clone(),
+      pinning: experiment.pinning,
+      cpu_affinity: cpu_affinity(experiment, &self.isolated_cpus),
+      transparent_hugepages: task.transparent_hugepages,
+      disable_aslr: task.disable_aslr,
+      cache_mask: cache_mask.map(|mask| format!("{:#x}", mask)),
+      key_provisioning: None,
+      trusted_libraries: Vec::new(),
+      gramine_version: None,
+      storage: StorageStack::probe(&storage_path),
+      disk_usage_bytes: None,
+    };
+    metadata.save(&experiment_path)?;
+
+    let mut experiment_config = build_experiment(
+      with_matrix_args(task, &combination),
+      threads,
+      &experiment_path,
+      &storage_path,
+    );
+    experiment_config.cpu_affinity = metadata.cpu_affinity.clone();
+    experiment_config.cache_mask = cache_mask;
+    Provenance::new(
+      &experiment_config.program,
+      &experiment_config.args,
+      experiment_config.env.as_ref(),
+    )
+    .save(&experiment_path)?;
+    self.collector.clone().attach(experiment_config)?;
+    metadata.record_disk_usage(&experiment_path)?;
+
+    // interrupted experiments are incomplete and must not be reused
+    if let Some(cached) = cached.filter(|_| !self.stop.load(Ordering::Relaxed)) {
+      store_baseline(&experiment_path, &cached)?;
     }
     Ok(())
   }
@@ -288,6 +675,541 @@ impl Profiler {
   }
 }
 
+/// An experiment of the expanded matrix of a task.
+///
+/// `id` is a hash of the parameters of the experiment prefixed with the program name (eg.
+/// `ls-5f0e3c1b9a2d4e67`), and `directory` the path of the experiment directory relative to
+/// `globals.output_directory` (eg. `ls/gramine-sgx/ls-5f0e3c1b9a2d4e67`). Both are stable across
+/// runs of the same configuration; `name` describes the experiment (eg. `ls-1-64M-untrusted`).
+/// SGX-only parameters are `None` for native experiments.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PlannedExperiment {
+  pub id: String,
+  pub name: String,
+  pub directory: String,
+  pub program: PathBuf,
+  pub sgx: bool,
+  pub num_threads: usize,
+  pub enclave_size: Option<String>,
+  pub storage_type: Option<StorageType>,
+  pub rpc_thread_num: Option<usize>,
+  /// `None` if the task does not sweep `log_level`.
+  pub log_level: Option<GramineLogLevel>,
+  /// The key of the encrypted files is provisioned instead of embedded in the manifest.
+  pub key_provisioning: bool,
+  pub args_matrix: BTreeMap<String, String>,
+  pub pinning: Pinning,
+}
+
+/// Expands a task into its experiments, in the order they are run: SGX experiments (threads,
+/// enclave sizes, storage types, RPC threads, log levels, key provisioning, pinning modes, matrix
+/// combinations) and then native
+/// ones. Experiment directories follow the default layout.
+pub fn plan(task: &Task) -> Vec<PlannedExperiment> {
+  plan_with_layout(task, DEFAULT_LAYOUT).expect("the default layout is valid")
+}
+
+/// Like [`plan`], with the experiment directories rendered from the handlebars template `layout`
+/// (see `GlobalParams`).
+pub fn plan_with_layout(task: &Task, layout: &str) -> Result<Vec<PlannedExperiment>, String> {
+  let program_name = task
+    .executable
+    .file_name()
+    .unwrap()
+    .to_string_lossy()
+    .into_owned();
+  let combinations = args_combinations(&task.args_matrix);
+  let log_levels: Vec<Option<GramineLogLevel>> = if task.log_level.is_empty() {
+    vec![None]
+  } else {
+    task.log_level.iter().copied().map(Some).collect()
+  };
+  let mut experiments = Vec::with_capacity(task.experiment_count());
+
+  for &threads in &task.num_threads {
+    for enclave_size in &task.enclave_size {
+      for storage_type in &task.storage_type {
+        for &rpc_thread_num in &task.rpc_thread_num {
+          for &log_level in &log_levels {
+            for key_provisioning in task.key_provisioning_modes(storage_type) {
+              for &pinning in &task.pinning {
+                for combination in &combinations {
+                  experiments.push(PlannedExperiment {
+                    id: String::new(),
+                    name: format!(
+                      "{0}-{1}-{2}-{3}{4}{5}{6}{7}{8}",
+                      program_name,
+                      threads,
+                      enclave_size,
+                      storage_type,
+                      rpc_thread_num_suffix(rpc_thread_num),
+                      log_level_suffix(log_level),
+                      if key_provisioning { "-provisioned" } else { "" },
+                      pinning_suffix(pinning),
+                      args_matrix_suffix(combination)
+                    ),
+                    directory: String::new(),
+                    program: task.executable.clone(),
+                    sgx: true,
+                    num_threads: threads,
+                    enclave_size: Some(enclave_size.clone()),
+                    storage_type: Some(storage_type.clone()),
+                    rpc_thread_num: Some(rpc_thread_num),
+                    log_level,
+                    key_provisioning,
+                    args_matrix: combination.iter().cloned().collect(),
+                    pinning,
+                  });
+                }
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+
+  for &threads in &task.num_threads {
+    for &pinning in &task.pinning {
+      for combination in &combinations {
+        experiments.push(PlannedExperiment {
+          id: String::new(),
+          name: format!(
+            "{0}-{1}{2}{3}",
+            program_name,
+            threads,
+            pinning_suffix(pinning),
+            args_matrix_suffix(combination)
+          ),
+          directory: String::new(),
+          program: task.executable.clone(),
+          sgx: false,
+          num_threads: threads,
+          enclave_size: None,
+          storage_type: None,
+          rpc_thread_num: None,
+          log_level: None,
+          key_provisioning: false,
+          args_matrix: combination.iter().cloned().collect(),
+          pinning,
+        });
+      }
+    }
+  }
+  let mut handlebars = Handlebars::new();
+  // names hold the `=` of args_matrix values
+  handlebars.register_escape_fn(handlebars::no_escape);
+  for experiment in &mut experiments {
+    experiment.id = format!("{}-{:016x}", program_name, fnv1a(&experiment.parameters()));
+    let context = serde_json::json!({
+      "program": program_name,
+      "kind": if experiment.sgx { "gramine-sgx" } else { "no-gramine-sgx" },
+      "mode": if experiment.sgx { "sgx" } else { "native" },
+      "id": experiment.id,
+      "name": experiment.name,
+      "num_threads": experiment.num_threads,
+      "enclave_size": experiment.enclave_size,
+      "storage_type": experiment.storage_type.as_ref().map(ToString::to_string),
+      "rpc_thread_num": experiment.rpc_thread_num,
+      "log_level": experiment.log_level.as_ref().map(ToString::to_string),
+      "key_provisioning": experiment.key_provisioning,
+      "pinning": experiment.pinning.to_string(),
+      "args_matrix": experiment.args_matrix,
+    });
+    let rendered = handlebars
+      .render_template(layout, &context)
+      .map_err(|e| format!("invalid layout {:?}: {}", layout, e))?;
+    // SGX-only parameters are empty for native experiments and leave out their component
+    let components: Vec<&str> = rendered
+      .split('/')
+      .map(str::trim)
+      .filter(|component| !component.is_empty() && *component != ".")
+      .collect();
+    if components.is_empty() || components.contains(&"..") {
+      return Err(format!(
+        "layout {:?} renders {:?} for experiment {}, which is not a directory in output_directory",
+        layout, rendered, experiment.name
+      ));
+    }
+    experiment.directory = components.join("/");
+  }
+  Ok(experiments)
+}
+
+impl PlannedExperiment {
+  /// Describes the parameters identifying the experiment, one `name=value` per line. Parameters
+  /// at their default value are left out, so that a new dimension of the matrix does not change
+  /// the ids of the experiments which do not use it.
+  fn parameters(&self) -> String {
+    let program_name = self.program.file_name().unwrap().to_string_lossy();
+    let mut parameters = vec![
+      format!("program={}", program_name),
+      format!("sgx={}", self.sgx),
+      format!("num_threads={}", self.num_threads),
+    ];
+    if let Some(enclave_size) = &self.enclave_size {
+      parameters.push(format!("enclave_size={}", enclave_size));
+    }
+    if let Some(storage_type) = &self.storage_type {
+      parameters.push(format!("storage_type={}", storage_type));
+    }
+    if let Some(rpc_thread_num) = self.rpc_thread_num.filter(|&n| n > 0) {
+      parameters.push(format!("rpc_thread_num={}", rpc_thread_num));
+    }
+    if let Some(log_level) = &self.log_level {
+      parameters.push(format!("log_level={}", log_level));
+    }
+    if self.key_provisioning {
+      parameters.push(String::from("key_provisioning=true"));
+    }
+    if self.pinning != Pinning::None {
+      parameters.push(format!("pinning={}", self.pinning));
+    }
+    for (argument, value) in &self.args_matrix {
+      parameters.push(format!("args_matrix.{}={}", argument, value));
+    }
+    parameters.join("\n")
+  }
+}
+
+// FNV-1a: stable across Rust versions, unlike the std hasher
+fn fnv1a(text: &str) -> u64 {
+  text.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+    (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+/// Checks that an external signing key is an RSA private key (PKCS#1 or PKCS#8 PEM), so that a
+/// wrong path fails before any experiment is built.
+fn check_signing_key(path: &Path) -> Result<(), std::io::Error> {
+  let pem = fs::read_to_string(path)?;
+  let key = RsaPrivateKey::from_pkcs1_pem(&pem)
+    .or_else(|_| RsaPrivateKey::from_pkcs8_pem(&pem))
+    .map_err(|e| {
+      std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{:?} is not an RSA private key: {}", path, e),
+      )
+    })?;
+  if key.size() * 8 != 3072 || *key.e() != BigUint::from(3u32) {
+    warn!(
+      "SGX only launches enclaves signed with RSA-3072 keys with exponent 3 ({:?} has {} bits, exponent {})",
+      path,
+      key.size() * 8,
+      key.e()
+    );
+  }
+  Ok(())
+}
+
+/// Everything that affects a native run, hashed into its key in the baseline cache. Task options
+/// only used by SGX experiments are left out, so that changing them keeps the baseline.
+#[derive(Serialize)]
+struct BaselineKey<'a, S> {
+  executable: &'a Path,
+  modified: Option<SystemTime>,
+  args: &'a [String],
+  env: BTreeMap<&'a String, &'a String>,
+  omp_places: &'a Option<String>,
+  omp_proc_bind: &'a Option<String>,
+  runtime: &'a Option<Runtime>,
+  energy_sample_interval: Option<Duration>,
+  pre_run: (&'a Option<PathBuf>, &'a [String]),
+  post_run: (&'a Option<PathBuf>, &'a [String]),
+  setup: (&'a Option<PathBuf>, &'a [String]),
+  teardown: (&'a Option<PathBuf>, &'a [String]),
+  uprobes: &'a [Uprobe],
+  usdt: &'a [Usdt],
+  network: &'a Network,
+  netem: &'a Option<Netem>,
+  fault: &'a Option<Fault>,
+  sample_duration: Option<Duration>,
+  metrics: &'a [MetricPattern],
+  perf_events: &'a PerfEvents,
+  systemd_scope: &'a Option<SystemdScope>,
+  transparent_hugepages: Option<TransparentHugePages>,
+  nice: Option<i32>,
+  sched_policy: Option<SchedPolicy>,
+  ready_check: &'a Option<ReadyCheck>,
+  disable_aslr: bool,
+  auxiliary_executables: &'a [PathBuf],
+  cache_allocation: &'a Option<CacheAllocation>,
+  num_threads: usize,
+  args_matrix: &'a BTreeMap<String, String>,
+  pinning: Pinning,
+  collector: &'a S,
+}
+
+/// Returns the directory name of a native experiment in the baseline cache: a hash of its
+/// `BaselineKey` with the settings of the collector, prefixed with the program name.
+fn baseline_key(
+  task: &Task,
+  experiment: &PlannedExperiment,
+  collector_settings: &impl Serialize,
+) -> String {
+  let key = BaselineKey {
+    executable: &task.executable,
+    modified: fs::metadata(&task.executable)
+      .and_then(|m| m.modified())
+      .ok(),
+    args: &task.args,
+    env: task.env.iter().flatten().collect(),
+    omp_places: &task.omp_places,
+    omp_proc_bind: &task.omp_proc_bind,
+    runtime: &task.runtime,
+    energy_sample_interval: task.energy_sample_interval,
+    pre_run: (&task.pre_run_executable, &task.pre_run_args),
+    post_run: (&task.post_run_executable, &task.post_run_args),
+    setup: (&task.setup_executable, &task.setup_args),
+    teardown: (&task.teardown_executable, &task.teardown_args),
+    uprobes: &task.uprobes,
+    usdt: &task.usdt,
+    network: &task.network,
+    netem: &task.netem,
+    fault: &task.fault,
+    sample_duration: task.sample_duration,
+    metrics: &task.metrics,
+    perf_events: &task.perf_events,
+    systemd_scope: &task.systemd_scope,
+    transparent_hugepages: task.transparent_hugepages,
+    nice: task.nice,
+    sched_policy: task.sched_policy,
+    ready_check: &task.ready_check,
+    disable_aslr: task.disable_aslr,
+    auxiliary_executables: &task.auxiliary_executables,
+    cache_allocation: &task.cache_allocation,
+    num_threads: experiment.num_threads,
+    args_matrix: &experiment.args_matrix,
+    pinning: experiment.pinning,
+    collector: collector_settings,
+  };
+  let description = serde_json::to_string(&key).expect("the baseline key is serializable");
+  let hash = fnv1a(&description);
+  let program_name = task.executable.file_name().unwrap().to_string_lossy();
+  format!("{}-{}-{:016x}", program_name, experiment.num_threads, hash)
+}
+
+/// Links `experiment_path` to the `cached` native experiment, replacing what a previous run in the
+/// same output directory left there (its own link or results).
+fn link_baseline(cached: &Path, experiment_path: &Path) -> Result<(), std::io::Error> {
+  create_dir_all(experiment_path.parent().unwrap())?;
+  match fs::symlink_metadata(experiment_path) {
+    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(experiment_path)?,
+    Ok(_) => fs::remove_file(experiment_path)?,
+    Err(_) => {}
+  }
+  symlink(cached.canonicalize()?, experiment_path)
+}
+
+/// Copies a completed native experiment into the baseline cache, leaving out the files written by
+/// the workload in `storage`. The copy is renamed into place so that an interrupted copy is never
+/// reused.
+fn store_baseline(experiment_path: &Path, cached: &Path) -> Result<(), std::io::Error> {
+  let partial = cached.with_extension("partial");
+  if partial.exists() {
+    fs::remove_dir_all(&partial)?;
+  }
+  create_dir_all(partial.join("storage"))?;
+  for entry in fs::read_dir(experiment_path)? {
+    let entry = entry?;
+    if entry.file_name() == "storage" {
+      continue;
+    }
+    if entry.file_type()?.is_dir() {
+      copy_dir_all(&entry.path(), &partial.join(entry.file_name()))?;
+    } else {
+      fs::copy(entry.path(), partial.join(entry.file_name()))?;
+    }
+  }
+  fs::rename(&partial, cached)?;
+  info!("stored native baseline in {:?}", cached);
+  Ok(())
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+  create_dir_all(to)?;
+  for entry in fs::read_dir(from)? {
+    let entry = entry?;
+    if entry.file_type()?.is_dir() {
+      copy_dir_all(&entry.path(), &to.join(entry.file_name()))?;
+    } else {
+      fs::copy(entry.path(), to.join(entry.file_name()))?;
+    }
+  }
+  Ok(())
+}
+
+/// Returns the installation directory of the JVM run by `java`, as it reports it (`java.home`);
+/// without an answer, the parent of its `bin` directory once links are resolved.
+fn java_home(java: &Path) -> PathBuf {
+  let reported = Command::new(java)
+    .args(["-XshowSettings:properties", "-version"])
+    .output()
+    .ok()
+    // the settings are printed on stderr
+    .and_then(|output| parse_java_home(&String::from_utf8_lossy(&output.stderr)));
+  reported.unwrap_or_else(|| {
+    warn!(
+      "cannot read java.home from {:?}; guessing it from its path",
+      java
+    );
+    // <java_home>/bin/java
+    let java = java.canonicalize().unwrap_or_else(|_| java.to_path_buf());
+    java
+      .ancestors()
+      .nth(2)
+      .unwrap_or(Path::new("/"))
+      .to_path_buf()
+  })
+}
+
+// finds the line "    java.home = /usr/lib/jvm/java-17-openjdk-amd64"
+fn parse_java_home(settings: &str) -> Option<PathBuf> {
+  settings.lines().find_map(|line| {
+    let (name, value) = line.split_once('=')?;
+    (name.trim() == "java.home").then(|| PathBuf::from(value.trim()))
+  })
+}
+
+/// Discovers the shared libraries required by `executable` with `ldd`. Returns an empty vector
+/// (trusting library directories instead) if the executable is static or `ldd` fails.
+fn discover_libraries(executable: &Path) -> Vec<PathBuf> {
+  match Command::new("ldd").arg(executable).output() {
+    Ok(output) if output.status.success() => {
+      parse_ldd_output(&String::from_utf8_lossy(&output.stdout))
+    }
+    Ok(output) => {
+      warn!(
+        "cannot discover libraries of {:?}: {}",
+        executable,
+        String::from_utf8_lossy(&output.stderr).trim()
+      );
+      Vec::new()
+    }
+    Err(e) => {
+      warn!("cannot discover libraries of {:?}: {}", executable, e);
+      Vec::new()
+    }
+  }
+}
+
+// parses lines like:
+//   libm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f...)
+//   /lib64/ld-linux-x86-64.so.2 (0x00007f...)
+// skipping the vDSO which has no backing file
+fn parse_ldd_output(output: &str) -> Vec<PathBuf> {
+  let mut libraries: Vec<PathBuf> = output
+    .lines()
+    .filter_map(|line| {
+      let line = line.trim();
+      let path = match line.split_once("=>") {
+        Some((_, resolved)) => resolved.split_whitespace().next()?,
+        None => line.split_whitespace().next()?,
+      };
+      path.starts_with('/').then(|| PathBuf::from(path))
+    })
+    .collect();
+  libraries.sort();
+  libraries.dedup();
+  libraries
+}
+
+/// Returns the cartesian product of the values in `args_matrix` as (argument, value) pairs.
+/// An empty matrix yields a single empty combination so that the task runs once.
+fn args_combinations(matrix: &BTreeMap<String, Vec<String>>) -> Vec<Vec<(String, String)>> {
+  let mut combinations: Vec<Vec<(String, String)>> = vec![vec![]];
+  for (arg, values) in matrix.iter().filter(|(_, values)| !values.is_empty()) {
+    combinations = combinations
+      .into_iter()
+      .flat_map(|combination| {
+        values.iter().map(move |value| {
+          let mut combination = combination.clone();
+          combination.push((arg.clone(), value.clone()));
+          combination
+        })
+      })
+      .collect();
+  }
+  combinations
+}
+
+// exitless experiments are marked in the directory name; the default (0) keeps the usual name
+fn rpc_thread_num_suffix(rpc_thread_num: usize) -> String {
+  if rpc_thread_num == 0 {
+    String::new()
+  } else {
+    format!("-rpc{}", rpc_thread_num)
+  }
+}
+
+// swept log levels are marked in the directory name, the level of `globals.debug` is not
+fn log_level_suffix(log_level: Option<GramineLogLevel>) -> String {
+  log_level
+    .map(|log_level| format!("-log{}", log_level))
+    .unwrap_or_default()
+}
+
+// pinned experiments are told apart from unpinned ones, whose names are unchanged
+pub(crate) fn pinning_suffix(pinning: Pinning) -> &'static str {
+  match pinning {
+    Pinning::None => "",
+    Pinning::Cores => "-pinned",
+  }
+}
+
+// the ways of a cache allocation depend on the cache of the host
+fn cache_mask(task: &Task) -> Result<Option<u64>, String> {
+  task
+    .cache_allocation
+    .as_ref()
+    .map(resolve_mask)
+    .transpose()
+    .map_err(|e| format!("cannot allocate the cache of {:?}: {}", task.executable, e))
+}
+
+// the CPUs an experiment runs on: one per thread of the workload if pinned (with its RPC threads
+// and the helper threads of Gramine in an enclave), the isolated CPUs (if any) otherwise
+fn cpu_affinity(experiment: &PlannedExperiment, isolated_cpus: &[usize]) -> Vec<usize> {
+  match experiment.pinning {
+    Pinning::None => isolated_cpus.to_vec(),
+    Pinning::Cores => pinned_cpus(pinned_threads(experiment), isolated_cpus),
+  }
+}
+
+// the threads of a pinned experiment which get a CPU of their own
+fn pinned_threads(experiment: &PlannedExperiment) -> usize {
+  if experiment.sgx {
+    experiment.num_threads + experiment.rpc_thread_num.unwrap_or_default() + GRAMINE_HELPER_THREADS
+  } else {
+    experiment.num_threads
+  }
+}
+
+// encodes the chosen matrix values in the experiment directory name
+// (eg. [("--batch-size", "32")] -> "-batch-size=32")
+fn args_matrix_suffix(combination: &[(String, String)]) -> String {
+  combination
+    .iter()
+    .map(|(arg, value)| {
+      format!(
+        "-{}={}",
+        arg.trim_start_matches('-'),
+        value.replace('/', "_")
+      )
+    })
+    .collect()
+}
+
+// appends the chosen matrix values to the task arguments as "<arg> <value>"
+fn with_matrix_args(task: &Task, combination: &[(String, String)]) -> Task {
+  let mut task = task.clone();
+  for (arg, value) in combination {
+    task.args.push(arg.clone());
+    task.args.push(value.clone());
+  }
+  task
+}
+
 fn build_experiment(
   Task {
     executable,
@@ -296,13 +1218,51 @@ fn build_experiment(
     pre_run_args,
     post_run_executable,
     post_run_args,
-    env,
+    setup_executable,
+    setup_args,
+    teardown_executable,
+    teardown_args,
+    on_fail_executable,
+    on_fail_args,
+    hooks_must_succeed,
+    hook_timeout,
+    mut env,
+    omp_places,
+    omp_proc_bind,
+    energy_sample_interval,
+    sgx_profile,
+    runtime,
+    uprobes,
+    usdt,
+    network,
+    netem,
+    fault,
+    sample_duration,
+    metrics,
+    perf_events,
+    systemd_scope,
+    transparent_hugepages,
+    nice,
+    sched_policy,
+    ready_check,
+    disable_aslr,
+    auxiliary_executables,
     ..
   }: Task,
   threads: usize,
   experiment_path: &Path,
   storage_path: &Path,
 ) -> ExperimentConfig {
+  // thread placement is passed through the environment so that it is the same for SGX
+  // (through the manifest) and native runs
+  for (key, value) in [("OMP_PLACES", omp_places), ("OMP_PROC_BIND", omp_proc_bind)] {
+    if let Some(value) = value {
+      env
+        .get_or_insert_with(HashMap::new)
+        .insert(key.to_string(), value);
+    }
+  }
+
   let context = HashMap::from([
     ("num_threads", threads.to_string()),
     (
@@ -312,25 +1272,72 @@ fn build_experiment(
   ]);
   let handlebars = Handlebars::new();
 
-  let expanded_args: Vec<Vec<String>> = [&args, &pre_run_args, &post_run_args]
-    .iter()
-    .map(|arg_list| {
-      arg_list
-        .iter()
-        .map(|template_string| handlebars.render_template(template_string, &context))
-        .collect::<Result<Vec<String>, _>>()
-    })
-    .collect::<Result<Vec<_>, _>>()
-    .unwrap();
+  let expanded_args: Vec<Vec<String>> = [
+    &args,
+    &pre_run_args,
+    &post_run_args,
+    &setup_args,
+    &teardown_args,
+    &on_fail_args,
+  ]
+  .iter()
+  .map(|arg_list| {
+    arg_list
+      .iter()
+      .map(|template_string| handlebars.render_template(template_string, &context))
+      .collect::<Result<Vec<String>, _>>()
+  })
+  .collect::<Result<Vec<_>, _>>()
+  .unwrap();
 
-  let [args, pre_run_args, post_run_args] = expanded_args.try_into().unwrap();
+  let [mut args, pre_run_args, post_run_args, setup_args, teardown_args, on_fail_args] =
+    expanded_args.try_into().unwrap();
+
+  // scripts are run by the interpreter: <interpreter> <script> <args>
+  let (program, script) = match &runtime {
+    Some(runtime) => {
+      args.splice(0..0, runtime.script_args(&executable));
+      (runtime.interpreter(), Some(executable))
+    }
+    None => (executable, None),
+  };
 
   ExperimentConfig {
-    program: executable.to_path_buf(),
+    program,
     pre_run: pre_run_executable.map(|x| (x, pre_run_args)),
     post_run: post_run_executable.map(|x| (x, post_run_args)),
+    setup: setup_executable.map(|x| (x, setup_args)),
+    teardown: teardown_executable.map(|x| (x, teardown_args)),
+    on_fail: on_fail_executable.map(|x| (x, on_fail_args)),
+    hooks_must_succeed,
+    hook_timeout,
     args,
     output_path: experiment_path.to_path_buf(),
+    energy_sample_interval,
+    sgx_profile,
+    rpc_thread_num: 0,
+    log_level: None,
+    key_provisioning: None,
+    trusted_libraries: Vec::new(),
+    runtime,
+    script,
+    uprobes,
+    usdt,
+    network,
+    netem,
+    fault,
+    sample_duration,
+    metrics,
+    perf_events,
+    systemd_scope,
+    cpu_affinity: Vec::new(),
+    transparent_hugepages,
+    nice,
+    sched_policy,
+    ready_check,
+    disable_aslr,
+    auxiliary_executables,
+    cache_mask: None,
     env: env.map(|c| {
       let mut expanded_env = HashMap::new();
       for (key, val) in c {
@@ -344,41 +1351,51 @@ fn build_experiment(
 
 #[cfg(test)]
 mod test {
-  use std::{collections::HashMap, fs::create_dir_all, time::Duration};
+  use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fs::{self, create_dir_all},
+    path::{Path, PathBuf},
+    time::Duration,
+  };
 
-  use common::StorageType;
-  use profiler::build_experiment;
+  use common::{GramineLogLevel, Network, Pinning, SigningKey, StorageType, Uprobe};
+  use constants::GRAMINE_HELPER_THREADS;
+  use profiler::{
+    args_combinations, args_matrix_suffix, baseline_key, build_experiment, check_signing_key,
+    link_baseline, parse_java_home, parse_ldd_output, pinned_threads, plan,
+  };
+  use rsa::{
+    pkcs8::{EncodePrivateKey, LineEnding},
+    RsaPrivateKey,
+  };
   use tempfile::TempDir;
 
   use crate::*;
 
   #[test]
   fn build_and_sign_enclave_success() {
-    let collector = collector::DefaultCollector::new(1, false, Duration::from_millis(100), None);
+    let collector =
+      collector::DefaultCollector::new(1, false, Duration::from_millis(100), None, None, false);
     let output_directory = TempDir::new().unwrap();
     let profiler = Profiler::new(
       output_directory.path().join("profiler").to_path_buf(),
       false,
       Arc::new(collector),
+      &SigningKey::default(),
     )
     .unwrap();
 
-    let task = Task {
-      executable: PathBuf::from("/bin/ls"),
-      args: vec![],
-      pre_run_executable: None,
-      pre_run_args: vec![],
-      post_run_executable: None,
-      post_run_args: vec![],
-      env: Some(HashMap::from([(
-        "OMP_NUM_THREADS".to_string(),
-        "4".to_string(),
-      )])),
-      num_threads: vec![4],
-      enclave_size: vec!["256M".to_string()],
-      storage_type: vec![StorageType::Encrypted],
-      custom_manifest_path: None,
-    };
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/bin/ls"
+            env = { OMP_NUM_THREADS = "4" }
+            num_threads = [4]
+            enclave_size = ["256M"]
+            storage_type = ["encrypted"]
+            "#,
+    )
+    .unwrap();
 
     let experiment_path = output_directory.path().join("experiment");
     let encrypted_path = experiment_path.join("encrypted");
@@ -394,7 +1411,7 @@ mod test {
         &task.enclave_size[0],
         &encrypted_path,
         &untrusted_path,
-        task.custom_manifest_path.clone(),
+        None,
       )
       .unwrap();
 
@@ -411,27 +1428,85 @@ mod test {
     assert!(signature_path.exists(), "Signature file should exist");
   }
 
+  // the files called `name` under `directory`
+  fn find_files(directory: &Path, name: &str) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(directory).unwrap().flatten() {
+      let path = entry.path();
+      if path.is_dir() {
+        found.extend(find_files(&path, name));
+      } else if path.file_name().is_some_and(|file_name| file_name == name) {
+        found.push(path);
+      }
+    }
+    found
+  }
+
+  // needs SGX, Gramine, root and a secret provisioning server (e.g. the one of the
+  // `secret_prov_minimal` example of Gramine), given as EB_TEST_PROVISIONING_SERVER and
+  // EB_TEST_PROVISIONING_CA_CHAIN
+  #[test]
+  #[ignore]
+  fn key_provisioning_end_to_end() {
+    let server = env::var("EB_TEST_PROVISIONING_SERVER").expect("no provisioning server");
+    let ca_chain = env::var("EB_TEST_PROVISIONING_CA_CHAIN").expect("no CA chain");
+    let collector =
+      collector::DefaultCollector::new(1, false, Duration::from_millis(100), None, None, false);
+    let output_directory = TempDir::new().unwrap();
+    let profiler = Profiler::new(
+      output_directory.path().join("profiler"),
+      false,
+      Arc::new(collector),
+      &SigningKey::default(),
+    )
+    .unwrap();
+    let task = toml::from_str::<Task>(&format!(
+      r#"
+            executable = "/bin/ls"
+            enclave_size = ["256M"]
+            storage_type = ["encrypted"]
+            key_provisioning = {{ server = "{}", ca_chain = "{}", embedded_key = false }}
+            "#,
+      server, ca_chain
+    ))
+    .unwrap();
+
+    profiler.profile(task).unwrap();
+
+    // the enclave verifies the certificate of the server against the configured name
+    let manifests = find_files(output_directory.path(), "ls.manifest.sgx");
+    assert_eq!(manifests.len(), 1);
+    assert!(fs::read_to_string(&manifests[0])
+      .unwrap()
+      .contains(&format!("SECRET_PROVISION_SERVERS = \"{}\"", server)));
+    // the session of the enclave with the server is timed
+    let provisioned: Vec<PathBuf> = find_files(output_directory.path(), "summary.csv")
+      .into_iter()
+      .filter(|summary| {
+        fs::read_to_string(summary)
+          .unwrap()
+          .lines()
+          .any(|line| line.starts_with("key_provisioning,"))
+      })
+      .collect();
+    assert!(!provisioned.is_empty());
+  }
+
   #[test]
   fn build_experiment_success() {
     let output_directory = TempDir::new().unwrap().path().join("storage");
-    let args = vec![
-      String::from("{{ output_directory }}"),
-      String::from("{{ num_threads }}"),
-    ];
-
-    let task = Task {
-      executable: PathBuf::from("/path/to/executable"),
-      args: args.clone(),
-      pre_run_executable: None,
-      pre_run_args: vec![],
-      post_run_executable: None,
-      post_run_args: vec![],
-      env: None,
-      num_threads: vec![4],
-      enclave_size: vec!["256M".to_string()],
-      storage_type: vec![StorageType::Encrypted],
-      custom_manifest_path: None,
-    };
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/path/to/executable"
+            args = ["{{ output_directory }}", "{{ num_threads }}"]
+            setup_executable = "/path/to/setup"
+            setup_args = ["{{ output_directory }}/dataset"]
+            num_threads = [4]
+            enclave_size = ["256M"]
+            storage_type = ["encrypted"]
+            "#,
+    )
+    .unwrap();
 
     let experiment_config = build_experiment(task, 4, &output_directory, &output_directory);
 
@@ -449,6 +1524,352 @@ mod test {
     assert_eq!(experiment_config.output_path, output_directory);
     assert!(experiment_config.pre_run.is_none());
     assert!(experiment_config.post_run.is_none());
+    assert_eq!(
+      experiment_config.setup,
+      Some((
+        PathBuf::from("/path/to/setup"),
+        vec![format!("{}/dataset", output_directory.to_string_lossy())]
+      ))
+    );
+    assert!(experiment_config.teardown.is_none());
     assert!(experiment_config.env.is_none());
   }
+
+  #[test]
+  fn args_matrix_combinations() {
+    let matrix = BTreeMap::from([
+      (
+        "--batch-size".to_string(),
+        vec!["32".to_string(), "128".to_string()],
+      ),
+      ("--mode".to_string(), vec!["a".to_string(), "b".to_string()]),
+    ]);
+
+    let combinations = args_combinations(&matrix);
+    assert_eq!(combinations.len(), 4);
+    assert_eq!(
+      args_matrix_suffix(&combinations[1]),
+      "-batch-size=32-mode=b".to_string()
+    );
+
+    let combinations = args_combinations(&BTreeMap::new());
+    assert_eq!(combinations, vec![vec![]]);
+  }
+
+  #[test]
+  fn experiment_plan() {
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/bin/ls"
+            enclave_size = ["64M", "128M"]
+            num_threads = [1, 2]
+            storage_type = ["encrypted"]
+            rpc_thread_num = [0, 4]
+            args_matrix = { "--size" = ["1", "2"] }
+            "#,
+    )
+    .unwrap();
+
+    let experiments = plan(&task);
+    assert_eq!(experiments.len(), task.experiment_count());
+    assert_eq!(experiments[0].name, "ls-1-64M-encrypted-size=1");
+    assert_eq!(experiments[3].name, "ls-1-64M-encrypted-rpc4-size=2");
+    assert_eq!(
+      experiments[0].directory,
+      format!("ls/gramine-sgx/{}", experiments[0].id)
+    );
+    assert!(experiments[0].id.starts_with("ls-") && experiments[0].id.len() == 19);
+    let ids: BTreeSet<&str> = experiments.iter().map(|e| e.id.as_str()).collect();
+    assert_eq!(ids.len(), experiments.len());
+    assert!(experiments[0].sgx);
+    assert_eq!(experiments[0].storage_type, Some(StorageType::Encrypted));
+
+    let native = experiments.iter().find(|e| !e.sgx).unwrap();
+    assert_eq!(native.name, "ls-1-size=1");
+    assert!(native.directory.starts_with("ls/no-gramine-sgx/ls-"));
+    assert_eq!(native.enclave_size, None);
+    assert_eq!(native.rpc_thread_num, None);
+    assert_eq!(
+      native.args_matrix,
+      BTreeMap::from([("--size".to_string(), "1".to_string())])
+    );
+    assert_eq!(native.pinning, Pinning::None);
+
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            pinning = ["none", "cores"]
+            "#,
+    )
+    .unwrap();
+    let experiments = plan(&task);
+    assert_eq!(experiments.len(), task.experiment_count());
+    let names: Vec<&str> = experiments.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(
+      names,
+      vec![
+        "ls-1-64M-untrusted",
+        "ls-1-64M-untrusted-pinned",
+        "ls-1",
+        "ls-1-pinned",
+      ]
+    );
+    assert_eq!(experiments[3].pinning, Pinning::Cores);
+    // a pinned enclave reserves CPUs for the helper threads of Gramine, a native workload does not
+    assert_eq!(pinned_threads(&experiments[1]), 1 + GRAMINE_HELPER_THREADS);
+    assert_eq!(pinned_threads(&experiments[3]), 1);
+    let rpc = plan(
+      &toml::from_str::<Task>(
+        r#"
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            num_threads = [2]
+            rpc_thread_num = [4]
+            pinning = ["cores"]
+            "#,
+      )
+      .unwrap(),
+    );
+    assert_eq!(pinned_threads(&rpc[0]), 2 + 4 + GRAMINE_HELPER_THREADS);
+
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            log_level = ["none", "debug"]
+            "#,
+    )
+    .unwrap();
+    let logged = plan(&task);
+    assert_eq!(logged.len(), task.experiment_count());
+    let names: Vec<&str> = logged.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(
+      names,
+      vec![
+        "ls-1-64M-untrusted-lognone",
+        "ls-1-64M-untrusted-logdebug",
+        "ls-1"
+      ]
+    );
+    assert_eq!(logged[1].log_level, Some(GramineLogLevel::Debug));
+    assert_eq!(experiments[2].log_level, None);
+
+    // a new dimension keeps the ids of the experiments at its default value
+    let unpinned = plan(
+      &toml::from_str::<Task>(
+        r#"
+            executable = "/bin/ls"
+            enclave_size = ["64M"]
+            "#,
+      )
+      .unwrap(),
+    );
+    assert_eq!(unpinned[0].id, experiments[0].id);
+    assert_eq!(unpinned[1].directory, experiments[2].directory);
+    assert_ne!(experiments[0].id, experiments[1].id);
+  }
+
+  #[test]
+  fn external_signing_key() {
+    let directory = TempDir::new().unwrap();
+    let path = directory.path().join("key.pem");
+    assert!(check_signing_key(&path).is_err());
+    fs::write(&path, "not a key").unwrap();
+    assert!(check_signing_key(&path).is_err());
+
+    let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    key.write_pkcs8_pem_file(&path, LineEnding::LF).unwrap();
+    assert!(check_signing_key(&path).is_ok());
+
+    // no key is generated in the output directory
+    let collector =
+      collector::DefaultCollector::new(1, false, Duration::from_millis(100), None, None, false);
+    let signing_key = SigningKey {
+      path: Some(path),
+      ..SigningKey::default()
+    };
+    Profiler::new(
+      directory.path().join("results"),
+      false,
+      Arc::new(collector),
+      &signing_key,
+    )
+    .unwrap();
+    assert!(!directory.path().join("results/private_key.pem").exists());
+  }
+
+  #[test]
+  fn native_baseline_key() {
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/bin/ls"
+            args = ["-l"]
+            enclave_size = ["64M", "128M"]
+            num_threads = [1, 2]
+            env = { A = "1", B = "2", C = "3" }
+            "#,
+    )
+    .unwrap();
+    let native: Vec<_> = plan(&task).into_iter().filter(|e| !e.sgx).collect();
+
+    let key = baseline_key(&task, &native[0], &"sample_size=1");
+    assert!(key.starts_with("ls-1-"));
+    assert_eq!(
+      key,
+      baseline_key(&task.clone(), &native[0], &"sample_size=1")
+    );
+    assert_ne!(key, baseline_key(&task, &native[1], &"sample_size=1"));
+    assert_ne!(key, baseline_key(&task, &native[0], &"sample_size=2"));
+
+    // enclave sizes do not affect native runs
+    let mut sgx_only = task.clone();
+    sgx_only.enclave_size = vec!["1G".to_string()];
+    assert_eq!(key, baseline_key(&sgx_only, &native[0], &"sample_size=1"));
+
+    let mut args = task.clone();
+    args.args.push("-a".to_string());
+    assert_ne!(key, baseline_key(&args, &native[0], &"sample_size=1"));
+
+    let mut isolated = task.clone();
+    isolated.network = Network::Loopback;
+    assert_ne!(key, baseline_key(&isolated, &native[0], &"sample_size=1"));
+
+    let mut probed = task.clone();
+    probed.uprobes.push(Uprobe {
+      binary: PathBuf::from("/bin/ls"),
+      symbol: String::from("main"),
+    });
+    assert_ne!(key, baseline_key(&probed, &native[0], &"sample_size=1"));
+  }
+
+  #[test]
+  fn baseline_link_replaced() {
+    let directory = TempDir::new().unwrap();
+    let cached = directory.path().join("cache/ls-1-0123456789abcdef");
+    create_dir_all(&cached).unwrap();
+    let experiment_path = directory.path().join("results/ls/ls-1");
+
+    link_baseline(&cached, &experiment_path).unwrap();
+    // a second run links again over the first link
+    link_baseline(&cached, &experiment_path).unwrap();
+    assert_eq!(fs::read_link(&experiment_path).unwrap(), cached);
+    // and over results of a run without the cache
+    fs::remove_file(&experiment_path).unwrap();
+    create_dir_all(experiment_path.join("1")).unwrap();
+    link_baseline(&cached, &experiment_path).unwrap();
+    assert_eq!(fs::read_link(&experiment_path).unwrap(), cached);
+  }
+
+  #[test]
+  fn build_experiment_omp_affinity() {
+    let output_directory = TempDir::new().unwrap().path().join("storage");
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/path/to/executable"
+            num_threads = [4]
+            enclave_size = ["256M"]
+            storage_type = ["encrypted"]
+            omp_places = "{0}:{{ num_threads }}"
+            omp_proc_bind = "close"
+            "#,
+    )
+    .unwrap();
+
+    let experiment_config = build_experiment(task, 4, &output_directory, &output_directory);
+    let env = experiment_config.env.unwrap();
+
+    assert_eq!(env["OMP_PLACES"], "{0}:4");
+    assert_eq!(env["OMP_PROC_BIND"], "close");
+  }
+
+  #[test]
+  fn build_experiment_python_runtime() {
+    let output_directory = TempDir::new().unwrap().path().join("storage");
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/path/to/bench.py"
+            args = ["{{ num_threads }}"]
+            num_threads = [4]
+            enclave_size = ["256M"]
+            storage_type = ["encrypted"]
+            runtime = "python3"
+            "#,
+    )
+    .unwrap();
+
+    let experiment_config = build_experiment(task, 4, &output_directory, &output_directory);
+
+    assert!(experiment_config.program.ends_with("python3"));
+    assert_eq!(experiment_config.args, vec!["/path/to/bench.py", "4"]);
+    assert_eq!(
+      experiment_config.script,
+      Some(PathBuf::from("/path/to/bench.py"))
+    );
+  }
+
+  #[test]
+  fn build_experiment_java_runtime() {
+    let output_directory = TempDir::new().unwrap().path().join("storage");
+    let task = toml::from_str::<Task>(
+      r#"
+            executable = "/path/to/bench.jar"
+            args = ["{{ num_threads }}"]
+            num_threads = [4]
+            enclave_size = ["256M"]
+            storage_type = ["encrypted"]
+            runtime = "java"
+            "#,
+    )
+    .unwrap();
+
+    let experiment_config = build_experiment(task, 4, &output_directory, &output_directory);
+
+    assert!(experiment_config.program.ends_with("java"));
+    assert_eq!(
+      experiment_config.args,
+      vec!["-jar", "/path/to/bench.jar", "4"]
+    );
+    assert_eq!(
+      experiment_config.script,
+      Some(PathBuf::from("/path/to/bench.jar"))
+    );
+  }
+
+  #[test]
+  fn ldd_output_parsing() {
+    let output = "\tlinux-vdso.so.1 (0x00007ffd4b5f1000)
+\tlibm.so.6 => /lib/x86_64-linux-gnu/libm.so.6 (0x00007f2a0c2f0000)
+\tlibgomp.so.1 => /lib/x86_64-linux-gnu/libgomp.so.1 (0x00007f2a0c2a0000)
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f2a0c000000)
+\tlibmissing.so => not found
+\t/lib64/ld-linux-x86-64.so.2 (0x00007f2a0c400000)
+";
+    assert_eq!(
+      parse_ldd_output(output),
+      vec![
+        PathBuf::from("/lib/x86_64-linux-gnu/libc.so.6"),
+        PathBuf::from("/lib/x86_64-linux-gnu/libgomp.so.1"),
+        PathBuf::from("/lib/x86_64-linux-gnu/libm.so.6"),
+        PathBuf::from("/lib64/ld-linux-x86-64.so.2"),
+      ]
+    );
+  }
+
+  #[test]
+  fn java_home_parsing() {
+    let settings = "Property settings:
+    file.encoding = UTF-8
+    java.class.path = /opt/app
+    java.home = /usr/lib/jvm/java-17-openjdk-amd64
+    java.vendor = Ubuntu
+
+openjdk version \"17.0.12\" 2024-07-16
+";
+    assert_eq!(
+      parse_java_home(settings),
+      Some(PathBuf::from("/usr/lib/jvm/java-17-openjdk-amd64"))
+    );
+    assert_eq!(parse_java_home("openjdk version \"17.0.12\""), None);
+  }
 }
@@ -0,0 +1,273 @@
+//! Machine-readable progress of a campaign (see `Progress`), for dashboards and schedulers,
+//! independent of the human-readable logs.
+use std::{
+  fmt,
+  io::Write,
+  sync::Mutex,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::common::RunHealth;
+
+/// Writes a JSON record per line (NDJSON) for every step of a campaign: start and end of the
+/// campaign and of each experiment, and every finished sample. Each record holds the time
+/// (`timestamp_ms`, since the Unix epoch), the `event` and the `percent` of the samples of the
+/// campaign completed so far. Experiments run sequentially, so sample records refer to the
+/// experiment started last.
+pub struct Progress {
+  writer: Mutex<Box<dyn Write + Send>>,
+  state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+  experiments: usize,
+  samples_per_experiment: usize,
+  finished_experiments: usize,
+  /// Samples finished in the running experiment.
+  finished_samples: usize,
+  experiment: String,
+  /// A write failed; progress is not reported anymore.
+  failed: bool,
+}
+
+impl State {
+  fn percent(&self) -> f64 {
+    let total = self.experiments * self.samples_per_experiment;
+    if total == 0 {
+      return 100.0;
+    }
+    let finished = self.finished_experiments * self.samples_per_experiment + self.finished_samples;
+    (finished.min(total) as f64 / total as f64 * 1000.0).round() / 10.0
+  }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+  CampaignStarted {
+    experiments: usize,
+    samples_per_experiment: usize,
+  },
+  ExperimentStarted {
+    experiment: &'a str,
+  },
+  SampleFinished {
+    experiment: &'a str,
+    /// Index of the sample; `None` for the deep-trace run.
+    sample: Option<u32>,
+    succeeded: bool,
+    partial: bool,
+  },
+  ExperimentFinished {
+    experiment: &'a str,
+  },
+  ExperimentFailed {
+    experiment: &'a str,
+    error: String,
+  },
+  CampaignFinished {
+    failed_samples: usize,
+    timed_out_hooks: usize,
+    partial_samples: usize,
+    error: Option<String>,
+  },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+  timestamp_ms: u128,
+  percent: f64,
+  #[serde(flatten)]
+  event: Event<'a>,
+}
+
+impl fmt::Debug for Progress {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Progress").finish_non_exhaustive()
+  }
+}
+
+impl Progress {
+  /// Reports progress on `writer`; every experiment runs `samples_per_experiment` samples
+  /// (including the deep-trace run).
+  pub fn new(writer: Box<dyn Write + Send>, samples_per_experiment: usize) -> Self {
+    Progress {
+      writer: Mutex::new(writer),
+      state: Mutex::new(State {
+        samples_per_experiment,
+        ..State::default()
+      }),
+    }
+  }
+
+  pub fn campaign_started(&self, experiments: usize) {
+    let mut state = self.state.lock().unwrap();
+    state.experiments = experiments;
+    let samples_per_experiment = state.samples_per_experiment;
+    self.write(
+      &mut state,
+      Event::CampaignStarted {
+        experiments,
+        samples_per_experiment,
+      },
+    );
+  }
+
+  pub fn experiment_started(&self, experiment: &str) {
+    let mut state = self.state.lock().unwrap();
+    state.experiment = experiment.to_string();
+    state.finished_samples = 0;
+    self.write(&mut state, Event::ExperimentStarted { experiment });
+  }
+
+  pub fn sample_finished(&self, sample: Option<u32>, succeeded: bool, partial: bool) {
+    let mut state = self.state.lock().unwrap();
+    state.finished_samples += 1;
+    let experiment = state.experiment.clone();
+    self.write(
+      &mut state,
+      Event::SampleFinished {
+        experiment: &experiment,
+        sample,
+        succeeded,
+        partial,
+      },
+    );
+  }
+
+  /// Reports the end of an experiment, with the error which stopped it if any. Samples it did not
+  /// run (e.g. reused from the baseline cache) count as completed.
+  pub fn experiment_finished(&self, experiment: &str, error: Option<String>) {
+    let mut state = self.state.lock().unwrap();
+    state.finished_experiments += 1;
+    state.finished_samples = 0;
+    let event = match error {
+      Some(error) => Event::ExperimentFailed { experiment, error },
+      None => Event::ExperimentFinished { experiment },
+    };
+    self.write(&mut state, event);
+  }
+
+  pub fn campaign_finished(&self, health: &RunHealth, error: Option<String>) {
+    let mut state = self.state.lock().unwrap();
+    self.write(
+      &mut state,
+      Event::CampaignFinished {
+        failed_samples: health.failed_samples,
+        timed_out_hooks: health.timed_out_hooks,
+        partial_samples: health.partial_samples,
+        error,
+      },
+    );
+  }
+
+  // progress must never stop a campaign: a failed write is reported once and progress is then
+  // dropped
+  fn write(&self, state: &mut State, event: Event) {
+    if state.failed {
+      return;
+    }
+    let record = Record {
+      timestamp_ms: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis(),
+      percent: state.percent(),
+      event,
+    };
+    let mut writer = self.writer.lock().unwrap();
+    let result = serde_json::to_writer(&mut *writer, &record)
+      .map_err(std::io::Error::from)
+      .and_then(|_| writeln!(writer))
+      .and_then(|_| writer.flush());
+    if let Err(e) = result {
+      warn!(
+        "cannot write progress: {}; progress is not reported anymore",
+        e
+      );
+      state.failed = true;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+  };
+
+  use serde_json::Value;
+
+  use super::Progress;
+  use crate::common::RunHealth;
+
+  #[derive(Clone, Default)]
+  struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn progress_records() {
+    let buffer = Buffer::default();
+    let progress = Progress::new(Box::new(buffer.clone()), 2);
+    progress.campaign_started(2);
+    progress.experiment_started("ls/no-gramine-sgx/ls-1");
+    progress.sample_finished(Some(1), true, false);
+    progress.sample_finished(Some(2), false, true);
+    progress.experiment_finished("ls/no-gramine-sgx/ls-1", None);
+    progress.experiment_started("ls/no-gramine-sgx/ls-2");
+    progress.experiment_finished("ls/no-gramine-sgx/ls-2", Some(String::from("setup failed")));
+    progress.campaign_finished(
+      &RunHealth {
+        failed_samples: 1,
+        ..RunHealth::default()
+      },
+      None,
+    );
+
+    let content = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let records: Vec<Value> = content
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect();
+    let events: Vec<&str> = records
+      .iter()
+      .map(|record| record["event"].as_str().unwrap())
+      .collect();
+    assert_eq!(
+      events,
+      vec![
+        "campaign_started",
+        "experiment_started",
+        "sample_finished",
+        "sample_finished",
+        "experiment_finished",
+        "experiment_started",
+        "experiment_failed",
+        "campaign_finished",
+      ]
+    );
+    assert_eq!(records[0]["samples_per_experiment"], 2);
+    assert_eq!(records[2]["experiment"], "ls/no-gramine-sgx/ls-1");
+    assert_eq!(records[2]["sample"], 1);
+    assert_eq!(records[2]["percent"], 25.0);
+    assert_eq!(records[3]["partial"], true);
+    assert_eq!(records[6]["error"], "setup failed");
+    assert_eq!(records[7]["percent"], 100.0);
+    assert_eq!(records[7]["failed_samples"], 1);
+    assert!(records[0]["timestamp_ms"].as_u64().unwrap() > 0);
+  }
+}
@@ -0,0 +1,232 @@
+//! Key provisioning of protected files (see `ProvisioningServer`): enclaves fetch the key of their
+//! encrypted files from a secret provisioning server after remote attestation. The eBPF program
+//! times the provisioning session of the workload, and the benchmark watches the encrypted
+//! directory for its first access (see `watch_first_access`).
+use std::{
+  ffi::CString,
+  fs::File,
+  io::{self, Read},
+  net::{IpAddr, SocketAddr, ToSocketAddrs},
+  os::{
+    fd::{AsRawFd, FromRawFd},
+    unix::ffi::OsStrExt,
+  },
+  path::Path,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+use tracing::warn;
+
+use crate::stats::monotonic_ns;
+
+/// A connection of an enclave to the secret provisioning server: remote attestation over RA-TLS
+/// and transfer of the key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvisioningSession {
+  /// Monotonic time the enclave connected at.
+  pub connected_ns: u64,
+  /// Monotonic time both ends closed the connection at.
+  pub closed_ns: u64,
+  /// Bytes sent by the enclave (its RA-TLS certificate with the quote).
+  pub bytes_sent: u64,
+  /// Bytes sent by the server (its certificate and the key).
+  pub bytes_received: u64,
+}
+
+/// The secret provisioning server of an experiment. The eBPF program times the sessions of the
+/// enclaves with it (see `TracedSession`), so that they keep the name of the server for the
+/// hostname verification of TLS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvisioningServer {
+  /// Addresses the server name resolves to, IPv4 ones mapped into IPv6 as the kernel traces them.
+  pub addresses: Vec<[u8; 16]>,
+  pub port: u16,
+}
+
+impl ProvisioningServer {
+  /// Resolves `server` (`host:port`).
+  pub fn resolve(server: &str) -> Result<Self, io::Error> {
+    let resolved: Vec<SocketAddr> = server.to_socket_addrs()?.collect();
+    let port = resolved
+      .first()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?
+      .port();
+    let mut addresses: Vec<[u8; 16]> = resolved
+      .iter()
+      .map(|address| match address.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+      })
+      .collect();
+    addresses.sort();
+    addresses.dedup();
+    Ok(Self { addresses, port })
+  }
+}
+
+/// Mirrors `struct provisioning_session` of the eBPF program.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracedSession {
+  pub sk: u64,
+  pub connected_ns: u64,
+  pub closed_ns: u64,
+  pub bytes_sent: u64,
+  pub bytes_received: u64,
+}
+
+impl TracedSession {
+  /// The session, once it is closed.
+  pub fn session(&self) -> Option<ProvisioningSession> {
+    (self.connected_ns != 0 && self.closed_ns != 0).then_some(ProvisioningSession {
+      connected_ns: self.connected_ns,
+      closed_ns: self.closed_ns,
+      bytes_sent: self.bytes_sent,
+      bytes_received: self.bytes_received,
+    })
+  }
+}
+
+/// Returns the monotonic time of the first file opened or created in `directory` (not in its
+/// subdirectories), waiting until `done` is set. `None` if nothing was accessed or `directory`
+/// cannot be watched.
+pub fn watch_first_access(directory: &Path, done: &AtomicBool) -> Option<u64> {
+  let path = CString::new(directory.as_os_str().as_bytes()).ok()?;
+  // Safety: inotify_init1 returns a new descriptor, owned by the File
+  let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+  if fd < 0 {
+    warn!(
+      "cannot watch {:?}: {}",
+      directory,
+      io::Error::last_os_error()
+    );
+    return None;
+  }
+  let mut inotify = unsafe { File::from_raw_fd(fd) };
+  let watch = unsafe {
+    libc::inotify_add_watch(
+      inotify.as_raw_fd(),
+      path.as_ptr(),
+      libc::IN_OPEN | libc::IN_CREATE,
+    )
+  };
+  if watch < 0 {
+    warn!(
+      "cannot watch {:?}: {}",
+      directory,
+      io::Error::last_os_error()
+    );
+    return None;
+  }
+  let mut buffer = [0u8; 4096];
+  loop {
+    // the flag is read first, so that the events before the workload exited are read
+    let finished = done.load(Ordering::Relaxed);
+    let mut poll = libc::pollfd {
+      fd: inotify.as_raw_fd(),
+      events: libc::POLLIN,
+      revents: 0,
+    };
+    // a short timeout bounds the wait after the workload exited; events wake the poll at once
+    if unsafe { libc::poll(&mut poll, 1, 50) } > 0 {
+      let now = monotonic_ns();
+      let Ok(read) = inotify.read(&mut buffer) else {
+        continue;
+      };
+      if first_file_event(&buffer[..read]) {
+        return Some(now);
+      }
+    }
+    if finished {
+      return None;
+    }
+  }
+}
+
+// whether `events` holds an event of a file in the watched directory, rather than of the
+// directory itself (which has no name)
+fn first_file_event(mut events: &[u8]) -> bool {
+  const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+  while events.len() >= HEADER {
+    // Safety: the kernel writes whole events, each a header followed by its name
+    let event = unsafe { std::ptr::read_unaligned(events.as_ptr() as *const libc::inotify_event) };
+    if event.len > 0 {
+      return true;
+    }
+    events = &events[(HEADER + event.len as usize).min(events.len())..];
+  }
+  false
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    fs,
+    net::{Ipv4Addr, Ipv6Addr},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+  };
+
+  use tempfile::TempDir;
+
+  use super::{watch_first_access, ProvisioningServer, ProvisioningSession, TracedSession};
+
+  #[test]
+  fn provisioning_server() {
+    let server = ProvisioningServer::resolve("127.0.0.1:4433").unwrap();
+    assert_eq!(server.port, 4433);
+    assert_eq!(
+      server.addresses,
+      vec![Ipv4Addr::LOCALHOST.to_ipv6_mapped().octets()]
+    );
+    let server = ProvisioningServer::resolve("[::1]:4433").unwrap();
+    assert_eq!(server.addresses, vec![Ipv6Addr::LOCALHOST.octets()]);
+    assert!(ProvisioningServer::resolve("localhost").is_err());
+  }
+
+  #[test]
+  fn traced_session() {
+    let mut traced = TracedSession {
+      sk: 1,
+      connected_ns: 10,
+      ..Default::default()
+    };
+    // the enclave is still talking to the server
+    assert_eq!(traced.session(), None);
+    traced.closed_ns = 25;
+    traced.bytes_sent = 5;
+    traced.bytes_received = 16;
+    assert_eq!(
+      traced.session(),
+      Some(ProvisioningSession {
+        connected_ns: 10,
+        closed_ns: 25,
+        bytes_sent: 5,
+        bytes_received: 16,
+      })
+    );
+    assert_eq!(TracedSession::default().session(), None);
+  }
+
+  #[test]
+  fn first_access() {
+    let directory = TempDir::new().unwrap();
+    let done = AtomicBool::new(false);
+    let accessed = thread::scope(|s| {
+      let watch = s.spawn(|| watch_first_access(directory.path(), &done));
+      thread::sleep(Duration::from_millis(100));
+      fs::write(directory.path().join("data"), "protected").unwrap();
+      done.store(true, Ordering::Relaxed);
+      watch.join().unwrap()
+    });
+    assert!(accessed.is_some());
+
+    let done = AtomicBool::new(true);
+    assert_eq!(watch_first_access(directory.path(), &done), None);
+    assert_eq!(
+      watch_first_access(&directory.path().join("missing"), &done),
+      None
+    );
+  }
+}
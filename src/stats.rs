@@ -1,10 +1,59 @@
-use std::fs::File;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
+use std::time::{Duration, SystemTime};
+
+use crate::constants::ENERGY_GAP_FACTOR;
 
 pub trait ToCsv {
   fn to_csv_rows(&self) -> Vec<String>;
 }
 
+/// Unit of a reported metric, written in the `unit` column of every `metric,unit,value` output.
+///
+/// Values are always written in the unit the kernel or the tool reports them; converting to a
+/// common scale is done once, at aggregation time, keyed on these symbols.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+  Count,
+  Percent,
+  Bytes,
+  Kilobytes,
+  Nanoseconds,
+  Nanojoules,
+  Microjoules,
+  Joules,
+  Watts,
+  PerSecond,
+  BytesPerSecond,
+  Grams,
+}
+
+impl Unit {
+  pub fn symbol(&self) -> &'static str {
+    match self {
+      Unit::Count => "#",
+      Unit::Percent => "%",
+      Unit::Bytes => "bytes",
+      Unit::Kilobytes => "kB",
+      Unit::Nanoseconds => "ns",
+      Unit::Nanojoules => "nJ",
+      Unit::Microjoules => "uJ",
+      Unit::Joules => "J",
+      Unit::Watts => "W",
+      Unit::PerSecond => "#/s",
+      Unit::BytesPerSecond => "bytes/s",
+      Unit::Grams => "g",
+    }
+  }
+}
+
+impl std::fmt::Display for Unit {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.symbol())
+  }
+}
+
 /// Partitions are loaded from `/proc/partitions`.
 #[derive(Clone)]
 pub struct Partition {
@@ -39,6 +88,7 @@ impl Partition {
   }
 
   /// Creates a Partition from a line in `/proc/partitions`
+  #[allow(clippy::should_implement_trait)]
   pub fn from_str(value: &str) -> Self {
     let parts = value.split_whitespace().collect::<Vec<&str>>();
     assert_eq!(parts.len(), 4);
@@ -53,47 +103,296 @@ impl Partition {
 }
 
 /// Disk statistics collected from the eBPF program.
+///
+/// Requests and bytes are split by direction; flushes are counted on their own, also when the
+/// flush is issued together with a write.
 #[derive(Clone)]
 pub struct DiskStats {
   pub name: String,
   pub bytes: u64,
   pub perc_random: u32,
   pub perc_seq: u32,
+  pub read_requests: u64,
+  pub read_bytes: u64,
+  pub write_requests: u64,
+  pub write_bytes: u64,
+  pub flushes: u64,
 }
 
 impl ToCsv for DiskStats {
   fn to_csv_rows(&self) -> Vec<String> {
     vec![
-      // Here we return three rows for this disk:
-      format!("disk_write_seq,%,{},{}", self.perc_seq, self.name),
-      format!("disk_write_rand,%,{},{}", self.perc_random, self.name),
-      format!("disk_tot_written_bytes,%,{},{}", self.bytes, self.name),
+      format!(
+        "disk_write_seq,{},{},{}",
+        Unit::Percent,
+        self.perc_seq,
+        self.name
+      ),
+      format!(
+        "disk_write_rand,{},{},{}",
+        Unit::Percent,
+        self.perc_random,
+        self.name
+      ),
+      format!(
+        "disk_tot_written_bytes,{},{},{}",
+        Unit::Bytes,
+        self.bytes,
+        self.name
+      ),
+      format!(
+        "disk_read_requests,{},{},{}",
+        Unit::Count,
+        self.read_requests,
+        self.name
+      ),
+      format!(
+        "disk_read_bytes,{},{},{}",
+        Unit::Bytes,
+        self.read_bytes,
+        self.name
+      ),
+      format!(
+        "disk_read_avg_request,{},{},{}",
+        Unit::Bytes,
+        self.read_bytes.checked_div(self.read_requests).unwrap_or(0),
+        self.name
+      ),
+      format!(
+        "disk_write_requests,{},{},{}",
+        Unit::Count,
+        self.write_requests,
+        self.name
+      ),
+      format!(
+        "disk_write_bytes,{},{},{}",
+        Unit::Bytes,
+        self.write_bytes,
+        self.name
+      ),
+      format!(
+        "disk_write_avg_request,{},{},{}",
+        Unit::Bytes,
+        self
+          .write_bytes
+          .checked_div(self.write_requests)
+          .unwrap_or(0),
+        self.name
+      ),
+      format!(
+        "disk_flushes,{},{},{}",
+        Unit::Count,
+        self.flushes,
+        self.name
+      ),
     ]
   }
 }
 
+/// I/O events of the deep trace; they carry the request size in bytes.
+pub const EVENT_SYS_READ: u32 = 0;
+pub const EVENT_SYS_WRITE: u32 = 1;
+pub const EVENT_READ_DISK: u32 = 6;
+pub const EVENT_WRITE_DISK: u32 = 7;
+
+/// Lifecycle markers added to the deep trace from user space (see `src/bpf/tracer.h`).
+pub const EVENT_PROCESS_START: u32 = 8;
+pub const EVENT_FIRST_OUTPUT: u32 = 9;
+pub const EVENT_PROCESS_EXIT: u32 = 10;
+pub const EVENT_AESM_CONNECT: u32 = 11;
+pub const EVENT_AESM_CLOSE: u32 = 12;
+pub const EVENT_MMAP: u32 = 13;
+pub const EVENT_MUNMAP: u32 = 14;
+pub const EVENT_BRK: u32 = 15;
+pub const EVENT_BRK_SHRINK: u32 = 16;
+pub const EVENT_READY: u32 = 17;
+/// Calls of the uprobes of a task, or-ed with the index of the uprobe.
+pub const EVENT_UPROBE_ENTER: u32 = 0x100;
+pub const EVENT_UPROBE_EXIT: u32 = 0x200;
+/// Hits of the USDT probes of a task, or-ed with the index of the probe.
+pub const EVENT_USDT: u32 = 0x300;
+const PROBE_INDEX_MASK: u32 = 0xff;
+/// Attach cookies of the uprobes of a task are below, those timing the OCALL handlers of Gramine
+/// from it (see `src/bpf/tracer.h`).
+pub const MAX_UPROBES: u32 = 256;
+
 /// An event from the deep trace eBPF program.
 #[repr(C)]
 #[derive(Default, Debug, Clone)]
 pub struct DeepTraceEvent {
   pub ev_type: u32,
   pub timestamp: u64,
+  /// Size of the event in bytes; only meaningful for the events in `sized()`.
+  pub bytes: u64,
+}
+
+impl DeepTraceEvent {
+  /// Whether the event carries a size.
+  fn sized(&self) -> bool {
+    matches!(
+      self.ev_type,
+      EVENT_SYS_READ
+        | EVENT_SYS_WRITE
+        | EVENT_READ_DISK
+        | EVENT_WRITE_DISK
+        | EVENT_MMAP
+        | EVENT_MUNMAP
+        | EVENT_BRK
+        | EVENT_BRK_SHRINK
+    )
+  }
 }
 
 impl ToCsv for DeepTraceEvent {
   fn to_csv_rows(&self) -> Vec<String> {
+    let index = self.ev_type & PROBE_INDEX_MASK;
+    let probe_event;
     let event_str = match self.ev_type {
-      0 => "sys-read",
-      1 => "sys-write",
+      EVENT_SYS_READ => "sys-read",
+      EVENT_SYS_WRITE => "sys-write",
       2 => "mm-page-alloc",
       3 => "mm-page-free",
       4 => "kmalloc",
       5 => "kfree",
-      6 => "disk-read",
-      7 => "disk-write",
+      EVENT_READ_DISK => "disk-read",
+      EVENT_WRITE_DISK => "disk-write",
+      EVENT_PROCESS_START => "process-start",
+      EVENT_FIRST_OUTPUT => "first-output",
+      EVENT_PROCESS_EXIT => "process-exit",
+      EVENT_AESM_CONNECT => "aesm-connect",
+      EVENT_AESM_CLOSE => "aesm-close",
+      EVENT_MMAP => "sys-mmap",
+      EVENT_MUNMAP => "sys-munmap",
+      EVENT_BRK => "sys-brk",
+      EVENT_BRK_SHRINK => "sys-brk-shrink",
+      EVENT_READY => "ready",
+      t if t & !PROBE_INDEX_MASK == EVENT_UPROBE_ENTER => {
+        probe_event = format!("uprobe-enter-{}", index);
+        &probe_event
+      }
+      t if t & !PROBE_INDEX_MASK == EVENT_UPROBE_EXIT => {
+        probe_event = format!("uprobe-exit-{}", index);
+        &probe_event
+      }
+      t if t & !PROBE_INDEX_MASK == EVENT_USDT => {
+        probe_event = format!("usdt-{}", index);
+        &probe_event
+      }
       _ => "unknown",
     };
-    vec![format!("{},{}", self.timestamp, event_str)]
+    let bytes = if self.sized() {
+      self.bytes.to_string()
+    } else {
+      String::new()
+    };
+    vec![format!("{},{},{}", self.timestamp, event_str, bytes)]
+  }
+}
+
+/// The time at which the target process was spawned, read from the clocks used by the collectors:
+/// the UNIX epoch clock for energy, pressure and memory samples and the monotonic clock
+/// (`bpf_ktime_get_ns`) for deep trace events.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockReference {
+  pub realtime_ns: u128,
+  pub monotonic_ns: u64,
+}
+
+impl ClockReference {
+  pub fn now() -> Self {
+    Self {
+      realtime_ns: SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos(),
+      monotonic_ns: monotonic_ns(),
+    }
+  }
+}
+
+/// Reads the monotonic clock, the same clock as `bpf_ktime_get_ns`.
+pub fn monotonic_ns() -> u64 {
+  let mut ts = libc::timespec {
+    tv_sec: 0,
+    tv_nsec: 0,
+  };
+  // Safety: ts is a valid timespec; CLOCK_MONOTONIC is always supported on Linux
+  unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+  ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// I/O accounting of the target process read from `/proc/<pid>/io`.
+///
+/// The file has a structure like this
+///
+/// rchar: 323934931
+/// wchar: 323929600
+/// syscr: 632687
+/// syscw: 632675
+/// read_bytes: 0
+/// write_bytes: 323932160
+/// cancelled_write_bytes: 0
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProcIo {
+  pub rchar: u64,
+  pub wchar: u64,
+  pub syscr: u64,
+  pub syscw: u64,
+  pub read_bytes: u64,
+  pub write_bytes: u64,
+  pub cancelled_write_bytes: u64,
+}
+
+impl ProcIo {
+  /// Loads I/O accounting for a process. Returns `None` if the process is gone.
+  pub fn load(pid: u32) -> Option<Self> {
+    fs::read_to_string(format!("/proc/{}/io", pid))
+      .ok()
+      .map(|content| Self::from_str(&content))
+  }
+
+  /// Creates a ProcIo from the content of `/proc/<pid>/io`.
+  #[allow(clippy::should_implement_trait)]
+  pub fn from_str(value: &str) -> Self {
+    let mut io = Self::default();
+    for line in value.lines() {
+      if let Some((key, val)) = line.split_once(':') {
+        let val = val.trim().parse::<u64>().unwrap_or(0);
+        match key {
+          "rchar" => io.rchar = val,
+          "wchar" => io.wchar = val,
+          "syscr" => io.syscr = val,
+          "syscw" => io.syscw = val,
+          "read_bytes" => io.read_bytes = val,
+          "write_bytes" => io.write_bytes = val,
+          "cancelled_write_bytes" => io.cancelled_write_bytes = val,
+          _ => {}
+        }
+      }
+    }
+    io
+  }
+}
+
+impl ToCsv for ProcIo {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![
+      format!("proc_rchar,{},{},procfs", Unit::Bytes, self.rchar),
+      format!("proc_wchar,{},{},procfs", Unit::Bytes, self.wchar),
+      format!("proc_syscr,{},{},procfs", Unit::Count, self.syscr),
+      format!("proc_syscw,{},{},procfs", Unit::Count, self.syscw),
+      format!("proc_read_bytes,{},{},procfs", Unit::Bytes, self.read_bytes),
+      format!(
+        "proc_write_bytes,{},{},procfs",
+        Unit::Bytes,
+        self.write_bytes
+      ),
+      format!(
+        "proc_cancelled_write_bytes,{},{},procfs",
+        Unit::Bytes,
+        self.cancelled_write_bytes
+      ),
+    ]
   }
 }
 
@@ -106,28 +405,207 @@ impl ToCsv for DeepTraceEvent {
 // # of async signals:  0
 #[derive(Default)]
 pub struct SGXStats {
-  pub eenter: u64,
-  pub eexit: u64,
-  pub aexit: u64,
-  pub sync_signals: u64,
-  pub async_signals: u64,
+  pub eenter: Option<u64>,
+  pub eexit: Option<u64>,
+  pub aexit: Option<u64>,
+  pub sync_signals: Option<u64>,
+  pub async_signals: Option<u64>,
   pub counters: LowLevelSgxCounters,
+  /// Whether the enclave build was traced; the counters of an untraced build are missing rather
+  /// than zero.
+  pub enclave_build_traced: bool,
+  pub ocalls: Vec<OcallStats>,
+  /// Statistics missing from stderr or that could not be parsed, with the line of the `stderr`
+  /// file of the sample; e.g. an enclave that crashed prints none.
+  pub parse_errors: Vec<String>,
 }
 
 impl ToCsv for SGXStats {
   fn to_csv_rows(&self) -> Vec<String> {
-    let mut rows = Vec::new();
-    rows.push(format!("sgx_enter,#,{},", self.eenter));
-    rows.push(format!("sgx_eexit,#,{},", self.eexit));
-    rows.push(format!("sgx_aexit,#,{},", self.aexit));
-    rows.push(format!("sgx_sync_signals,#,{},", self.sync_signals));
-    rows.push(format!("sgx_async_signals,#,{},", self.async_signals));
+    let statistics = [
+      ("sgx_enter", self.eenter),
+      ("sgx_eexit", self.eexit),
+      ("sgx_aexit", self.aexit),
+      ("sgx_sync_signals", self.sync_signals),
+      ("sgx_async_signals", self.async_signals),
+    ];
+    let mut rows: Vec<String> = statistics
+      .iter()
+      .filter_map(|(name, value)| value.map(|value| format!("{},{},{},", name, Unit::Count, value)))
+      .collect();
     // Append CSV rows from the low-level counters.
-    rows.extend(self.counters.to_csv_rows());
+    rows.extend(self.counters.to_csv_rows().into_iter().filter(|row| {
+      self.enclave_build_traced
+        || !row
+          .split_once(',')
+          .is_some_and(|(name, _)| ENCLAVE_BUILD_COUNTERS.contains(&name))
+    }));
     rows
   }
 }
 
+/// OCALLs of a given type, derived from the Gramine SGX profiler output and timed by the uprobes
+/// on the OCALL handlers of the untrusted loader.
+///
+/// In `ocall_inner` and `ocall_outer` mode the profiler records a sample for every OCALL, so the
+/// number of samples of an `ocall_*` symbol is the number of OCALLs of that type. The profiler
+/// does not record how long an OCALL takes: `time_ns` is the time spent in its handler on the
+/// host, `None` if the handler could not be traced.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcallStats {
+  pub name: String,
+  pub count: u64,
+  pub share: f64,
+  pub time_ns: Option<u64>,
+}
+
+impl OcallStats {
+  /// Parses the output of `perf report --stdio --sort dso,sym -F overhead,sample,dso,sym`: the
+  /// sample count is the first column which is not a percentage, the symbol the last one.
+  pub fn from_perf_report(report: &str) -> Vec<Self> {
+    let mut ocalls: Vec<Self> = Vec::new();
+    for line in report.lines() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let parts = line.split_whitespace().collect::<Vec<&str>>();
+      let (Some(count), Some(name)) =
+        (parts.iter().find(|part| !part.ends_with('%')), parts.last())
+      else {
+        continue;
+      };
+      let Ok(count) = count.parse::<u64>() else {
+        continue;
+      };
+      if !name.starts_with("ocall_") {
+        continue;
+      }
+      match ocalls.iter_mut().find(|o| o.name == *name) {
+        Some(ocall) => ocall.count += count,
+        None => ocalls.push(OcallStats {
+          name: name.to_string(),
+          count,
+          share: 0.0,
+          time_ns: None,
+        }),
+      }
+    }
+    Self::update_shares(&mut ocalls);
+    ocalls
+  }
+
+  /// Merges OCALLs of the same type and recomputes shares.
+  pub fn merge(mut ocalls: Vec<Self>, other: Vec<Self>) -> Vec<Self> {
+    for o in other {
+      match ocalls.iter_mut().find(|v| v.name == o.name) {
+        Some(ocall) => ocall.count += o.count,
+        None => ocalls.push(o),
+      }
+    }
+    Self::update_shares(&mut ocalls);
+    ocalls
+  }
+
+  /// Adds the time of the `timed` OCALLs (traced on their handlers) to the OCALLs counted by the
+  /// profiler. OCALLs missing from the profile (e.g. profiling is disabled) are counted by their
+  /// handler.
+  pub fn with_times(mut ocalls: Vec<Self>, timed: &[Self]) -> Vec<Self> {
+    for o in timed.iter().filter(|o| o.count > 0) {
+      match ocalls.iter_mut().find(|v| v.name == o.name) {
+        Some(ocall) => ocall.time_ns = o.time_ns,
+        None => ocalls.push(o.clone()),
+      }
+    }
+    Self::update_shares(&mut ocalls);
+    ocalls
+  }
+
+  fn update_shares(ocalls: &mut [Self]) {
+    let total: u64 = ocalls.iter().map(|o| o.count).sum();
+    for ocall in ocalls.iter_mut() {
+      ocall.share = if total > 0 {
+        ocall.count as f64 * 100.0 / total as f64
+      } else {
+        0.0
+      };
+    }
+    ocalls.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+  }
+}
+
+impl ToCsv for OcallStats {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!(
+      "{},{},{:.2},{}",
+      self.name,
+      self.count,
+      self.share,
+      self
+        .time_ns
+        .map_or_else(String::new, |time| time.to_string())
+    )]
+  }
+}
+
+/// Share of the run time a perf event was counted. With more events than hardware counters the
+/// kernel multiplexes them and perf scales each count up from the time it was counted, which is
+/// unreliable for short runs such as the ones of small enclaves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PerfScaling {
+  pub event: String,
+  /// Percentage of the run time the event was counted; 100 if it was not multiplexed.
+  pub counted_percent: f64,
+}
+
+impl PerfScaling {
+  /// Parses the output of `perf stat --field-separator ,`, whose lines are
+  /// `<counter>,<unit>,<event>,<runtime>,<counted (%)>,...`. Unsupported events have no
+  /// percentage and are skipped.
+  pub fn from_perf_output(perf_output: &[u8]) -> Vec<Self> {
+    perf_output
+      .lines()
+      .map_while(Result::ok)
+      .filter_map(|line| {
+        let parts = line.split(',').collect::<Vec<&str>>();
+        if parts.len() < 5 || line.starts_with('#') {
+          return None;
+        }
+        let counted_percent = parts[4].trim().parse::<f64>().ok()?;
+        let event = parts[2].split(':').next().unwrap_or_default();
+        Some(PerfScaling {
+          event: event.to_string(),
+          counted_percent,
+        })
+      })
+      .collect()
+  }
+
+  /// Factor perf multiplied the count by; `None` if the event was never counted.
+  pub fn factor(&self) -> Option<f64> {
+    (self.counted_percent > 0.0).then(|| 100.0 / self.counted_percent)
+  }
+
+  /// Returns true if the count of the event was extrapolated.
+  pub fn multiplexed(&self) -> bool {
+    self.counted_percent < 100.0
+  }
+}
+
+impl ToCsv for PerfScaling {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!(
+      "{},{:.2},{}",
+      self.event,
+      self.counted_percent,
+      self.factor().map_or(String::new(), |f| format!("{:.3}", f))
+    )]
+  }
+}
+
+/// Counters of `LowLevelSgxCounters` timing the enclave build.
+const ENCLAVE_BUILD_COUNTERS: [&str; 3] = ["sgx_eadd_pages", "sgx_eadd", "sgx_einit"];
+
 /// A low-level view of SGX counters.
 #[repr(C)]
 #[derive(Default)]
@@ -136,35 +614,847 @@ pub struct LowLevelSgxCounters {
   pub encl_wb: u64,
   pub vma_access: u64,
   pub vma_fault: u64,
+  /// Connections of the enclave loader to aesmd.
+  pub aesm_connections: u64,
+  /// Time spent with a connection to aesmd open.
+  pub aesm_wait_ns: u64,
+  /// Pages added to enclaves while they were built (EADD and EEXTEND).
+  pub eadd_pages: u64,
+  /// Time spent adding pages to enclaves.
+  pub eadd_ns: u64,
+  /// Time spent initializing enclaves (EINIT).
+  pub einit_ns: u64,
 }
 
 impl ToCsv for LowLevelSgxCounters {
   fn to_csv_rows(&self) -> Vec<String> {
     vec![
-      format!("sgx_encl_load_page,#,{},", self.encl_load_page),
-      format!("sgx_encl_wb,#,{},", self.encl_wb),
-      format!("sgx_vma_access,#,{},", self.vma_access),
-      format!("sgx_vma_fault,#,{},", self.vma_fault),
+      format!(
+        "sgx_encl_load_page,{},{},",
+        Unit::Count,
+        self.encl_load_page
+      ),
+      format!("sgx_encl_wb,{},{},", Unit::Count, self.encl_wb),
+      format!("sgx_vma_access,{},{},", Unit::Count, self.vma_access),
+      format!("sgx_vma_fault,{},{},", Unit::Count, self.vma_fault),
+      format!(
+        "sgx_aesm_connections,{},{},",
+        Unit::Count,
+        self.aesm_connections
+      ),
+      format!("sgx_aesm_wait,{},{},", Unit::Nanoseconds, self.aesm_wait_ns),
+      format!("sgx_eadd_pages,{},{},", Unit::Count, self.eadd_pages),
+      format!("sgx_eadd,{},{},", Unit::Nanoseconds, self.eadd_ns),
+      format!("sgx_einit,{},{},", Unit::Nanoseconds, self.einit_ns),
+    ]
+  }
+}
+
+/// VFS operations of the target process and of the processes it forked, mirroring
+/// `struct vfs_counters` of the eBPF program.
+#[repr(C)]
+#[derive(Default, Debug, PartialEq)]
+pub struct VfsCounters {
+  pub open: u64,
+  pub close: u64,
+  pub fsync: u64,
+  pub stat: u64,
+}
+
+impl ToCsv for VfsCounters {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![
+      format!("vfs_open,{},{},", Unit::Count, self.open),
+      format!("vfs_close,{},{},", Unit::Count, self.close),
+      format!("vfs_fsync,{},{},", Unit::Count, self.fsync),
+      format!("vfs_stat,{},{},", Unit::Count, self.stat),
+    ]
+  }
+}
+
+/// Latency of the `read` and `write` syscalls of the workload, from the log2 histograms of the
+/// eBPF program.
+///
+/// Percentiles are the upper bound of the bucket they fall in (capped at the maximum), so they
+/// overestimate the real value by less than a factor of two.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyscallStats {
+  /// `sys_read` or `sys_write`.
+  pub name: &'static str,
+  pub count: u64,
+  pub avg_ns: u64,
+  pub p50_ns: u64,
+  pub p95_ns: u64,
+  pub p99_ns: u64,
+  pub max_ns: u64,
+}
+
+impl SyscallStats {
+  /// Builds the statistics from a histogram where `slots[i]` counts the calls that lasted
+  /// `[2^i, 2^(i+1))` ns; the last slot is unbounded.
+  pub fn from_histogram(
+    name: &'static str,
+    count: u64,
+    total_ns: u64,
+    max_ns: u64,
+    slots: &[u64],
+  ) -> Self {
+    let percentile = |p: u64| {
+      // nearest-rank: the smallest bucket covering p% of the calls
+      let rank = (count * p).div_ceil(100).max(1);
+      let mut seen = 0;
+      for (i, calls) in slots.iter().enumerate() {
+        seen += calls;
+        if seen >= rank {
+          if i + 1 == slots.len() {
+            return max_ns;
+          }
+          return ((2u64 << i) - 1).min(max_ns);
+        }
+      }
+      max_ns
+    };
+
+    Self {
+      name,
+      count,
+      avg_ns: total_ns.checked_div(count).unwrap_or(0),
+      p50_ns: percentile(50),
+      p95_ns: percentile(95),
+      p99_ns: percentile(99),
+      max_ns,
+    }
+  }
+}
+
+impl ToCsv for SyscallStats {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![
+      format!("{},{},{},", self.name, Unit::Count, self.count),
+      format!("{},{},{},", self.name, Unit::Nanoseconds, self.avg_ns),
+      format!("{}_p50,{},{},", self.name, Unit::Nanoseconds, self.p50_ns),
+      format!("{}_p95,{},{},", self.name, Unit::Nanoseconds, self.p95_ns),
+      format!("{}_p99,{},{},", self.name, Unit::Nanoseconds, self.p99_ns),
+      format!("{}_max,{},{},", self.name, Unit::Nanoseconds, self.max_ns),
+    ]
+  }
+}
+
+/// Calls of a function probed through `extra_kprobes` (kernel) or the `uprobes` of a task
+/// (workload).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeStats {
+  /// `kprobe` or `uprobe`.
+  pub kind: &'static str,
+  pub symbol: String,
+  pub count: u64,
+  pub total_ns: u64,
+}
+
+impl ToCsv for ProbeStats {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![
+      format!(
+        "{}_{},{},{},",
+        self.kind,
+        self.symbol,
+        Unit::Count,
+        self.count
+      ),
+      format!(
+        "{}_{},{},{},",
+        self.kind,
+        self.symbol,
+        Unit::Nanoseconds,
+        self.total_ns.checked_div(self.count).unwrap_or(0)
+      ),
     ]
   }
 }
 
 /// A sample of energy consumption.
+///
+/// `power_w` is the average power since the previous sample of the same domain, computed with
+/// the actual elapsed time between the two readings.
 #[derive(Clone, Debug)]
 pub struct EnergySample {
   pub timestamp: u128,
+  /// Monotonic time of the reading (see `monotonic_ns`). Unlike `timestamp`, it is never adjusted,
+  /// so power is computed with it.
+  pub monotonic_ns: u64,
   pub energy_uj: u64,
+  /// The value the counter wraps around at, if known (see `EnergyCounter::range_uj`).
+  pub range_uj: Option<u64>,
+  pub power_w: Option<f64>,
+  /// The reading came late, after more than `ENERGY_GAP_FACTOR` sampling intervals (the monitor
+  /// stalled): `power_w` is averaged over the whole gap.
+  pub gap: bool,
+}
+
+impl EnergySample {
+  /// Creates the sample of a reading of the energy counter (wrapping around at `range_uj`) taken
+  /// after `previous`, with the average power since `previous` and whether the reading came late
+  /// for `sample_interval`.
+  pub fn after(
+    previous: Option<&EnergySample>,
+    timestamp: u128,
+    monotonic_ns: u64,
+    energy_uj: u64,
+    range_uj: Option<u64>,
+    sample_interval: Duration,
+  ) -> Self {
+    let gap = previous.is_some_and(|previous| {
+      monotonic_ns.saturating_sub(previous.monotonic_ns) as u128
+        > (sample_interval * ENERGY_GAP_FACTOR).as_nanos()
+    });
+    Self {
+      timestamp,
+      monotonic_ns,
+      energy_uj,
+      range_uj,
+      power_w: previous.and_then(|previous| Self::power_between(previous, monotonic_ns, energy_uj)),
+      gap,
+    }
+  }
+
+  /// Energy consumed between a previous sample and a new reading of its counter. A counter which
+  /// wrapped around in between is counted up to its range; returns `None` if the range is unknown.
+  pub fn energy_between(previous: &EnergySample, energy_uj: u64) -> Option<u64> {
+    energy_uj.checked_sub(previous.energy_uj).or_else(|| {
+      previous
+        .range_uj
+        .map(|range| range.saturating_sub(previous.energy_uj) + energy_uj)
+    })
+  }
+
+  /// Average power in watts between a previous sample and a new reading at `monotonic_ns`.
+  /// Returns `None` if no time elapsed or the energy counter wrapped around at an unknown range.
+  pub fn power_between(previous: &EnergySample, monotonic_ns: u64, energy_uj: u64) -> Option<f64> {
+    let elapsed_ns = monotonic_ns
+      .checked_sub(previous.monotonic_ns)
+      .filter(|v| *v > 0)?;
+    let energy_uj = Self::energy_between(previous, energy_uj)?;
+    // uJ / ns = 1e3 W
+    Some(energy_uj as f64 * 1e3 / elapsed_ns as f64)
+  }
 }
 
 impl ToCsv for EnergySample {
   fn to_csv_rows(&self) -> Vec<String> {
-    vec![format!("{},{}", self.timestamp, self.energy_uj)]
+    vec![format!(
+      "{},{},{},{},{}",
+      self.timestamp,
+      self.energy_uj,
+      self.power_w.map_or(String::new(), |v| format!("{:.6}", v)),
+      self.monotonic_ns,
+      u8::from(self.gap)
+    )]
+  }
+}
+
+/// A sample of Pressure Stall Information read from `/proc/pressure/<resource>`.
+///
+/// The file has a structure like this (the `full` line is missing for `cpu` on older kernels)
+///
+/// some avg10=0.00 avg60=0.00 avg300=0.00 total=11027863
+/// full avg10=0.00 avg60=0.00 avg300=0.00 total=8310131
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PressureSample {
+  pub timestamp: u128,
+  pub resource: String,
+  pub some_avg10: f64,
+  pub some_total_us: u64,
+  pub full_avg10: Option<f64>,
+  pub full_total_us: Option<u64>,
+}
+
+impl PressureSample {
+  /// Creates a PressureSample from the content of a `/proc/pressure/<resource>` file.
+  pub fn from_str(timestamp: u128, resource: &str, value: &str) -> Option<Self> {
+    let mut sample = Self {
+      timestamp,
+      resource: resource.to_string(),
+      ..Default::default()
+    };
+    let mut has_some = false;
+    for line in value.lines() {
+      let mut parts = line.split_whitespace();
+      let kind = parts.next();
+      let (mut avg10, mut total) = (None, None);
+      for field in parts {
+        match field.split_once('=') {
+          Some(("avg10", v)) => avg10 = v.parse::<f64>().ok(),
+          Some(("total", v)) => total = v.parse::<u64>().ok(),
+          _ => {}
+        }
+      }
+      match kind {
+        Some("some") => {
+          sample.some_avg10 = avg10?;
+          sample.some_total_us = total?;
+          has_some = true;
+        }
+        Some("full") => {
+          sample.full_avg10 = avg10;
+          sample.full_total_us = total;
+        }
+        _ => {}
+      }
+    }
+    has_some.then_some(sample)
+  }
+}
+
+impl ToCsv for PressureSample {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!(
+      "{},{},{},{},{},{}",
+      self.timestamp,
+      self.resource,
+      self.some_avg10,
+      self.some_total_us,
+      self.full_avg10.map_or(String::new(), |v| v.to_string()),
+      self.full_total_us.map_or(String::new(), |v| v.to_string()),
+    )]
+  }
+}
+
+/// A sample of the memory used by the workload process tree.
+///
+/// Values are summed over all processes from `/proc/<pid>/smaps_rollup`. PSS splits shared pages
+/// among the processes mapping them, while USS only counts private pages.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemorySample {
+  pub timestamp: u128,
+  pub processes: usize,
+  pub rss_kb: u64,
+  pub pss_kb: u64,
+  pub uss_kb: u64,
+  /// Anonymous memory backed by transparent huge pages.
+  pub anon_hugepages_kb: u64,
+}
+
+impl MemorySample {
+  /// Adds the values of a `/proc/<pid>/smaps_rollup` file to the sample.
+  ///
+  /// The file has a structure like this (values in kB)
+  ///
+  /// 55d0c5a5b000-7ffd3b5f5000 ---p 00000000 00:00 0                          [rollup]
+  /// Rss:                3872 kB
+  /// Pss:                 521 kB
+  /// Private_Clean:       108 kB
+  /// Private_Dirty:       260 kB
+  pub fn add_smaps_rollup(&mut self, value: &str) {
+    for line in value.lines() {
+      let Some((key, val)) = line.split_once(':') else {
+        continue;
+      };
+      let Some(val) = val
+        .split_whitespace()
+        .next()
+        .and_then(|v| v.parse::<u64>().ok())
+      else {
+        continue;
+      };
+      match key {
+        "Rss" => self.rss_kb += val,
+        "Pss" => self.pss_kb += val,
+        "Private_Clean" | "Private_Dirty" | "Private_Hugetlb" => self.uss_kb += val,
+        "AnonHugePages" => self.anon_hugepages_kb += val,
+        _ => {}
+      }
+    }
+    self.processes += 1;
+  }
+}
+
+impl ToCsv for MemorySample {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!(
+      "{},{},{},{},{},{}",
+      self.timestamp, self.processes, self.rss_kb, self.pss_kb, self.uss_kb, self.anon_hugepages_kb
+    )]
+  }
+}
+
+/// Huge page activity during a sample, from the counters of `/proc/vmstat`, which look like
+///
+/// thp_fault_alloc 1045
+/// thp_fault_fallback 12
+///
+/// The counters are system-wide and include the activity of other processes of the host.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HugePageStats {
+  /// Huge pages allocated on a page fault.
+  pub thp_fault_alloc: u64,
+  /// Page faults served with regular pages as no huge page was available.
+  pub thp_fault_fallback: u64,
+  /// Huge pages assembled from regular pages by `khugepaged`.
+  pub thp_collapse_alloc: u64,
+  /// Huge pages split into regular pages.
+  pub thp_split_page: u64,
+}
+
+impl HugePageStats {
+  /// Reads the counters of the host. Returns `None` if `/proc/vmstat` cannot be read.
+  pub fn load() -> Option<Self> {
+    fs::read_to_string("/proc/vmstat")
+      .ok()
+      .map(|content| Self::from_vmstat(&content))
+  }
+
+  pub fn from_vmstat(value: &str) -> Self {
+    let mut stats = Self::default();
+    for line in value.lines() {
+      let Some((key, val)) = line.split_once(' ') else {
+        continue;
+      };
+      let val = val.trim().parse::<u64>().unwrap_or(0);
+      match key {
+        "thp_fault_alloc" => stats.thp_fault_alloc = val,
+        "thp_fault_fallback" => stats.thp_fault_fallback = val,
+        "thp_collapse_alloc" => stats.thp_collapse_alloc = val,
+        "thp_split_page" => stats.thp_split_page = val,
+        _ => {}
+      }
+    }
+    stats
+  }
+
+  /// Returns the activity between `before` and `self`.
+  pub fn since(&self, before: &Self) -> Self {
+    Self {
+      thp_fault_alloc: self.thp_fault_alloc.saturating_sub(before.thp_fault_alloc),
+      thp_fault_fallback: self
+        .thp_fault_fallback
+        .saturating_sub(before.thp_fault_fallback),
+      thp_collapse_alloc: self
+        .thp_collapse_alloc
+        .saturating_sub(before.thp_collapse_alloc),
+      thp_split_page: self.thp_split_page.saturating_sub(before.thp_split_page),
+    }
+  }
+}
+
+impl ToCsv for HugePageStats {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![
+      format!("thp_fault_alloc,{},{}", Unit::Count, self.thp_fault_alloc),
+      format!(
+        "thp_fault_fallback,{},{}",
+        Unit::Count,
+        self.thp_fault_fallback
+      ),
+      format!(
+        "thp_collapse_alloc,{},{}",
+        Unit::Count,
+        self.thp_collapse_alloc
+      ),
+      format!("thp_split_page,{},{}", Unit::Count, self.thp_split_page),
+    ]
+  }
+}
+
+/// Cumulative times (in clock ticks) of a CPU read from `/proc/stat`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CpuTimes {
+  pub cpu: String,
+  /// user and nice
+  pub user: u64,
+  /// system, irq and softirq
+  pub system: u64,
+  pub idle: u64,
+  pub iowait: u64,
+  /// all the above plus steal (guest time is already included in user)
+  pub total: u64,
+}
+
+impl CpuTimes {
+  /// Reads the per-CPU lines of `/proc/stat`, which look like
+  ///
+  /// cpu0 4705 356 584 3699 23 23 0 0 0 0
+  ///
+  /// (user nice system idle iowait irq softirq steal guest guest_nice). The line with the
+  /// aggregated values of all CPUs (`cpu`) is skipped.
+  pub fn from_proc_stat(value: &str) -> Vec<Self> {
+    value
+      .lines()
+      .filter_map(|line| {
+        let mut parts = line.split_whitespace();
+        let cpu = parts.next().filter(|c| {
+          c.strip_prefix("cpu")
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })?;
+        let values = parts
+          .take(8)
+          .map(|v| v.parse::<u64>().ok())
+          .collect::<Option<Vec<u64>>>()?;
+        let [user, nice, system, idle, iowait, irq, softirq, rest @ ..] = values.as_slice() else {
+          return None;
+        };
+        let steal = rest.first().copied().unwrap_or_default();
+        Some(Self {
+          cpu: cpu.to_string(),
+          user: user + nice,
+          system: system + irq + softirq,
+          idle: *idle,
+          iowait: *iowait,
+          total: user + nice + system + idle + iowait + irq + softirq + steal,
+        })
+      })
+      .collect()
+  }
+}
+
+/// Utilization of a CPU between two readings of `/proc/stat`, in percent of the elapsed ticks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CpuSample {
+  pub timestamp: u128,
+  pub cpu: String,
+  pub user: f64,
+  pub system: f64,
+  pub iowait: f64,
+  /// everything except idle and iowait time
+  pub busy: f64,
+}
+
+impl CpuSample {
+  /// Returns None if no tick elapsed between the readings.
+  pub fn between(timestamp: u128, previous: &CpuTimes, current: &CpuTimes) -> Option<Self> {
+    let total = current
+      .total
+      .checked_sub(previous.total)
+      .filter(|&t| t > 0)? as f64;
+    let share =
+      |current: u64, previous: u64| current.saturating_sub(previous) as f64 * 100.0 / total;
+    let idle = share(current.idle, previous.idle);
+    let iowait = share(current.iowait, previous.iowait);
+    Some(Self {
+      timestamp,
+      cpu: current.cpu.clone(),
+      user: share(current.user, previous.user),
+      system: share(current.system, previous.system),
+      iowait,
+      busy: (100.0 - idle - iowait).max(0.0),
+    })
+  }
+}
+
+impl ToCsv for CpuSample {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!(
+      "{},{},{:.2},{:.2},{:.2},{:.2}",
+      self.timestamp, self.cpu, self.user, self.system, self.iowait, self.busy
+    )]
+  }
+}
+
+/// Memory traffic counted in a domain between two readings of a counter of the memory bandwidth
+/// monitor. Domains are `l3-<N>` (resctrl, traffic of the workload only) or `package-<N>` (memory
+/// controllers of a socket); counters are `total` and `local` (the share of `total` to the memory
+/// of the domain) for resctrl, `read` and `write` for the memory controllers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BandwidthSample {
+  pub timestamp: u128,
+  pub domain: String,
+  pub counter: String,
+  pub bytes: u64,
+}
+
+impl ToCsv for BandwidthSample {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!(
+      "{},{},{},{}",
+      self.timestamp, self.domain, self.counter, self.bytes
+    )]
+  }
+}
+
+/// L3 occupancy of the tasks of the cache allocation group of the workload in a cache domain
+/// (`l3-<N>`), as reported by the cache monitoring of Intel RDT.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CacheOccupancySample {
+  pub timestamp: u128,
+  pub domain: String,
+  pub bytes: u64,
+}
+
+impl ToCsv for CacheOccupancySample {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!("{},{},{}", self.timestamp, self.domain, self.bytes)]
+  }
+}
+
+/// Highest L3 occupancy of the workload over all the cache domains in `samples`. None without
+/// samples.
+pub fn peak_llc_occupancy(samples: &[CacheOccupancySample]) -> Option<u64> {
+  let mut by_timestamp: BTreeMap<u128, u64> = BTreeMap::new();
+  for sample in samples {
+    *by_timestamp.entry(sample.timestamp).or_default() += sample.bytes;
+  }
+  by_timestamp.into_values().max()
+}
+
+/// Memory traffic of the workload in `samples`: the `total` traffic of its resctrl group, or the
+/// traffic of the memory controllers of `packages` (every package if empty). None without samples.
+pub fn memory_traffic(samples: &[BandwidthSample], packages: &BTreeSet<u32>) -> Option<u64> {
+  if samples.is_empty() {
+    return None;
+  }
+  let attributed = |sample: &&BandwidthSample| match sample.domain.strip_prefix("package-") {
+    Some(package) => {
+      packages.is_empty()
+        || package
+          .parse()
+          .is_ok_and(|package| packages.contains(&package))
+    }
+    None => true,
+  };
+  Some(
+    samples
+      .iter()
+      .filter(|sample| matches!(sample.counter.as_str(), "total" | "read" | "write"))
+      .filter(attributed)
+      .map(|sample| sample.bytes)
+      .sum(),
+  )
+}
+
+/// Energy-efficiency metrics derived from a single sample.
+///
+/// Only built when both RAPL energy and the `instructions` perf counter are available.
+#[derive(Clone, Debug, Default)]
+pub struct EfficiencyStats {
+  pub energy_uj: u64,
+  pub instructions: u64,
+  pub bytes_written: u64,
+}
+
+impl EfficiencyStats {
+  /// Energy spent per retired instruction in nanojoules.
+  pub fn nj_per_instruction(&self) -> Option<f64> {
+    if self.instructions == 0 {
+      return None;
+    }
+    Some(self.energy_uj as f64 * 1e3 / self.instructions as f64)
+  }
+
+  /// Energy spent per GB written to disk in joules.
+  pub fn j_per_gb_written(&self) -> Option<f64> {
+    if self.bytes_written == 0 {
+      return None;
+    }
+    Some((self.energy_uj as f64 / 1e6) / (self.bytes_written as f64 / 1e9))
+  }
+}
+
+impl ToCsv for EfficiencyStats {
+  fn to_csv_rows(&self) -> Vec<String> {
+    let mut rows = vec![
+      format!("energy,{},{}", Unit::Microjoules, self.energy_uj),
+      format!("instructions,{},{}", Unit::Count, self.instructions),
+    ];
+    if let Some(v) = self.nj_per_instruction() {
+      rows.push(format!(
+        "energy_per_instruction,{},{:.6}",
+        Unit::Nanojoules,
+        v
+      ));
+    }
+    if let Some(v) = self.j_per_gb_written() {
+      rows.push(format!("energy_per_gb_written,{},{:.6}", Unit::Joules, v));
+    }
+    rows
+  }
+}
+
+/// Rates over a fixed-duration sample (see `sample_duration`), whose runtime is set by the
+/// benchmark rather than by the workload.
+#[derive(Clone, Debug, Default)]
+pub struct RateStats {
+  /// Time from the spawn to the exit of the workload; 0 if unknown.
+  pub duration_ns: u64,
+  pub energy_uj: Option<u64>,
+  pub instructions: Option<u64>,
+  pub read_bytes: u64,
+  pub write_bytes: u64,
+  /// Calls of the traced syscalls by name.
+  pub syscalls: Vec<(&'static str, u64)>,
+}
+
+impl RateStats {
+  /// Returns `value` per second of the sample.
+  pub fn per_second(&self, value: u64) -> Option<f64> {
+    if self.duration_ns == 0 {
+      return None;
+    }
+    Some(value as f64 * 1e9 / self.duration_ns as f64)
+  }
+}
+
+impl ToCsv for RateStats {
+  fn to_csv_rows(&self) -> Vec<String> {
+    let mut rows = vec![format!(
+      "duration,{},{}",
+      Unit::Nanoseconds,
+      self.duration_ns
+    )];
+    if self.duration_ns == 0 {
+      return rows;
+    }
+    if let Some(v) = self.energy_uj.and_then(|e| self.per_second(e)) {
+      rows.push(format!("power,{},{:.6}", Unit::Watts, v / 1e6));
+    }
+    if let Some(v) = self.instructions.and_then(|i| self.per_second(i)) {
+      rows.push(format!(
+        "instructions_per_second,{},{:.3}",
+        Unit::PerSecond,
+        v
+      ));
+    }
+    for (name, value) in [
+      ("disk_read_throughput", self.read_bytes),
+      ("disk_write_throughput", self.write_bytes),
+    ] {
+      if let Some(v) = self.per_second(value) {
+        rows.push(format!("{},{},{:.3}", name, Unit::BytesPerSecond, v));
+      }
+    }
+    for (name, count) in &self.syscalls {
+      if let Some(v) = self.per_second(*count) {
+        rows.push(format!("{}_per_second,{},{:.3}", name, Unit::PerSecond, v));
+      }
+    }
+    rows
+  }
+}
+
+/// An application-level metric extracted from the output of the workload (see `MetricPattern`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkloadMetric {
+  pub name: String,
+  pub unit: String,
+  pub value: f64,
+}
+
+impl ToCsv for WorkloadMetric {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!("{},{},{}", self.name, self.unit, self.value)]
+  }
+}
+
+/// The start of a phase of the workload ("load", "train", "eval"), written by the workload as a
+/// line of its phases file (see `PHASES_FILE_ENV`) and timestamped by the collector when read.
+/// A phase lasts until the next marker or the exit of the workload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseMarker {
+  /// Time of the marker on the clock of the energy, pressure, memory and CPU samples.
+  pub timestamp: u128,
+  /// Time of the marker on the clock of the deep trace events.
+  pub monotonic_ns: u64,
+  pub phase: String,
+}
+
+impl ToCsv for PhaseMarker {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!(
+      "{},{},{}",
+      self.timestamp, self.monotonic_ns, self.phase
+    )]
+  }
+}
+
+/// Energy of every CPU package (socket) during a sample. On multi-socket hosts the total energy
+/// includes sockets the workload never ran on, so the energy of the packages of its CPU affinity
+/// is reported apart.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SocketEnergy {
+  /// Energy by package id, in microjoules.
+  pub packages_uj: BTreeMap<u32, u64>,
+  /// Packages of the CPUs the workload was allowed to run on; empty if unknown.
+  pub workload_packages: BTreeSet<u32>,
+}
+
+impl SocketEnergy {
+  /// Energy of the packages the workload was allowed to run on.
+  pub fn workload_uj(&self) -> Option<u64> {
+    if self.workload_packages.is_empty() {
+      return None;
+    }
+    Some(
+      self
+        .workload_packages
+        .iter()
+        .filter_map(|package| self.packages_uj.get(package))
+        .sum(),
+    )
+  }
+}
+
+impl ToCsv for SocketEnergy {
+  // with a single package both figures are the total energy
+  fn to_csv_rows(&self) -> Vec<String> {
+    if self.packages_uj.len() < 2 {
+      return Vec::new();
+    }
+    let mut rows: Vec<String> = self
+      .packages_uj
+      .iter()
+      .map(|(package, energy_uj)| {
+        format!(
+          "energy_package_{},{},{}",
+          package,
+          Unit::Microjoules,
+          energy_uj
+        )
+      })
+      .collect();
+    if let Some(energy_uj) = self.workload_uj() {
+      rows.push(format!(
+        "workload_socket_energy,{},{}",
+        Unit::Microjoules,
+        energy_uj
+      ));
+    }
+    rows
+  }
+}
+
+/// CO2e estimate for the energy consumed by a sample.
+#[derive(Clone, Debug)]
+pub struct CarbonEstimate {
+  pub energy_uj: u64,
+  pub carbon_intensity_g_per_kwh: f64,
+}
+
+impl CarbonEstimate {
+  /// Estimated emissions in grams of CO2e (1 kWh = 3.6e12 uJ).
+  pub fn co2e_g(&self) -> f64 {
+    self.energy_uj as f64 / 3.6e12 * self.carbon_intensity_g_per_kwh
+  }
+}
+
+impl ToCsv for CarbonEstimate {
+  fn to_csv_rows(&self) -> Vec<String> {
+    vec![format!("co2e,{},{:.9}", Unit::Grams, self.co2e_g())]
   }
 }
 
 #[cfg(test)]
 mod test {
-  use crate::stats::Partition;
+  use std::time::Duration;
+
+  use std::collections::{BTreeMap, BTreeSet};
+
+  use crate::stats::{
+    memory_traffic, peak_llc_occupancy, BandwidthSample, CacheOccupancySample, CarbonEstimate,
+    CpuSample, CpuTimes, DeepTraceEvent, DiskStats, EfficiencyStats, EnergySample, HugePageStats,
+    LowLevelSgxCounters, MemorySample, OcallStats, Partition, PerfScaling, PressureSample, ProcIo,
+    RateStats, SGXStats, SocketEnergy, SyscallStats, ToCsv, Unit, VfsCounters, EVENT_AESM_CLOSE,
+    EVENT_AESM_CONNECT, EVENT_BRK, EVENT_BRK_SHRINK, EVENT_FIRST_OUTPUT, EVENT_MMAP, EVENT_MUNMAP,
+    EVENT_PROCESS_EXIT, EVENT_PROCESS_START, EVENT_READY, EVENT_READ_DISK, EVENT_SYS_READ,
+    EVENT_SYS_WRITE, EVENT_UPROBE_ENTER, EVENT_UPROBE_EXIT, EVENT_USDT, EVENT_WRITE_DISK,
+  };
 
   #[test]
   fn test_partition_from_string() {
@@ -174,4 +1464,562 @@ mod test {
     assert_eq!(partition.name, "nvme0n1");
     assert_eq!(partition.dev, 271581184);
   }
+
+  #[test]
+  fn test_perf_scaling() {
+    let output = b"# started on Mon Jan  1 00:00:00 2024\n\n\
+1000,,instructions:u,500,100.00,,\n\
+200,,LLC-load-misses:u,125,25.00,,\n\
+<not counted>,,dTLB-load-misses:u,0,0.00,,\n\
+<not supported>,,mem-stores:u,0,,,\n";
+    let scaling = PerfScaling::from_perf_output(output);
+
+    assert_eq!(scaling.len(), 3);
+    assert_eq!(scaling[0].event, "instructions");
+    assert!(!scaling[0].multiplexed());
+    assert_eq!(scaling[1].factor(), Some(4.0));
+    assert!(scaling[1].multiplexed());
+    assert_eq!(scaling[2].factor(), None);
+    assert_eq!(scaling[2].to_csv_rows(), vec!["dTLB-load-misses,0.00,"]);
+  }
+
+  #[test]
+  fn test_efficiency_stats() {
+    let stats = EfficiencyStats {
+      energy_uj: 2_000_000,
+      instructions: 1_000_000_000,
+      bytes_written: 500_000_000,
+    };
+
+    assert_eq!(stats.nj_per_instruction(), Some(2.0));
+    assert_eq!(stats.j_per_gb_written(), Some(4.0));
+
+    let stats = EfficiencyStats {
+      bytes_written: 0,
+      ..stats
+    };
+    assert!(stats.j_per_gb_written().is_none());
+    assert_eq!(stats.to_csv_rows().len(), 3);
+  }
+
+  #[test]
+  fn test_rate_stats() {
+    let stats = RateStats {
+      duration_ns: 2_000_000_000,
+      energy_uj: Some(30_000_000),
+      instructions: None,
+      read_bytes: 0,
+      write_bytes: 4_000_000,
+      syscalls: vec![("sys_read", 1000)],
+    };
+    assert_eq!(
+      stats.to_csv_rows(),
+      vec![
+        "duration,ns,2000000000",
+        "power,W,15.000000",
+        "disk_read_throughput,bytes/s,0.000",
+        "disk_write_throughput,bytes/s,2000000.000",
+        "sys_read_per_second,#/s,500.000",
+      ]
+    );
+
+    // without a runtime only the duration is known
+    let stats = RateStats {
+      duration_ns: 0,
+      ..stats
+    };
+    assert_eq!(stats.to_csv_rows(), vec!["duration,ns,0"]);
+  }
+
+  #[test]
+  fn test_carbon_estimate() {
+    let estimate = CarbonEstimate {
+      energy_uj: 3_600_000_000_000,
+      carbon_intensity_g_per_kwh: 250.0,
+    };
+    assert_eq!(estimate.co2e_g(), 250.0);
+  }
+
+  #[test]
+  fn test_pressure_sample_from_string() {
+    let raw = "some avg10=1.50 avg60=0.00 avg300=0.00 total=11027863\nfull avg10=0.25 avg60=0.00 avg300=0.00 total=8310131\n";
+    let sample = PressureSample::from_str(42, "io", raw).unwrap();
+
+    assert_eq!(sample.some_avg10, 1.5);
+    assert_eq!(sample.some_total_us, 11027863);
+    assert_eq!(sample.full_avg10, Some(0.25));
+    assert_eq!(sample.full_total_us, Some(8310131));
+
+    let raw = "some avg10=0.00 avg60=0.00 avg300=0.00 total=10";
+    let sample = PressureSample::from_str(42, "cpu", raw).unwrap();
+    assert!(sample.full_total_us.is_none());
+    assert_eq!(sample.to_csv_rows()[0], "42,cpu,0,10,,");
+
+    assert!(PressureSample::from_str(42, "cpu", "").is_none());
+  }
+
+  #[test]
+  fn test_proc_io_from_string() {
+    let raw = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\nread_bytes: 4096\nwrite_bytes: 323932160\ncancelled_write_bytes: 12\n";
+    let io = ProcIo::from_str(raw);
+
+    assert_eq!(io.rchar, 323934931);
+    assert_eq!(io.syscw, 632675);
+    assert_eq!(io.read_bytes, 4096);
+    assert_eq!(io.write_bytes, 323932160);
+    assert_eq!(io.cancelled_write_bytes, 12);
+  }
+
+  #[test]
+  fn test_memory_sample_smaps_rollup() {
+    let raw = "55d0c5a5b000-7ffd3b5f5000 ---p 00000000 00:00 0    [rollup]\nRss:                3872 kB\nPss:                 521 kB\nShared_Clean:       3504 kB\nPrivate_Clean:       108 kB\nPrivate_Dirty:       260 kB\nAnonHugePages:      2048 kB\n";
+    let mut sample = MemorySample::default();
+    sample.add_smaps_rollup(raw);
+    sample.add_smaps_rollup(raw);
+
+    assert_eq!(sample.processes, 2);
+    assert_eq!(sample.rss_kb, 7744);
+    assert_eq!(sample.pss_kb, 1042);
+    assert_eq!(sample.uss_kb, 736);
+    assert_eq!(sample.anon_hugepages_kb, 4096);
+  }
+
+  #[test]
+  fn test_memory_traffic() {
+    let sample = |domain: &str, counter: &str, bytes| BandwidthSample {
+      timestamp: 42,
+      domain: domain.to_string(),
+      counter: counter.to_string(),
+      bytes,
+    };
+    let resctrl = [
+      sample("l3-0", "total", 1000),
+      sample("l3-0", "local", 800),
+      sample("l3-1", "total", 200),
+    ];
+    assert_eq!(memory_traffic(&resctrl, &BTreeSet::from([1])), Some(1200));
+    assert_eq!(resctrl[1].to_csv_rows(), vec!["42,l3-0,local,800"]);
+
+    let imc = [
+      sample("package-0", "read", 4096),
+      sample("package-0", "write", 1024),
+      sample("package-1", "read", 64),
+    ];
+    assert_eq!(memory_traffic(&imc, &BTreeSet::from([0])), Some(5120));
+    assert_eq!(memory_traffic(&imc, &BTreeSet::new()), Some(5184));
+    assert_eq!(memory_traffic(&[], &BTreeSet::new()), None);
+
+    let occupancy = |timestamp, domain: &str, bytes| CacheOccupancySample {
+      timestamp,
+      domain: domain.to_string(),
+      bytes,
+    };
+    let samples = [
+      occupancy(1, "l3-0", 4096),
+      occupancy(1, "l3-1", 1024),
+      occupancy(2, "l3-0", 4608),
+    ];
+    assert_eq!(peak_llc_occupancy(&samples), Some(5120));
+    assert_eq!(samples[2].to_csv_rows(), vec!["2,l3-0,4608"]);
+    assert_eq!(peak_llc_occupancy(&[]), None);
+  }
+
+  #[test]
+  fn test_hugepage_stats_from_vmstat() {
+    let before = HugePageStats::from_vmstat(
+      "nr_free_pages 1000\nthp_fault_alloc 1045\nthp_fault_fallback 12\nthp_fault_fallback_charge 0\nthp_collapse_alloc 3\nthp_split_page 7\n",
+    );
+    assert_eq!(before.thp_fault_alloc, 1045);
+    assert_eq!(before.thp_fault_fallback, 12);
+    let after = HugePageStats::from_vmstat(
+      "thp_fault_alloc 1100\nthp_fault_fallback 20\nthp_collapse_alloc 3\nthp_split_page 9\n",
+    );
+    let delta = after.since(&before);
+    assert_eq!(
+      delta.to_csv_rows(),
+      vec![
+        "thp_fault_alloc,#,55",
+        "thp_fault_fallback,#,8",
+        "thp_collapse_alloc,#,0",
+        "thp_split_page,#,2",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_cpu_sample_from_proc_stat() {
+    let before = "cpu  9410 712 1168 7398 46 46 0 0 0 0\ncpu0 4705 356 584 3699 23 23 0 0 0 0\ncpu1 4705 356 584 3699 23 23 0 0 0 0\nintr 1234\n";
+    let after = "cpu  9610 712 1268 7598 46 46 0 0 0 0\ncpu0 4805 356 584 3749 73 23 0 0 0 0\ncpu1 4805 356 684 3799 23 23 0 0 0 0\nintr 1234\n";
+    let before = CpuTimes::from_proc_stat(before);
+    let after = CpuTimes::from_proc_stat(after);
+    assert_eq!(before.len(), 2);
+    assert_eq!(before[0].cpu, "cpu0");
+    assert_eq!(before[0].user, 5061);
+
+    let sample = CpuSample::between(42, &before[0], &after[0]).unwrap();
+    assert_eq!(sample.user, 50.0);
+    assert_eq!(sample.iowait, 25.0);
+    assert_eq!(sample.busy, 50.0);
+    assert_eq!(sample.to_csv_rows()[0], "42,cpu0,50.00,0.00,25.00,50.00");
+
+    let sample = CpuSample::between(42, &before[1], &after[1]).unwrap();
+    assert!((sample.busy - 200.0 / 3.0).abs() < 1e-9);
+    assert!(CpuSample::between(42, &before[1], &before[1]).is_none());
+  }
+
+  #[test]
+  fn test_deep_trace_lifecycle_markers() {
+    let rows: Vec<String> = [
+      EVENT_PROCESS_START,
+      EVENT_FIRST_OUTPUT,
+      EVENT_READY,
+      EVENT_PROCESS_EXIT,
+    ]
+    .iter()
+    .enumerate()
+    .flat_map(|(i, &ev_type)| {
+      DeepTraceEvent {
+        ev_type,
+        timestamp: i as u64,
+        ..Default::default()
+      }
+      .to_csv_rows()
+    })
+    .collect();
+    assert_eq!(
+      rows,
+      vec![
+        "0,process-start,",
+        "1,first-output,",
+        "2,ready,",
+        "3,process-exit,"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_sgx_counters_aesm() {
+    let counters = LowLevelSgxCounters {
+      aesm_connections: 2,
+      aesm_wait_ns: 1500,
+      ..Default::default()
+    };
+    let rows = counters.to_csv_rows();
+    assert!(rows.contains(&String::from("sgx_aesm_connections,#,2,")));
+    assert!(rows.contains(&String::from("sgx_aesm_wait,ns,1500,")));
+
+    let events = [EVENT_AESM_CONNECT, EVENT_AESM_CLOSE].map(|ev_type| {
+      DeepTraceEvent {
+        ev_type,
+        timestamp: 0,
+        ..Default::default()
+      }
+      .to_csv_rows()
+    });
+    assert_eq!(events, [["0,aesm-connect,"], ["0,aesm-close,"]]);
+  }
+
+  #[test]
+  fn test_deep_trace_uprobe_markers() {
+    let rows: Vec<String> = [EVENT_UPROBE_ENTER | 2, EVENT_UPROBE_EXIT | 2, EVENT_USDT]
+      .iter()
+      .flat_map(|&ev_type| {
+        DeepTraceEvent {
+          ev_type,
+          timestamp: 7,
+          ..Default::default()
+        }
+        .to_csv_rows()
+      })
+      .collect();
+    assert_eq!(
+      rows,
+      vec!["7,uprobe-enter-2,", "7,uprobe-exit-2,", "7,usdt-0,"]
+    );
+  }
+
+  #[test]
+  fn test_deep_trace_memory_mappings() {
+    let rows: Vec<String> = [
+      (EVENT_MMAP, 4096),
+      (EVENT_BRK, 135168),
+      (EVENT_BRK_SHRINK, 4096),
+      (EVENT_MUNMAP, 4096),
+    ]
+    .iter()
+    .flat_map(|&(ev_type, bytes)| {
+      DeepTraceEvent {
+        ev_type,
+        timestamp: 1,
+        bytes,
+      }
+      .to_csv_rows()
+    })
+    .collect();
+    assert_eq!(
+      rows,
+      vec![
+        "1,sys-mmap,4096",
+        "1,sys-brk,135168",
+        "1,sys-brk-shrink,4096",
+        "1,sys-munmap,4096"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_vfs_counters() {
+    let counters = VfsCounters {
+      open: 12,
+      close: 10,
+      fsync: 1,
+      stat: 40,
+    };
+    assert_eq!(
+      counters.to_csv_rows(),
+      vec![
+        "vfs_open,#,12,",
+        "vfs_close,#,10,",
+        "vfs_fsync,#,1,",
+        "vfs_stat,#,40,"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_disk_stats_by_direction() {
+    let stats = DiskStats {
+      name: String::from("nvme0n1p3"),
+      bytes: 12288,
+      perc_random: 25,
+      perc_seq: 75,
+      read_requests: 2,
+      read_bytes: 8192,
+      write_requests: 0,
+      write_bytes: 0,
+      flushes: 1,
+    };
+    let rows = stats.to_csv_rows();
+    assert!(rows.contains(&String::from("disk_read_requests,#,2,nvme0n1p3")));
+    assert!(rows.contains(&String::from("disk_read_avg_request,bytes,4096,nvme0n1p3")));
+    assert!(rows.contains(&String::from("disk_write_avg_request,bytes,0,nvme0n1p3")));
+    assert!(rows.contains(&String::from("disk_flushes,#,1,nvme0n1p3")));
+    assert!(rows.contains(&String::from(
+      "disk_tot_written_bytes,bytes,12288,nvme0n1p3"
+    )));
+  }
+
+  #[test]
+  fn test_unit_symbols() {
+    assert_eq!(Unit::Count.to_string(), "#");
+    assert_eq!(Unit::Nanoseconds.to_string(), "ns");
+    assert_eq!(Unit::Microjoules.to_string(), "uJ");
+    assert_eq!(Unit::BytesPerSecond.to_string(), "bytes/s");
+  }
+
+  #[test]
+  fn test_syscall_latency_percentiles() {
+    let mut slots = [0; 32];
+    slots[10] = 90;
+    slots[14] = 8;
+    slots[20] = 2;
+    let stats = SyscallStats::from_histogram("sys_read", 100, 200_000, 1_500_000, &slots);
+    assert_eq!(
+      stats.to_csv_rows(),
+      vec![
+        "sys_read,#,100,",
+        "sys_read,ns,2000,",
+        "sys_read_p50,ns,2047,",
+        "sys_read_p95,ns,32767,",
+        "sys_read_p99,ns,1500000,",
+        "sys_read_max,ns,1500000,"
+      ]
+    );
+
+    let empty = SyscallStats::from_histogram("sys_write", 0, 0, 0, &[0; 32]);
+    assert_eq!((empty.avg_ns, empty.p99_ns, empty.max_ns), (0, 0, 0));
+  }
+
+  #[test]
+  fn test_deep_trace_io_sizes() {
+    let rows: Vec<String> = [
+      (EVENT_SYS_READ, 4096),
+      (EVENT_SYS_WRITE, 512),
+      (EVENT_READ_DISK, 8192),
+      (EVENT_WRITE_DISK, 4096),
+    ]
+    .iter()
+    .flat_map(|&(ev_type, bytes)| {
+      DeepTraceEvent {
+        ev_type,
+        timestamp: 2,
+        bytes,
+      }
+      .to_csv_rows()
+    })
+    .collect();
+    assert_eq!(
+      rows,
+      vec![
+        "2,sys-read,4096",
+        "2,sys-write,512",
+        "2,disk-read,8192",
+        "2,disk-write,4096"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_sgx_counters_enclave_load() {
+    let counters = LowLevelSgxCounters {
+      eadd_pages: 16384,
+      eadd_ns: 2_000_000,
+      einit_ns: 300_000,
+      ..Default::default()
+    };
+    let rows = counters.to_csv_rows();
+    assert!(rows.contains(&String::from("sgx_eadd_pages,#,16384,")));
+    assert!(rows.contains(&String::from("sgx_eadd,ns,2000000,")));
+    assert!(rows.contains(&String::from("sgx_einit,ns,300000,")));
+  }
+
+  #[test]
+  fn test_sgx_stats_untraced_enclave_build() {
+    let stats = SGXStats {
+      counters: LowLevelSgxCounters {
+        vma_fault: 3,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let rows = stats.to_csv_rows();
+    assert!(rows.contains(&String::from("sgx_vma_fault,#,3,")));
+    assert!(!rows
+      .iter()
+      .any(|row| row.starts_with("sgx_eadd") || row.starts_with("sgx_einit")));
+  }
+
+  #[test]
+  fn test_socket_energy() {
+    let energy = SocketEnergy {
+      packages_uj: BTreeMap::from([(0, 3_000_000), (1, 1_000_000)]),
+      workload_packages: BTreeSet::from([0]),
+    };
+    assert_eq!(energy.workload_uj(), Some(3_000_000));
+    assert_eq!(
+      energy.to_csv_rows(),
+      vec![
+        "energy_package_0,uJ,3000000",
+        "energy_package_1,uJ,1000000",
+        "workload_socket_energy,uJ,3000000",
+      ]
+    );
+
+    // affinity unknown: only the packages
+    let unknown = SocketEnergy {
+      workload_packages: BTreeSet::new(),
+      ..energy.clone()
+    };
+    assert_eq!(unknown.to_csv_rows().len(), 2);
+    // a single socket adds nothing to the total energy
+    let single = SocketEnergy {
+      packages_uj: BTreeMap::from([(0, 3_000_000)]),
+      ..energy
+    };
+    assert!(single.to_csv_rows().is_empty());
+  }
+
+  #[test]
+  fn test_energy_sample_power() {
+    let previous = EnergySample {
+      timestamp: 1_000_000_000,
+      monotonic_ns: 1_000_000_000,
+      energy_uj: 5_000_000,
+      range_uj: None,
+      power_w: None,
+      gap: false,
+    };
+
+    // 2 J in 0.5 s
+    let power = EnergySample::power_between(&previous, 1_500_000_000, 7_000_000);
+    assert_eq!(power, Some(4.0));
+    // counter wrap around, at an unknown range
+    assert!(EnergySample::power_between(&previous, 1_500_000_000, 10).is_none());
+    // and at a known one: 1 J up to the range, 1 J after it
+    let previous = EnergySample {
+      range_uj: Some(6_000_000),
+      ..previous
+    };
+    assert_eq!(
+      EnergySample::energy_between(&previous, 1_000_000),
+      Some(2_000_000)
+    );
+    assert_eq!(
+      EnergySample::power_between(&previous, 1_500_000_000, 1_000_000),
+      Some(4.0)
+    );
+
+    // the wall clock stepped back: power follows the monotonic clock
+    let interval = Duration::from_millis(500);
+    let sample = EnergySample::after(Some(&previous), 0, 1_500_000_000, 7_000_000, None, interval);
+    assert_eq!(sample.power_w, Some(4.0));
+    assert!(!sample.gap);
+    // a reading 1.5 s late: 2 J over 2 s
+    let sample = EnergySample::after(Some(&previous), 0, 3_000_000_000, 9_000_000, None, interval);
+    assert_eq!(sample.power_w, Some(2.0));
+    assert!(sample.gap);
+    assert_eq!(
+      sample.to_csv_rows(),
+      vec!["0,9000000,2.000000,3000000000,1"]
+    );
+
+    let first = EnergySample::after(None, 0, 1_000_000_000, 5_000_000, None, interval);
+    assert_eq!(first.power_w, None);
+    assert!(!first.gap);
+  }
+
+  #[test]
+  fn test_ocall_stats_from_perf_report() {
+    let report = r#"
+# Samples: 400  of event 'cpu-clock'
+#
+# Overhead   Samples  Shared Object  Symbol
+# ........  ........  .............  ..................
+#
+    69.77%       300  libpal.so      [.] ocall_read
+    13.95%        60  libpal.so      [.] ocall_write
+     6.98%        30  libsysdb.so    [.] libos_syscall_read
+     9.30%        40  libpal.so      [.] ocall_futex
+"#;
+    let ocalls = OcallStats::from_perf_report(report);
+    assert_eq!(3, ocalls.len());
+    assert_eq!("ocall_read", ocalls[0].name);
+    assert_eq!(300, ocalls[0].count);
+    assert_eq!(75.0, ocalls[0].share);
+    assert_eq!("ocall_futex", ocalls[2].name);
+
+    let merged = OcallStats::merge(ocalls, OcallStats::from_perf_report("100 [.] ocall_futex"));
+    assert_eq!(140, merged[1].count);
+    assert_eq!(vec!["ocall_read,300,60.00,"], merged[0].to_csv_rows());
+
+    // the handlers time the profiled OCALLs and count the others
+    let timed = |name: &str, count, time_ns| OcallStats {
+      name: name.to_string(),
+      count,
+      share: 0.0,
+      time_ns: Some(time_ns),
+    };
+    let ocalls = OcallStats::with_times(
+      merged,
+      &[
+        timed("ocall_read", 300, 90_000),
+        timed("ocall_open", 100, 5_000),
+        timed("ocall_close", 0, 0),
+      ],
+    );
+    assert_eq!(4, ocalls.len());
+    assert_eq!(vec!["ocall_read,300,50.00,90000"], ocalls[0].to_csv_rows());
+    assert_eq!(vec!["ocall_futex,140,23.33,"], ocalls[1].to_csv_rows());
+    assert_eq!(Some(5_000), ocalls[2].time_ns);
+  }
 }
@@ -0,0 +1,370 @@
+//! Console summary of a campaign (see `summarize`), printed at the end of `run` for immediate
+//! feedback without opening the output directory.
+use std::{
+  collections::BTreeMap,
+  fmt::Write,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::common::{indexed_experiments, Pinning};
+
+/// Sample markers of results left out of the means, as in the aggregator: invalid samples, and
+/// samples faulted or recovering from a fault.
+const EXCLUDED_SAMPLE_MARKERS: [&str; 3] = ["invalid", "fault", "recovery"];
+
+/// Columns of the summary table.
+const COLUMNS: [&str; 8] = [
+  "task",
+  "mode",
+  "threads",
+  "size",
+  "storage",
+  "runtime (s)",
+  "energy (J)",
+  "SGX overhead (%)",
+];
+
+/// Mean results of an experiment over its valid samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryRow {
+  /// Program name, with the RPC threads, log level, key provisioning, pinning and `args_matrix` values of the experiment if any.
+  pub task: String,
+  /// Path of the program, as in `metadata.json`.
+  pub program: PathBuf,
+  pub sgx: bool,
+  pub num_threads: usize,
+  pub enclave_size: Option<String>,
+  pub storage_type: Option<String>,
+  pub args_matrix: BTreeMap<String, String>,
+  /// Samples the means are computed on.
+  pub samples: usize,
+  pub runtime_ns: Option<f64>,
+  pub energy_uj: Option<f64>,
+  /// Means of every metric of `summary.csv` (including workload metrics), by name.
+  pub metrics: BTreeMap<String, f64>,
+  /// Runtime increase of an SGX experiment over the native experiment with the same program,
+  /// threads, pinning and `args_matrix` values, in percent.
+  pub sgx_overhead: Option<f64>,
+}
+
+// the fields of `metadata.json` shown in the summary
+#[derive(Deserialize)]
+struct Metadata {
+  program: PathBuf,
+  sgx: bool,
+  num_threads: usize,
+  enclave_size: Option<String>,
+  storage_type: Option<String>,
+  rpc_thread_num: Option<usize>,
+  #[serde(default)]
+  log_level: Option<String>,
+  #[serde(default)]
+  key_provisioning: Option<String>,
+  #[serde(default)]
+  args_matrix: BTreeMap<String, String>,
+  #[serde(default)]
+  pinning: Pinning,
+}
+
+/// Summarizes the experiments in `output_directory` in the order of the plan, as listed in its
+/// index. Results of older versions, without an index, are summarized by program, SGX experiments
+/// first, in the order of their directories. Native experiments linked from the baseline cache are
+/// included.
+pub fn summarize(output_directory: &Path) -> Result<Vec<SummaryRow>, std::io::Error> {
+  let directories = match indexed_experiments(output_directory) {
+    Some(directories) => directories,
+    None => {
+      let mut directories = Vec::new();
+      for program in sorted_directories(output_directory)? {
+        for kind in ["gramine-sgx", "no-gramine-sgx"] {
+          let path = program.join(kind);
+          if path.is_dir() {
+            directories.extend(sorted_directories(&path)?);
+          }
+        }
+      }
+      directories
+    }
+  };
+  let mut experiments = Vec::new();
+  for experiment in directories {
+    let Ok(metadata) = fs::read_to_string(experiment.join("metadata.json")) else {
+      continue;
+    };
+    let metadata: Metadata = serde_json::from_str(&metadata)?;
+    experiments.push((metadata, summarize_samples(&experiment)?));
+  }
+
+  let native: Vec<(String, Option<f64>)> = experiments
+    .iter()
+    .filter(|(metadata, _)| !metadata.sgx)
+    .map(|(metadata, (_, metrics))| (baseline_key(metadata), metrics.get("runtime").copied()))
+    .collect();
+  Ok(
+    experiments
+      .into_iter()
+      .map(|(metadata, (samples, metrics))| {
+        let runtime_ns = metrics.get("runtime").copied();
+        let sgx_overhead = if metadata.sgx {
+          let key = baseline_key(&metadata);
+          native
+            .iter()
+            .find(|(native, _)| *native == key)
+            .and_then(|(_, native)| overhead(runtime_ns, *native))
+        } else {
+          None
+        };
+        SummaryRow {
+          task: task_name(&metadata),
+          program: metadata.program,
+          sgx: metadata.sgx,
+          num_threads: metadata.num_threads,
+          enclave_size: metadata.enclave_size,
+          storage_type: metadata.storage_type,
+          args_matrix: metadata.args_matrix,
+          samples,
+          runtime_ns,
+          energy_uj: metrics.get("energy").copied(),
+          metrics,
+          sgx_overhead,
+        }
+      })
+      .collect(),
+  )
+}
+
+/// Formats `rows` as a table with aligned columns, missing values shown as `-`.
+pub fn format_table(rows: &[SummaryRow]) -> String {
+  let cells: Vec<[String; 8]> = rows
+    .iter()
+    .map(|row| {
+      let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("-"));
+      [
+        row.task.clone(),
+        String::from(if row.sgx { "sgx" } else { "native" }),
+        row.num_threads.to_string(),
+        optional(row.enclave_size.clone()),
+        optional(row.storage_type.clone()),
+        optional(row.runtime_ns.map(|ns| format!("{:.3}", ns / 1e9))),
+        optional(row.energy_uj.map(|uj| format!("{:.2}", uj / 1e6))),
+        optional(row.sgx_overhead.map(|overhead| format!("{:+.1}", overhead))),
+      ]
+    })
+    .collect();
+  let widths: Vec<usize> = (0..COLUMNS.len())
+    .map(|i| {
+      cells
+        .iter()
+        .map(|row| row[i].len())
+        .chain([COLUMNS[i].len()])
+        .max()
+        .unwrap_or_default()
+    })
+    .collect();
+
+  let mut table = String::new();
+  let header = COLUMNS.map(String::from);
+  for row in [&header].into_iter().chain(&cells) {
+    let line: Vec<String> = row
+      .iter()
+      .zip(&widths)
+      .enumerate()
+      // text is left aligned, numbers right aligned
+      .map(|(i, (cell, width))| {
+        if i < 5 {
+          format!("{:<width$}", cell)
+        } else {
+          format!("{:>width$}", cell)
+        }
+      })
+      .collect();
+    writeln!(table, "{}", line.join("  ").trim_end()).unwrap();
+  }
+  table
+}
+
+// number of valid samples and means of their metrics
+fn summarize_samples(experiment: &Path) -> Result<(usize, BTreeMap<String, f64>), std::io::Error> {
+  let mut samples = 0;
+  let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+  for entry in fs::read_dir(experiment)?.flatten() {
+    let sample = entry.path();
+    // samples are numbered; `deep-trace` and storage directories are not samples
+    if entry.file_name().to_string_lossy().parse::<u32>().is_err()
+      || !sample.is_dir()
+      || EXCLUDED_SAMPLE_MARKERS
+        .iter()
+        .any(|marker| sample.join(marker).is_file())
+    {
+      continue;
+    }
+    let Ok(summary) = fs::read_to_string(sample.join("summary.csv")) else {
+      continue;
+    };
+    samples += 1;
+    for line in summary.lines().skip(1) {
+      let fields: Vec<&str> = line.split(',').collect();
+      let value = fields.get(2).and_then(|value| value.parse::<f64>().ok());
+      if let (Some(name), Some(value)) = (fields.first(), value) {
+        values.entry(name.to_string()).or_default().push(value);
+      }
+    }
+  }
+  Ok((
+    samples,
+    values
+      .into_iter()
+      .filter_map(|(name, values)| Some((name, mean(&values)?)))
+      .collect(),
+  ))
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+  if values.is_empty() {
+    return None;
+  }
+  Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn overhead(sgx: Option<f64>, native: Option<f64>) -> Option<f64> {
+  match (sgx, native) {
+    (Some(sgx), Some(native)) if native > 0.0 => Some((sgx / native - 1.0) * 100.0),
+    _ => None,
+  }
+}
+
+// native experiments are compared with the SGX experiments of the same program, threads,
+// pinning and matrix values
+fn baseline_key(metadata: &Metadata) -> String {
+  format!(
+    "{:?}-{}-{}-{:?}",
+    metadata.program, metadata.num_threads, metadata.pinning, metadata.args_matrix
+  )
+}
+
+fn task_name(metadata: &Metadata) -> String {
+  let mut name = metadata
+    .program
+    .file_name()
+    .unwrap_or_default()
+    .to_string_lossy()
+    .into_owned();
+  let mut parameters: Vec<String> = metadata
+    .args_matrix
+    .iter()
+    .map(|(argument, value)| format!("{}={}", argument.trim_start_matches('-'), value))
+    .collect();
+  if metadata.pinning == Pinning::Cores {
+    parameters.insert(0, String::from("pinned"));
+  }
+  if metadata.key_provisioning.is_some() {
+    parameters.insert(0, String::from("provisioned"));
+  }
+  if let Some(log_level) = metadata.log_level.as_ref().filter(|level| *level != "none") {
+    parameters.insert(0, format!("log={}", log_level));
+  }
+  if let Some(rpc_thread_num) = metadata.rpc_thread_num.filter(|&n| n > 0) {
+    parameters.insert(0, format!("rpc={}", rpc_thread_num));
+  }
+  if !parameters.is_empty() {
+    name.push_str(&format!(" [{}]", parameters.join(" ")));
+  }
+  name
+}
+
+// directories (or symlinks to directories) in `path`, by name
+fn sorted_directories(path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+  let mut directories: Vec<PathBuf> = fs::read_dir(path)?
+    .flatten()
+    .map(|entry| entry.path())
+    .filter(|path| path.is_dir())
+    .collect();
+  directories.sort();
+  Ok(directories)
+}
+
+#[cfg(test)]
+mod test {
+  use std::{fs, path::Path};
+
+  use tempfile::TempDir;
+
+  use super::{format_table, summarize};
+
+  fn experiment(path: &Path, metadata: &str, samples: &[(&str, Option<&str>)]) {
+    fs::create_dir_all(path.join("deep-trace")).unwrap();
+    fs::write(path.join("metadata.json"), metadata).unwrap();
+    for (i, (summary, marker)) in samples.iter().enumerate() {
+      let sample = path.join((i + 1).to_string());
+      fs::create_dir(&sample).unwrap();
+      fs::write(
+        sample.join("summary.csv"),
+        format!("metric,unit,value\n{}", summary),
+      )
+      .unwrap();
+      if let Some(marker) = marker {
+        fs::write(sample.join(marker), "").unwrap();
+      }
+    }
+  }
+
+  #[test]
+  fn summarize_campaign() {
+    let results = TempDir::new().unwrap();
+    experiment(
+      &results.path().join("ls/gramine-sgx/ls-1-64M-untrusted"),
+      r#"{"program": "/bin/ls", "sgx": true, "num_threads": 1, "enclave_size": "64M",
+          "storage_type": "untrusted", "rpc_thread_num": 0, "log_level": "none", "args_matrix": {}}"#,
+      &[
+        ("runtime,ns,3000000000\nenergy,uJ,2000000", None),
+        ("runtime,ns,1000000000\nenergy,uJ,4000000", None),
+        ("runtime,ns,9000000000", Some("invalid")),
+      ],
+    );
+    experiment(
+      &results.path().join("ls/no-gramine-sgx/ls-1"),
+      r#"{"program": "/bin/ls", "sgx": false, "num_threads": 1, "enclave_size": null,
+          "storage_type": null, "rpc_thread_num": null, "args_matrix": {}}"#,
+      &[("runtime,ns,1000000000", None)],
+    );
+    experiment(
+      &results.path().join("ls/no-gramine-sgx/ls-1-size=2"),
+      r#"{"program": "/bin/ls", "sgx": false, "num_threads": 1, "args_matrix": {"--size": "2"}}"#,
+      &[("runtime,ns,5000000000", Some("fault"))],
+    );
+
+    let rows = summarize(results.path()).unwrap();
+    assert_eq!(rows.len(), 3);
+    assert!(rows[0].sgx);
+    // the default log level is not shown
+    assert_eq!(rows[0].task, "ls");
+    assert_eq!(rows[0].samples, 2);
+    assert_eq!(rows[0].runtime_ns, Some(2e9));
+    assert_eq!(rows[0].energy_uj, Some(3e6));
+    assert_eq!(rows[0].sgx_overhead, Some(100.0));
+    assert_eq!(rows[1].task, "ls");
+    assert_eq!(rows[1].sgx_overhead, None);
+    assert_eq!(rows[2].task, "ls [size=2]");
+    assert_eq!(rows[2].args_matrix["--size"], "2");
+    assert_eq!(rows[2].samples, 0);
+    assert_eq!(rows[2].runtime_ns, None);
+
+    let table = format_table(&rows);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(
+      lines[0],
+      "task         mode    threads  size  storage    runtime (s)  energy (J)  SGX overhead (%)"
+    );
+    assert_eq!(
+      lines[1],
+      "ls           sgx     1        64M   untrusted        2.000        3.00            +100.0"
+    );
+    assert_eq!(
+      lines[3],
+      "ls [size=2]  native  1        -     -                    -           -                 -"
+    );
+  }
+}
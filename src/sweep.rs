@@ -0,0 +1,368 @@
+//! Storage microbenchmark (see `IoSweep`): a campaign of the builtin I/O workload across block
+//! sizes, file sizes and storage types, characterizing the encrypted, untrusted and tmpfs storage
+//! of a machine with a single command.
+use std::{
+  fmt::Write as _,
+  fs::{self, File},
+  io::{self, Read, Write},
+  os::fd::AsRawFd,
+  path::{Path, PathBuf},
+  time::Instant,
+};
+
+use crate::{
+  common::{format_size, parse_size},
+  summary::SummaryRow,
+};
+
+/// Subcommand of the benchmark running the I/O workload (see `run_worker`).
+pub const WORKER_COMMAND: &str = "io-worker";
+
+/// Arguments of the I/O workload varied by the sweep through `args_matrix`.
+const BLOCK_SIZE_ARG: &str = "--block-size";
+const FILE_SIZE_ARG: &str = "--file-size";
+
+/// Metrics printed by the I/O workload, with the patterns extracting them from its output.
+const METRICS: [(&str, &str); 2] = [
+  ("write_throughput", r"write: ([0-9.]+) MiB/s"),
+  ("read_throughput", r"read: ([0-9.]+) MiB/s"),
+];
+
+/// Columns of the report.
+const COLUMNS: [&str; 5] = [
+  "storage",
+  "block size",
+  "file size",
+  "write (MiB/s)",
+  "read (MiB/s)",
+];
+
+/// Settings of a storage sweep. Every combination of block size and file size runs with a single
+/// thread on every storage type, and natively as a reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoSweep {
+  pub block_sizes: Vec<u64>,
+  pub file_sizes: Vec<u64>,
+  /// Enclave size of the SGX experiments; tmpfs files are kept in the memory of the enclave, so it
+  /// must be larger than the largest file.
+  pub enclave_size: String,
+  pub sample_size: u32,
+  pub output_directory: PathBuf,
+}
+
+impl IoSweep {
+  /// Returns the content of the configuration file of the sweep. The workload is `executable` (the
+  /// benchmark itself) running `WORKER_COMMAND`.
+  pub fn config(&self, executable: &Path) -> Result<String, toml::ser::Error> {
+    let mut globals = toml::Table::new();
+    globals.insert("sample_size".into(), i64::from(self.sample_size).into());
+    globals.insert(
+      "output_directory".into(),
+      self.output_directory.to_string_lossy().into_owned().into(),
+    );
+
+    let sizes = |sizes: &[u64]| {
+      sizes
+        .iter()
+        .map(|&size| toml::Value::from(format_size(size)))
+        .collect::<Vec<_>>()
+    };
+    let mut args_matrix = toml::Table::new();
+    args_matrix.insert(BLOCK_SIZE_ARG.into(), sizes(&self.block_sizes).into());
+    args_matrix.insert(FILE_SIZE_ARG.into(), sizes(&self.file_sizes).into());
+
+    let mut metrics = toml::Table::new();
+    for (name, pattern) in METRICS {
+      let mut metric = toml::Table::new();
+      metric.insert("pattern".into(), pattern.into());
+      metric.insert("unit".into(), "MiB/s".into());
+      metrics.insert(name.into(), metric.into());
+    }
+
+    let mut task = toml::Table::new();
+    task.insert(
+      "executable".into(),
+      executable.to_string_lossy().into_owned().into(),
+    );
+    task.insert(
+      "args".into(),
+      vec![
+        WORKER_COMMAND,
+        "--path",
+        "{{ output_directory }}/io-sweep.dat",
+      ]
+      .into(),
+    );
+    task.insert("args_matrix".into(), args_matrix.into());
+    task.insert("num_threads".into(), vec![1].into());
+    task.insert(
+      "enclave_size".into(),
+      vec![self.enclave_size.clone()].into(),
+    );
+    task.insert(
+      "storage_type".into(),
+      vec!["encrypted", "untrusted", "tmpfs"].into(),
+    );
+    task.insert("metrics".into(), metrics.into());
+
+    let mut config = toml::Table::new();
+    config.insert("globals".into(), globals.into());
+    config.insert("tasks".into(), vec![toml::Value::Table(task)].into());
+    toml::to_string(&config)
+  }
+}
+
+/// The I/O workload: writes a file of `file_size` bytes at `path` in blocks of `block_size` bytes
+/// and flushes it to storage, then reads it back, printing the throughput of both phases on
+/// `output`. The file is removed afterwards.
+pub fn run_worker(
+  path: &Path,
+  block_size: u64,
+  file_size: u64,
+  output: &mut impl Write,
+) -> io::Result<()> {
+  let block: Vec<u8> = (0..block_size).map(|i| (i % 251) as u8).collect();
+  let start = Instant::now();
+  let mut file = File::create(path)?;
+  let mut written = 0;
+  while written < file_size {
+    let length = block_size.min(file_size - written);
+    file.write_all(&block[..length as usize])?;
+    written += length;
+  }
+  file.sync_all()?;
+  writeln!(output, "write: {:.2} MiB/s", throughput(written, start))?;
+
+  // drops the pages of the file from the page cache, so that they are read from storage; not
+  // supported (and ignored) in an enclave
+  unsafe {
+    libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+  }
+  drop(file);
+
+  let start = Instant::now();
+  let mut file = File::open(path)?;
+  let mut buffer = vec![0u8; block_size as usize];
+  let mut read = 0;
+  loop {
+    match file.read(&mut buffer)? {
+      0 => break,
+      n => read += n as u64,
+    }
+  }
+  writeln!(output, "read: {:.2} MiB/s", throughput(read, start))?;
+  fs::remove_file(path)
+}
+
+fn throughput(bytes: u64, start: Instant) -> f64 {
+  bytes as f64 / (1 << 20) as f64 / start.elapsed().as_secs_f64()
+}
+
+/// Mean throughput of the I/O workload in an experiment of the sweep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepRow {
+  /// Storage type of the experiment, `native` for native experiments.
+  pub storage: String,
+  pub block_size: String,
+  pub file_size: String,
+  pub write_mib_s: Option<f64>,
+  pub read_mib_s: Option<f64>,
+}
+
+/// Selects the experiments of the sweep in `rows` (see `summary::summarize`), by storage, block
+/// size and file size.
+pub fn report(rows: &[SummaryRow]) -> Vec<SweepRow> {
+  let mut report: Vec<SweepRow> = rows
+    .iter()
+    .filter_map(|row| {
+      Some(SweepRow {
+        storage: match &row.storage_type {
+          Some(storage_type) if row.sgx => storage_type.clone(),
+          _ => String::from("native"),
+        },
+        block_size: row.args_matrix.get(BLOCK_SIZE_ARG)?.clone(),
+        file_size: row.args_matrix.get(FILE_SIZE_ARG)?.clone(),
+        write_mib_s: row.metrics.get(METRICS[0].0).copied(),
+        read_mib_s: row.metrics.get(METRICS[1].0).copied(),
+      })
+    })
+    .collect();
+  report.sort_by_key(|row| {
+    (
+      row.storage.clone(),
+      parse_size(&row.block_size),
+      parse_size(&row.file_size),
+    )
+  });
+  report
+}
+
+/// Formats `rows` as CSV if `csv`, or else as a table with aligned columns, missing values shown
+/// as `-`.
+pub fn format_report(rows: &[SweepRow], csv: bool) -> String {
+  let cells: Vec<[String; 5]> = rows
+    .iter()
+    .map(|row| {
+      let throughput =
+        |value: Option<f64>| value.map_or(String::from("-"), |v| format!("{:.2}", v));
+      [
+        row.storage.clone(),
+        row.block_size.clone(),
+        row.file_size.clone(),
+        throughput(row.write_mib_s),
+        throughput(row.read_mib_s),
+      ]
+    })
+    .collect();
+  let header = COLUMNS.map(String::from);
+
+  let mut report = String::new();
+  if csv {
+    for row in [&header].into_iter().chain(&cells) {
+      writeln!(report, "{}", row.join(",")).unwrap();
+    }
+    return report;
+  }
+  let widths: Vec<usize> = (0..COLUMNS.len())
+    .map(|i| {
+      cells
+        .iter()
+        .map(|row| row[i].len())
+        .chain([COLUMNS[i].len()])
+        .max()
+        .unwrap_or_default()
+    })
+    .collect();
+  for row in [&header].into_iter().chain(&cells) {
+    let line: Vec<String> = row
+      .iter()
+      .zip(&widths)
+      .enumerate()
+      // text is left aligned, numbers right aligned
+      .map(|(i, (cell, width))| {
+        if i < 3 {
+          format!("{:<width$}", cell)
+        } else {
+          format!("{:>width$}", cell)
+        }
+      })
+      .collect();
+    writeln!(report, "{}", line.join("  ").trim_end()).unwrap();
+  }
+  report
+}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+  };
+
+  use tempfile::TempDir;
+
+  use super::{format_report, report, run_worker, IoSweep};
+  use crate::{common::StorageType, summary::SummaryRow, Config};
+
+  #[test]
+  fn sweep_config() {
+    let sweep = IoSweep {
+      block_sizes: vec![4 << 10, 1 << 20],
+      file_sizes: vec![16 << 20],
+      enclave_size: String::from("1G"),
+      sample_size: 3,
+      output_directory: "io-sweep".into(),
+    };
+    let content = sweep
+      .config(Path::new("/usr/bin/enclave-benchmark"))
+      .unwrap();
+    let config = toml::from_str::<Config>(&content).unwrap();
+    assert_eq!(config.globals.sample_size, 3);
+    let task = &config.tasks[0];
+    task.validate().unwrap();
+    assert_eq!(task.args[0], "io-worker");
+    assert_eq!(task.args_matrix["--block-size"], vec!["4K", "1M"]);
+    assert_eq!(task.args_matrix["--file-size"], vec!["16M"]);
+    assert_eq!(
+      task.storage_type,
+      vec![
+        StorageType::Encrypted,
+        StorageType::Untrusted,
+        StorageType::Tmpfs
+      ]
+    );
+    assert_eq!(task.metrics.len(), 2);
+    // 2 block sizes on 3 storage types and natively
+    assert_eq!(config.plan().len(), 8);
+
+    let metric = &task.metrics[1];
+    assert_eq!(metric.name, "write_throughput");
+    let output = "write: 812.50 MiB/s\nread: 2048.00 MiB/s\n";
+    assert_eq!(&metric.regex.captures(output).unwrap()[1], "812.50");
+  }
+
+  #[test]
+  fn worker_throughput() {
+    let directory = TempDir::new().unwrap();
+    let path = directory.path().join("io-sweep.dat");
+    let mut output = Vec::new();
+    // the last block is partial
+    run_worker(&path, 4096, 10000, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("write: ") && lines[0].ends_with(" MiB/s"));
+    assert!(lines[1].starts_with("read: "));
+    assert!(!path.exists());
+  }
+
+  #[test]
+  fn sweep_report() {
+    let row =
+      |sgx: bool, storage_type: Option<&str>, block_size: &str, write: Option<f64>| SummaryRow {
+        task: String::from("enclave-benchmark"),
+        program: PathBuf::from("enclave-benchmark"),
+        sgx,
+        num_threads: 1,
+        enclave_size: None,
+        storage_type: storage_type.map(String::from),
+        args_matrix: BTreeMap::from([
+          (String::from("--block-size"), String::from(block_size)),
+          (String::from("--file-size"), String::from("16M")),
+        ]),
+        samples: 1,
+        runtime_ns: None,
+        energy_uj: None,
+        metrics: write
+          .map(|write| (String::from("write_throughput"), write))
+          .into_iter()
+          .collect(),
+        sgx_overhead: None,
+      };
+    let mut other = row(false, None, "4K", None);
+    other.args_matrix.clear();
+    let rows = report(&[
+      row(true, Some("untrusted"), "1M", Some(400.0)),
+      row(true, Some("untrusted"), "4K", Some(100.0)),
+      row(false, None, "4K", Some(1000.0)),
+      other,
+    ]);
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0].storage, "native");
+    assert_eq!(rows[1].block_size, "4K");
+    assert_eq!(rows[2].write_mib_s, Some(400.0));
+
+    let table = format_report(&rows, false);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(
+      lines[0],
+      "storage    block size  file size  write (MiB/s)  read (MiB/s)"
+    );
+    assert_eq!(
+      lines[1],
+      "native     4K          16M              1000.00             -"
+    );
+    let csv = format_report(&rows, true);
+    assert_eq!(csv.lines().nth(3), Some("untrusted,1M,16M,400.00,-"));
+  }
+}
@@ -0,0 +1,619 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs, io,
+  path::{Path, PathBuf},
+  thread,
+};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+  common::{format_size, parse_cpu_list, parse_size, Task, TransparentHugePages},
+  profiler::plan,
+};
+
+/// Characteristics of the host which change the results of a campaign. Stored as `system.json`
+/// in the output directory.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SystemInfo {
+  /// Names the source of the results when archives of several hosts are imported together.
+  pub hostname: Option<String>,
+  pub kernel: Option<String>,
+  /// The kernel command line, which can turn mitigations off (e.g. `mitigations=off`).
+  pub kernel_cmdline: Option<String>,
+  pub cpu_model: Option<String>,
+  pub microcode: Option<String>,
+  pub cores: usize,
+  /// Mitigation status of CPU vulnerabilities by name (e.g. `mds`, `l1tf`), as reported by the
+  /// kernel. Mitigations such as buffer clearing or L1D flushes on enclave exits change the cost
+  /// of SGX transitions.
+  pub vulnerabilities: BTreeMap<String, String>,
+  /// Transparent huge page mode (`always`, `madvise` or `never`) and defrag policy of the host.
+  /// Tasks can change the mode for their experiments (see `metadata.json`).
+  pub transparent_hugepages: Option<String>,
+  pub transparent_hugepages_defrag: Option<String>,
+  /// Address space layout randomization of the host (`kernel.randomize_va_space`): 0 disabled, 1
+  /// stack, mmap and vDSO randomized, 2 the heap as well. Tasks can disable it for their workload
+  /// (see `metadata.json`).
+  pub randomize_va_space: Option<u8>,
+  /// `None` if the CPU does not support SGX.
+  pub sgx: Option<SgxPlatform>,
+  /// Default perf events the host cannot count, which are missing from the results.
+  pub unsupported_perf_events: Vec<String>,
+}
+
+/// SGX capabilities of the CPU and the driver exposing them.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SgxPlatform {
+  pub sgx1: bool,
+  /// SGX2 instructions, required by EDMM (`sgx.edmm_enable`).
+  pub sgx2: bool,
+  /// Flexible Launch Control, required by the in-kernel driver to launch enclaves signed with
+  /// any key.
+  pub flc: bool,
+  /// Size in bytes of every EPC section.
+  pub epc_sections: Vec<u64>,
+  /// `in-kernel` (`/dev/sgx_enclave`, Linux 5.11+), `dcap` (`/dev/sgx/enclave`) or `isgx`
+  /// (`/dev/isgx`); `None` if no driver is loaded.
+  pub driver: Option<String>,
+}
+
+impl SgxPlatform {
+  /// Reads SGX capabilities with CPUID (leaf 7 and leaf 0x12).
+  #[cfg(target_arch = "x86_64")]
+  pub fn probe() -> Option<Self> {
+    use std::arch::x86_64::{__cpuid, __cpuid_count};
+
+    if __cpuid(0).eax < 0x12 || __cpuid_count(7, 0).ebx & (1 << 2) == 0 {
+      return None;
+    }
+    let capabilities = __cpuid_count(0x12, 0).eax;
+    let mut epc_sections = Vec::new();
+    for subleaf in 2.. {
+      let section = __cpuid_count(0x12, subleaf);
+      // type 0 marks the end of the sections
+      if section.eax & 0xf != 1 {
+        break;
+      }
+      epc_sections
+        .push((section.ecx as u64 & 0xffff_f000) | ((section.edx as u64 & 0xf_ffff) << 32));
+    }
+    Some(Self {
+      sgx1: capabilities & 1 != 0,
+      sgx2: capabilities & (1 << 1) != 0,
+      flc: __cpuid_count(7, 0).ecx & (1 << 30) != 0,
+      epc_sections,
+      driver: [
+        ("/dev/sgx_enclave", "in-kernel"),
+        ("/dev/sgx/enclave", "dcap"),
+        ("/dev/isgx", "isgx"),
+      ]
+      .iter()
+      .find(|(device, _)| Path::new(device).exists())
+      .map(|(_, driver)| driver.to_string()),
+    })
+  }
+
+  #[cfg(not(target_arch = "x86_64"))]
+  pub fn probe() -> Option<Self> {
+    None
+  }
+
+  pub fn epc_size(&self) -> u64 {
+    self.epc_sections.iter().sum()
+  }
+}
+
+impl SystemInfo {
+  pub fn probe() -> Self {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    Self {
+      hostname: read_trimmed(Path::new("/proc/sys/kernel/hostname")),
+      kernel: read_trimmed(Path::new("/proc/sys/kernel/osrelease")),
+      kernel_cmdline: read_trimmed(Path::new("/proc/cmdline")),
+      cpu_model: cpuinfo_field(&cpuinfo, "model name"),
+      microcode: cpuinfo_field(&cpuinfo, "microcode"),
+      cores: thread::available_parallelism().map_or(1, |n| n.get()),
+      vulnerabilities: vulnerabilities(Path::new("/sys/devices/system/cpu/vulnerabilities")),
+      transparent_hugepages: read_trimmed(Path::new(TRANSPARENT_HUGEPAGE_ENABLED))
+        .and_then(|modes| selected_mode(&modes)),
+      transparent_hugepages_defrag: read_trimmed(Path::new(
+        "/sys/kernel/mm/transparent_hugepage/defrag",
+      ))
+      .and_then(|modes| selected_mode(&modes)),
+      randomize_va_space: read_trimmed(Path::new("/proc/sys/kernel/randomize_va_space"))
+        .and_then(|value| value.parse().ok()),
+      sgx: SgxPlatform::probe(),
+      unsupported_perf_events: Vec::new(),
+    }
+  }
+
+  pub fn save(&self, output_directory: &Path) -> Result<(), std::io::Error> {
+    fs::write(
+      output_directory.join("system.json"),
+      serde_json::to_string_pretty(self)?,
+    )
+  }
+}
+
+/// Checks the SGX experiments of `tasks` against the platform and describes every feature they
+/// need which the platform lacks.
+pub fn sgx_warnings(platform: Option<&SgxPlatform>, tasks: &[Task]) -> Vec<String> {
+  let sgx_tasks: Vec<&Task> = tasks
+    .iter()
+    .filter(|task| plan(task).iter().any(|experiment| experiment.sgx))
+    .collect();
+  if sgx_tasks.is_empty() {
+    return Vec::new();
+  }
+  let Some(platform) = platform else {
+    return vec![String::from(
+      "the CPU does not support SGX; SGX experiments will fail",
+    )];
+  };
+
+  let mut warnings = Vec::new();
+  match platform.driver.as_deref() {
+    None => warnings.push(String::from(
+      "no SGX driver found (/dev/sgx_enclave, /dev/sgx/enclave or /dev/isgx); SGX experiments will fail",
+    )),
+    Some("in-kernel") if !platform.flc => warnings.push(String::from(
+      "the in-kernel SGX driver requires Flexible Launch Control, which the CPU does not support",
+    )),
+    _ => {}
+  }
+
+  let epc_size = platform.epc_size();
+  for task in sgx_tasks {
+    let name = task
+      .executable
+      .file_name()
+      .unwrap_or_default()
+      .to_string_lossy();
+    if task.edmm() && !platform.sgx2 {
+      warnings.push(format!(
+        "task {} enables EDMM, which requires SGX2 instructions the CPU does not support",
+        name
+      ));
+    }
+    let paged: Vec<&str> = task
+      .enclave_size
+      .iter()
+      .filter(|size| parse_size(size).is_some_and(|size| epc_size > 0 && size > epc_size))
+      .map(String::as_str)
+      .collect();
+    if !paged.is_empty() {
+      warnings.push(format!(
+        "task {}: enclave sizes {} exceed the EPC ({}); their pages are swapped by the driver",
+        name,
+        paged.join(", "),
+        format_size(epc_size)
+      ));
+    }
+  }
+  warnings
+}
+
+/// Checks that the enclaves of the SGX experiments of `tasks` fit on the platform. Without EDMM
+/// every page of an enclave is added when it is loaded and the driver can only evict pages of the
+/// EPC to memory, so enclaves larger than the EPC and the memory and swap of the host
+/// (`memory`, in bytes) cannot be loaded.
+pub fn check_enclave_sizes(
+  platform: &SgxPlatform,
+  memory: u64,
+  tasks: &[Task],
+) -> Result<(), String> {
+  let available = platform.epc_size().saturating_add(memory);
+  for task in tasks.iter().filter(|task| !task.edmm()) {
+    if let Some(size) = task
+      .enclave_size
+      .iter()
+      .find(|size| parse_size(size).is_some_and(|size| size > available))
+    {
+      return Err(format!(
+        "task {:?}: enclave size {} exceeds the EPC, memory and swap of the host ({})",
+        task.executable,
+        size,
+        format_size(available)
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Returns the memory and swap of the host in bytes (`MemTotal` and `SwapTotal` of
+/// `/proc/meminfo`).
+pub fn memory_and_swap() -> Option<u64> {
+  let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+  let kib = |field: &str| -> Option<u64> {
+    cpuinfo_field(&meminfo, field)?
+      .strip_suffix("kB")?
+      .trim()
+      .parse()
+      .ok()
+  };
+  Some((kib("MemTotal")? + kib("SwapTotal").unwrap_or_default()) << 10)
+}
+
+/// Returns the packages (sockets) of the logical CPUs in `cpus`.
+pub fn cpu_packages(cpus: &[usize]) -> BTreeSet<u32> {
+  packages_of(Path::new("/sys/devices/system/cpu"), cpus)
+}
+
+fn packages_of(directory: &Path, cpus: &[usize]) -> BTreeSet<u32> {
+  cpus
+    .iter()
+    .filter_map(|cpu| {
+      read_trimmed(
+        &directory
+          .join(format!("cpu{}", cpu))
+          .join("topology/physical_package_id"),
+      )?
+      .parse()
+      .ok()
+    })
+    .collect()
+}
+
+/// Chooses `count` logical CPUs among `allowed` (every CPU if empty) to pin a workload with
+/// `count` threads to: one per physical core first, so that threads do not share a core, and then
+/// the remaining hyper-threads. Returns all the allowed CPUs if there are fewer than `count`.
+pub fn pinned_cpus(count: usize, allowed: &[usize]) -> Vec<usize> {
+  let mut cpus = cpus_by_core(Path::new("/sys/devices/system/cpu"));
+  cpus.retain(|cpu| allowed.is_empty() || allowed.contains(cpu));
+  cpus.truncate(count.max(1));
+  cpus
+}
+
+/// Returns the online logical CPUs.
+pub fn online_cpus() -> Vec<usize> {
+  read_trimmed(Path::new("/sys/devices/system/cpu/online"))
+    .and_then(|online| parse_cpu_list(&online))
+    .unwrap_or_default()
+}
+
+/// Returns the CPUs the process or thread `pid` (0 for the calling thread) is allowed to run on.
+pub fn affinity(pid: libc::pid_t) -> Result<Vec<usize>, io::Error> {
+  // Safety: an all-zero cpu_set_t is an empty set, filled by sched_getaffinity.
+  unsafe {
+    let mut set: libc::cpu_set_t = std::mem::zeroed();
+    if libc::sched_getaffinity(pid, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(
+      (0..libc::CPU_SETSIZE as usize)
+        .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+        .collect(),
+    )
+  }
+}
+
+/// Returns the set of `cpus` given to `sched_setaffinity`; CPUs beyond `CPU_SETSIZE` are left
+/// out.
+pub fn cpu_set(cpus: &[usize]) -> libc::cpu_set_t {
+  // Safety: an all-zero cpu_set_t is an empty set.
+  unsafe {
+    let mut set: libc::cpu_set_t = std::mem::zeroed();
+    for &cpu in cpus.iter().filter(|&&cpu| cpu < libc::CPU_SETSIZE as usize) {
+      libc::CPU_SET(cpu, &mut set);
+    }
+    set
+  }
+}
+
+// logical CPUs ordered by sibling index (first thread of every core, then the second ...) and then
+// by package and core; cpus without topology (offline) are left out
+fn cpus_by_core(directory: &Path) -> Vec<usize> {
+  let Ok(entries) = fs::read_dir(directory) else {
+    return Vec::new();
+  };
+  let mut cpus: Vec<(usize, usize, usize)> = entries
+    .flatten()
+    .filter_map(|entry| {
+      let cpu = entry
+        .file_name()
+        .to_str()?
+        .strip_prefix("cpu")?
+        .parse::<usize>()
+        .ok()?;
+      let topology = entry.path().join("topology");
+      let package = read_trimmed(&topology.join("physical_package_id"))?
+        .parse()
+        .ok()?;
+      let core = read_trimmed(&topology.join("core_id"))?.parse().ok()?;
+      Some((package, core, cpu))
+    })
+    .collect();
+  cpus.sort_unstable();
+  let mut siblings: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+  let mut ordered: Vec<(usize, (usize, usize), usize)> = cpus
+    .into_iter()
+    .map(|(package, core, cpu)| {
+      let sibling = siblings.entry((package, core)).or_default();
+      *sibling += 1;
+      (*sibling, (package, core), cpu)
+    })
+    .collect();
+  ordered.sort_unstable();
+  ordered.into_iter().map(|(_, _, cpu)| cpu).collect()
+}
+
+/// Returns the disk holding the block device `name` of `directory` (`/sys/class/block`), e.g.
+/// nvme0n1 for nvme0n1p3: block requests are accounted to the disk, not to its partitions.
+/// Other devices are returned as they are.
+pub fn parent_disk(directory: &Path, name: &str) -> String {
+  let device = directory.join(name);
+  if !device.join("partition").is_file() {
+    return name.to_string();
+  }
+  // the entries are links to the device directories, where partitions are in their disk
+  fs::canonicalize(device.join(".."))
+    .ok()
+    .and_then(|disk| Some(disk.file_name()?.to_str()?.to_string()))
+    .unwrap_or_else(|| name.to_string())
+}
+
+/// Sets the transparent huge page mode of the host while it is alive. Dropping it restores the
+/// previous mode.
+#[derive(Debug)]
+pub struct TransparentHugePageMode {
+  path: PathBuf,
+  previous: String,
+}
+
+impl TransparentHugePageMode {
+  pub fn set(mode: TransparentHugePages) -> Result<Self, std::io::Error> {
+    Self::set_at(Path::new(TRANSPARENT_HUGEPAGE_ENABLED), mode)
+  }
+
+  fn set_at(path: &Path, mode: TransparentHugePages) -> Result<Self, std::io::Error> {
+    let previous = fs::read_to_string(path)
+      .ok()
+      .and_then(|modes| selected_mode(&modes))
+      .ok_or_else(|| {
+        std::io::Error::new(
+          std::io::ErrorKind::NotFound,
+          format!("{:?} does not report the transparent huge page mode", path),
+        )
+      })?;
+    fs::write(path, mode.to_string())?;
+    Ok(Self {
+      path: path.to_path_buf(),
+      previous,
+    })
+  }
+}
+
+impl Drop for TransparentHugePageMode {
+  fn drop(&mut self) {
+    if let Err(e) = fs::write(&self.path, &self.previous) {
+      warn!(
+        "cannot restore transparent huge pages to {}: {}",
+        self.previous, e
+      );
+    }
+  }
+}
+
+const TRANSPARENT_HUGEPAGE_ENABLED: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+// the files of /sys/kernel/mm/transparent_hugepage list the modes with the selected one in
+// brackets, e.g. "always [madvise] never"
+fn selected_mode(modes: &str) -> Option<String> {
+  modes
+    .split_whitespace()
+    .find_map(|mode| mode.strip_prefix('[')?.strip_suffix(']'))
+    .map(String::from)
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+  fs::read_to_string(path)
+    .ok()
+    .map(|content| content.trim().to_string())
+}
+
+// returns the value of the first `<field> : <value>` line of /proc/cpuinfo (or /proc/meminfo)
+fn cpuinfo_field(cpuinfo: &str, field: &str) -> Option<String> {
+  cpuinfo.lines().find_map(|line| {
+    let (key, value) = line.split_once(':')?;
+    (key.trim() == field).then(|| value.trim().to_string())
+  })
+}
+
+// every file in the directory holds the status of a vulnerability, e.g.
+// "Mitigation: Clear CPU buffers; SMT vulnerable"
+fn vulnerabilities(directory: &Path) -> BTreeMap<String, String> {
+  let Ok(entries) = fs::read_dir(directory) else {
+    return BTreeMap::new();
+  };
+  entries
+    .flatten()
+    .filter_map(|entry| {
+      let status = read_trimmed(&entry.path())?;
+      Some((entry.file_name().to_string_lossy().into_owned(), status))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs;
+
+  use tempfile::TempDir;
+
+  use crate::{
+    common::TransparentHugePages,
+    system::{
+      check_enclave_sizes, cpuinfo_field, cpus_by_core, packages_of, parent_disk, selected_mode,
+      sgx_warnings, vulnerabilities, SgxPlatform, TransparentHugePageMode,
+    },
+    Config,
+  };
+
+  #[test]
+  fn cpu_vulnerabilities() {
+    let directory = TempDir::new().unwrap();
+    fs::write(
+      directory.path().join("mds"),
+      "Mitigation: Clear CPU buffers; SMT vulnerable\n",
+    )
+    .unwrap();
+    fs::write(directory.path().join("meltdown"), "Not affected\n").unwrap();
+
+    let vulnerabilities = vulnerabilities(directory.path());
+    assert_eq!(vulnerabilities.len(), 2);
+    assert_eq!(
+      vulnerabilities["mds"],
+      "Mitigation: Clear CPU buffers; SMT vulnerable"
+    );
+    assert_eq!(vulnerabilities["meltdown"], "Not affected");
+
+    let cpuinfo =
+      "processor\t: 0\nmodel name\t: Intel(R) Xeon(R) E-2288G CPU @ 3.70GHz\nmicrocode\t: 0xf8\n";
+    assert_eq!(
+      cpuinfo_field(cpuinfo, "model name").as_deref(),
+      Some("Intel(R) Xeon(R) E-2288G CPU @ 3.70GHz")
+    );
+    assert_eq!(cpuinfo_field(cpuinfo, "microcode").as_deref(), Some("0xf8"));
+    assert!(cpuinfo_field(cpuinfo, "flags").is_none());
+  }
+
+  #[test]
+  fn transparent_hugepage_mode() {
+    assert_eq!(
+      selected_mode("always [madvise] never").as_deref(),
+      Some("madvise")
+    );
+    assert_eq!(
+      selected_mode("[always] defer defer+madvise madvise never").as_deref(),
+      Some("always")
+    );
+    assert!(selected_mode("always madvise never").is_none());
+
+    let directory = TempDir::new().unwrap();
+    let enabled = directory.path().join("enabled");
+    fs::write(&enabled, "always [madvise] never\n").unwrap();
+    let mode = TransparentHugePageMode::set_at(&enabled, TransparentHugePages::Never).unwrap();
+    assert_eq!(fs::read_to_string(&enabled).unwrap(), "never");
+    drop(mode);
+    assert_eq!(fs::read_to_string(&enabled).unwrap(), "madvise");
+
+    let missing = directory.path().join("missing");
+    assert!(TransparentHugePageMode::set_at(&missing, TransparentHugePages::Always).is_err());
+  }
+
+  #[test]
+  fn cpu_topology() {
+    let directory = TempDir::new().unwrap();
+    // 2 cores with 2 hyper-threads each: cpu0/cpu1 and cpu2/cpu3
+    for (cpu, core) in [(0, 0), (1, 0), (2, 1), (3, 1)] {
+      let topology = directory.path().join(format!("cpu{}/topology", cpu));
+      fs::create_dir_all(&topology).unwrap();
+      fs::write(topology.join("physical_package_id"), "0\n").unwrap();
+      fs::write(topology.join("core_id"), format!("{}\n", core)).unwrap();
+    }
+    fs::create_dir_all(directory.path().join("cpufreq")).unwrap();
+
+    assert_eq!(cpus_by_core(directory.path()), vec![0, 2, 1, 3]);
+    assert!(cpus_by_core(&directory.path().join("missing")).is_empty());
+
+    let topology = directory.path().join("cpu4/topology");
+    fs::create_dir_all(&topology).unwrap();
+    fs::write(topology.join("physical_package_id"), "1\n").unwrap();
+    assert_eq!(
+      packages_of(directory.path(), &[0, 3, 4, 9]),
+      [0, 1].into_iter().collect()
+    );
+  }
+
+  #[test]
+  fn partition_disk() {
+    // /sys/class/block links to the devices, with the partitions in the directory of their disk
+    let directory = TempDir::new().unwrap();
+    let disk = directory.path().join("devices/nvme0/nvme0n1");
+    fs::create_dir_all(disk.join("nvme0n1p3")).unwrap();
+    fs::write(disk.join("nvme0n1p3/partition"), "3\n").unwrap();
+    let class = directory.path().join("class/block");
+    fs::create_dir_all(&class).unwrap();
+    std::os::unix::fs::symlink(&disk, class.join("nvme0n1")).unwrap();
+    std::os::unix::fs::symlink(disk.join("nvme0n1p3"), class.join("nvme0n1p3")).unwrap();
+
+    assert_eq!(parent_disk(&class, "nvme0n1p3"), "nvme0n1");
+    assert_eq!(parent_disk(&class, "nvme0n1"), "nvme0n1");
+    assert_eq!(parent_disk(&class, "sda"), "sda");
+  }
+
+  #[test]
+  fn sgx_platform_warnings() {
+    let config = toml::from_str::<Config>(
+      r#"
+      [globals]
+      sample_size = 1
+      output_directory = "/tmp/test"
+
+      [[tasks]]
+      executable = "/bin/dd"
+      enclave_size = ["64M", "256M"]
+      env = { EDMM = "1" }
+      "#,
+    )
+    .unwrap();
+    let sgx2 = SgxPlatform {
+      sgx1: true,
+      sgx2: true,
+      flc: true,
+      epc_sections: vec![128 << 20],
+      driver: Some("in-kernel".to_string()),
+    };
+    let warnings = sgx_warnings(Some(&sgx2), &config.tasks);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("256M"));
+
+    let sgx1 = SgxPlatform {
+      sgx2: false,
+      flc: false,
+      epc_sections: vec![512 << 20],
+      ..sgx2
+    };
+    let warnings = sgx_warnings(Some(&sgx1), &config.tasks);
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].contains("Flexible Launch Control"));
+    assert!(warnings[1].contains("EDMM"));
+
+    assert_eq!(sgx_warnings(None, &config.tasks).len(), 1);
+    assert!(sgx_warnings(None, &[]).is_empty());
+  }
+
+  #[test]
+  fn enclave_sizes_fit() {
+    let config = toml::from_str::<Config>(
+      r#"
+      [globals]
+      sample_size = 1
+      output_directory = "/tmp/test"
+
+      [[tasks]]
+      executable = "/bin/dd"
+      enclave_size = ["64M", "8G"]
+
+      [[tasks]]
+      executable = "/bin/ls"
+      enclave_size = ["16G"]
+      env = { EDMM = "1" }
+      "#,
+    )
+    .unwrap();
+    let platform = SgxPlatform {
+      sgx1: true,
+      sgx2: true,
+      flc: true,
+      epc_sections: vec![128 << 20],
+      driver: Some("in-kernel".to_string()),
+    };
+    assert!(check_enclave_sizes(&platform, 8 << 30, &config.tasks).is_ok());
+    let error = check_enclave_sizes(&platform, 4 << 30, &config.tasks).unwrap_err();
+    assert!(error.contains("8G"));
+    assert!(!error.contains("16G"));
+  }
+}
@@ -0,0 +1,440 @@
+//! Combined timeline of the deep traces of several samples (see `load`): the events of every
+//! `trace.csv` are made relative to the start of their process, so that repetitions of an
+//! experiment line up and behavior common to all of them stands out from sporadic one. The
+//! timeline is exported as a CSV (see `write_csv`) and as a standalone HTML page (see
+//! `write_html`).
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt::Write as _,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use crate::constants::{TIMELINE_CSV_HEADER, TIMELINE_HTML_BUCKETS};
+
+/// Events drawn as vertical lines across the rows of their sample rather than as a row.
+const MARKERS: [&str; 4] = ["process-start", "first-output", "ready", "process-exit"];
+
+/// Colors of the event rows, in order of the event names.
+const COLORS: [&str; 10] = [
+  "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+  "#bcbd22", "#17becf",
+];
+
+/// An event of a deep trace, relative to the start of its process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineEvent {
+  pub offset_ns: u64,
+  pub event: String,
+  pub bytes: u64,
+}
+
+/// The deep trace of a sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineSample {
+  /// The path of the sample directory from the directory holding all the experiments (at least
+  /// `<experiment>/<sample>`), unique among the loaded samples.
+  pub label: String,
+  pub events: Vec<TimelineEvent>,
+}
+
+impl TimelineSample {
+  fn duration_ns(&self) -> u64 {
+    self
+      .events
+      .iter()
+      .map(|event| event.offset_ns)
+      .max()
+      .unwrap_or_default()
+  }
+}
+
+/// Loads the deep traces of `directories`: sample directories with a `trace.csv`, or experiment
+/// directories, whose samples with a `trace.csv` (the `deep-trace` run) are loaded in order. The
+/// origin of every sample is its `process-start` marker, or its first event without one.
+pub fn load(directories: &[PathBuf]) -> Result<Vec<TimelineSample>, Box<dyn std::error::Error>> {
+  let mut loaded = Vec::new();
+  for directory in directories {
+    let sample_directories = if directory.join("trace.csv").is_file() {
+      vec![directory.clone()]
+    } else {
+      let mut sample_directories: Vec<PathBuf> = fs::read_dir(directory)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.join("trace.csv").is_file())
+        .collect();
+      // numbered samples first, in order
+      sample_directories.sort_by_key(|path| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        (name.parse::<u32>().unwrap_or(u32::MAX), name.into_owned())
+      });
+      sample_directories
+    };
+    if sample_directories.is_empty() {
+      return Err(format!("no deep trace (trace.csv) in {:?}", directory).into());
+    }
+    for sample_directory in sample_directories {
+      let content = fs::read_to_string(sample_directory.join("trace.csv"))?;
+      loaded.push((sample_directory, parse_trace(&content)));
+    }
+  }
+  let sample_directories: Vec<&Path> = loaded.iter().map(|(path, _)| path.as_path()).collect();
+  let labels = labels(&sample_directories);
+  Ok(
+    loaded
+      .into_iter()
+      .zip(labels)
+      .map(|((_, events), label)| TimelineSample { label, events })
+      .collect(),
+  )
+}
+
+// the paths of the sample directories from the deepest directory holding all their experiment
+// directories, so that samples of experiments with the same name in different campaigns differ;
+// the labels are numbered if a directory was given twice
+fn labels(sample_directories: &[&Path]) -> Vec<String> {
+  let canonical: Vec<PathBuf> = sample_directories
+    .iter()
+    .map(|path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf()))
+    .collect();
+  let mut common: Option<PathBuf> = None;
+  for path in &canonical {
+    let root = path
+      .parent()
+      .and_then(Path::parent)
+      .unwrap_or(Path::new(""));
+    common = Some(match common {
+      None => root.to_path_buf(),
+      Some(common) => common
+        .components()
+        .zip(root.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect(),
+    });
+  }
+  let common = common.unwrap_or_default();
+  let labels: Vec<String> = canonical
+    .iter()
+    .map(|path| {
+      path
+        .strip_prefix(&common)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+    })
+    .collect();
+  let unique: BTreeSet<&String> = labels.iter().collect();
+  if unique.len() == labels.len() {
+    return labels;
+  }
+  labels
+    .into_iter()
+    .enumerate()
+    .map(|(i, label)| format!("{}:{}", i + 1, label))
+    .collect()
+}
+
+// rows of trace.csv are "<timestamp (ns)>,<event>,<bytes>"
+fn parse_trace(content: &str) -> Vec<TimelineEvent> {
+  let rows: Vec<(u64, &str, u64)> = content
+    .lines()
+    .skip(1)
+    .filter_map(|line| {
+      let mut fields = line.split(',');
+      let timestamp = fields.next()?.trim().parse().ok()?;
+      let event = fields.next()?.trim();
+      let bytes = fields.next()?.trim().parse().unwrap_or_default();
+      Some((timestamp, event, bytes))
+    })
+    .collect();
+  let origin = rows
+    .iter()
+    .find(|(_, event, _)| *event == "process-start")
+    .or_else(|| rows.iter().min_by_key(|(timestamp, ..)| *timestamp))
+    .map(|(timestamp, ..)| *timestamp)
+    .unwrap_or_default();
+  let mut events: Vec<TimelineEvent> = rows
+    .into_iter()
+    .map(|(timestamp, event, bytes)| TimelineEvent {
+      offset_ns: timestamp.saturating_sub(origin),
+      event: event.to_string(),
+      bytes,
+    })
+    .collect();
+  events.sort_by_key(|event| event.offset_ns);
+  events
+}
+
+/// Writes the events of all `samples` to `path`, one row per event.
+pub fn write_csv(samples: &[TimelineSample], path: &Path) -> Result<(), std::io::Error> {
+  let mut csv = format!("{}\n", TIMELINE_CSV_HEADER);
+  for sample in samples {
+    for event in &sample.events {
+      writeln!(
+        csv,
+        "{},{},{},{}",
+        sample.label, event.offset_ns, event.event, event.bytes
+      )
+      .unwrap();
+    }
+  }
+  fs::write(path, csv)
+}
+
+/// Writes a standalone HTML page to `path` with a row per event type and sample on a common time
+/// axis, the markers of every sample, and a table of how consistently every event type occurs.
+pub fn write_html(samples: &[TimelineSample], path: &Path) -> Result<(), std::io::Error> {
+  fs::write(path, render_html(samples))
+}
+
+fn render_html(samples: &[TimelineSample]) -> String {
+  const LABEL_WIDTH: usize = 220;
+  const PLOT_WIDTH: usize = 1000;
+  const ROW_HEIGHT: usize = 14;
+  const SECTION_GAP: usize = 10;
+
+  let duration_ns = samples
+    .iter()
+    .map(TimelineSample::duration_ns)
+    .max()
+    .unwrap_or_default()
+    .max(1);
+  let event_types: BTreeSet<&str> = samples
+    .iter()
+    .flat_map(|sample| sample.events.iter().map(|event| event.event.as_str()))
+    .filter(|event| !MARKERS.contains(event))
+    .collect();
+  let x =
+    |offset_ns: u64| LABEL_WIDTH as f64 + offset_ns as f64 / duration_ns as f64 * PLOT_WIDTH as f64;
+  let bucket_width = PLOT_WIDTH as f64 / TIMELINE_HTML_BUCKETS as f64;
+
+  let mut svg = String::new();
+  let mut y = 20;
+  for (i, event_type) in event_types.iter().enumerate() {
+    let color = COLORS[i % COLORS.len()];
+    writeln!(
+      svg,
+      r#"<text x="0" y="{}" font-weight="bold">{}</text>"#,
+      y + ROW_HEIGHT - 3,
+      escape(event_type)
+    )
+    .unwrap();
+    y += ROW_HEIGHT;
+    for sample in samples {
+      writeln!(
+        svg,
+        r#"<text x="10" y="{}">{}</text>"#,
+        y + ROW_HEIGHT - 3,
+        escape(&sample.label)
+      )
+      .unwrap();
+      // events are counted by bucket: traces have far more events than pixels
+      let mut buckets: BTreeMap<usize, usize> = BTreeMap::new();
+      for event in sample.events.iter().filter(|e| e.event == *event_type) {
+        let bucket =
+          (event.offset_ns as u128 * TIMELINE_HTML_BUCKETS as u128 / duration_ns as u128) as usize;
+        *buckets
+          .entry(bucket.min(TIMELINE_HTML_BUCKETS - 1))
+          .or_default() += 1;
+      }
+      let busiest = buckets.values().max().copied().unwrap_or(1) as f64;
+      for (bucket, count) in buckets {
+        writeln!(
+          svg,
+          r#"<rect x="{:.1}" y="{}" width="{:.1}" height="{}" fill="{}" fill-opacity="{:.2}"><title>{}: {} events</title></rect>"#,
+          LABEL_WIDTH as f64 + bucket as f64 * bucket_width,
+          y + 2,
+          bucket_width.max(1.0),
+          ROW_HEIGHT - 4,
+          color,
+          0.3 + 0.7 * count as f64 / busiest,
+          escape(&sample.label),
+          count
+        )
+        .unwrap();
+      }
+      for marker in sample
+        .events
+        .iter()
+        .filter(|event| MARKERS.contains(&event.event.as_str()))
+      {
+        writeln!(
+          svg,
+          r#"<line x1="{0:.1}" x2="{0:.1}" y1="{1}" y2="{2}" stroke="black"><title>{3}</title></line>"#,
+          x(marker.offset_ns),
+          y,
+          y + ROW_HEIGHT,
+          escape(&marker.event)
+        )
+        .unwrap();
+      }
+      y += ROW_HEIGHT;
+    }
+    y += SECTION_GAP;
+  }
+  // time axis
+  for tick in 0..=10 {
+    let offset_ns = duration_ns / 10 * tick;
+    writeln!(
+      svg,
+      r#"<text x="{:.1}" y="{}" text-anchor="middle">{:.3}s</text>"#,
+      x(offset_ns),
+      y + ROW_HEIGHT,
+      offset_ns as f64 / 1e9
+    )
+    .unwrap();
+  }
+  y += 2 * ROW_HEIGHT;
+
+  let mut table = String::from(
+    "<tr><th>event</th><th>samples</th><th>events (min / mean / max)</th><th>first at (min / max)</th></tr>\n",
+  );
+  for event_type in &event_types {
+    let counts: Vec<usize> = samples
+      .iter()
+      .map(|sample| {
+        sample
+          .events
+          .iter()
+          .filter(|e| e.event == *event_type)
+          .count()
+      })
+      .collect();
+    let first: Vec<u64> = samples
+      .iter()
+      .filter_map(|sample| {
+        sample
+          .events
+          .iter()
+          .find(|e| e.event == *event_type)
+          .map(|e| e.offset_ns)
+      })
+      .collect();
+    writeln!(
+      table,
+      "<tr><td>{}</td><td>{} / {}</td><td>{} / {:.1} / {}</td><td>{:.6}s / {:.6}s</td></tr>",
+      escape(event_type),
+      first.len(),
+      samples.len(),
+      counts.iter().min().unwrap_or(&0),
+      counts.iter().sum::<usize>() as f64 / samples.len().max(1) as f64,
+      counts.iter().max().unwrap_or(&0),
+      first.iter().min().copied().unwrap_or_default() as f64 / 1e9,
+      first.iter().max().copied().unwrap_or_default() as f64 / 1e9
+    )
+    .unwrap();
+  }
+
+  format!(
+    r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Deep trace timeline</title>
+<style>
+body {{ font-family: sans-serif; font-size: 12px; }}
+table {{ border-collapse: collapse; }}
+td, th {{ border: 1px solid #ccc; padding: 2px 6px; text-align: left; }}
+</style>
+</head>
+<body>
+<h1>Deep trace timeline</h1>
+<p>{} samples, aligned on the start of their process. Darker cells hold more events; black lines are the process-start, first-output, ready and process-exit markers.</p>
+<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-size="11">
+{}</svg>
+<h2>Consistency</h2>
+<table>
+{}</table>
+</body>
+</html>
+"#,
+    samples.len(),
+    LABEL_WIDTH + PLOT_WIDTH + 40,
+    y,
+    svg,
+    table
+  )
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+  use std::{collections::BTreeSet, fs};
+
+  use tempfile::TempDir;
+
+  use super::{load, render_html, write_csv};
+
+  #[test]
+  fn combined_timeline() {
+    let directory = TempDir::new().unwrap();
+    let first = directory.path().join("campaign-1/ls-1");
+    let second = directory.path().join("campaign-2/ls-1");
+    for (experiment, start) in [(&first, 1000), (&second, 5000)] {
+      fs::create_dir_all(experiment.join("deep-trace")).unwrap();
+      fs::create_dir_all(experiment.join("1")).unwrap();
+      fs::write(
+        experiment.join("deep-trace/trace.csv"),
+        format!(
+          "timestamp (ns),event,bytes\n{},sys-read,4096\n{},process-start,0\n{},process-exit,0\n",
+          start + 10,
+          start,
+          start + 100
+        ),
+      )
+      .unwrap();
+    }
+    fs::write(
+      second.join("1/trace.csv"),
+      "timestamp (ns),event,bytes\n70,sys-write,512\n50,sys-read,8\n",
+    )
+    .unwrap();
+
+    let samples = load(&[first.clone(), second.join("deep-trace"), second.join("1")]).unwrap();
+    assert_eq!(samples.len(), 3);
+    // the experiments of both campaigns have the same name
+    let labels: Vec<&str> = samples.iter().map(|s| s.label.as_str()).collect();
+    assert_eq!(
+      labels,
+      vec![
+        "campaign-1/ls-1/deep-trace",
+        "campaign-2/ls-1/deep-trace",
+        "campaign-2/ls-1/1"
+      ]
+    );
+    assert_eq!(labels.iter().collect::<BTreeSet<_>>().len(), labels.len());
+    assert_eq!(samples[0].events[0].event, "process-start");
+    assert_eq!(samples[0].events[1].offset_ns, 10);
+    assert_eq!(samples[1].events[2].offset_ns, 100);
+    // without a process-start marker, the first event is the origin
+    assert_eq!(samples[2].events[1].offset_ns, 20);
+    assert!(load(&[directory.path().to_path_buf()]).is_err());
+    // the samples of a single experiment keep its name
+    let single = load(std::slice::from_ref(&second)).unwrap();
+    assert_eq!(single[0].label, "ls-1/1");
+    assert_eq!(single[1].label, "ls-1/deep-trace");
+    // a directory given twice
+    let twice = load(&[first.clone(), first.clone()]).unwrap();
+    assert_eq!(twice[0].label, "1:ls-1/deep-trace");
+    assert_eq!(twice[1].label, "2:ls-1/deep-trace");
+
+    let csv = directory.path().join("timeline.csv");
+    write_csv(&samples, &csv).unwrap();
+    let csv = fs::read_to_string(csv).unwrap();
+    assert_eq!(csv.lines().count(), 9);
+    assert!(csv.contains("campaign-2/ls-1/1,20,sys-write,512"));
+
+    let html = render_html(&samples);
+    assert!(html.contains("<td>sys-read</td><td>3 / 3</td>"));
+    assert!(html.contains("<td>sys-write</td><td>1 / 3</td>"));
+    assert!(html.contains("<title>process-exit</title>"));
+  }
+}
@@ -0,0 +1,368 @@
+//! Long-term tracking of campaigns (see `track`): the summaries of every campaign are appended to
+//! an SQLite database, keyed by host, configuration and workload version, so that a metric can be
+//! followed across campaigns (see `trend`).
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path, process::Command, time::UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+  constants::CONFIG_FILE,
+  summary::{summarize, SummaryRow},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS campaigns (
+  id INTEGER PRIMARY KEY,
+  host TEXT NOT NULL,
+  -- sha256 of the configuration saved with the results
+  config_hash TEXT NOT NULL,
+  -- seconds since the epoch
+  started_at INTEGER NOT NULL,
+  results TEXT NOT NULL,
+  UNIQUE (host, config_hash, started_at)
+);
+CREATE TABLE IF NOT EXISTS metrics (
+  campaign INTEGER NOT NULL REFERENCES campaigns (id),
+  -- `git describe` of the workload, NULL if it is not in a git repository
+  workload_version TEXT,
+  task TEXT NOT NULL,
+  mode TEXT NOT NULL,
+  num_threads INTEGER NOT NULL,
+  enclave_size TEXT,
+  storage_type TEXT,
+  samples INTEGER NOT NULL,
+  metric TEXT NOT NULL,
+  value REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS metrics_by_name ON metrics (metric, task);
+";
+
+/// Columns of the trend table.
+const COLUMNS: [&str; 11] = [
+  "started",
+  "host",
+  "config",
+  "workload",
+  "task",
+  "mode",
+  "threads",
+  "size",
+  "storage",
+  "value",
+  "change (%)",
+];
+
+/// A campaign recorded in the database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedCampaign {
+  pub host: String,
+  pub config_hash: String,
+  /// Start of the campaign in seconds since the epoch: the time its configuration was saved.
+  pub started_at: u64,
+  /// Metrics recorded, over all the experiments.
+  pub metrics: usize,
+  /// `false` if the campaign was already in the database, which is left unchanged.
+  pub added: bool,
+}
+
+/// Mean of a metric in an experiment of a tracked campaign.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrendPoint {
+  /// Start of the campaign, in UTC (`YYYY-MM-DD HH:MM:SS`).
+  pub started: String,
+  pub host: String,
+  pub config_hash: String,
+  pub workload_version: Option<String>,
+  pub task: String,
+  pub mode: String,
+  pub num_threads: usize,
+  pub enclave_size: Option<String>,
+  pub storage_type: Option<String>,
+  pub value: f64,
+}
+
+impl TrendPoint {
+  // points of the same series are the same experiment on the same host
+  fn series(&self) -> impl PartialEq + '_ {
+    (
+      &self.host,
+      &self.task,
+      &self.mode,
+      self.num_threads,
+      &self.enclave_size,
+      &self.storage_type,
+    )
+  }
+}
+
+/// Appends the summary of the campaign in `results` to the database `db`, created if missing.
+/// Every metric of `summary.csv` and the SGX overhead of the experiments are recorded.
+pub fn track(db: &Path, results: &Path) -> Result<TrackedCampaign, Box<dyn std::error::Error>> {
+  let config = results.join(CONFIG_FILE);
+  let config_hash = format!("{:x}", Sha256::digest(fs::read(&config)?));
+  let started_at = fs::metadata(&config)?
+    .modified()?
+    .duration_since(UNIX_EPOCH)?
+    .as_secs();
+  let host =
+    serde_json::from_str::<serde_json::Value>(&fs::read_to_string(results.join("system.json"))?)?
+      ["hostname"]
+      .as_str()
+      .unwrap_or("unknown")
+      .to_string();
+  let rows = summarize(results)?;
+
+  let mut connection = open(db)?;
+  let transaction = connection.transaction()?;
+  let existing: Option<i64> = transaction
+    .query_row(
+      "SELECT id FROM campaigns WHERE host = ?1 AND config_hash = ?2 AND started_at = ?3",
+      params![host, config_hash, started_at],
+      |row| row.get(0),
+    )
+    .optional()?;
+  let mut campaign = TrackedCampaign {
+    host,
+    config_hash,
+    started_at,
+    metrics: 0,
+    added: existing.is_none(),
+  };
+  if existing.is_some() {
+    return Ok(campaign);
+  }
+
+  transaction.execute(
+    "INSERT INTO campaigns (host, config_hash, started_at, results) VALUES (?1, ?2, ?3, ?4)",
+    params![
+      campaign.host,
+      campaign.config_hash,
+      campaign.started_at,
+      results.to_string_lossy()
+    ],
+  )?;
+  let id = transaction.last_insert_rowid();
+  {
+    let mut insert = transaction.prepare(
+      "INSERT INTO metrics (campaign, workload_version, task, mode, num_threads, enclave_size,
+       storage_type, samples, metric, value) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )?;
+    // programs are usually shared by several experiments
+    let mut versions = BTreeMap::new();
+    for row in &rows {
+      let version = versions
+        .entry(row.program.clone())
+        .or_insert_with(|| workload_version(&row.program))
+        .clone();
+      for (metric, value) in metrics(row) {
+        insert.execute(params![
+          id,
+          version,
+          row.task,
+          mode(row),
+          row.num_threads,
+          row.enclave_size,
+          row.storage_type,
+          row.samples,
+          metric,
+          value
+        ])?;
+        campaign.metrics += 1;
+      }
+    }
+  }
+  transaction.commit()?;
+  Ok(campaign)
+}
+
+/// Reads the values of `metric` from the database `db`, optionally only for the tasks and host
+/// given, by experiment and in the order of the campaigns.
+pub fn trend(
+  db: &Path,
+  metric: &str,
+  task: Option<&str>,
+  host: Option<&str>,
+) -> Result<Vec<TrendPoint>, rusqlite::Error> {
+  let connection = open(db)?;
+  let mut query = connection.prepare(
+    "SELECT datetime(c.started_at, 'unixepoch'), c.host, c.config_hash, m.workload_version,
+     m.task, m.mode, m.num_threads, m.enclave_size, m.storage_type, m.value
+     FROM metrics m JOIN campaigns c ON m.campaign = c.id
+     WHERE m.metric = ?1 AND (?2 IS NULL OR m.task = ?2) AND (?3 IS NULL OR c.host = ?3)
+     ORDER BY c.host, m.task, m.mode DESC, m.num_threads, m.enclave_size, m.storage_type,
+     c.started_at, c.id",
+  )?;
+  let points = query.query_map(params![metric, task, host], |row| {
+    Ok(TrendPoint {
+      started: row.get(0)?,
+      host: row.get(1)?,
+      config_hash: row.get(2)?,
+      workload_version: row.get(3)?,
+      task: row.get(4)?,
+      mode: row.get(5)?,
+      num_threads: row.get(6)?,
+      enclave_size: row.get(7)?,
+      storage_type: row.get(8)?,
+      value: row.get(9)?,
+    })
+  })?;
+  points.collect()
+}
+
+/// Formats `points` as a table with aligned columns, with the change of every value from the
+/// previous campaign of the same experiment.
+pub fn format_trend(points: &[TrendPoint]) -> String {
+  let cells: Vec<[String; 11]> = points
+    .iter()
+    .enumerate()
+    .map(|(i, point)| {
+      let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("-"));
+      let previous = i
+        .checked_sub(1)
+        .map(|previous| &points[previous])
+        .filter(|previous| previous.series() == point.series() && previous.value != 0.0);
+      [
+        point.started.clone(),
+        point.host.clone(),
+        point.config_hash.chars().take(12).collect(),
+        optional(point.workload_version.clone()),
+        point.task.clone(),
+        point.mode.clone(),
+        point.num_threads.to_string(),
+        optional(point.enclave_size.clone()),
+        optional(point.storage_type.clone()),
+        format!("{:.3}", point.value),
+        optional(
+          previous.map(|previous| format!("{:+.1}", (point.value / previous.value - 1.0) * 100.0)),
+        ),
+      ]
+    })
+    .collect();
+  let widths: Vec<usize> = (0..COLUMNS.len())
+    .map(|i| {
+      cells
+        .iter()
+        .map(|row| row[i].len())
+        .chain([COLUMNS[i].len()])
+        .max()
+        .unwrap_or_default()
+    })
+    .collect();
+
+  let mut table = String::new();
+  let header = COLUMNS.map(String::from);
+  for row in [&header].into_iter().chain(&cells) {
+    let line: Vec<String> = row
+      .iter()
+      .zip(&widths)
+      .enumerate()
+      // text is left aligned, numbers right aligned
+      .map(|(i, (cell, width))| {
+        if i < 6 || i == 7 || i == 8 {
+          format!("{:<width$}", cell)
+        } else {
+          format!("{:>width$}", cell)
+        }
+      })
+      .collect();
+    writeln!(table, "{}", line.join("  ").trim_end()).unwrap();
+  }
+  table
+}
+
+fn open(db: &Path) -> Result<Connection, rusqlite::Error> {
+  let connection = Connection::open(db)?;
+  connection.execute_batch(SCHEMA)?;
+  Ok(connection)
+}
+
+fn mode(row: &SummaryRow) -> &'static str {
+  if row.sgx {
+    "sgx"
+  } else {
+    "native"
+  }
+}
+
+// metrics recorded for an experiment
+fn metrics(row: &SummaryRow) -> impl Iterator<Item = (&str, f64)> {
+  row
+    .metrics
+    .iter()
+    .map(|(metric, value)| (metric.as_str(), *value))
+    .chain(row.sgx_overhead.map(|overhead| ("sgx_overhead", overhead)))
+}
+
+// `git describe` of the repository containing the program, if any
+fn workload_version(program: &Path) -> Option<String> {
+  let directory = program.parent().filter(|parent| parent.is_dir())?;
+  let output = Command::new("git")
+    .arg("-C")
+    .arg(directory)
+    .args(["describe", "--always", "--dirty", "--tags"])
+    .output()
+    .ok()
+    .filter(|output| output.status.success())?;
+  Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+  use std::{fs, path::Path};
+
+  use tempfile::TempDir;
+
+  use super::{format_trend, track, trend};
+
+  fn campaign(results: &Path, config: &str, runtime: &str) {
+    let experiment = results.join("ls/no-gramine-sgx/ls-1");
+    fs::create_dir_all(experiment.join("1")).unwrap();
+    fs::write(results.join("config.toml"), config).unwrap();
+    fs::write(results.join("system.json"), r#"{"hostname": "lab-node3"}"#).unwrap();
+    fs::write(
+      experiment.join("metadata.json"),
+      r#"{"program": "/nonexistent/ls", "sgx": false, "num_threads": 1, "enclave_size": null,
+          "storage_type": null, "rpc_thread_num": 0, "args_matrix": {}}"#,
+    )
+    .unwrap();
+    fs::write(
+      experiment.join("1/summary.csv"),
+      format!("metric,unit,value\nruntime,ns,{}\nenergy,uJ,5", runtime),
+    )
+    .unwrap();
+  }
+
+  #[test]
+  fn track_campaigns() {
+    let directory = TempDir::new().unwrap();
+    let db = directory.path().join("trends.sqlite");
+    let first = directory.path().join("first");
+    let second = directory.path().join("second");
+    campaign(&first, "[globals]\nsample_size = 1\n", "1000");
+    campaign(&second, "[globals]\nsample_size = 2\n", "1500");
+
+    let tracked = track(&db, &first).unwrap();
+    assert_eq!(tracked.host, "lab-node3");
+    assert_eq!(tracked.metrics, 2);
+    assert!(tracked.added);
+    // tracking the same campaign again does not duplicate it
+    assert!(!track(&db, &first).unwrap().added);
+    track(&db, &second).unwrap();
+
+    let points = trend(&db, "runtime", Some("ls"), None).unwrap();
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].mode, "native");
+    assert_eq!(points[0].workload_version, None);
+    assert_eq!(points[1].value, 1500.0);
+    assert_ne!(points[0].config_hash, points[1].config_hash);
+    assert!(trend(&db, "runtime", None, Some("other"))
+      .unwrap()
+      .is_empty());
+
+    let table = format_trend(&points);
+    assert_eq!(table.lines().count(), 3);
+    assert!(table.lines().last().unwrap().ends_with("+50.0"));
+  }
+}
@@ -0,0 +1,344 @@
+use std::{
+  io::{self, BufRead, Write},
+  path::Path,
+  thread,
+};
+
+use crate::{
+  common::{format_size, parse_size},
+  system::SgxPlatform,
+};
+
+/// Machine characteristics used to suggest configuration values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineInfo {
+  pub cores: usize,
+  /// Size of the Enclave Page Cache in bytes; `None` if SGX is not available.
+  pub epc_size: Option<u64>,
+}
+
+impl MachineInfo {
+  pub fn probe() -> Self {
+    Self {
+      cores: thread::available_parallelism().map_or(1, |n| n.get()),
+      // without a driver no enclave can be run, whatever the CPU supports
+      epc_size: SgxPlatform::probe()
+        .filter(|platform| platform.driver.is_some())
+        .map(|platform| platform.epc_size())
+        .filter(|&size| size > 0),
+    }
+  }
+
+  /// Powers of two up to the number of cores (the core count itself is always included).
+  pub fn suggested_threads(&self) -> Vec<usize> {
+    let mut threads: Vec<usize> = (0..)
+      .map(|i| 1 << i)
+      .take_while(|&n| n <= self.cores)
+      .collect();
+    if threads.last() != Some(&self.cores) {
+      threads.push(self.cores);
+    }
+    threads
+  }
+
+  /// Enclave sizes from 64M doubling up to twice the EPC, so that the sweep crosses the point
+  /// where the enclave starts paging. Without SGX a small default sweep is returned.
+  pub fn suggested_enclave_sizes(&self) -> Vec<String> {
+    let Some(epc_size) = self.epc_size.filter(|&s| s > 0) else {
+      return vec![String::from("256M"), String::from("1G")];
+    };
+    let limit = epc_size.saturating_mul(2).max(64 << 20);
+    (0..)
+      .map(|i| (64u64 << 20) << i)
+      .take_while(|&size| size <= limit)
+      .map(format_size)
+      .collect()
+  }
+}
+
+// asks a question showing the default value, which is returned on an empty answer
+fn prompt(
+  input: &mut impl BufRead,
+  output: &mut impl Write,
+  question: &str,
+  default: &str,
+) -> io::Result<String> {
+  if default.is_empty() {
+    write!(output, "{}: ", question)?;
+  } else {
+    write!(output, "{} [{}]: ", question, default)?;
+  }
+  output.flush()?;
+  let mut answer = String::new();
+  if input.read_line(&mut answer)? == 0 {
+    return Err(io::Error::new(
+      io::ErrorKind::UnexpectedEof,
+      "input closed before the configuration was complete",
+    ));
+  }
+  let answer = answer.trim();
+  Ok(if answer.is_empty() {
+    default.to_string()
+  } else {
+    answer.to_string()
+  })
+}
+
+// asks a question until `parse` accepts the answer, explaining why the previous one was rejected
+fn prompt_parsed<T>(
+  input: &mut impl BufRead,
+  output: &mut impl Write,
+  question: &str,
+  default: &str,
+  parse: impl Fn(&str) -> Result<T, String>,
+) -> io::Result<T> {
+  loop {
+    let answer = prompt(input, output, question, default)?;
+    match parse(&answer) {
+      Ok(value) => return Ok(value),
+      Err(e) => writeln!(output, "{}", e)?,
+    }
+  }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+  value
+    .split(',')
+    .map(str::trim)
+    .filter(|v| !v.is_empty())
+    .map(String::from)
+    .collect()
+}
+
+/// Asks for the settings of a benchmark, suggesting values based on `machine`, and returns the
+/// content of a configuration file.
+pub fn run(
+  input: &mut impl BufRead,
+  output: &mut impl Write,
+  machine: &MachineInfo,
+) -> io::Result<String> {
+  writeln!(
+    output,
+    "Detected {} cores; EPC size: {}",
+    machine.cores,
+    machine
+      .epc_size
+      .map_or(String::from("unknown (SGX not available)"), format_size)
+  )?;
+
+  let executable = loop {
+    let executable = prompt(input, output, "Executable to benchmark (absolute path)", "")?;
+    if executable.is_empty() {
+      writeln!(output, "An executable is required")?;
+      continue;
+    }
+    if !Path::new(&executable).is_file() {
+      writeln!(
+        output,
+        "Warning: {:?} does not exist on this machine",
+        executable
+      )?;
+    }
+    break executable;
+  };
+  let args = prompt(input, output, "Arguments (separated by spaces)", "")?;
+
+  let threads = machine
+    .suggested_threads()
+    .iter()
+    .map(usize::to_string)
+    .collect::<Vec<_>>()
+    .join(", ");
+  let num_threads = prompt_parsed(input, output, "Thread counts", &threads, |threads| {
+    let threads = split_list(threads)
+      .iter()
+      .map(|t| match t.parse::<usize>() {
+        Ok(t) if t > 0 => Ok(t),
+        _ => Err(format!("Invalid thread count {:?}", t)),
+      })
+      .collect::<Result<Vec<usize>, String>>()?;
+    if threads.is_empty() {
+      return Err(String::from("At least one thread count is required"));
+    }
+    Ok(threads)
+  })?;
+
+  let sizes = machine.suggested_enclave_sizes().join(", ");
+  let enclave_size = prompt_parsed(input, output, "Enclave sizes", &sizes, |sizes| {
+    let sizes = split_list(sizes);
+    if let Some(s) = sizes.iter().find(|s| parse_size(s).unwrap_or(0) == 0) {
+      return Err(format!("Invalid enclave size {:?} (e.g. 256M, 1G)", s));
+    }
+    if sizes.is_empty() {
+      return Err(String::from("At least one enclave size is required"));
+    }
+    Ok(sizes)
+  })?;
+
+  let storage_type = prompt_parsed(
+    input,
+    output,
+    "Storage types (untrusted, encrypted, tmpfs)",
+    "untrusted, encrypted",
+    |storage| {
+      let storage = split_list(storage);
+      if let Some(s) = storage
+        .iter()
+        .find(|s| !["untrusted", "encrypted", "tmpfs"].contains(&s.as_str()))
+      {
+        return Err(format!("Invalid storage type {:?}", s));
+      }
+      if storage.is_empty() {
+        return Err(String::from("At least one storage type is required"));
+      }
+      Ok(storage)
+    },
+  )?;
+
+  let sample_size = prompt_parsed(
+    input,
+    output,
+    "Samples per experiment",
+    "5",
+    |size| match size.parse::<u32>() {
+      Ok(size) if size > 0 => Ok(size),
+      _ => Err(format!("Invalid sample size {:?}", size)),
+    },
+  )?;
+  let output_directory = prompt(input, output, "Output directory", "results")?;
+
+  let mut globals = toml::Table::new();
+  globals.insert("sample_size".into(), i64::from(sample_size).into());
+  globals.insert("output_directory".into(), output_directory.into());
+
+  let mut task = toml::Table::new();
+  task.insert("executable".into(), executable.into());
+  task.insert(
+    "args".into(),
+    args
+      .split_whitespace()
+      .map(String::from)
+      .collect::<Vec<_>>()
+      .into(),
+  );
+  task.insert(
+    "num_threads".into(),
+    num_threads
+      .into_iter()
+      .map(|t| t as i64)
+      .collect::<Vec<_>>()
+      .into(),
+  );
+  task.insert("enclave_size".into(), enclave_size.into());
+  task.insert("storage_type".into(), storage_type.into());
+
+  let mut config = toml::Table::new();
+  config.insert("globals".into(), globals.into());
+  config.insert("tasks".into(), vec![toml::Value::Table(task)].into());
+  toml::to_string(&config).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::Cursor;
+
+  use crate::{
+    wizard::{run, MachineInfo},
+    Config,
+  };
+
+  #[test]
+  fn suggestions() {
+    let machine = MachineInfo {
+      cores: 6,
+      epc_size: Some(128 << 20),
+    };
+    assert_eq!(machine.suggested_threads(), vec![1, 2, 4, 6]);
+    assert_eq!(
+      machine.suggested_enclave_sizes(),
+      vec!["64M", "128M", "256M"]
+    );
+
+    let machine = MachineInfo {
+      cores: 1,
+      epc_size: None,
+    };
+    assert_eq!(machine.suggested_threads(), vec![1]);
+    assert_eq!(machine.suggested_enclave_sizes(), vec!["256M", "1G"]);
+  }
+
+  #[test]
+  fn wizard_writes_valid_config() {
+    let machine = MachineInfo {
+      cores: 4,
+      epc_size: Some(64 << 20),
+    };
+    // executable, args, then defaults except for the storage types
+    let mut input = Cursor::new("/bin/dd\nif=/dev/zero of=/dev/null count=10\n\n\nencrypted\n\n\n");
+    let mut output = Vec::new();
+    let content = run(&mut input, &mut output, &machine).unwrap();
+
+    let config = toml::from_str::<Config>(&content).unwrap();
+    assert_eq!(config.globals.sample_size, 5);
+    assert_eq!(config.tasks.len(), 1);
+    assert_eq!(config.tasks[0].args.len(), 3);
+    assert_eq!(config.tasks[0].num_threads, vec![1, 2, 4]);
+    assert_eq!(config.tasks[0].enclave_size, vec!["64M", "128M"]);
+    assert_eq!(config.tasks[0].storage_type.len(), 1);
+    assert!(String::from_utf8(output)
+      .unwrap()
+      .contains("Detected 4 cores"));
+  }
+
+  #[test]
+  fn wizard_asks_again_on_invalid_answers() {
+    let machine = MachineInfo {
+      cores: 2,
+      epc_size: None,
+    };
+    // every invalid answer is followed by a valid one for the same question
+    let mut input = Cursor::new(
+      "
+/bin/true
+
+1, x
+0
+4
+12Q
+64M
+nfs
+tmpfs
+-1
+3
+
+",
+    );
+    let mut output = Vec::new();
+    let content = run(&mut input, &mut output, &machine).unwrap();
+
+    let config = toml::from_str::<Config>(&content).unwrap();
+    assert_eq!(config.globals.sample_size, 3);
+    assert_eq!(config.tasks[0].num_threads, vec![4]);
+    assert_eq!(config.tasks[0].enclave_size, vec!["64M"]);
+    let output = String::from_utf8(output).unwrap();
+    for message in [
+      "An executable is required",
+      "Invalid thread count \"x\"",
+      "Invalid thread count \"0\"",
+      "Invalid enclave size \"12Q\"",
+      "Invalid storage type \"nfs\"",
+      "Invalid sample size \"-1\"",
+    ] {
+      assert!(output.contains(message), "{}", message);
+    }
+
+    // closing the input still aborts
+    let mut input = Cursor::new(
+      "/bin/true
+
+x
+",
+    );
+    assert!(run(&mut input, &mut Vec::new(), &machine).is_err());
+  }
+}